@@ -0,0 +1,74 @@
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use kv_rust::app::models::{CreateKVRequest, ValueEncoding, ValueType};
+use kv_rust::service::{InMemoryStorage, Storage};
+use kv_rust::types::Key;
+use std::thread;
+
+const THREADS: usize = 8;
+const OPS_PER_THREAD: usize = 2_000;
+const KEY_SPACE: usize = 1_024;
+
+fn keys() -> Vec<Key> {
+    (0..KEY_SPACE)
+        .map(|i| Key::new(format!("key-{:05}", i)).unwrap())
+        .collect()
+}
+
+fn contended_mixed_workload(storage: &InMemoryStorage, keys: &[Key]) {
+    thread::scope(|scope| {
+        for thread_id in 0..THREADS {
+            scope.spawn(move || {
+                for op in 0..OPS_PER_THREAD {
+                    let key = &keys[(thread_id * 7919 + op * 31) % keys.len()];
+                    if op % 4 == 0 {
+                        storage
+                            .upsert(CreateKVRequest {
+                                key: key.clone(),
+                                value: "value".into(),
+                                value_type: ValueType::Text,
+                                encoding: ValueEncoding::Utf8,
+                                ttl_seconds: None,
+                            })
+                            .unwrap();
+                    } else {
+                        let _ = storage.get(key.clone());
+                    }
+                }
+            });
+        }
+    });
+}
+
+fn bench_shard_counts(c: &mut Criterion) {
+    let keys = keys();
+    let mut group = c.benchmark_group("shards/8_threads_25pct_writes");
+    group.throughput(Throughput::Elements((THREADS * OPS_PER_THREAD) as u64));
+
+    let mut configs: Vec<(String, InMemoryStorage)> =
+        vec![("default".to_string(), InMemoryStorage::new())];
+    for shards in [4, 16, 64, 256] {
+        configs.push((shards.to_string(), InMemoryStorage::with_shards(shards)));
+    }
+
+    for (label, storage) in &configs {
+        for key in &keys {
+            storage
+                .upsert(CreateKVRequest {
+                    key: key.clone(),
+                    value: "value".into(),
+                    value_type: ValueType::Text,
+                    encoding: ValueEncoding::Utf8,
+                    ttl_seconds: None,
+                })
+                .unwrap();
+        }
+        group.bench_with_input(BenchmarkId::from_parameter(label), storage, |b, storage| {
+            b.iter(|| contended_mixed_workload(storage, &keys))
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_shard_counts);
+criterion_main!(benches);
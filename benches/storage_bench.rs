@@ -0,0 +1,202 @@
+use criterion::{BatchSize, BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use kv_rust::app::models::{CreateKVRequest, ValueEncoding, ValueType};
+use kv_rust::service::{InMemoryStorage, Storage, WriteOp};
+use kv_rust::types::Key;
+use std::thread;
+
+const KEY_COUNTS: [usize; 3] = [1_000, 10_000, 100_000];
+const CONTENDED_THREADS: usize = 8;
+const CONTENDED_OPS_PER_THREAD: usize = 1_000;
+const BATCH_SIZE: usize = 1_000;
+
+fn key(i: usize) -> Key {
+    Key::new(format!("key-{:07}", i)).unwrap()
+}
+
+fn request(i: usize) -> CreateKVRequest {
+    CreateKVRequest {
+        key: key(i),
+        value: format!("value-{}", i).into(),
+        value_type: ValueType::Text,
+        encoding: ValueEncoding::Utf8,
+        ttl_seconds: None,
+    }
+}
+
+fn populated(key_count: usize) -> InMemoryStorage {
+    let storage = InMemoryStorage::new();
+    for i in 0..key_count {
+        storage.upsert(request(i)).unwrap();
+    }
+    storage
+}
+
+fn bench_single_threaded(c: &mut Criterion) {
+    let mut group = c.benchmark_group("single_thread");
+    group.throughput(Throughput::Elements(1));
+
+    for key_count in KEY_COUNTS {
+        let storage = populated(key_count);
+        let mut next = 0;
+
+        group.bench_with_input(
+            BenchmarkId::new("get", key_count),
+            &storage,
+            |b, storage| {
+                b.iter(|| {
+                    next = (next + 7919) % key_count;
+                    storage.get(key(next)).unwrap()
+                })
+            },
+        );
+
+        group.bench_with_input(
+            BenchmarkId::new("upsert", key_count),
+            &storage,
+            |b, storage| {
+                b.iter(|| {
+                    next = (next + 7919) % key_count;
+                    storage.upsert(request(next)).unwrap()
+                })
+            },
+        );
+
+        let mut fresh = key_count;
+        group.bench_with_input(
+            BenchmarkId::new("insert", key_count),
+            &storage,
+            |b, storage| {
+                b.iter(|| {
+                    fresh += 1;
+                    storage.insert(request(fresh)).unwrap()
+                })
+            },
+        );
+
+        group.bench_function(BenchmarkId::new("delete", key_count), |b| {
+            b.iter_batched(
+                || {
+                    next = (next + 7919) % key_count;
+                    storage.upsert(request(next)).unwrap();
+                    key(next)
+                },
+                |victim| storage.delete(victim).unwrap(),
+                BatchSize::SmallInput,
+            )
+        });
+    }
+
+    group.finish();
+}
+
+fn bench_contended(c: &mut Criterion) {
+    let mut group = c.benchmark_group("contended_8_threads");
+    group.throughput(Throughput::Elements(
+        (CONTENDED_THREADS * CONTENDED_OPS_PER_THREAD) as u64,
+    ));
+
+    for key_count in KEY_COUNTS {
+        let storage = populated(key_count);
+
+        group.bench_with_input(
+            BenchmarkId::new("get", key_count),
+            &storage,
+            |b, storage| {
+                b.iter(|| {
+                    run_threads(|thread_id, op| {
+                        let _ = storage.get(key((thread_id * 7919 + op * 31) % key_count));
+                    })
+                })
+            },
+        );
+
+        group.bench_with_input(
+            BenchmarkId::new("upsert", key_count),
+            &storage,
+            |b, storage| {
+                b.iter(|| {
+                    run_threads(|thread_id, op| {
+                        storage
+                            .upsert(request((thread_id * 7919 + op * 31) % key_count))
+                            .unwrap();
+                    })
+                })
+            },
+        );
+
+        group.bench_with_input(
+            BenchmarkId::new("upsert_delete", key_count),
+            &storage,
+            |b, storage| {
+                b.iter(|| {
+                    run_threads(|thread_id, op| {
+                        let i = (thread_id * 7919 + op * 31) % key_count;
+                        if op % 2 == 0 {
+                            storage.upsert(request(i)).unwrap();
+                        } else {
+                            let _ = storage.delete(key(i));
+                        }
+                    })
+                })
+            },
+        );
+    }
+
+    group.finish();
+}
+
+fn bench_batch(c: &mut Criterion) {
+    let mut group = c.benchmark_group("batch_1000");
+    group.throughput(Throughput::Elements(BATCH_SIZE as u64));
+    let storage = populated(BATCH_SIZE);
+
+    group.bench_function("upsert_loop", |b| {
+        b.iter_batched(
+            || (0..BATCH_SIZE).map(request).collect::<Vec<_>>(),
+            |requests| {
+                for request in requests {
+                    storage.upsert(request).unwrap();
+                }
+            },
+            BatchSize::SmallInput,
+        )
+    });
+
+    group.bench_function("batch_apply", |b| {
+        b.iter_batched(
+            || {
+                (0..BATCH_SIZE)
+                    .map(|i| WriteOp::Upsert(request(i)))
+                    .collect()
+            },
+            |ops| storage.batch_apply(ops),
+            BatchSize::SmallInput,
+        )
+    });
+
+    group.bench_function("upsert_many", |b| {
+        b.iter_batched(
+            || (0..BATCH_SIZE).map(request).collect::<Vec<_>>(),
+            |requests| storage.upsert_many(requests),
+            BatchSize::SmallInput,
+        )
+    });
+
+    group.finish();
+}
+
+fn run_threads(op: impl Fn(usize, usize) + Sync) {
+    thread::scope(|scope| {
+        for thread_id in 0..CONTENDED_THREADS {
+            let op = &op;
+            scope.spawn(move || {
+                for i in 0..CONTENDED_OPS_PER_THREAD {
+                    op(thread_id, i);
+                }
+            });
+        }
+    });
+}
+
+criterion_group!(benches, bench_single_threaded, bench_contended, bench_batch);
+criterion_main!(benches);
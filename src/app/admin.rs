@@ -0,0 +1,83 @@
+use crate::app::models::{
+    ErrorDetail, ErrorResponse, FlushQuery, FlushResponse, LargestEntryResponse, LargestQuery,
+};
+use crate::app::write_ops::MAX_LISTED_ENTRIES;
+use crate::service::AsyncStorage;
+use actix_web::{HttpResponse, Responder, delete, get, web};
+use std::sync::Arc;
+
+pub const DEFAULT_LARGEST_LIMIT: usize = 20;
+
+#[utoipa::path(
+    get,
+    path = "/admin/largest",
+    params(LargestQuery),
+    responses(
+        (status = 200, description = "Entries with the biggest values, largest first", body = Vec<LargestEntryResponse>,
+            example = json!([
+                {"key": "report-2026-01", "size_bytes": 1048576, "updated_at": "2026-01-22T15:45:00Z"},
+                {"key": "user-123", "size_bytes": 8, "updated_at": "2026-01-22T10:30:00Z"}
+            ]))
+    ),
+    tag = "Admin",
+    summary = "List the largest entries",
+    description = "Returns the `limit` entries with the biggest values as `{key, size_bytes, updated_at}`, sorted by `size_bytes` descending (ties ordered by key). Use it to find which keys are responsible when memory usage climbs. The ranking reads only entry metadata and keeps a bounded heap of `limit` candidates, so it never copies values or sorts the whole store. `limit` defaults to 20 and is capped at 1000."
+)]
+#[get("/admin/largest")]
+pub async fn largest_entries(
+    query: web::Query<LargestQuery>,
+    storage: web::Data<Arc<dyn AsyncStorage>>,
+) -> impl Responder {
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_LARGEST_LIMIT)
+        .clamp(1, MAX_LISTED_ENTRIES);
+
+    let entries: Vec<LargestEntryResponse> = storage
+        .largest_entries(limit)
+        .await
+        .into_iter()
+        .map(|(key, metadata)| LargestEntryResponse {
+            key,
+            size_bytes: metadata.size_bytes,
+            updated_at: metadata.updated_at,
+        })
+        .collect();
+
+    HttpResponse::Ok().json(entries)
+}
+
+#[utoipa::path(
+    delete,
+    path = "/admin/flush",
+    params(FlushQuery),
+    responses(
+        (status = 200, description = "Every key was removed; returns how many live keys were cleared", body = FlushResponse),
+        (status = 400, description = "`confirm=true` is missing", body = ErrorResponse)
+    ),
+    tag = "Admin",
+    summary = "Remove every key",
+    description = "Deletes all keys in the store and returns how many live (unexpired) keys were cleared, for resetting a development server or a test harness between runs. Because the wipe cannot be undone the request must carry `confirm=true`; without it nothing is deleted and the response is 400 `CONFIRMATION_REQUIRED`. Writes that race with the flush may survive it."
+)]
+#[delete("/admin/flush")]
+pub async fn flush(
+    query: web::Query<FlushQuery>,
+    storage: web::Data<Arc<dyn AsyncStorage>>,
+) -> impl Responder {
+    if !query.confirm {
+        return HttpResponse::BadRequest().json(ErrorResponse {
+            error: ErrorDetail {
+                code: "CONFIRMATION_REQUIRED".to_string(),
+                message: "Flushing deletes every key; repeat the request with ?confirm=true"
+                    .to_string(),
+            },
+        });
+    }
+
+    let cleared = storage.clear().await;
+    HttpResponse::Ok().json(FlushResponse { cleared })
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(largest_entries).service(flush);
+}
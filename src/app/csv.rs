@@ -0,0 +1,296 @@
+use std::borrow::Cow;
+
+/// Longest record the reader keeps in memory; as large as a default request
+/// body. Longer records are skipped and reported, so an unterminated quote
+/// cannot make the reader buffer the rest of the file.
+pub const MAX_RECORD_BYTES: usize = crate::app::DEFAULT_MAX_BODY_BYTES;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CsvError {
+    TooLarge,
+    InvalidUtf8,
+    UnterminatedQuote,
+}
+
+impl std::fmt::Display for CsvError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CsvError::TooLarge => write!(
+                f,
+                "Record exceeds the maximum of {} bytes",
+                MAX_RECORD_BYTES
+            ),
+            CsvError::InvalidUtf8 => write!(f, "Record is not valid UTF-8"),
+            CsvError::UnterminatedQuote => {
+                write!(f, "Quoted field is not closed before the end of the file")
+            }
+        }
+    }
+}
+
+/// One record and the line it starts on (1-based, counting the line breaks
+/// inside quoted fields).
+#[derive(Debug, PartialEq)]
+pub struct CsvRecord {
+    pub line: usize,
+    pub fields: Result<Vec<String>, CsvError>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum State {
+    Unquoted,
+    Quoted,
+    QuoteInQuoted,
+}
+
+/// An incremental RFC 4180 reader: feed it the body chunk by chunk and it
+/// hands back each record as soon as its line ends, holding only the record
+/// in progress. Commas, quotes and line breaks inside quoted fields are
+/// data, `""` is an escaped quote, and CRLF and LF both end a record; a
+/// carriage return anywhere else is data. Blank lines are skipped. Stray
+/// quotes in unquoted fields are kept as-is rather than rejected.
+#[derive(Debug)]
+pub struct CsvReader {
+    state: State,
+    field_start: bool,
+    field: Vec<u8>,
+    fields: Vec<Vec<u8>>,
+    line: usize,
+    record_line: usize,
+    record_bytes: usize,
+    oversized: bool,
+    /// An unquoted `\r` not yet known to start a CRLF; it may be the last
+    /// byte of a chunk.
+    pending_cr: bool,
+}
+
+impl Default for CsvReader {
+    fn default() -> Self {
+        CsvReader {
+            state: State::Unquoted,
+            field_start: true,
+            field: Vec::new(),
+            fields: Vec::new(),
+            line: 1,
+            record_line: 1,
+            record_bytes: 0,
+            oversized: false,
+            pending_cr: false,
+        }
+    }
+}
+
+impl CsvReader {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parses `bytes`, appending every record it completes to `records`.
+    pub fn feed(&mut self, bytes: &[u8], records: &mut Vec<CsvRecord>) {
+        for &byte in bytes {
+            if byte == b'\n' {
+                self.line += 1;
+            }
+            if std::mem::take(&mut self.pending_cr) && byte != b'\n' {
+                self.push_unquoted(b'\r');
+            }
+            match (self.state, byte) {
+                (State::Quoted, b'"') => self.state = State::QuoteInQuoted,
+                (State::Quoted, _) => self.push(byte),
+                (State::QuoteInQuoted, b'"') => {
+                    self.push(b'"');
+                    self.state = State::Quoted;
+                }
+                (_, b'"') if self.field_start => {
+                    self.field_start = false;
+                    self.state = State::Quoted;
+                }
+                (_, b',') => {
+                    self.end_field();
+                    self.state = State::Unquoted;
+                }
+                (_, b'\n') => {
+                    self.end_field();
+                    self.state = State::Unquoted;
+                    records.extend(self.end_record());
+                }
+                (_, b'\r') => self.pending_cr = true,
+                (_, _) => self.push_unquoted(byte),
+            }
+        }
+    }
+
+    /// Completes the last record when the input does not end with a line
+    /// break.
+    pub fn finish(mut self) -> Option<CsvRecord> {
+        if self.state == State::Quoted {
+            return Some(CsvRecord {
+                line: self.record_line,
+                fields: Err(CsvError::UnterminatedQuote),
+            });
+        }
+        if self.pending_cr {
+            self.push_unquoted(b'\r');
+        }
+        self.end_field();
+        self.end_record()
+    }
+
+    fn push(&mut self, byte: u8) {
+        self.field_start = false;
+        self.record_bytes += 1;
+        if self.record_bytes > MAX_RECORD_BYTES {
+            self.oversized = true;
+            self.field = Vec::new();
+            self.fields = Vec::new();
+        }
+        if !self.oversized {
+            self.field.push(byte);
+        }
+    }
+
+    fn push_unquoted(&mut self, byte: u8) {
+        self.push(byte);
+        self.state = State::Unquoted;
+    }
+
+    fn end_field(&mut self) {
+        self.field_start = true;
+        if !self.oversized {
+            self.fields.push(std::mem::take(&mut self.field));
+        }
+    }
+
+    fn end_record(&mut self) -> Option<CsvRecord> {
+        let line = std::mem::replace(&mut self.record_line, self.line);
+        let fields = std::mem::take(&mut self.fields);
+        self.record_bytes = 0;
+        if std::mem::take(&mut self.oversized) {
+            return Some(CsvRecord {
+                line,
+                fields: Err(CsvError::TooLarge),
+            });
+        }
+        if fields.len() == 1 && fields[0].is_empty() {
+            return None;
+        }
+        let fields = fields
+            .into_iter()
+            .map(String::from_utf8)
+            .collect::<Result<Vec<String>, _>>()
+            .map_err(|_| CsvError::InvalidUtf8);
+        Some(CsvRecord { line, fields })
+    }
+}
+
+/// Quotes a field as RFC 4180 asks: fields holding a comma, a quote or a
+/// line break are wrapped in quotes, with inner quotes doubled.
+pub fn quote(field: &str) -> Cow<'_, str> {
+    if field.contains([',', '"', '\n', '\r']) {
+        Cow::Owned(format!("\"{}\"", field.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(field)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(chunks: &[&[u8]]) -> Vec<CsvRecord> {
+        let mut reader = CsvReader::new();
+        let mut records = Vec::new();
+        for chunk in chunks {
+            reader.feed(chunk, &mut records);
+        }
+        records.extend(reader.finish());
+        records
+    }
+
+    fn fields(record: &CsvRecord) -> Vec<&str> {
+        record
+            .fields
+            .as_ref()
+            .unwrap()
+            .iter()
+            .map(String::as_str)
+            .collect()
+    }
+
+    #[test]
+    fn test_quoted_fields_keep_commas_quotes_and_newlines() {
+        let records = read(&[b"key,value\r\na,\"say \"\"hi\"\", bye\"\r\nb,\"one\ntwo\"\nc,plain"]);
+        assert_eq!(records.len(), 4);
+        assert_eq!(fields(&records[0]), ["key", "value"]);
+        assert_eq!(fields(&records[1]), ["a", "say \"hi\", bye"]);
+        assert_eq!(fields(&records[2]), ["b", "one\ntwo"]);
+        assert_eq!(fields(&records[3]), ["c", "plain"]);
+        let lines: Vec<usize> = records.iter().map(|record| record.line).collect();
+        assert_eq!(lines, [1, 2, 3, 5]);
+    }
+
+    #[test]
+    fn test_records_split_across_chunks() {
+        let whole = read(&[b"key,value\nk,\"a,\"\"b\"\"\"\n"]);
+        let bytes = b"key,value\nk,\"a,\"\"b\"\"\"\n";
+        let split: Vec<&[u8]> = bytes.chunks(1).collect();
+        assert_eq!(read(&split), whole);
+        assert_eq!(fields(&whole[1]), ["k", "a,\"b\""]);
+    }
+
+    #[test]
+    fn test_blank_lines_are_skipped_and_empty_fields_kept() {
+        let records = read(&[b"key,value\n\nk,\n\r\n,v\n"]);
+        assert_eq!(records.len(), 3);
+        assert_eq!(fields(&records[1]), ["k", ""]);
+        assert_eq!(records[1].line, 3);
+        assert_eq!(fields(&records[2]), ["", "v"]);
+        assert_eq!(records[2].line, 5);
+    }
+
+    #[test]
+    fn test_bare_carriage_returns_are_data() {
+        let bytes = b"a\rb,c\r\n\rd,\"q\"\re\r\nlast,\r";
+        let records = read(&[bytes]);
+        assert_eq!(fields(&records[0]), ["a\rb", "c"]);
+        assert_eq!(fields(&records[1]), ["\rd", "q\re"]);
+        assert_eq!(fields(&records[2]), ["last", "\r"]);
+
+        let split: Vec<&[u8]> = bytes.chunks(1).collect();
+        assert_eq!(read(&split), records);
+    }
+
+    #[test]
+    fn test_broken_records_are_reported() {
+        let records = read(&[b"key,value\nk,\"never closed\nmore"]);
+        assert_eq!(records[1].line, 2);
+        assert_eq!(records[1].fields, Err(CsvError::UnterminatedQuote));
+
+        let records = read(&[b"k,\xff\nk2,v\n"]);
+        assert_eq!(records[0].fields, Err(CsvError::InvalidUtf8));
+        assert_eq!(fields(&records[1]), ["k2", "v"]);
+    }
+
+    #[test]
+    fn test_oversized_record_is_skipped() {
+        let mut reader = CsvReader::new();
+        let mut records = Vec::new();
+        reader.feed(b"big,\"", &mut records);
+        let chunk = vec![b'x'; 64 * 1024];
+        for _ in 0..(MAX_RECORD_BYTES / chunk.len() + 1) {
+            reader.feed(&chunk, &mut records);
+        }
+        reader.feed(b"\"\nsmall,v\n", &mut records);
+        assert!(reader.field.capacity() <= MAX_RECORD_BYTES);
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].fields, Err(CsvError::TooLarge));
+        assert_eq!(fields(&records[1]), ["small", "v"]);
+    }
+
+    #[test]
+    fn test_quote_only_when_needed() {
+        assert_eq!(quote("plain"), "plain");
+        assert_eq!(quote("say \"hi\", bye"), "\"say \"\"hi\"\", bye\"");
+        assert_eq!(quote("a\nb"), "\"a\nb\"");
+    }
+}
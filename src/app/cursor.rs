@@ -0,0 +1,53 @@
+use crate::app::models::{ErrorDetail, ErrorResponse};
+use crate::types::Key;
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+
+pub fn encode_cursor(last_key: &Key) -> String {
+    URL_SAFE_NO_PAD.encode(last_key.as_str())
+}
+
+pub fn decode_cursor(cursor: &str) -> Result<String, ErrorResponse> {
+    URL_SAFE_NO_PAD
+        .decode(cursor)
+        .ok()
+        .and_then(|bytes| String::from_utf8(bytes).ok())
+        .ok_or_else(|| ErrorResponse {
+            error: ErrorDetail {
+                code: "INVALID_CURSOR".to_string(),
+                message: format!("Cursor '{}' is not a cursor returned by this API", cursor),
+            },
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cursor_round_trip() {
+        let key = Key::new("order-000123".to_string()).unwrap();
+        let cursor = encode_cursor(&key);
+        assert_ne!(cursor, "order-000123");
+        assert_eq!(decode_cursor(&cursor).unwrap(), "order-000123");
+    }
+
+    #[test]
+    fn test_cursor_is_url_safe() {
+        let key = Key::new("a".repeat(255)).unwrap();
+        let cursor = encode_cursor(&key);
+        assert!(
+            cursor
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        );
+    }
+
+    #[test]
+    fn test_decode_rejects_garbage() {
+        let err = decode_cursor("not a cursor!").unwrap_err();
+        assert_eq!(err.error.code, "INVALID_CURSOR");
+        let err = decode_cursor(&URL_SAFE_NO_PAD.encode([0xff, 0xfe])).unwrap_err();
+        assert_eq!(err.error.code, "INVALID_CURSOR");
+    }
+}
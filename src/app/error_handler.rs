@@ -1,17 +1,487 @@
-use crate::app::models::{ErrorDetail, ErrorResponse};
-use actix_web::{HttpResponse, error::JsonPayloadError};
+use crate::app::models::{
+    ErrorDetail, ErrorResponse, SchemaViolationResponse, ValidationErrorResponse,
+};
+use crate::service::{StorageError, ValidationError, validation};
+use crate::types::key::KeyError;
+use actix_web::{
+    HttpRequest, HttpResponse, ResponseError,
+    error::{JsonPayloadError, PathError, PayloadError, QueryPayloadError},
+    http::StatusCode,
+};
+use serde_json::error::Category;
 
+/// Every storage error reaches a client through here, so this is where an
+/// internal error is logged in full before its details are dropped.
+impl From<&StorageError> for ErrorDetail {
+    fn from(storage_error: &StorageError) -> Self {
+        if let StorageError::Internal(_) = storage_error {
+            log::error!("{}", storage_error);
+        }
+        ErrorDetail {
+            code: storage_error.error_code().to_string(),
+            message: storage_error.public_message(),
+        }
+    }
+}
+
+impl From<&ValidationError> for ErrorDetail {
+    fn from(error: &ValidationError) -> Self {
+        ErrorDetail {
+            code: error.code.to_string(),
+            message: error.to_string(),
+        }
+    }
+}
+
+impl From<&ValidationError> for ValidationErrorResponse {
+    fn from(error: &ValidationError) -> Self {
+        ValidationErrorResponse {
+            error: ErrorDetail::from(error),
+            field: error.field.clone(),
+            reason: error.reason.clone(),
+        }
+    }
+}
+
+/// A field that failed validation is 400, with the field and the reason
+/// alongside the usual error, whether it came in the body or the path.
+impl ResponseError for ValidationError {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::BAD_REQUEST
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status_code()).json(ValidationErrorResponse::from(self))
+    }
+}
+
+/// The status every handler answers a storage error with, unless the route
+/// documents a different one (412 for failed preconditions, for example).
+/// Rendered as the usual `ErrorResponse` body, with the list of violations
+/// added for schema failures.
+impl ResponseError for StorageError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            StorageError::KeyNotFound(_)
+            | StorageError::KeysNotFound(_)
+            | StorageError::FieldNotFound { .. }
+            | StorageError::ListEmpty(_)
+            | StorageError::VersionNotFound(_) => StatusCode::NOT_FOUND,
+            StorageError::KeyAlreadyExists(_)
+            | StorageError::NotAppendable(_)
+            | StorageError::NotJson(_)
+            | StorageError::BinaryValue(_)
+            | StorageError::NotBinary(_)
+            | StorageError::WrongType(_)
+            | StorageError::TooManyMembers { .. }
+            | StorageError::NotCounter(_)
+            | StorageError::CounterOutOfRange { .. } => StatusCode::CONFLICT,
+            StorageError::VersionMismatch { .. } | StorageError::ModifiedSince { .. } => {
+                StatusCode::PRECONDITION_FAILED
+            }
+            StorageError::TypeMismatch { .. } | StorageError::Validation(_) => {
+                StatusCode::BAD_REQUEST
+            }
+            StorageError::NotMergeable(_)
+            | StorageError::SchemaViolation { .. }
+            | StorageError::TransformFailed { .. } => StatusCode::UNPROCESSABLE_ENTITY,
+            StorageError::ValueTooLarge { .. } => StatusCode::PAYLOAD_TOO_LARGE,
+            StorageError::InsufficientStorage { .. } => StatusCode::INSUFFICIENT_STORAGE,
+            StorageError::Unavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            StorageError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let mut response = HttpResponse::build(self.status_code());
+        match self {
+            StorageError::SchemaViolation { violations, .. } => {
+                response.json(SchemaViolationResponse {
+                    error: ErrorDetail::from(self),
+                    violations: violations.clone(),
+                })
+            }
+            StorageError::Validation(error) => response.json(ValidationErrorResponse::from(error)),
+            _ => response.json(ErrorResponse {
+                error: ErrorDetail::from(self),
+            }),
+        }
+    }
+}
+
+/// Renders a JSON body that could not be extracted. A body that is not JSON
+/// at all is 400, and so is an invalid key, reported like any other field
+/// that fails validation. Well-formed JSON with a missing field or a field
+/// of the wrong type is 422.
 pub fn json_error_handler(
     err: JsonPayloadError,
     _req: &actix_web::HttpRequest,
 ) -> actix_web::Error {
-    let error_response = ErrorResponse {
+    let response = match &err {
+        JsonPayloadError::Overflow { limit } => HttpResponse::PayloadTooLarge().json(error_body(
+            "PAYLOAD_TOO_LARGE",
+            format!("Request body exceeds the limit of {} bytes", limit),
+        )),
+        JsonPayloadError::OverflowKnownLength { length, limit } => HttpResponse::PayloadTooLarge()
+            .json(error_body(
+                "PAYLOAD_TOO_LARGE",
+                format!(
+                    "Request body is {} bytes, over the limit of {} bytes",
+                    length, limit
+                ),
+            )),
+        JsonPayloadError::Deserialize(json_err) if json_err.is_data() => {
+            match body_key_error(json_err) {
+                Some(error) => error.error_response(),
+                None => {
+                    HttpResponse::UnprocessableEntity().json(classify_deserialize_error(json_err))
+                }
+            }
+        }
+        JsonPayloadError::Deserialize(json_err) => {
+            HttpResponse::BadRequest().json(classify_deserialize_error(json_err))
+        }
+        _ => HttpResponse::BadRequest().json(error_body("VALIDATION_ERROR", err.to_string())),
+    };
+
+    actix_web::error::InternalError::from_response(err, response).into()
+}
+
+pub fn query_error_handler(
+    err: QueryPayloadError,
+    _req: &actix_web::HttpRequest,
+) -> actix_web::Error {
+    let response = HttpResponse::BadRequest().json(error_body(
+        "INVALID_QUERY",
+        format!("Invalid query parameters: {}", err),
+    ));
+
+    actix_web::error::InternalError::from_response(err, response).into()
+}
+
+/// Renders a failed raw body extraction as JSON. Handlers that take
+/// `web::Bytes` receive it as `Result<web::Bytes, actix_web::Error>` and call
+/// this, since actix answers an oversized raw body with a plain-text 413.
+pub fn payload_error_response(err: &actix_web::Error) -> HttpResponse {
+    match err.as_error::<PayloadError>() {
+        Some(PayloadError::Overflow) => HttpResponse::PayloadTooLarge().json(error_body(
+            "PAYLOAD_TOO_LARGE",
+            "Request body exceeds the configured size limit (KV_MAX_BODY_BYTES)".to_string(),
+        )),
+        _ => HttpResponse::BadRequest().json(error_body(
+            "VALIDATION_ERROR",
+            format!("Could not read the request body: {}", err),
+        )),
+    }
+}
+
+fn classify_deserialize_error(err: &serde_json::Error) -> ErrorResponse {
+    match err.classify() {
+        Category::Syntax | Category::Eof | Category::Io => error_body(
+            "MALFORMED_JSON",
+            format!("Request body is not valid JSON: {}", err),
+        ),
+        Category::Data => {
+            let message = err.to_string();
+            if let Some(field) = missing_field(&message) {
+                error_body(
+                    "MISSING_FIELD",
+                    format!("Required field '{}' is missing", field),
+                )
+            } else if let Some(error) = body_key_error(err) {
+                ErrorResponse {
+                    error: ErrorDetail::from(&error),
+                }
+            } else {
+                error_body("VALIDATION_ERROR", message)
+            }
+        }
+    }
+}
+
+fn missing_field(message: &str) -> Option<&str> {
+    message
+        .strip_prefix("missing field `")
+        .and_then(|rest| rest.split('`').next())
+}
+
+/// The key of a body that failed to parse as a [`Key`](crate::types::Key),
+/// reported like the same key failing anywhere else.
+fn body_key_error(err: &serde_json::Error) -> Option<ValidationError> {
+    let message = err.to_string();
+    [
+        KeyError::Empty,
+        KeyError::TooLong,
+        KeyError::InvalidCharacters,
+        KeyError::Whitespace,
+    ]
+    .into_iter()
+    .find(|key_error| message.starts_with(&key_error.to_string()))
+    .map(|key_error| ValidationError::new("key", "INVALID_KEY", key_error.to_string()))
+}
+
+/// Renders a path parameter that could not be extracted. An invalid key or
+/// field name in the path is the same 400 and body a body field gets, so
+/// `PUT /keys/bad key` and `POST /keys {"key": "bad key"}` read alike. Any
+/// other mismatch means the route does not exist and stays 404.
+pub fn path_error_handler(err: PathError, req: &HttpRequest) -> actix_web::Error {
+    let invalid = req
+        .match_info()
+        .iter()
+        .find_map(|(name, value)| validation::key(name, value.to_string()).err());
+    let response = match invalid {
+        Some(error) => error.error_response(),
+        None => HttpResponse::NotFound().finish(),
+    };
+
+    actix_web::error::InternalError::from_response(err, response).into()
+}
+
+pub fn error_body(code: &str, message: String) -> ErrorResponse {
+    ErrorResponse {
         error: ErrorDetail {
-            code: "VALIDATION_ERROR".to_string(),
-            message: format!("{}", err),
+            code: code.to_string(),
+            message,
         },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::idempotency::IdempotencyCache;
+    use crate::app::models::{
+        CreateKVRequest, KeyValueResponse, Metadata, ValueResponse, ValueType,
     };
+    use crate::app::{read_ops, write_ops};
+    use crate::service::{AsyncStorage, KeyFilter, Storage, UpsertOutcome, VersionPrecondition};
+    use crate::types::Key;
+    use actix_web::test::{TestRequest, call_service, init_service, read_body_json};
+    use actix_web::{App, web};
+    use chrono::Utc;
+    use std::sync::Arc;
+    use std::time::Duration;
 
-    let response = HttpResponse::BadRequest().json(error_response);
-    actix_web::error::InternalError::from_response(err, response).into()
+    /// Fails every read and write with the same error.
+    struct FailingStorage(StorageError);
+
+    impl Storage for FailingStorage {
+        fn get(&self, _key: Key) -> Result<ValueResponse, StorageError> {
+            Err(self.0.clone())
+        }
+        fn insert(&self, _body: CreateKVRequest) -> Result<KeyValueResponse, StorageError> {
+            Err(self.0.clone())
+        }
+        fn upsert(
+            &self,
+            _body: CreateKVRequest,
+        ) -> Result<(KeyValueResponse, UpsertOutcome), StorageError> {
+            Err(self.0.clone())
+        }
+        fn upsert_if_version(
+            &self,
+            _body: CreateKVRequest,
+            _expected: &VersionPrecondition,
+        ) -> Result<KeyValueResponse, StorageError> {
+            Err(self.0.clone())
+        }
+        fn delete(&self, _key: Key) -> Result<ValueResponse, StorageError> {
+            Err(self.0.clone())
+        }
+        fn list_keys(&self) -> Vec<Key> {
+            Vec::new()
+        }
+        fn list_entries(&self, _filter: &KeyFilter, _limit: usize) -> Vec<KeyValueResponse> {
+            Vec::new()
+        }
+        fn len(&self) -> usize {
+            0
+        }
+        fn set_ttl(&self, _key: &Key, _ttl: Duration) -> Result<Metadata, StorageError> {
+            Err(self.0.clone())
+        }
+    }
+
+    fn every_variant() -> Vec<(StorageError, u16)> {
+        let key = Key::new("k".to_string()).unwrap();
+        vec![
+            (StorageError::KeyNotFound(key.clone()), 404),
+            (StorageError::KeysNotFound(vec![key.clone()]), 404),
+            (StorageError::KeyAlreadyExists(key.clone()), 409),
+            (
+                StorageError::Unavailable("disk is read-only".to_string()),
+                503,
+            ),
+            (
+                StorageError::VersionMismatch {
+                    key: key.clone(),
+                    current: None,
+                },
+                412,
+            ),
+            (
+                StorageError::ModifiedSince {
+                    key: key.clone(),
+                    updated_at: Utc::now(),
+                },
+                412,
+            ),
+            (
+                StorageError::InsufficientStorage {
+                    key: key.clone(),
+                    limit_bytes: 1024,
+                },
+                507,
+            ),
+            (
+                StorageError::ValueTooLarge {
+                    key: key.clone(),
+                    size_bytes: 2048,
+                    limit_bytes: 1024,
+                },
+                413,
+            ),
+            (StorageError::VersionNotFound(key.clone()), 404),
+            (StorageError::NotAppendable(key.clone()), 409),
+            (StorageError::NotMergeable(key.clone()), 422),
+            (StorageError::NotJson(key.clone()), 409),
+            (StorageError::BinaryValue(key.clone()), 409),
+            (StorageError::NotBinary(key.clone()), 409),
+            (StorageError::WrongType(key.clone()), 409),
+            (
+                StorageError::FieldNotFound {
+                    key: key.clone(),
+                    field: key.clone(),
+                },
+                404,
+            ),
+            (StorageError::ListEmpty(key.clone()), 404),
+            (
+                StorageError::TooManyMembers {
+                    key: key.clone(),
+                    limit: 10,
+                },
+                409,
+            ),
+            (
+                StorageError::TypeMismatch {
+                    key: key.clone(),
+                    expected: ValueType::Number,
+                },
+                400,
+            ),
+            (StorageError::NotCounter(key.clone()), 409),
+            (
+                StorageError::CounterOutOfRange {
+                    key: key.clone(),
+                    min: 0,
+                    max: 10,
+                },
+                409,
+            ),
+            (
+                StorageError::TransformFailed {
+                    key,
+                    message: "The transformed value is empty".to_string(),
+                },
+                422,
+            ),
+            (
+                StorageError::Validation(ValidationError::new(
+                    "ttl_seconds",
+                    "VALIDATION_ERROR",
+                    "ttl_seconds must be positive",
+                )),
+                400,
+            ),
+            (
+                StorageError::Internal("EIO reading /var/lib/kv/segment-7".to_string()),
+                500,
+            ),
+        ]
+    }
+
+    #[actix_web::test]
+    async fn test_handlers_map_every_storage_error() {
+        for (storage_error, status) in every_variant() {
+            let storage: Arc<dyn AsyncStorage> = Arc::new(FailingStorage(storage_error.clone()));
+            let app = init_service(
+                App::new()
+                    .app_data(web::Data::new(storage))
+                    .app_data(web::Data::new(IdempotencyCache::new(Duration::from_secs(
+                        60,
+                    ))))
+                    .service(write_ops::create_kv)
+                    .service(read_ops::get_value_by_key)
+                    .service(write_ops::update_kv)
+                    .service(write_ops::delete_kv),
+            )
+            .await;
+
+            // PUT reports an existing key as a failed `If-None-Match: *`.
+            let put_status = match storage_error {
+                StorageError::KeyAlreadyExists(_) => 412,
+                _ => status,
+            };
+            let requests = [
+                (
+                    TestRequest::post()
+                        .uri("/keys")
+                        .set_json(serde_json::json!({"key": "k", "value": "v"})),
+                    status,
+                ),
+                (TestRequest::get().uri("/keys/k"), status),
+                (
+                    TestRequest::put()
+                        .uri("/keys/k")
+                        .set_json(serde_json::json!({"value": "v"})),
+                    put_status,
+                ),
+                (TestRequest::delete().uri("/keys/k"), status),
+            ];
+            for (request, status) in requests {
+                let resp = call_service(&app, request.to_request()).await;
+                assert_eq!(resp.status().as_u16(), status, "{:?}", storage_error);
+                let body: serde_json::Value = read_body_json(resp).await;
+                assert_eq!(body["error"]["code"], storage_error.error_code());
+            }
+        }
+    }
+
+    fn classify(body: &str) -> ErrorResponse {
+        let err = serde_json::from_str::<CreateKVRequest>(body).unwrap_err();
+        classify_deserialize_error(&err)
+    }
+
+    #[test]
+    fn test_malformed_json() {
+        assert_eq!(classify("{\"key\": ").error.code, "MALFORMED_JSON");
+        assert_eq!(classify("not json").error.code, "MALFORMED_JSON");
+    }
+
+    #[test]
+    fn test_missing_field() {
+        let response = classify(r#"{"key": "abc"}"#);
+        assert_eq!(response.error.code, "MISSING_FIELD");
+        assert_eq!(response.error.message, "Required field 'value' is missing");
+    }
+
+    #[test]
+    fn test_invalid_key() {
+        let response = classify(r#"{"key": "has space", "value": "v"}"#);
+        assert_eq!(response.error.code, "INVALID_KEY");
+        assert_eq!(
+            response.error.message,
+            format!(
+                "Invalid value for field 'key': {}",
+                KeyError::InvalidCharacters
+            )
+        );
+    }
+
+    #[test]
+    fn test_wrong_type_is_validation_error() {
+        let response = classify(r#"{"key": 42, "value": "v"}"#);
+        assert_eq!(response.error.code, "VALIDATION_ERROR");
+    }
 }
@@ -1,28 +1,183 @@
-use actix_web::{HttpResponse, Responder, get};
+use crate::app::models::{
+    ComponentHealth, ComponentStatus, ErrorDetail, HealthResponse, ReadinessResponse, StatsResponse,
+};
+use crate::service::AsyncStorage;
+use actix_web::{HttpResponse, Responder, get, web};
+use std::sync::Arc;
+use std::time::Instant;
+
+pub struct ServerInfo {
+    started_at: Instant,
+}
+
+impl Default for ServerInfo {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ServerInfo {
+    pub fn new() -> Self {
+        Self {
+            started_at: Instant::now(),
+        }
+    }
+
+    pub fn uptime_seconds(&self) -> u64 {
+        self.started_at.elapsed().as_secs()
+    }
+}
 
 #[utoipa::path(
     get,
     path = "/health",
     responses(
-        (status = 200, description = "Service is healthy and ready to accept requests", body = String, example = json!("OK"))
+        (status = 200, description = "Service is healthy and ready to accept requests", body = HealthResponse)
     ),
     tag = "Health",
     summary = "Health check endpoint",
-    description = "Returns a simple OK response to indicate the service is running and healthy. Use this endpoint for monitoring, load balancer health checks, or to verify the service is accessible."
+    description = "Returns the service status together with the running version and the number of seconds since the process started. Use this endpoint for monitoring, load balancer health checks, or to verify the service is accessible."
 )]
 #[get("/health")]
-pub async fn health() -> impl Responder {
-    HttpResponse::Ok().body("OK")
+pub async fn health(server_info: web::Data<ServerInfo>) -> impl Responder {
+    HttpResponse::Ok().json(HealthResponse {
+        status: "ok".to_string(),
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        uptime_seconds: server_info.uptime_seconds(),
+    })
+}
+
+#[utoipa::path(
+    get,
+    path = "/health/ready",
+    responses(
+        (status = 200, description = "All components are ready to serve traffic", body = ReadinessResponse),
+        (status = 503, description = "At least one component failed its probe", body = ReadinessResponse)
+    ),
+    tag = "Health",
+    summary = "Readiness check endpoint",
+    description = "Probes the storage backend and reports the status of each component. Returns 503 when any component is down so orchestrators can stop routing traffic to this instance. Unlike `/health`, which only proves the process is alive, this endpoint reflects whether requests can actually be served."
+)]
+#[get("/health/ready")]
+pub async fn ready(storage: web::Data<Arc<dyn AsyncStorage>>) -> impl Responder {
+    let storage_health = match storage.ping().await {
+        Ok(()) => ComponentHealth {
+            name: "storage".to_string(),
+            status: ComponentStatus::Up,
+            error: None,
+        },
+        Err(storage_error) => ComponentHealth {
+            name: "storage".to_string(),
+            status: ComponentStatus::Down,
+            error: Some(ErrorDetail::from(&storage_error)),
+        },
+    };
+
+    let components = vec![storage_health];
+    let all_up = components
+        .iter()
+        .all(|component| component.status == ComponentStatus::Up);
+
+    if all_up {
+        HttpResponse::Ok().json(ReadinessResponse {
+            status: ComponentStatus::Up,
+            components,
+        })
+    } else {
+        HttpResponse::ServiceUnavailable().json(ReadinessResponse {
+            status: ComponentStatus::Down,
+            components,
+        })
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/stats",
+    responses(
+        (status = 200, description = "Current key count and memory usage", body = StatsResponse)
+    ),
+    tag = "Health",
+    summary = "Store statistics",
+    description = "Reports the number of stored keys and, for backends that track it, `memory_bytes`: the running total of key and value lengths in bytes. When a memory budget is configured with `KV_MAX_BYTES`, `max_memory_bytes` shows it; writes that would push `memory_bytes` past the budget are rejected with 507 `INSUFFICIENT_STORAGE`. The figure counts payload bytes only, not allocator or index overhead, so size the budget with some headroom. When the negative cache is enabled with `KV_NEGATIVE_CACHE_MS`, `negative_cache_hits` counts the `GET` misses answered from it without consulting the backend."
+)]
+#[get("/stats")]
+pub async fn stats(storage: web::Data<Arc<dyn AsyncStorage>>) -> impl Responder {
+    let memory = storage.memory_usage().await;
+    HttpResponse::Ok().json(StatsResponse {
+        keys: storage.len().await,
+        memory_bytes: memory.map(|memory| memory.used_bytes),
+        max_memory_bytes: memory.and_then(|memory| memory.limit_bytes),
+        negative_cache_hits: storage.negative_cache_hits().await,
+    })
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::app::models::{CreateKVRequest, KeyValueResponse, Metadata, ValueResponse};
+    use crate::service::{
+        InMemoryStorage, KeyFilter, NegativeCache, Storage, StorageError, UpsertOutcome,
+        VersionPrecondition,
+    };
+    use crate::types::Key;
     use actix_web::{App, test};
+    use std::time::Duration;
+
+    struct UnreachableStorage;
+
+    impl Storage for UnreachableStorage {
+        fn get(&self, key: Key) -> Result<ValueResponse, StorageError> {
+            Err(StorageError::KeyNotFound(key))
+        }
+        fn insert(&self, body: CreateKVRequest) -> Result<KeyValueResponse, StorageError> {
+            Err(StorageError::KeyAlreadyExists(body.key))
+        }
+        fn upsert(
+            &self,
+            _body: CreateKVRequest,
+        ) -> Result<(KeyValueResponse, UpsertOutcome), StorageError> {
+            Err(StorageError::Unavailable("disk is read-only".to_string()))
+        }
+        fn upsert_if_version(
+            &self,
+            _body: CreateKVRequest,
+            _expected: &VersionPrecondition,
+        ) -> Result<KeyValueResponse, StorageError> {
+            Err(StorageError::Unavailable("disk is read-only".to_string()))
+        }
+        fn delete(&self, key: Key) -> Result<ValueResponse, StorageError> {
+            Err(StorageError::KeyNotFound(key))
+        }
+        fn list_keys(&self) -> Vec<Key> {
+            Vec::new()
+        }
+        fn list_entries(&self, _filter: &KeyFilter, _limit: usize) -> Vec<KeyValueResponse> {
+            Vec::new()
+        }
+        fn len(&self) -> usize {
+            0
+        }
+        fn set_ttl(&self, key: &Key, _ttl: Duration) -> Result<Metadata, StorageError> {
+            Err(StorageError::KeyNotFound(key.clone()))
+        }
+        fn ping(&self) -> Result<(), StorageError> {
+            Err(StorageError::Unavailable("disk is read-only".to_string()))
+        }
+    }
+
+    fn storage_data(storage: Arc<dyn AsyncStorage>) -> web::Data<Arc<dyn AsyncStorage>> {
+        web::Data::new(storage)
+    }
     #[actix_web::test]
     async fn test_health_returns_ok() {
         // Create test app with health endpoint
-        let app = test::init_service(App::new().service(health)).await;
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(ServerInfo::new()))
+                .service(health),
+        )
+        .await;
 
         // Create GET request to /health
         let req = test::TestRequest::get().uri("/health").to_request();
@@ -34,9 +189,19 @@ mod tests {
         assert!(resp.status().is_success());
         assert_eq!(resp.status().as_u16(), 200);
 
-        // Assert body is "OK"
-        let body = test::read_body(resp).await;
-        assert_eq!(body, "OK");
+        // Assert body reports status, version and uptime
+        let body: HealthResponse = test::read_body_json(resp).await;
+        assert_eq!(body.status, "ok");
+        assert_eq!(body.version, env!("CARGO_PKG_VERSION"));
+        assert_eq!(body.uptime_seconds, 0);
+    }
+
+    #[actix_web::test]
+    async fn test_uptime_counts_from_start() {
+        let server_info = ServerInfo {
+            started_at: Instant::now() - std::time::Duration::from_secs(90),
+        };
+        assert_eq!(server_info.uptime_seconds(), 90);
     }
     #[actix_web::test]
     async fn test_health_wrong_method() {
@@ -64,4 +229,89 @@ mod tests {
         // Should be 404 Not Found
         assert_eq!(resp.status().as_u16(), 404);
     }
+
+    #[actix_web::test]
+    async fn test_ready_with_healthy_storage() {
+        let app = test::init_service(
+            App::new()
+                .app_data(storage_data(Arc::new(InMemoryStorage::new())))
+                .service(ready),
+        )
+        .await;
+
+        let req = test::TestRequest::get().uri("/health/ready").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status().as_u16(), 200);
+
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body["status"], "up");
+        assert_eq!(body["components"][0]["name"], "storage");
+        assert_eq!(body["components"][0]["status"], "up");
+        assert!(body["components"][0].get("error").is_none());
+    }
+
+    #[actix_web::test]
+    async fn test_ready_with_unreachable_storage() {
+        let app = test::init_service(
+            App::new()
+                .app_data(storage_data(Arc::new(UnreachableStorage)))
+                .service(ready),
+        )
+        .await;
+
+        let req = test::TestRequest::get().uri("/health/ready").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status().as_u16(), 503);
+
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body["status"], "down");
+        assert_eq!(body["components"][0]["status"], "down");
+        assert_eq!(
+            body["components"][0]["error"]["code"],
+            "STORAGE_UNAVAILABLE"
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_stats_reports_negative_cache_hits() {
+        let storage = NegativeCache::new(InMemoryStorage::new(), Duration::from_secs(60));
+        for _ in 0..3 {
+            let _ = Storage::get(&storage, Key::new("ghost".to_string()).unwrap());
+        }
+        let app = test::init_service(
+            App::new()
+                .app_data(storage_data(Arc::new(storage)))
+                .service(stats),
+        )
+        .await;
+
+        let req = test::TestRequest::get().uri("/stats").to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["negative_cache_hits"], 2);
+
+        let app = test::init_service(
+            App::new()
+                .app_data(storage_data(Arc::new(InMemoryStorage::new())))
+                .service(stats),
+        )
+        .await;
+        let req = test::TestRequest::get().uri("/stats").to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert!(body.get("negative_cache_hits").is_none());
+    }
+
+    #[actix_web::test]
+    async fn test_liveness_ignores_storage_health() {
+        let app = test::init_service(
+            App::new()
+                .app_data(storage_data(Arc::new(UnreachableStorage)))
+                .app_data(web::Data::new(ServerInfo::new()))
+                .service(health),
+        )
+        .await;
+
+        let req = test::TestRequest::get().uri("/health").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status().as_u16(), 200);
+    }
 }
@@ -0,0 +1,328 @@
+use crate::app::models::{CreateKVRequest, KeyValueResponse, StoredValue, sorted_members};
+use dashmap::DashMap;
+use dashmap::mapref::entry::Entry;
+use std::collections::VecDeque;
+use std::fmt;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+pub const IDEMPOTENCY_HEADER: &str = "Idempotency-Key";
+pub const MAX_IDEMPOTENCY_KEY_LEN: usize = 255;
+pub const DEFAULT_IDEMPOTENCY_WINDOW: Duration = Duration::from_secs(10 * 60);
+/// Upper bound on remembered keys, so a flood of distinct Idempotency-Keys
+/// cannot grow the cache without limit between sweeps. The oldest records
+/// make room for new ones.
+pub const MAX_IDEMPOTENCY_RECORDS: usize = 100_000;
+
+enum RecordState {
+    /// The create is still running; the reservation holding `id` finishes it.
+    Pending {
+        id: u64,
+    },
+    Done(Box<KeyValueResponse>),
+}
+
+struct IdempotencyRecord {
+    fingerprint: u64,
+    state: RecordState,
+    stored_at: Instant,
+}
+
+#[derive(Debug)]
+pub enum IdempotencyLookup<'a> {
+    /// Nobody has used the key yet: run the create and hand its response to
+    /// [`IdempotencyReservation::complete`].
+    Reserved(IdempotencyReservation<'a>),
+    Replay(Box<KeyValueResponse>),
+    Conflict,
+    /// The same request is still being processed under this key.
+    InProgress,
+}
+
+/// Holds an Idempotency-Key while its create runs. Dropping it without
+/// calling [`complete`](Self::complete) forgets the key, so a failed create
+/// can be retried as-is.
+pub struct IdempotencyReservation<'a> {
+    cache: &'a IdempotencyCache,
+    key: String,
+    id: u64,
+}
+
+impl fmt::Debug for IdempotencyReservation<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("IdempotencyReservation")
+            .field("key", &self.key)
+            .finish()
+    }
+}
+
+impl IdempotencyReservation<'_> {
+    /// Remembers `response` for replays of the reserved key.
+    pub fn complete(self, response: KeyValueResponse) {
+        if let Some(mut record) = self.cache.records.get_mut(&self.key)
+            && matches!(record.state, RecordState::Pending { id } if id == self.id)
+        {
+            record.state = RecordState::Done(Box::new(response));
+        }
+    }
+}
+
+impl Drop for IdempotencyReservation<'_> {
+    fn drop(&mut self) {
+        self.cache.records.remove_if(
+            &self.key,
+            |_, record| matches!(record.state, RecordState::Pending { id } if id == self.id),
+        );
+    }
+}
+
+pub struct IdempotencyCache {
+    records: DashMap<String, IdempotencyRecord>,
+    /// Keys in the order they were reserved, for evicting the oldest once
+    /// `max_records` is reached. Entries whose record is gone or was replaced
+    /// are skipped when they come up.
+    order: Mutex<VecDeque<(String, Instant)>>,
+    window: Duration,
+    max_records: usize,
+    next_id: AtomicU64,
+}
+
+impl Default for IdempotencyCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_IDEMPOTENCY_WINDOW)
+    }
+}
+
+impl IdempotencyCache {
+    pub fn new(window: Duration) -> Self {
+        Self::with_max_records(window, MAX_IDEMPOTENCY_RECORDS)
+    }
+
+    pub fn with_max_records(window: Duration, max_records: usize) -> Self {
+        Self {
+            records: DashMap::new(),
+            order: Mutex::new(VecDeque::new()),
+            window,
+            max_records,
+            next_id: AtomicU64::new(0),
+        }
+    }
+
+    pub fn fingerprint(request: &CreateKVRequest) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        request.key.as_str().hash(&mut hasher);
+        match &request.value {
+            StoredValue::Json(value) => value.to_string().hash(&mut hasher),
+            StoredValue::Binary {
+                bytes,
+                content_type,
+            } => {
+                bytes.hash(&mut hasher);
+                content_type.hash(&mut hasher);
+            }
+            StoredValue::Hash(fields) => fields.hash(&mut hasher),
+            StoredValue::List(elements) => elements.hash(&mut hasher),
+            StoredValue::Set(members) => sorted_members(members).hash(&mut hasher),
+        }
+        request.value_type.hash(&mut hasher);
+        request.encoding.hash(&mut hasher);
+        request.ttl_seconds.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// Claims `idempotency_key` for a create with `fingerprint`, or reports
+    /// what an earlier request under the same key decided. The check and the
+    /// claim happen under the key's entry lock, so of several concurrent
+    /// requests only one is `Reserved`.
+    pub fn reserve(&self, idempotency_key: &str, fingerprint: u64) -> IdempotencyLookup<'_> {
+        if !self.records.contains_key(idempotency_key) {
+            self.make_room();
+        }
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let stored_at = Instant::now();
+        let record = IdempotencyRecord {
+            fingerprint,
+            state: RecordState::Pending { id },
+            stored_at,
+        };
+
+        match self.records.entry(idempotency_key.to_string()) {
+            Entry::Occupied(mut entry) if entry.get().stored_at.elapsed() >= self.window => {
+                entry.insert(record);
+            }
+            Entry::Occupied(entry) => {
+                let existing = entry.get();
+                return if existing.fingerprint != fingerprint {
+                    IdempotencyLookup::Conflict
+                } else {
+                    match &existing.state {
+                        RecordState::Pending { .. } => IdempotencyLookup::InProgress,
+                        RecordState::Done(response) => IdempotencyLookup::Replay(response.clone()),
+                    }
+                };
+            }
+            Entry::Vacant(entry) => {
+                entry.insert(record);
+            }
+        }
+
+        self.order
+            .lock()
+            .unwrap()
+            .push_back((idempotency_key.to_string(), stored_at));
+        IdempotencyLookup::Reserved(IdempotencyReservation {
+            cache: self,
+            key: idempotency_key.to_string(),
+            id,
+        })
+    }
+
+    /// Evicts the oldest finished records until a new one fits. Records still
+    /// pending are left alone: they are bounded by the requests in flight.
+    fn make_room(&self) {
+        let mut order = self.order.lock().unwrap();
+        while self.records.len() >= self.max_records {
+            let Some((key, stored_at)) = order.pop_front() else {
+                break;
+            };
+            self.records.remove_if(&key, |_, record| {
+                record.stored_at == stored_at && matches!(record.state, RecordState::Done(_))
+            });
+        }
+    }
+
+    pub fn purge_expired(&self) -> usize {
+        let before = self.records.len();
+        self.records.retain(|_, record| {
+            matches!(record.state, RecordState::Pending { .. })
+                || record.stored_at.elapsed() < self.window
+        });
+        let mut order = self.order.lock().unwrap();
+        while order
+            .front()
+            .is_some_and(|(_, stored_at)| stored_at.elapsed() >= self.window)
+        {
+            order.pop_front();
+        }
+        before.saturating_sub(self.records.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::models::{Metadata, ValueEncoding, ValueType};
+    use crate::types::Key;
+    use chrono::Utc;
+
+    fn request(value: &str) -> CreateKVRequest {
+        CreateKVRequest {
+            key: Key::new("order-1".to_string()).unwrap(),
+            value: value.into(),
+            value_type: ValueType::Text,
+            encoding: ValueEncoding::Utf8,
+            ttl_seconds: None,
+        }
+    }
+
+    fn response(value: &str) -> KeyValueResponse {
+        let now = Utc::now();
+        KeyValueResponse {
+            key: Key::new("order-1".to_string()).unwrap(),
+            value: value.into(),
+            metadata: Metadata {
+                created_at: now,
+                updated_at: now,
+                accessed_at: None,
+                expires_at: None,
+                size_bytes: value.len(),
+                value_type: ValueType::Text,
+                encoding: ValueEncoding::Utf8,
+                content_type: None,
+                compressed_size_bytes: None,
+                member_count: None,
+                counter: None,
+                schema: None,
+                version: 1,
+            },
+        }
+    }
+
+    fn reserve(cache: &IdempotencyCache, key: &str, fingerprint: u64, value: &str) {
+        match cache.reserve(key, fingerprint) {
+            IdempotencyLookup::Reserved(reservation) => reservation.complete(response(value)),
+            other => panic!("expected a reservation, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_replay_and_conflict() {
+        let cache = IdempotencyCache::default();
+        let first = IdempotencyCache::fingerprint(&request("a"));
+        let other = IdempotencyCache::fingerprint(&request("b"));
+        assert_ne!(first, other);
+
+        reserve(&cache, "req-1", first, "a");
+
+        match cache.reserve("req-1", first) {
+            IdempotencyLookup::Replay(replayed) => assert_eq!(replayed.value, "a"),
+            other => panic!("expected replay, got {:?}", other),
+        }
+        assert!(matches!(
+            cache.reserve("req-1", other),
+            IdempotencyLookup::Conflict
+        ));
+        reserve(&cache, "req-2", first, "a");
+    }
+
+    #[test]
+    fn test_pending_reservations_hold_the_key() {
+        let cache = IdempotencyCache::default();
+        let first = IdempotencyCache::fingerprint(&request("a"));
+        let other = IdempotencyCache::fingerprint(&request("b"));
+
+        let IdempotencyLookup::Reserved(reservation) = cache.reserve("req-1", first) else {
+            panic!("expected a reservation");
+        };
+        assert!(matches!(
+            cache.reserve("req-1", first),
+            IdempotencyLookup::InProgress
+        ));
+        assert!(matches!(
+            cache.reserve("req-1", other),
+            IdempotencyLookup::Conflict
+        ));
+
+        drop(reservation);
+        reserve(&cache, "req-1", other, "b");
+    }
+
+    #[test]
+    fn test_records_expire_after_window() {
+        let cache = IdempotencyCache::new(Duration::ZERO);
+        let fingerprint = IdempotencyCache::fingerprint(&request("a"));
+        reserve(&cache, "req-1", fingerprint, "a");
+        reserve(&cache, "req-2", fingerprint, "a");
+
+        reserve(&cache, "req-1", fingerprint, "a");
+        assert_eq!(cache.purge_expired(), 2);
+    }
+
+    #[test]
+    fn test_oldest_records_make_room() {
+        let cache = IdempotencyCache::with_max_records(DEFAULT_IDEMPOTENCY_WINDOW, 2);
+        let fingerprint = IdempotencyCache::fingerprint(&request("a"));
+        for key in ["req-1", "req-2", "req-3"] {
+            reserve(&cache, key, fingerprint, "a");
+        }
+
+        assert_eq!(cache.records.len(), 2);
+        reserve(&cache, "req-1", fingerprint, "a");
+        assert!(matches!(
+            cache.reserve("req-3", fingerprint),
+            IdempotencyLookup::Replay(_)
+        ));
+    }
+}
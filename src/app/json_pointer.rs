@@ -0,0 +1,104 @@
+use serde_json::Value;
+
+#[derive(Debug, PartialEq)]
+pub enum PointerError {
+    InvalidPointer,
+    NotJson(String),
+    NotFound,
+}
+
+pub fn extract(value: &Value, pointer: &str) -> Result<Value, PointerError> {
+    if !pointer.is_empty() && !pointer.starts_with('/') {
+        return Err(PointerError::InvalidPointer);
+    }
+
+    let mut document: Value = match value {
+        Value::String(raw) => {
+            serde_json::from_str(raw).map_err(|err| PointerError::NotJson(err.to_string()))?
+        }
+        structured => structured.clone(),
+    };
+
+    document
+        .pointer_mut(pointer)
+        .map(Value::take)
+        .ok_or(PointerError::NotFound)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const DOCUMENT: &str = r#"{
+        "settings": {"theme": "dark", "retries": 3},
+        "servers": [{"host": "a"}, {"host": "b"}],
+        "a/b": 1,
+        "m~n": 2,
+        "": "empty"
+    }"#;
+
+    #[test]
+    fn test_extract_nested_object_and_array_index() {
+        assert_eq!(
+            extract(&json!(DOCUMENT), "/settings/theme"),
+            Ok(json!("dark"))
+        );
+        assert_eq!(
+            extract(&json!(DOCUMENT), "/settings"),
+            Ok(json!({"theme": "dark", "retries": 3}))
+        );
+        assert_eq!(extract(&json!(DOCUMENT), "/servers/1/host"), Ok(json!("b")));
+    }
+
+    #[test]
+    fn test_extract_whole_document_and_escapes() {
+        assert_eq!(
+            extract(&json!(DOCUMENT), "").unwrap()["settings"]["retries"],
+            json!(3)
+        );
+        assert_eq!(extract(&json!(DOCUMENT), "/a~1b"), Ok(json!(1)));
+        assert_eq!(extract(&json!(DOCUMENT), "/m~0n"), Ok(json!(2)));
+        assert_eq!(extract(&json!(DOCUMENT), "/"), Ok(json!("empty")));
+    }
+
+    #[test]
+    fn test_extract_missing_paths() {
+        assert_eq!(
+            extract(&json!(DOCUMENT), "/settings/font"),
+            Err(PointerError::NotFound)
+        );
+        assert_eq!(
+            extract(&json!(DOCUMENT), "/servers/2"),
+            Err(PointerError::NotFound)
+        );
+        assert_eq!(
+            extract(&json!(DOCUMENT), "/servers/-"),
+            Err(PointerError::NotFound)
+        );
+        assert_eq!(
+            extract(&json!(DOCUMENT), "/servers/01"),
+            Err(PointerError::NotFound)
+        );
+    }
+
+    #[test]
+    fn test_extract_rejects_bad_input() {
+        assert_eq!(
+            extract(&json!(DOCUMENT), "settings"),
+            Err(PointerError::InvalidPointer)
+        );
+        assert!(matches!(
+            extract(&json!("plain text"), "/a"),
+            Err(PointerError::NotJson(_))
+        ));
+    }
+
+    #[test]
+    fn test_extract_from_structured_value() {
+        let document = json!({"servers": [{"host": "a"}], "retries": 3});
+        assert_eq!(extract(&document, "/servers/0/host"), Ok(json!("a")));
+        assert_eq!(extract(&document, ""), Ok(document.clone()));
+        assert_eq!(extract(&json!(3), "/a"), Err(PointerError::NotFound));
+    }
+}
@@ -1,6 +1,39 @@
+pub mod admin;
+pub mod csv;
+pub mod cursor;
 pub mod error_handler;
 pub mod health;
+pub mod idempotency;
+pub mod json_pointer;
 pub mod models;
+pub mod msgpack;
 pub mod openapi;
 pub mod read_ops;
+pub mod seed;
+pub mod snapshot;
+#[cfg(feature = "otel")]
+pub mod telemetry;
+pub mod validation;
 pub mod write_ops;
+
+use actix_web::web;
+
+pub const DEFAULT_MAX_BODY_BYTES: usize = 2 * 1024 * 1024;
+
+pub fn json_config(limit: usize) -> web::JsonConfig {
+    web::JsonConfig::default()
+        .limit(limit)
+        .error_handler(error_handler::json_error_handler)
+}
+
+pub fn payload_config(limit: usize) -> web::PayloadConfig {
+    web::PayloadConfig::new(limit)
+}
+
+pub fn path_config() -> web::PathConfig {
+    web::PathConfig::default().error_handler(error_handler::path_error_handler)
+}
+
+pub fn query_config() -> web::QueryConfig {
+    web::QueryConfig::default().error_handler(error_handler::query_error_handler)
+}
@@ -1,22 +1,363 @@
-use crate::types::Key;
+use crate::types::{Key, timestamp};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use utoipa::ToSchema;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::time::Duration;
+use utoipa::{IntoParams, ToSchema};
 
 #[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct Metadata {
+    #[serde(with = "timestamp::configured")]
     #[schema(example = "2026-01-22T10:30:00Z")]
     pub created_at: DateTime<Utc>,
+    #[serde(with = "timestamp::configured")]
     #[schema(example = "2026-01-22T15:45:00Z")]
     pub updated_at: DateTime<Utc>,
+    /// When the key was last read by a `GET`; only tracked when the server runs with `KV_TRACK_ACCESS`, and absent until the first read
+    #[serde(
+        default,
+        with = "timestamp::configured_option",
+        skip_serializing_if = "Option::is_none"
+    )]
+    #[schema(example = "2026-01-23T09:12:00Z")]
+    pub accessed_at: Option<DateTime<Utc>>,
+    #[serde(
+        default,
+        with = "timestamp::configured_option",
+        skip_serializing_if = "Option::is_none"
+    )]
+    #[schema(example = "2026-01-23T15:45:00Z")]
+    pub expires_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    #[schema(example = 8)]
+    pub size_bytes: usize,
+    #[serde(default)]
+    pub value_type: ValueType,
+    #[serde(default)]
+    pub encoding: ValueEncoding,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(example = "image/png")]
+    pub content_type: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(example = 412)]
+    pub compressed_size_bytes: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(example = 12)]
+    pub member_count: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub counter: Option<CounterBounds>,
+    /// The JSON Schema attached with `PUT /keys/{key}/schema`; every write to the key must satisfy it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<Object>, example = json!({"type": "object", "required": ["port"]}))]
+    pub schema: Option<Value>,
+    #[serde(default)]
+    #[schema(example = 3)]
+    pub version: u64,
 }
 
-#[derive(Debug, Deserialize, ToSchema)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ValueType {
+    #[default]
+    #[serde(alias = "string")]
+    Text,
+    Json,
+    Number,
+    Counter,
+}
+
+/// Where a counter starts and the range it is held to. A missing bound
+/// leaves that side limited only by `i64`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, ToSchema)]
+pub struct CounterBounds {
+    #[schema(example = 0)]
+    pub initial: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(example = 0)]
+    pub min: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(example = 100)]
+    pub max: Option<i64>,
+}
+
+impl CounterBounds {
+    pub fn floor(&self) -> i64 {
+        self.min.unwrap_or(i64::MIN)
+    }
+
+    pub fn ceiling(&self) -> i64 {
+        self.max.unwrap_or(i64::MAX)
+    }
+
+    pub fn contains(&self, value: i64) -> bool {
+        (self.floor()..=self.ceiling()).contains(&value)
+    }
+
+    /// `value + delta`, or `None` when the sum leaves the bounds or `i64`.
+    pub fn checked_add(&self, value: i64, delta: i64) -> Option<i64> {
+        value.checked_add(delta).filter(|sum| self.contains(*sum))
+    }
+
+    /// `value + delta` pulled back to the nearest bound when it leaves them.
+    pub fn saturating_add(&self, value: i64, delta: i64) -> i64 {
+        value
+            .saturating_add(delta)
+            .clamp(self.floor(), self.ceiling())
+    }
+}
+
+impl ValueType {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ValueType::Text => "text",
+            ValueType::Json => "json",
+            ValueType::Number => "number",
+            ValueType::Counter => "counter",
+        }
+    }
+
+    /// Whether `value`, sent with `encoding`, is data of this type. Any value
+    /// is text; strings are decoded and parsed for the other types, a number
+    /// is anything that parses as a JSON number and a counter is an `i64`.
+    pub fn admits(self, value: &StoredValue, encoding: ValueEncoding) -> bool {
+        let text = match (self, value) {
+            (ValueType::Text, _) => return true,
+            (_, StoredValue::Json(Value::String(text))) => text,
+            (_, StoredValue::Json(value)) => return self.admits_parsed(value),
+            _ => return false,
+        };
+        let Ok(bytes) = encoding.decode(text) else {
+            return false;
+        };
+        serde_json::from_slice::<Value>(&bytes).is_ok_and(|parsed| self.admits_parsed(&parsed))
+    }
+
+    fn admits_parsed(self, value: &Value) -> bool {
+        match self {
+            ValueType::Text | ValueType::Json => true,
+            ValueType::Number => value.is_number(),
+            ValueType::Counter => value.is_i64(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ValueEncoding {
+    #[default]
+    Utf8,
+    Base64,
+}
+
+impl ValueEncoding {
+    pub fn decode(self, value: &str) -> Result<Cow<'_, [u8]>, base64::DecodeError> {
+        match self {
+            ValueEncoding::Utf8 => Ok(Cow::Borrowed(value.as_bytes())),
+            ValueEncoding::Base64 => STANDARD.decode(value).map(Cow::Owned),
+        }
+    }
+}
+
+/// What a key holds: a JSON value written through the JSON endpoints, raw
+/// bytes written through `PUT /keys/{key}/blob` together with their content
+/// type, a hash of string fields written through `/keys/{key}/fields`, a
+/// list of strings written through `/keys/{key}/list`, or a set of strings
+/// written through `/keys/{key}/set`. JSON serialization renders binary
+/// values as base64 strings, hashes as objects, lists as arrays and sets as
+/// sorted arrays so list and export responses stay valid JSON.
+#[derive(Debug, Clone, PartialEq)]
+pub enum StoredValue {
+    Json(Value),
+    Binary {
+        bytes: Vec<u8>,
+        content_type: String,
+    },
+    Hash(BTreeMap<String, String>),
+    List(VecDeque<String>),
+    Set(HashSet<String>),
+}
+
+/// The families of values that cannot be mixed under one key: each is only
+/// reachable through its own routes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueKind {
+    Plain,
+    Hash,
+    List,
+    Set,
+}
+
+impl StoredValue {
+    pub fn as_json(&self) -> Option<&Value> {
+        match self {
+            StoredValue::Json(value) => Some(value),
+            StoredValue::Binary { .. }
+            | StoredValue::Hash(_)
+            | StoredValue::List(_)
+            | StoredValue::Set(_) => None,
+        }
+    }
+
+    pub fn is_binary(&self) -> bool {
+        matches!(self, StoredValue::Binary { .. })
+    }
+
+    pub fn kind(&self) -> ValueKind {
+        match self {
+            StoredValue::Json(_) | StoredValue::Binary { .. } => ValueKind::Plain,
+            StoredValue::Hash(_) => ValueKind::Hash,
+            StoredValue::List(_) => ValueKind::List,
+            StoredValue::Set(_) => ValueKind::Set,
+        }
+    }
+
+    /// The integer a counter holds, whether it was written as a JSON number
+    /// or as a string; `None` when the value is not an `i64`.
+    pub fn as_counter(&self, encoding: ValueEncoding) -> Option<i64> {
+        match self {
+            StoredValue::Json(Value::String(text)) => encoding
+                .decode(text)
+                .ok()
+                .and_then(|bytes| serde_json::from_slice::<Value>(&bytes).ok())
+                .and_then(|value| value.as_i64()),
+            StoredValue::Json(value) => value.as_i64(),
+            _ => None,
+        }
+    }
+
+    /// The number of members of a set; `None` for every other kind.
+    pub fn member_count(&self) -> Option<usize> {
+        match self {
+            StoredValue::Set(members) => Some(members.len()),
+            _ => None,
+        }
+    }
+
+    pub fn content_type(&self) -> Option<&str> {
+        match self {
+            StoredValue::Binary { content_type, .. } => Some(content_type),
+            StoredValue::Json(_)
+            | StoredValue::Hash(_)
+            | StoredValue::List(_)
+            | StoredValue::Set(_) => None,
+        }
+    }
+
+    /// The bytes the value is sized, hashed and tagged by: the text form of a
+    /// JSON value, the raw bytes of a binary one, or a collection as JSON.
+    pub fn as_bytes(&self) -> Cow<'_, [u8]> {
+        match self {
+            StoredValue::Json(value) => match value_text(value) {
+                Cow::Borrowed(text) => Cow::Borrowed(text.as_bytes()),
+                Cow::Owned(text) => Cow::Owned(text.into_bytes()),
+            },
+            StoredValue::Binary { bytes, .. } => Cow::Borrowed(bytes),
+            StoredValue::Hash(fields) => {
+                Cow::Owned(serde_json::to_vec(fields).expect("string maps always serialize"))
+            }
+            StoredValue::List(elements) => {
+                Cow::Owned(serde_json::to_vec(elements).expect("string lists always serialize"))
+            }
+            StoredValue::Set(members) => Cow::Owned(
+                serde_json::to_vec(&sorted_members(members)).expect("string sets always serialize"),
+            ),
+        }
+    }
+
+    pub fn size_bytes(&self) -> usize {
+        match self {
+            StoredValue::Json(value) => value_text(value).len(),
+            StoredValue::Binary { bytes, .. } => bytes.len(),
+            StoredValue::Hash(_) | StoredValue::List(_) | StoredValue::Set(_) => {
+                self.as_bytes().len()
+            }
+        }
+    }
+
+    pub fn etag(&self) -> String {
+        content_etag_bytes(&self.as_bytes())
+    }
+}
+
+/// Set members in a stable order, so equal sets serialize, size and tag alike.
+pub fn sorted_members(members: &HashSet<String>) -> Vec<&str> {
+    let mut sorted: Vec<&str> = members.iter().map(String::as_str).collect();
+    sorted.sort_unstable();
+    sorted
+}
+
+impl From<Value> for StoredValue {
+    fn from(value: Value) -> Self {
+        StoredValue::Json(value)
+    }
+}
+
+impl From<&str> for StoredValue {
+    fn from(value: &str) -> Self {
+        StoredValue::Json(value.into())
+    }
+}
+
+impl From<String> for StoredValue {
+    fn from(value: String) -> Self {
+        StoredValue::Json(value.into())
+    }
+}
+
+impl PartialEq<Value> for StoredValue {
+    fn eq(&self, other: &Value) -> bool {
+        self.as_json() == Some(other)
+    }
+}
+
+impl PartialEq<String> for StoredValue {
+    fn eq(&self, other: &String) -> bool {
+        self.as_json().is_some_and(|value| value == other)
+    }
+}
+
+impl PartialEq<&str> for StoredValue {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_json().is_some_and(|value| value == other)
+    }
+}
+
+impl Serialize for StoredValue {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            StoredValue::Json(value) => value.serialize(serializer),
+            StoredValue::Binary { bytes, .. } => serializer.serialize_str(&STANDARD.encode(bytes)),
+            StoredValue::Hash(fields) => fields.serialize(serializer),
+            StoredValue::List(elements) => elements.serialize(serializer),
+            StoredValue::Set(members) => sorted_members(members).serialize(serializer),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for StoredValue {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Value::deserialize(deserializer).map(StoredValue::Json)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, ToSchema)]
 pub struct CreateKVRequest {
     #[schema(example = "user-123")]
     pub key: Key,
-    #[schema(example = "John Doe")]
-    pub value: String,
+    #[schema(value_type = Value, example = "John Doe")]
+    pub value: StoredValue,
+    #[serde(default)]
+    pub value_type: ValueType,
+    #[serde(default)]
+    pub encoding: ValueEncoding,
+    #[serde(default)]
+    #[schema(example = 3600)]
+    pub ttl_seconds: Option<i64>,
 }
 
 // #[derive(Debug, Deserialize)] for now since the endpoint is a get endpoint forget about this
@@ -27,25 +368,75 @@ pub struct CreateKVRequest {
 #[derive(Debug, Deserialize, ToSchema)]
 pub struct UpdateKVRequest {
     #[schema(example = "Jane Doe")]
-    pub value: String,
+    pub value: Value,
+    #[serde(default)]
+    pub value_type: ValueType,
+    #[serde(default)]
+    pub encoding: ValueEncoding,
+    #[serde(default)]
+    #[schema(example = 3600)]
+    pub ttl_seconds: Option<i64>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct KeyValueResponse {
     #[schema(example = "user-123")]
     pub key: Key,
-    #[schema(example = "John Doe")]
-    pub value: String,
+    #[schema(value_type = Value, example = "John Doe")]
+    pub value: StoredValue,
+    pub metadata: Metadata,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct KeyMetadataResponse {
+    #[schema(example = "user-123")]
+    pub key: Key,
+    #[serde(flatten)]
     pub metadata: Metadata,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct ValueResponse {
-    #[schema(example = "John Doe")]
-    pub value: String,
+    #[schema(value_type = Value, example = "John Doe")]
+    pub value: StoredValue,
     pub metadata: Metadata,
 }
 
+/// What `GET /keys/{key}?default=...` returns for a missing key.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct DefaultValueResponse {
+    /// The `default` query parameter, decoded
+    #[schema(example = "en-US")]
+    pub value: String,
+    /// Always false: the key does not exist and the value is the requested default
+    #[schema(example = false)]
+    pub exists: bool,
+}
+
+impl ValueResponse {
+    pub fn etag(&self) -> String {
+        self.value.etag()
+    }
+}
+
+pub fn value_text(value: &Value) -> Cow<'_, str> {
+    match value {
+        Value::String(text) => Cow::Borrowed(text),
+        structured => Cow::Owned(structured.to_string()),
+    }
+}
+
+pub fn content_etag(value: &str) -> String {
+    content_etag_bytes(value.as_bytes())
+}
+
+pub fn content_etag_bytes(value: &[u8]) -> String {
+    Sha256::digest(value)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
 #[derive(Debug, Serialize, ToSchema)]
 pub struct ErrorDetail {
     #[schema(example = "KEY_NOT_FOUND")]
@@ -54,7 +445,884 @@ pub struct ErrorDetail {
     pub message: String,
 }
 
+/// One way a value breaks the schema attached to its key.
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct SchemaViolation {
+    /// JSON Pointer to the offending part of the value; empty for the value itself
+    #[schema(example = "/port")]
+    pub path: String,
+    #[schema(example = "\"eighty\" is not of type \"integer\"")]
+    pub message: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SchemaViolationResponse {
+    pub error: ErrorDetail,
+    pub violations: Vec<SchemaViolation>,
+}
+
+/// A request field that failed validation. The same body is returned
+/// wherever the field came from: an invalid key reads the same in a `POST`
+/// body as in a `PUT` path.
+#[derive(Debug, Serialize, ToSchema)]
+#[schema(example = json!({
+    "error": {
+        "code": "INVALID_KEY",
+        "message": "Invalid value for field 'key': Key contains invalid characters (only a-z, A-Z, 0-9, _, - allowed)"
+    },
+    "field": "key",
+    "reason": "Key contains invalid characters (only a-z, A-Z, 0-9, _, - allowed)"
+}))]
+pub struct ValidationErrorResponse {
+    pub error: ErrorDetail,
+    #[schema(example = "key")]
+    pub field: String,
+    #[schema(example = "Key contains invalid characters (only a-z, A-Z, 0-9, _, - allowed)")]
+    pub reason: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct VersionConflictResponse {
+    pub error: ErrorDetail,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(example = "3b9e6a1b0c5f4e7d...")]
+    pub current_version: Option<String>,
+    /// For a failed `If-Unmodified-Since`, when the key was last written
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub current_updated_at: Option<DateTime<Utc>>,
+}
+
 #[derive(Debug, Serialize, ToSchema)]
 pub struct ErrorResponse {
     pub error: ErrorDetail,
 }
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct FieldError {
+    #[schema(example = 3)]
+    pub index: usize,
+    #[schema(example = "key")]
+    pub field: String,
+    #[schema(example = "INVALID_KEY")]
+    pub code: String,
+    #[schema(example = "Key contains invalid characters")]
+    pub message: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct BatchValidationResponse {
+    pub error: ErrorDetail,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<FieldError>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum OnConflict {
+    #[default]
+    Fail,
+    Replace,
+    Ignore,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct CreateQuery {
+    /// What to do when the key already exists: `fail` (409, the default), `replace` (overwrite, keeping `created_at`) or `ignore` (return the existing entry with 200)
+    #[serde(default)]
+    pub on_conflict: OnConflict,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ReturnPreference {
+    #[default]
+    Representation,
+    Minimal,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct DeleteQuery {
+    /// `representation` (the default) returns the deleted value; `minimal` returns only `{"deleted": true}`
+    #[serde(default, rename = "return")]
+    #[param(rename = "return")]
+    pub return_preference: ReturnPreference,
+    /// Only delete if the current value equals this text (structured values compare as compact JSON); the `If-Match-Value` header takes precedence
+    #[param(example = "pending")]
+    pub if_value: Option<String>,
+    /// Set to `true` to answer 204 No Content with no body; overrides `return`
+    #[serde(default)]
+    pub quiet: bool,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct DeleteResponse {
+    #[schema(example = true)]
+    pub deleted: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ListInclude {
+    Values,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct ListKeysQuery {
+    /// Comma-separated keys to fetch; returns a `values` map and a `missing` array instead of a listing
+    #[param(example = "user-123,config-prod")]
+    pub keys: Option<String>,
+    /// Set to `values` to return full entries (value and metadata) instead of bare keys
+    pub include: Option<ListInclude>,
+    /// Only list keys starting with this prefix
+    pub prefix: Option<String>,
+    /// Only list keys matching this glob, where `*` matches any run of characters and `?` exactly one
+    #[param(example = "user-*-session")]
+    pub pattern: Option<String>,
+    /// Only list keys whose `updated_at` is strictly after this RFC 3339 instant
+    pub updated_since: Option<DateTime<Utc>>,
+    /// Only list keys whose `created_at` is strictly after this RFC 3339 instant
+    pub created_since: Option<DateTime<Utc>>,
+    /// Inclusive lower bound of a lexicographic key range; returns sorted keys
+    #[param(example = "order-000100")]
+    pub start: Option<String>,
+    /// Exclusive upper bound of a lexicographic key range; returns sorted keys
+    #[param(example = "order-000200")]
+    pub end: Option<String>,
+    /// Maximum number of keys returned by a range query or a page (default and maximum 1000)
+    pub limit: Option<usize>,
+    /// Opaque `next_cursor` from a previous page; returns the keys after it
+    pub cursor: Option<String>,
+    /// Set to `true` to page over a snapshot of the keyspace taken when the first page is served, unaffected by later writes
+    pub consistent: Option<bool>,
+    /// `snapshot` token from the first page of a consistent listing; expires 60 seconds after it was issued
+    pub snapshot: Option<String>,
+    /// Set to `true` to stream every key as newline-delimited JSON instead of building one array
+    pub stream: Option<bool>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(untagged)]
+pub enum KeyListResponse {
+    Keys(Vec<Key>),
+    Entries(Vec<KeyValueResponse>),
+    Lookup(MultiGetResponse),
+    Page(KeyPageResponse),
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct KeyPageResponse {
+    #[schema(example = json!(["order-000100", "order-000101"]))]
+    pub keys: Vec<Key>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(example = "b3JkZXItMDAwMTAx")]
+    pub next_cursor: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(example = "9f2c4e7a1b3d5f6e8a0c2e4f6a8b0d1e")]
+    pub snapshot: Option<String>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct BatchGetRequest {
+    #[schema(example = json!(["user-123", "config-prod", "missing-key"]))]
+    pub keys: Vec<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct MultiGetResponse {
+    pub values: HashMap<Key, ValueResponse>,
+    #[schema(example = json!(["missing-key"]))]
+    pub missing: Vec<Key>,
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub errors: HashMap<Key, ErrorDetail>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(transparent)]
+#[schema(example = json!({"user-123": true, "missing-key": false}))]
+pub struct ExistsResponse(pub HashMap<Key, bool>);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum BatchGetStatus {
+    Ok,
+    NotFound,
+    Error,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct BatchGetResult {
+    #[schema(example = "user-123")]
+    pub key: Key,
+    pub status: BatchGetStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(example = "John Doe")]
+    pub value: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<Metadata>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<ErrorDetail>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct BatchGetResponse {
+    pub results: Vec<BatchGetResult>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct BatchUpsertEntry {
+    #[schema(example = "flag-dark-mode")]
+    pub key: String,
+    #[serde(default)]
+    #[schema(example = true)]
+    pub value: Value,
+    #[serde(default)]
+    pub value_type: ValueType,
+    #[serde(default)]
+    pub encoding: ValueEncoding,
+    #[serde(default)]
+    #[schema(example = 3600)]
+    pub ttl_seconds: Option<i64>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct BatchUpsertRequest {
+    pub entries: Vec<BatchUpsertEntry>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct BatchUpsertQuery {
+    /// Reject the whole batch with 400 if any entry is invalid, instead of skipping the invalid entries
+    #[serde(default)]
+    pub atomic: bool,
+    /// Report what the batch would do without writing anything
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum BatchUpsertStatus {
+    Created,
+    Updated,
+    Error,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct BatchUpsertResult {
+    #[schema(example = "flag-dark-mode")]
+    pub key: String,
+    pub status: BatchUpsertStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<Metadata>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<ErrorDetail>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct BatchUpsertResponse {
+    pub results: Vec<BatchUpsertResult>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct ImportCsvQuery {
+    /// What to do when a row's key already exists: `replace` (overwrite, keeping `created_at`; the default), `fail` (reject the row with `KEY_ALREADY_EXISTS`) or `ignore` (keep the stored value and count the row as ignored)
+    pub on_conflict: Option<OnConflict>,
+    /// Read and check the whole file and report what importing it would do, without writing anything
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ImportCsvRowError {
+    /// Line of the file the row starts on, counting the header as line 1
+    #[schema(example = 7)]
+    pub line: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(example = "bad key!")]
+    pub key: Option<String>,
+    pub error: ErrorDetail,
+}
+
+#[derive(Debug, Default, Serialize, ToSchema)]
+pub struct ImportCsvResponse {
+    /// Rows written or, with `on_conflict=ignore`, left alone because the key existed
+    #[schema(example = 41)]
+    pub accepted: usize,
+    #[schema(example = 1)]
+    pub rejected: usize,
+    #[schema(example = 30)]
+    pub created: usize,
+    #[schema(example = 11)]
+    pub updated: usize,
+    #[schema(example = 0)]
+    pub ignored: usize,
+    /// The first 1000 rejected rows, in file order
+    pub errors: Vec<ImportCsvRowError>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct BatchDeleteQuery {
+    /// Fail with 404 and delete nothing if any listed key does not exist
+    #[serde(default)]
+    pub strict: bool,
+    /// Report what would be deleted without deleting anything
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum BatchDeleteStatus {
+    Deleted,
+    NotFound,
+    Error,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct BatchDeleteResult {
+    #[schema(example = "session-42")]
+    pub key: String,
+    pub status: BatchDeleteStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<Metadata>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<ErrorDetail>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct BatchDeleteResponse {
+    pub results: Vec<BatchDeleteResult>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct CountQuery {
+    /// Only count keys starting with this prefix
+    pub prefix: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct CountResponse {
+    #[schema(example = 12345)]
+    pub count: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ComponentStatus {
+    Up,
+    Down,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ComponentHealth {
+    #[schema(example = "storage")]
+    pub name: String,
+    pub status: ComponentStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<ErrorDetail>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ReadinessResponse {
+    pub status: ComponentStatus,
+    pub components: Vec<ComponentHealth>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct HealthResponse {
+    #[schema(example = "ok")]
+    pub status: String,
+    #[schema(example = "0.1.0")]
+    pub version: String,
+    #[schema(example = 3600)]
+    pub uptime_seconds: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct StatsResponse {
+    #[schema(example = 12345)]
+    pub keys: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(example = 1048576)]
+    pub memory_bytes: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(example = 268435456)]
+    pub max_memory_bytes: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(example = 42)]
+    pub negative_cache_hits: Option<u64>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct RandomKeyQuery {
+    /// Only sample among keys starting with this prefix
+    pub prefix: Option<String>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ExpireRequest {
+    #[schema(example = 3600)]
+    pub ttl_seconds: u64,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct AppendRequest {
+    #[schema(example = "2026-01-22 worker started\n")]
+    pub value: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct AppendResponse {
+    #[schema(example = "job-42-log")]
+    pub key: Key,
+    #[schema(example = 128)]
+    pub length: usize,
+    pub metadata: Metadata,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct SetFieldRequest {
+    #[schema(example = "Ada")]
+    pub value: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct FieldResponse {
+    #[schema(example = "user-123")]
+    pub key: Key,
+    #[schema(example = "name")]
+    pub field: Key,
+    #[schema(example = "Ada")]
+    pub value: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct FieldsResponse {
+    #[schema(example = "user-123")]
+    pub key: Key,
+    #[schema(example = json!({"email": "ada@example.com", "name": "Ada"}))]
+    pub fields: BTreeMap<String, String>,
+}
+
+/// Which end of a list a push or pop works on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ListSide {
+    Left,
+    Right,
+}
+
+fn default_push_side() -> ListSide {
+    ListSide::Right
+}
+
+fn default_pop_side() -> ListSide {
+    ListSide::Left
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ListPushRequest {
+    #[schema(example = "job-17")]
+    pub value: String,
+    #[serde(default = "default_push_side")]
+    #[schema(default = "right")]
+    pub side: ListSide,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct ListPopQuery {
+    /// End to pop from: `left` (the default, which with the default right-hand pushes makes the list a FIFO queue) or `right`
+    #[serde(default = "default_pop_side")]
+    pub side: ListSide,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct ListRangeQuery {
+    /// Index of the first element (default 0); negative indexes count from the end
+    #[serde(default)]
+    #[param(example = 0)]
+    pub start: i64,
+    /// Index of the last element, inclusive (default -1, the last element)
+    #[serde(default = "default_range_end")]
+    #[param(example = 10)]
+    pub end: i64,
+}
+
+fn default_range_end() -> i64 {
+    -1
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ListPushResponse {
+    #[schema(example = "jobs")]
+    pub key: Key,
+    #[schema(example = 3)]
+    pub length: usize,
+    pub metadata: Metadata,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ListPopResponse {
+    #[schema(example = "jobs")]
+    pub key: Key,
+    #[schema(example = "job-17")]
+    pub element: String,
+    #[schema(example = 2)]
+    pub length: usize,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ListRangeResponse {
+    #[schema(example = "jobs")]
+    pub key: Key,
+    #[schema(example = json!(["job-17", "job-18"]))]
+    pub elements: Vec<String>,
+    #[schema(example = 2)]
+    pub length: usize,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct SetMemberRequest {
+    #[schema(example = "rust")]
+    pub member: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SetAddResponse {
+    #[schema(example = "post-42-tags")]
+    pub key: Key,
+    #[schema(example = "rust")]
+    pub member: String,
+    /// False when the member was already in the set and nothing was written
+    #[schema(example = true)]
+    pub added: bool,
+    #[schema(example = 3)]
+    pub cardinality: usize,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SetRemoveResponse {
+    #[schema(example = "post-42-tags")]
+    pub key: Key,
+    #[schema(example = "rust")]
+    pub member: String,
+    /// False when the member was not in the set
+    #[schema(example = true)]
+    pub removed: bool,
+    #[schema(example = 2)]
+    pub cardinality: usize,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SetMembersResponse {
+    #[schema(example = "post-42-tags")]
+    pub key: Key,
+    #[schema(example = json!(["databases", "rust"]))]
+    pub members: Vec<String>,
+    #[schema(example = 2)]
+    pub cardinality: usize,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SetContainsResponse {
+    #[schema(example = "post-42-tags")]
+    pub key: Key,
+    #[schema(example = "rust")]
+    pub member: String,
+    #[schema(example = true)]
+    pub contains: bool,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CreateCounterRequest {
+    #[serde(default)]
+    #[schema(example = 0)]
+    pub initial: i64,
+    #[serde(default)]
+    #[schema(example = 0)]
+    pub min: Option<i64>,
+    #[serde(default)]
+    #[schema(example = 100)]
+    pub max: Option<i64>,
+}
+
+/// What `counter/add` does when the result would leave the counter's bounds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum CounterOverflow {
+    /// Reject the change with `COUNTER_OUT_OF_RANGE`
+    #[default]
+    Error,
+    /// Stop at the bound that was crossed
+    Clamp,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CounterAddRequest {
+    #[schema(example = 5)]
+    pub delta: i64,
+    #[serde(default)]
+    pub overflow: CounterOverflow,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CounterResponse {
+    #[schema(example = "page-views")]
+    pub key: Key,
+    #[schema(example = 42)]
+    pub value: i64,
+    /// True when `overflow: clamp` stopped the counter at a bound
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub clamped: bool,
+    pub metadata: Metadata,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CasRequest {
+    #[schema(example = "free")]
+    pub expected: Option<Value>,
+    #[schema(example = "held-by-worker-7")]
+    pub new: Value,
+    #[serde(default)]
+    pub value_type: ValueType,
+    #[serde(default)]
+    pub encoding: ValueEncoding,
+    #[serde(default)]
+    #[schema(example = 30)]
+    pub ttl_seconds: Option<i64>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CasConflictResponse {
+    pub error: ErrorDetail,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub current: Option<Metadata>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum TxOpKind {
+    Set,
+    /// Same as `set`
+    Put,
+    Delete,
+    Cas,
+    CheckValue,
+    CheckVersion,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct TxOpRequest {
+    pub op: TxOpKind,
+    #[schema(example = "account-alice")]
+    pub key: String,
+    /// The value to write; required for `set`, `put` and `cas`, ignored otherwise
+    #[serde(default)]
+    #[schema(example = 70)]
+    pub value: Value,
+    /// For `cas` and `check_value`, the value the key must currently hold; null or omitted means the key must not exist
+    #[schema(example = 100)]
+    pub expected: Option<Value>,
+    /// For `check_version`, the version the key must currently be at
+    #[serde(default)]
+    #[schema(example = 3)]
+    pub version: Option<u64>,
+    #[serde(default)]
+    pub value_type: ValueType,
+    #[serde(default)]
+    pub encoding: ValueEncoding,
+    #[serde(default)]
+    #[schema(example = 3600)]
+    pub ttl_seconds: Option<i64>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct TxRequest {
+    pub ops: Vec<TxOpRequest>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum TxOpStatus {
+    Created,
+    Updated,
+    Deleted,
+    Swapped,
+    Checked,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TxOpResult {
+    pub key: Key,
+    pub status: TxOpStatus,
+    /// The written metadata, for `deleted` the metadata the key had, and for
+    /// `checked` the key's current metadata (absent when the check required
+    /// the key not to exist)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<Metadata>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TxResponse {
+    pub results: Vec<TxOpResult>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TxErrorResponse {
+    pub error: ErrorDetail,
+    /// Position in `ops` of the op that aborted the transaction
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(example = 1)]
+    pub index: Option<usize>,
+    /// For a `cas` mismatch or a failed check, the current metadata of the key (when it exists)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub current: Option<Metadata>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct RestoreRequest {
+    #[schema(example = 2)]
+    pub version: Option<u64>,
+    #[schema(example = "2026-01-22T10:30:00Z")]
+    pub at: Option<DateTime<Utc>>,
+}
+
+pub const NO_EXPIRY_TTL_SECONDS: i64 = -1;
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TtlResponse {
+    #[schema(example = 3600)]
+    pub ttl_seconds: i64,
+}
+
+impl TtlResponse {
+    pub fn from_remaining(remaining: Option<Duration>) -> Self {
+        let ttl_seconds = match remaining {
+            Some(remaining) => remaining.as_secs() as i64 + i64::from(remaining.subsec_nanos() > 0),
+            None => NO_EXPIRY_TTL_SECONDS,
+        };
+        Self { ttl_seconds }
+    }
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct SearchQuery {
+    /// Substring to look for in stored values
+    #[param(example = "internal.example.com")]
+    pub value_contains: String,
+    /// Set to `false` for case-insensitive matching (default `true`)
+    pub case_sensitive: Option<bool>,
+    /// Maximum number of matches to return (default 100, at most 1000)
+    pub limit: Option<usize>,
+    /// Resume a truncated search from the `next_cursor` of the previous page
+    pub cursor: Option<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SearchResponse {
+    pub matches: Vec<KeyMetadataResponse>,
+    pub truncated: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<String>, example = "user-123")]
+    pub next_cursor: Option<Key>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SizeResponse {
+    #[schema(example = "user-123")]
+    pub key: Key,
+    #[schema(example = 8)]
+    pub size_bytes: usize,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ValueLookupRequest {
+    #[schema(example = "John Doe")]
+    pub value: Value,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct PageQuery {
+    /// Maximum number of matches to return (default 100, at most 1000)
+    pub limit: Option<usize>,
+    /// Resume from the `next_cursor` of the previous page
+    pub cursor: Option<String>,
+}
+
+/// How fresh a read must be: `strong` reads see every acknowledged write,
+/// `eventual` reads may be served by a replica that has not caught up yet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Consistency {
+    #[default]
+    Strong,
+    Eventual,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct RawValueQuery {
+    /// Return the stored bytes without the JSON envelope, decoding base64 values
+    #[param(example = false)]
+    #[serde(default)]
+    pub raw: bool,
+    /// Returned with 200 instead of a 404 when the key does not exist; the key is not created
+    #[param(example = "en-US")]
+    pub default: Option<String>,
+    /// `strong` (the default) always reads the latest write; `eventual` allows a possibly stale replica to answer
+    #[serde(default)]
+    pub consistency: Consistency,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct JsonPointerQuery {
+    /// RFC 6901 JSON Pointer into the stored document; empty or omitted returns the whole document
+    #[param(example = "/settings/theme")]
+    #[serde(default)]
+    pub pointer: String,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct HistoryQuery {
+    /// Number of past versions to return, newest first (default all retained, at most 1000)
+    #[param(example = 10)]
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct HistoryResponse {
+    #[schema(example = "user-123")]
+    pub key: Key,
+    pub versions: Vec<ValueResponse>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct LargestQuery {
+    /// Number of entries to return (default 20, at most 1000)
+    #[param(example = 20)]
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct FlushQuery {
+    /// Must be `true`; guards against wiping the store by accident
+    #[serde(default)]
+    #[param(example = true)]
+    pub confirm: bool,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct FlushResponse {
+    #[schema(example = 42)]
+    pub cleared: usize,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct LargestEntryResponse {
+    #[schema(example = "report-2026-01")]
+    pub key: Key,
+    #[schema(example = 1048576)]
+    pub size_bytes: usize,
+    #[serde(with = "timestamp::configured")]
+    #[schema(example = "2026-01-22T15:45:00Z")]
+    pub updated_at: DateTime<Utc>,
+}
@@ -0,0 +1,185 @@
+use crate::app::error_handler::{error_body, payload_error_response};
+use actix_web::body::{self, BoxBody, MessageBody};
+use actix_web::dev::{Payload, ServiceRequest, ServiceResponse};
+use actix_web::error::ErrorInternalServerError;
+use actix_web::http::header::{
+    self, Accept, CONTENT_LENGTH, CONTENT_TYPE, HeaderMap, HeaderValue, VARY,
+};
+use actix_web::middleware::Next;
+use actix_web::web::Bytes;
+use actix_web::{Error, HttpResponse, mime};
+use serde_json::Value;
+
+pub const MSGPACK_CONTENT_TYPE: &str = "application/msgpack";
+const MSGPACK_ALIASES: [&str; 2] = [MSGPACK_CONTENT_TYPE, "application/x-msgpack"];
+
+/// Wire format of a request or response body. JSON unless MessagePack is
+/// named explicitly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Json,
+    MsgPack,
+}
+
+impl Format {
+    /// The format of the request body, from `Content-Type`.
+    pub fn of_request(headers: &HeaderMap) -> Self {
+        let content_type = headers
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse::<mime::Mime>().ok());
+        match content_type {
+            Some(mime) if is_msgpack(&mime) => Format::MsgPack,
+            _ => Format::Json,
+        }
+    }
+
+    /// The format the client prefers for the response, from `Accept`.
+    pub fn accepted(headers: &HeaderMap) -> Self {
+        let preferred = header::from_comma_delimited(headers.get_all(header::ACCEPT))
+            .ok()
+            .filter(|items| !items.is_empty())
+            .map(|items| Accept(items).preference());
+        match preferred {
+            Some(mime) if is_msgpack(&mime) => Format::MsgPack,
+            _ => Format::Json,
+        }
+    }
+}
+
+fn is_msgpack(mime: &mime::Mime) -> bool {
+    MSGPACK_ALIASES.contains(&mime.essence_str())
+}
+
+/// Blob routes carry the client's bytes verbatim, whatever their type.
+fn is_raw_body(path: &str) -> bool {
+    path.ends_with("/blob")
+}
+
+/// Middleware that lets clients speak MessagePack: request bodies sent as
+/// `application/msgpack` are handed to the handlers as JSON, and JSON
+/// responses are re-encoded when `Accept` prefers MessagePack.
+pub async fn negotiate(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    if is_raw_body(req.path()) {
+        return Ok(next.call(req).await?.map_into_boxed_body());
+    }
+
+    let accepted = Format::accepted(req.headers());
+    let response = match Format::of_request(req.headers()) {
+        Format::MsgPack => match decode_request(&mut req).await {
+            Ok(()) => next.call(req).await?.map_into_boxed_body(),
+            Err(response) => req.into_response(response),
+        },
+        Format::Json => next.call(req).await?.map_into_boxed_body(),
+    };
+
+    match accepted {
+        Format::MsgPack => encode_response(response).await,
+        Format::Json => Ok(response),
+    }
+}
+
+async fn decode_request(req: &mut ServiceRequest) -> Result<(), HttpResponse> {
+    let bytes = req
+        .extract::<Bytes>()
+        .await
+        .map_err(|err| payload_error_response(&err))?;
+    let value: Value = rmp_serde::from_slice(&bytes).map_err(|err| {
+        HttpResponse::BadRequest().json(error_body(
+            "MALFORMED_MSGPACK",
+            format!("Request body is not valid MessagePack: {}", err),
+        ))
+    })?;
+    let json = serde_json::to_vec(&value).expect("JSON values always serialize");
+
+    let headers = req.headers_mut();
+    headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    headers.insert(CONTENT_LENGTH, HeaderValue::from(json.len()));
+    req.set_payload(Payload::from(json));
+    Ok(())
+}
+
+async fn encode_response(
+    response: ServiceResponse<BoxBody>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let is_json = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/json"));
+    if !is_json {
+        return Ok(response);
+    }
+
+    let (req, res) = response.into_parts();
+    let (mut head, body) = res.into_parts();
+    let bytes = body::to_bytes(body)
+        .await
+        .map_err(ErrorInternalServerError)?;
+    let Ok(value) = serde_json::from_slice::<Value>(&bytes) else {
+        return Ok(ServiceResponse::new(
+            req,
+            head.set_body(bytes).map_into_boxed_body(),
+        ));
+    };
+    let encoded = rmp_serde::to_vec(&value).map_err(ErrorInternalServerError)?;
+
+    let headers = head.headers_mut();
+    headers.insert(CONTENT_TYPE, HeaderValue::from_static(MSGPACK_CONTENT_TYPE));
+    headers.remove(CONTENT_LENGTH);
+    headers.append(VARY, HeaderValue::from_static("Accept"));
+    Ok(ServiceResponse::new(
+        req,
+        head.set_body(encoded).map_into_boxed_body(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(name: header::HeaderName, value: &'static str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(name, HeaderValue::from_static(value));
+        headers
+    }
+
+    #[test]
+    fn test_accept_picks_the_preferred_format() {
+        assert_eq!(Format::accepted(&HeaderMap::new()), Format::Json);
+        for (accept, expected) in [
+            ("application/msgpack", Format::MsgPack),
+            ("application/x-msgpack", Format::MsgPack),
+            ("application/json", Format::Json),
+            ("*/*", Format::Json),
+            ("application/json, application/msgpack", Format::Json),
+            (
+                "application/json;q=0.5, application/msgpack",
+                Format::MsgPack,
+            ),
+        ] {
+            assert_eq!(
+                Format::accepted(&headers(header::ACCEPT, accept)),
+                expected,
+                "{}",
+                accept
+            );
+        }
+    }
+
+    #[test]
+    fn test_content_type_picks_the_request_format() {
+        assert_eq!(Format::of_request(&HeaderMap::new()), Format::Json);
+        assert_eq!(
+            Format::of_request(&headers(CONTENT_TYPE, "application/msgpack")),
+            Format::MsgPack
+        );
+        assert_eq!(
+            Format::of_request(&headers(CONTENT_TYPE, "application/json; charset=utf-8")),
+            Format::Json
+        );
+    }
+}
@@ -1,4 +1,4 @@
-use crate::app::{health, models, read_ops, write_ops};
+use crate::app::{admin, health, models, read_ops, write_ops};
 use crate::types::Key;
 use utoipa::OpenApi;
 
@@ -6,11 +6,58 @@ use utoipa::OpenApi;
 #[openapi(
     paths(
         health::health,
+        health::ready,
+        health::stats,
+        read_ops::count_keys,
+        read_ops::random_entry,
         read_ops::get_value_by_key,
+        read_ops::key_exists,
+        read_ops::get_raw_value,
+        read_ops::get_blob,
+        read_ops::get_json_pointer,
+        read_ops::get_metadata_by_key,
+        read_ops::get_size_by_key,
+        read_ops::get_ttl,
+        read_ops::get_history,
+        read_ops::get_fields,
+        read_ops::get_field,
+        read_ops::list_range,
+        read_ops::set_members,
+        read_ops::set_contains,
+        read_ops::batch_get,
+        read_ops::bulk_exists,
+        read_ops::search_values,
+        read_ops::find_by_value,
         write_ops::create_kv,
+        write_ops::batch_upsert,
+        write_ops::bulk_upsert,
         write_ops::get_keys_list,
         write_ops::update_kv,
+        write_ops::merge_patch_kv,
+        write_ops::put_blob,
+        write_ops::expire_kv,
+        write_ops::restore_kv,
+        write_ops::append_kv,
+        write_ops::compare_and_swap_kv,
+        write_ops::transaction,
+        write_ops::transactions,
+        write_ops::set_field,
+        write_ops::delete_field,
+        write_ops::list_push,
+        write_ops::list_pop,
+        write_ops::set_add,
+        write_ops::set_remove,
+        write_ops::create_counter,
+        write_ops::counter_add,
+        write_ops::counter_reset,
         write_ops::delete_kv,
+        write_ops::batch_delete,
+        write_ops::put_schema,
+        write_ops::delete_schema,
+        read_ops::get_schema,
+        write_ops::import_csv,
+        admin::largest_entries,
+        admin::flush,
     ),
     components(schemas(
         Key,
@@ -18,14 +65,92 @@ use utoipa::OpenApi;
         models::UpdateKVRequest,
         models::KeyValueResponse,
         models::ValueResponse,
+        models::DefaultValueResponse,
+        models::KeyMetadataResponse,
         models::ErrorResponse,
         models::ErrorDetail,
+        models::FieldError,
+        models::BatchValidationResponse,
         models::Metadata,
+        models::ValueType,
+        models::ValueEncoding,
+        models::OnConflict,
+        models::ReturnPreference,
+        models::Consistency,
+        models::DeleteResponse,
+        models::ListInclude,
+        models::KeyListResponse,
+        models::KeyPageResponse,
+        models::VersionConflictResponse,
+        models::SchemaViolation,
+        models::SchemaViolationResponse,
+        models::ValidationErrorResponse,
+        models::BatchGetRequest,
+        models::BatchGetResponse,
+        models::BatchGetResult,
+        models::BatchGetStatus,
+        models::BatchUpsertEntry,
+        models::BatchUpsertRequest,
+        models::BatchUpsertStatus,
+        models::BatchUpsertResult,
+        models::BatchUpsertResponse,
+        models::ImportCsvRowError,
+        models::ImportCsvResponse,
+        models::BatchDeleteStatus,
+        models::BatchDeleteResult,
+        models::BatchDeleteResponse,
+        models::MultiGetResponse,
+        models::ExistsResponse,
+        models::CountResponse,
+        models::ComponentStatus,
+        models::ComponentHealth,
+        models::ReadinessResponse,
+        models::HealthResponse,
+        models::StatsResponse,
+        models::ExpireRequest,
+        models::RestoreRequest,
+        models::AppendRequest,
+        models::AppendResponse,
+        models::CasRequest,
+        models::CasConflictResponse,
+        models::TxOpKind,
+        models::TxOpRequest,
+        models::TxRequest,
+        models::TxOpStatus,
+        models::TxOpResult,
+        models::TxResponse,
+        models::TxErrorResponse,
+        models::TtlResponse,
+        models::SearchResponse,
+        models::SizeResponse,
+        models::ValueLookupRequest,
+        models::LargestEntryResponse,
+        models::FlushResponse,
+        models::SetFieldRequest,
+        models::FieldResponse,
+        models::FieldsResponse,
+        models::ListSide,
+        models::ListPushRequest,
+        models::ListPushResponse,
+        models::ListPopResponse,
+        models::ListRangeResponse,
+        models::SetMemberRequest,
+        models::SetAddResponse,
+        models::SetRemoveResponse,
+        models::SetMembersResponse,
+        models::SetContainsResponse,
+        models::CounterBounds,
+        models::CreateCounterRequest,
+        models::CounterOverflow,
+        models::CounterAddRequest,
+        models::CounterResponse,
+        models::HistoryResponse,
     )),
     tags(
         (name = "Health", description = "Service health check endpoints"),
         (name = "Keys - Read Operations", description = "Endpoints for reading key-value data"),
         (name = "Keys - Write Operations", description = "Endpoints for creating, updating, and deleting key-value data"),
+        (name = "Admin", description = "Operational endpoints for inspecting and maintaining the store"),
     ),
     info(
         title = "KV-Rust API",
@@ -1,44 +1,1017 @@
-use crate::app::models::{ErrorDetail, ErrorResponse, ValueResponse};
-use crate::service::Storage;
+use crate::app::json_pointer::{self, PointerError};
+use crate::app::models::{
+    BatchGetRequest, BatchGetResponse, BatchGetResult, BatchGetStatus, BatchValidationResponse,
+    Consistency, CountQuery, CountResponse, DefaultValueResponse, ErrorDetail, ErrorResponse,
+    ExistsResponse, FieldResponse, FieldsResponse, HistoryQuery, HistoryResponse, JsonPointerQuery,
+    KeyMetadataResponse, KeyValueResponse, ListRangeQuery, ListRangeResponse, Metadata,
+    MultiGetResponse, PageQuery, RandomKeyQuery, RawValueQuery, SearchQuery, SearchResponse,
+    SetContainsResponse, SetMembersResponse, SizeResponse, StoredValue, TtlResponse, ValueEncoding,
+    ValueKind, ValueLookupRequest, ValueResponse, value_text,
+};
+use crate::app::validation::parse_key_list;
+use crate::app::write_ops::MAX_LISTED_ENTRIES;
+use crate::service::{AsyncStorage, SearchPage, StorageError, ValueSearch};
 use crate::types::Key;
-use actix_web::{HttpResponse, Responder, get, web};
+use crate::types::key::fold_case;
+use actix_web::http::header::{
+    Accept, CONTENT_LENGTH, ContentType, ETag, EntityTag, HttpDate, IfModifiedSince, IfNoneMatch,
+    LastModified, VARY,
+};
+use actix_web::{
+    HttpMessage, HttpRequest, HttpResponse, Responder, ResponseError, get, head, mime, post, route,
+    web,
+};
+use chrono::{DateTime, Utc};
+use std::borrow::Cow;
+use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::SystemTime;
+
+/// Set to `true` on a `GET /keys/{key}` answered with the `default` parameter.
+pub const DEFAULT_VALUE_HEADER: &str = "X-KV-Default";
+
+#[utoipa::path(
+    get,
+    path = "/keys/count",
+    params(CountQuery),
+    responses(
+        (status = 200, description = "Number of stored keys, optionally restricted to a prefix", body = CountResponse)
+    ),
+    tag = "Keys - Read Operations",
+    summary = "Count stored keys",
+    description = "Returns the number of keys in the store without transferring the key list. When `prefix` is given only keys starting with it are counted, for example `prefix=tenant-1-` for per-tenant quotas; the in-memory store reads them from its sorted key index, so the cost grows with the number of matching keys rather than with the size of the store. An empty or missing `prefix` counts every key. Keys whose TTL has passed are not counted, even before the background sweep removes them. Note that this route takes precedence over `GET /keys/{key}` for a key literally named `count`."
+)]
+#[get("/keys/count")]
+pub async fn count_keys(
+    query: web::Query<CountQuery>,
+    storage: web::Data<Arc<dyn AsyncStorage>>,
+) -> impl Responder {
+    let prefix = fold_case(query.prefix.as_deref().unwrap_or(""));
+    let count = storage.count_prefix(&prefix).await;
+    HttpResponse::Ok().json(CountResponse { count })
+}
+
+#[utoipa::path(
+    get,
+    path = "/keys/random",
+    params(RandomKeyQuery),
+    responses(
+        (status = 200, description = "A uniformly random entry", body = KeyValueResponse),
+        (status = 404, description = "The store (or the requested prefix) has no keys", body = ErrorResponse)
+    ),
+    tag = "Keys - Read Operations",
+    summary = "Get a random entry",
+    description = "Returns one existing entry chosen uniformly at random, optionally restricted to keys starting with `prefix`. Responds 404 with code `STORE_EMPTY` when there is nothing to sample. Note that this route takes precedence over `GET /keys/{key}` for a key literally named `random`."
+)]
+#[get("/keys/random")]
+pub async fn random_entry(
+    query: web::Query<RandomKeyQuery>,
+    storage: web::Data<Arc<dyn AsyncStorage>>,
+) -> impl Responder {
+    let prefix = query.prefix.as_deref().map(fold_case);
+    match storage.random_entry(prefix.as_deref()).await {
+        Some(entry) => HttpResponse::Ok().json(entry),
+        None => {
+            let message = match query.prefix.as_deref() {
+                Some(prefix) => format!("No keys start with '{}'", prefix),
+                None => "The store has no keys".to_string(),
+            };
+            HttpResponse::NotFound().json(ErrorResponse {
+                error: ErrorDetail {
+                    code: "STORE_EMPTY".to_string(),
+                    message,
+                },
+            })
+        }
+    }
+}
 
 #[utoipa::path(
     get,
     path = "/keys/{key}",
     params(
-        ("key" = String, Path, description = "Unique key identifier (alphanumeric, hyphens, underscores, 1-255 chars)", example = "user-123")
+        ("key" = String, Path, description = "Unique key identifier (alphanumeric, hyphens, underscores, 1-255 chars)", example = "user-123"),
+        ("If-None-Match" = Option<String>, Header, description = "ETag from a previous response; the value is only returned if it has changed since", example = "\"3b9e6a1b...\""),
+        ("Accept" = Option<String>, Header, description = "`text/plain` to receive the bare value; anything else returns JSON", example = "text/plain"),
+        ("If-Modified-Since" = Option<String>, Header, description = "HTTP date; the value is only returned if it was updated after this second. Ignored when If-None-Match is present or when the date is malformed", example = "Thu, 22 Jan 2026 10:30:00 GMT"),
+        RawValueQuery
     ),
     responses(
-        (status = 200, description = "Successfully retrieved value with metadata", body = ValueResponse),
-        (status = 404, description = "Key not found in the store", body = ErrorResponse)
+        (status = 200, description = "Successfully retrieved value with metadata. With `Accept: text/plain` the body is the bare value and the timestamps move into headers. With `raw=true` the body is the stored value without an envelope, decoded to bytes for base64 values. When the key does not exist and `default` was given, the body is a `DefaultValueResponse` (or the bare default for text/plain and `raw=true`) with no metadata, validators or timestamps",
+            content(
+                (KeyValueResponse = "application/json"),
+                (DefaultValueResponse = "application/json"),
+                (String = "text/plain", example = "John Doe"),
+                (Vec<u8> = "application/octet-stream")
+            ),
+            headers(
+                ("X-KV-Default" = String, description = "`true` when the body is the requested default rather than a stored value; absent otherwise"),
+                ("ETag" = String, description = "Strong validator derived from a SHA-256 hash of the value"),
+                ("Last-Modified" = String, description = "The entry's `updated_at`, truncated to whole seconds"),
+                ("X-KV-Created-At" = String, description = "RFC 3339 `created_at`; only sent on text/plain responses"),
+                ("X-KV-Updated-At" = String, description = "RFC 3339 `updated_at`; only sent on text/plain responses")
+            )),
+        (status = 304, description = "The value still matches the ETag sent in If-None-Match, or has not been updated since If-Modified-Since; no body is returned",
+            headers(
+                ("ETag" = String, description = "Current ETag of the value"),
+                ("Last-Modified" = String, description = "The entry's `updated_at`, truncated to whole seconds")
+            )),
+        (status = 404, description = "Key not found in the store, and no `default` was given", body = ErrorResponse),
+        (status = 409, description = "The key holds a binary value (`BINARY_VALUE`); read it with `GET /keys/{key}/blob` or `raw=true`", body = ErrorResponse)
     ),
     tag = "Keys - Read Operations",
     summary = "Get value by key",
-    description = "Retrieves the value associated with the given key, along with metadata including creation and last update timestamps. Returns 404 if the key does not exist."
+    description = "Retrieves the value associated with the given key, along with metadata including creation and last update timestamps. Returns 404 if the key does not exist. Every successful response carries an `ETag` computed from the value's content, so it changes whenever the value changes and stays the same across restarts. Send it back in `If-None-Match` to receive 304 Not Modified instead of the body when nothing has changed. Responses also carry `Last-Modified` (the `updated_at` timestamp truncated to whole seconds, as HTTP dates require), and `If-Modified-Since` returns 304 when the entry has not been updated after the given second. Because two updates within the same second share a `Last-Modified`, `If-None-Match` takes precedence: when it is present `If-Modified-Since` is ignored, so clients that send both are never served a stale 304. Malformed `If-Modified-Since` dates are ignored rather than rejected. The representation follows the `Accept` header: when `text/plain` ranks ahead of `application/json` and `*/*` the response is the raw value as `text/plain; charset=utf-8` (structured values are written as compact JSON), with `created_at` and `updated_at` in the `X-KV-Created-At` and `X-KV-Updated-At` headers, which lets shell scripts use the value without a JSON parser. Any other `Accept` (including `*/*` or no header) returns JSON holding the `key`, its `value` and its `metadata`, the same shape writes return. Values written with `encoding: base64` come back in JSON and `text/plain` exactly as they were sent, base64 text included, with `metadata.encoding` set to `base64`; pass `raw=true` to receive the decoded bytes as `application/octet-stream` instead, the same body `GET /keys/{key}/raw` returns. `raw=true` takes precedence over `Accept`. Errors such as 404 are always JSON `ErrorResponse` bodies, whatever the `Accept` header says. To skip the client-side fallback for missing keys, pass `default`: when the key does not exist the response is 200 with `{\"value\": <default>, \"exists\": false}` and an `X-KV-Default: true` header instead of 404, and the key is not created. The default is always a string, decoded from the URL like any query parameter (send `%26` for `&` and `%20` or `+` for a space). When the key exists `default` is ignored entirely. Reads are strongly consistent by default: they reflect every write that has been acknowledged. Pass `consistency=eventual` to allow the read to be served by a replica, which scales reads but may return a value that has since been overwritten, a key that has since been deleted, or 404 for a key created moments ago; the value returned was always current at some point, and validators and `If-None-Match` / `If-Modified-Since` apply to what was read. Backends without replicas, including the in-memory store, answer eventual reads exactly like strong ones."
 )]
 #[get("/keys/{key}")]
 pub async fn get_value_by_key(
+    req: HttpRequest,
+    key: web::Path<Key>,
+    query: web::Query<RawValueQuery>,
+    storage: web::Data<Arc<dyn AsyncStorage>>,
+) -> impl Responder {
+    let key = key.into_inner();
+
+    let read = match query.consistency {
+        Consistency::Strong => storage.get(key.clone()).await,
+        Consistency::Eventual => storage.get_stale(key.clone()).await,
+    };
+    match read {
+        Ok(value_response) => {
+            let etag = EntityTag::new_strong(value_response.etag());
+            let updated_at = value_response.metadata.updated_at;
+            let last_modified = HttpDate::from(SystemTime::from(updated_at));
+
+            if is_not_modified(&req, &etag, updated_at) {
+                HttpResponse::NotModified()
+                    .insert_header(ETag(etag))
+                    .insert_header(LastModified(last_modified))
+                    .finish()
+            } else if value_response.value.kind() != ValueKind::Plain {
+                StorageError::WrongType(key).error_response()
+            } else if query.raw {
+                raw_value_response(value_response, etag, last_modified)
+            } else if value_response.value.is_binary() {
+                StorageError::BinaryValue(key).error_response()
+            } else if prefers_plain_text(&req) {
+                HttpResponse::Ok()
+                    .insert_header(ETag(etag))
+                    .insert_header(LastModified(last_modified))
+                    .insert_header((VARY, "Accept"))
+                    .insert_header((
+                        "X-KV-Created-At",
+                        value_response.metadata.created_at.to_rfc3339(),
+                    ))
+                    .insert_header(("X-KV-Updated-At", updated_at.to_rfc3339()))
+                    .content_type(ContentType::plaintext())
+                    .body(value_response.value.as_bytes().into_owned())
+            } else {
+                HttpResponse::Ok()
+                    .insert_header(ETag(etag))
+                    .insert_header(LastModified(last_modified))
+                    .insert_header((VARY, "Accept"))
+                    .json(KeyValueResponse {
+                        key,
+                        value: value_response.value,
+                        metadata: value_response.metadata,
+                    })
+            }
+        }
+        Err(StorageError::KeyNotFound(_)) if query.default.is_some() => {
+            let bare = query.raw || prefers_plain_text(&req);
+            let value = query.into_inner().default.unwrap_or_default();
+            let mut builder = HttpResponse::Ok();
+            builder.insert_header((DEFAULT_VALUE_HEADER, "true"));
+            if bare {
+                builder.content_type(ContentType::plaintext()).body(value)
+            } else {
+                builder.json(DefaultValueResponse {
+                    value,
+                    exists: false,
+                })
+            }
+        }
+        Err(storage_error) => storage_error.error_response(),
+    }
+}
+
+/// The error a JSON endpoint reports for a value it cannot render as JSON.
+fn not_json(key: Key, value: &StoredValue) -> StorageError {
+    if value.kind() != ValueKind::Plain {
+        StorageError::WrongType(key)
+    } else {
+        StorageError::BinaryValue(key)
+    }
+}
+
+fn raw_value_response(
+    value_response: ValueResponse,
+    etag: EntityTag,
+    last_modified: HttpDate,
+) -> HttpResponse {
+    let encoding = value_response.metadata.encoding;
+    let (content_type, body) = match value_response.value {
+        StoredValue::Binary {
+            bytes,
+            content_type,
+        } => (content_type, bytes),
+        StoredValue::Json(value) => {
+            let content_type = match encoding {
+                ValueEncoding::Utf8 => mime::TEXT_PLAIN_UTF_8,
+                ValueEncoding::Base64 => mime::APPLICATION_OCTET_STREAM,
+            };
+            let text = value_text(&value);
+            let body = match encoding.decode(&text).map(Cow::into_owned) {
+                Ok(bytes) => bytes,
+                Err(_) => text.into_owned().into_bytes(),
+            };
+            (content_type.to_string(), body)
+        }
+        value @ (StoredValue::Hash(_) | StoredValue::List(_) | StoredValue::Set(_)) => (
+            mime::APPLICATION_JSON.to_string(),
+            value.as_bytes().into_owned(),
+        ),
+    };
+
+    HttpResponse::Ok()
+        .insert_header(ETag(etag))
+        .insert_header(LastModified(last_modified))
+        .insert_header((CONTENT_LENGTH, body.len()))
+        .content_type(content_type)
+        .body(body)
+}
+
+fn prefers_plain_text(req: &HttpRequest) -> bool {
+    req.get_header::<Accept>()
+        .and_then(|accept| {
+            accept
+                .ranked()
+                .into_iter()
+                .find_map(|mime| match (mime.type_(), mime.subtype()) {
+                    (mime::TEXT, mime::PLAIN) => Some(true),
+                    (mime::APPLICATION, mime::JSON | mime::STAR) | (mime::STAR, _) => Some(false),
+                    _ => None,
+                })
+        })
+        .unwrap_or(false)
+}
+
+fn is_not_modified(req: &HttpRequest, etag: &EntityTag, updated_at: DateTime<Utc>) -> bool {
+    if let Some(if_none_match) = req.get_header::<IfNoneMatch>() {
+        return match if_none_match {
+            IfNoneMatch::Any => true,
+            IfNoneMatch::Items(tags) => tags.iter().any(|tag| tag.weak_eq(etag)),
+        };
+    }
+
+    req.get_header::<IfModifiedSince>()
+        .map(|IfModifiedSince(since)| DateTime::<Utc>::from(SystemTime::from(since)))
+        .is_some_and(|since| updated_at.timestamp() <= since.timestamp())
+}
+
+#[utoipa::path(
+    head,
+    path = "/keys/{key}",
+    params(
+        ("key" = String, Path, description = "Unique key identifier (alphanumeric, hyphens, underscores, 1-255 chars)", example = "user-123")
+    ),
+    responses(
+        (status = 200, description = "Key exists; no body is returned"),
+        (status = 404, description = "Key not found in the store")
+    ),
+    tag = "Keys - Read Operations",
+    summary = "Check whether a key exists",
+    description = "Answers whether a key is present without transferring its value. Responds 200 with an empty body when the key exists and 404 when it does not."
+)]
+#[head("/keys/{key}")]
+pub async fn key_exists(
+    key: web::Path<Key>,
+    storage: web::Data<Arc<dyn AsyncStorage>>,
+) -> impl Responder {
+    if storage.exists(&key).await {
+        HttpResponse::Ok().finish()
+    } else {
+        HttpResponse::NotFound().finish()
+    }
+}
+
+#[utoipa::path(
+    method(get, head),
+    path = "/keys/{key}/raw",
+    params(
+        ("key" = String, Path, description = "Unique key identifier (alphanumeric, hyphens, underscores, 1-255 chars)", example = "app-config"),
+        ("If-None-Match" = Option<String>, Header, description = "ETag from a previous response; the value is only returned if it has changed since", example = "\"3b9e6a1b...\""),
+        ("If-Modified-Since" = Option<String>, Header, description = "HTTP date; ignored when If-None-Match is present or when malformed", example = "Thu, 22 Jan 2026 10:30:00 GMT")
+    ),
+    responses(
+        (status = 200, description = "The stored value, byte for byte, or the decoded bytes for base64 values",
+            content(
+                (String = "text/plain", example = "{\"theme\": \"dark\"}"),
+                (Vec<u8> = "application/octet-stream")
+            ),
+            headers(
+                ("Content-Length" = u64, description = "Size of the body in bytes, after base64 decoding"),
+                ("ETag" = String, description = "Strong validator derived from a SHA-256 hash of the value"),
+                ("Last-Modified" = String, description = "The entry's `updated_at`, truncated to whole seconds")
+            )),
+        (status = 304, description = "The value has not changed since the validator sent by the client; no body is returned"),
+        (status = 404, description = "Key not found in the store", body = ErrorResponse)
+    ),
+    tag = "Keys - Read Operations",
+    summary = "Get the raw value",
+    description = "Returns the value exactly as stored, with `Content-Type: text/plain; charset=utf-8` and no JSON envelope, whatever the `Accept` header says. Values that look like JSON are passed through untouched rather than re-encoded, and structured values (objects, arrays, numbers, booleans) are written as compact JSON. This makes the store usable as a plain file or config server, for example behind nginx. Values written with `encoding: base64` are decoded and served as `application/octet-stream`, so binary files round-trip: POST them base64-encoded and read the original bytes back here. `HEAD` returns the same headers without the body. `ETag`, `Last-Modified`, `If-None-Match` and `If-Modified-Since` behave as on `GET /keys/{key}`. A missing key returns the usual JSON `ErrorResponse` with 404 so error handling is the same across endpoints."
+)]
+#[route("/keys/{key}/raw", method = "GET", method = "HEAD")]
+pub async fn get_raw_value(
+    req: HttpRequest,
+    key: web::Path<Key>,
+    storage: web::Data<Arc<dyn AsyncStorage>>,
+) -> impl Responder {
+    let key = key.into_inner();
+
+    match storage.get(key.clone()).await {
+        Ok(value_response) if value_response.value.kind() != ValueKind::Plain => {
+            StorageError::WrongType(key).error_response()
+        }
+        Ok(value_response) => {
+            let etag = EntityTag::new_strong(value_response.etag());
+            let updated_at = value_response.metadata.updated_at;
+            let last_modified = HttpDate::from(SystemTime::from(updated_at));
+
+            if is_not_modified(&req, &etag, updated_at) {
+                HttpResponse::NotModified()
+                    .insert_header(ETag(etag))
+                    .insert_header(LastModified(last_modified))
+                    .finish()
+            } else {
+                raw_value_response(value_response, etag, last_modified)
+            }
+        }
+        Err(storage_error) => storage_error.error_response(),
+    }
+}
+
+#[utoipa::path(
+    method(get, head),
+    path = "/keys/{key}/blob",
+    params(
+        ("key" = String, Path, description = "Unique key identifier (alphanumeric, hyphens, underscores, 1-255 chars)", example = "avatar-42"),
+        ("If-None-Match" = Option<String>, Header, description = "ETag from a previous response; the blob is only returned if it has changed since", example = "\"3b9e6a1b...\""),
+        ("If-Modified-Since" = Option<String>, Header, description = "HTTP date; ignored when If-None-Match is present or when malformed", example = "Thu, 22 Jan 2026 10:30:00 GMT")
+    ),
+    responses(
+        (status = 200, description = "The stored bytes, served with the content type they were uploaded with",
+            content(
+                (Vec<u8> = "application/octet-stream")
+            ),
+            headers(
+                ("Content-Type" = String, description = "The `Content-Type` sent with `PUT /keys/{key}/blob`"),
+                ("Content-Length" = u64, description = "Size of the blob in bytes"),
+                ("ETag" = String, description = "Strong validator derived from a SHA-256 hash of the bytes"),
+                ("Last-Modified" = String, description = "The entry's `updated_at`, truncated to whole seconds")
+            )),
+        (status = 304, description = "The blob has not changed since the validator sent by the client; no body is returned"),
+        (status = 404, description = "Key not found in the store", body = ErrorResponse),
+        (status = 409, description = "The key holds a JSON value, not a blob (`VALUE_NOT_BINARY`)", body = ErrorResponse)
+    ),
+    tag = "Keys - Read Operations",
+    summary = "Download a binary value",
+    description = "Returns the bytes stored with `PUT /keys/{key}/blob`, unchanged, with the `Content-Type` they were uploaded with and their `Content-Length`. `HEAD` returns the same headers without the body. `ETag`, `Last-Modified`, `If-None-Match` and `If-Modified-Since` behave as on `GET /keys/{key}`. Keys holding a value written through the JSON endpoints are rejected with 409 `VALUE_NOT_BINARY`; read those with `GET /keys/{key}` or `GET /keys/{key}/raw`."
+)]
+#[route("/keys/{key}/blob", method = "GET", method = "HEAD")]
+pub async fn get_blob(
+    req: HttpRequest,
     key: web::Path<Key>,
-    storage: web::Data<Arc<dyn Storage + Send + Sync>>,
+    storage: web::Data<Arc<dyn AsyncStorage>>,
 ) -> impl Responder {
     let key = key.into_inner();
 
-    match storage.get(key) {
-        Ok(value_response) => HttpResponse::Ok().json(value_response),
-        Err(storage_error) => {
-            let error = ErrorResponse {
+    match storage.get(key.clone()).await {
+        Ok(value_response) if value_response.value.kind() != ValueKind::Plain => {
+            StorageError::WrongType(key).error_response()
+        }
+        Ok(value_response) if !value_response.value.is_binary() => {
+            StorageError::NotBinary(key).error_response()
+        }
+        Ok(value_response) => {
+            let etag = EntityTag::new_strong(value_response.etag());
+            let updated_at = value_response.metadata.updated_at;
+            let last_modified = HttpDate::from(SystemTime::from(updated_at));
+
+            if is_not_modified(&req, &etag, updated_at) {
+                HttpResponse::NotModified()
+                    .insert_header(ETag(etag))
+                    .insert_header(LastModified(last_modified))
+                    .finish()
+            } else {
+                raw_value_response(value_response, etag, last_modified)
+            }
+        }
+        Err(storage_error) => storage_error.error_response(),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/keys/{key}/json",
+    params(
+        ("key" = String, Path, description = "Unique key identifier (alphanumeric, hyphens, underscores, 1-255 chars)", example = "app-config"),
+        JsonPointerQuery
+    ),
+    responses(
+        (status = 200, description = "The JSON fragment addressed by the pointer", content_type = "application/json", body = serde_json::Value,
+            examples(
+                ("Nested object member" = (summary = "pointer=/settings/theme", value = json!("dark"))),
+                ("Array index" = (summary = "pointer=/servers/0", value = json!({"host": "db-1", "port": 5432}))),
+                ("Whole document" = (summary = "pointer omitted", value = json!({"settings": {"theme": "dark"}, "servers": [{"host": "db-1", "port": 5432}]})))
+            )),
+        (status = 400, description = "The pointer is neither empty nor starts with `/`", body = ErrorResponse),
+        (status = 404, description = "Key not found (`KEY_NOT_FOUND`) or nothing exists at the pointer (`POINTER_NOT_FOUND`)", body = ErrorResponse),
+        (status = 409, description = "The key holds a binary value (`BINARY_VALUE`)", body = ErrorResponse),
+        (status = 422, description = "The stored value is not valid JSON", body = ErrorResponse)
+    ),
+    tag = "Keys - Read Operations",
+    summary = "Extract part of a JSON value",
+    description = "Parses the stored value as JSON and returns only the fragment addressed by the RFC 6901 JSON Pointer in `pointer`, so clients that need one field of a large config document don't have to download all of it. Object members are addressed by name and array elements by zero-based index, with `~1` standing for `/` and `~0` for `~` inside names (for example `/settings/theme` or `/servers/0/host`). An empty or omitted pointer returns the whole document. Structured values are used directly; string values are parsed as JSON first, and the endpoint responds 422 `VALUE_NOT_JSON` if the string does not parse, whatever its `value_type`, and 404 `POINTER_NOT_FOUND` if the path does not exist."
+)]
+#[get("/keys/{key}/json")]
+pub async fn get_json_pointer(
+    key: web::Path<Key>,
+    query: web::Query<JsonPointerQuery>,
+    storage: web::Data<Arc<dyn AsyncStorage>>,
+) -> impl Responder {
+    let key = key.into_inner();
+    let value_response = match storage.get(key.clone()).await {
+        Ok(value_response) => value_response,
+        Err(storage_error) => return storage_error.error_response(),
+    };
+
+    let Some(document) = value_response.value.as_json() else {
+        return not_json(key, &value_response.value).error_response();
+    };
+
+    match json_pointer::extract(document, &query.pointer) {
+        Ok(fragment) => HttpResponse::Ok().json(fragment),
+        Err(PointerError::InvalidPointer) => HttpResponse::BadRequest().json(ErrorResponse {
+            error: ErrorDetail {
+                code: "INVALID_POINTER".to_string(),
+                message: format!(
+                    "JSON Pointer '{}' must be empty or start with '/'",
+                    query.pointer
+                ),
+            },
+        }),
+        Err(PointerError::NotJson(reason)) => {
+            HttpResponse::UnprocessableEntity().json(ErrorResponse {
                 error: ErrorDetail {
-                    code: storage_error.error_code().to_string(),
-                    message: storage_error.to_string(),
+                    code: "VALUE_NOT_JSON".to_string(),
+                    message: format!("The stored value is not valid JSON: {}", reason),
                 },
-            };
-            HttpResponse::NotFound().json(error)
+            })
+        }
+        Err(PointerError::NotFound) => HttpResponse::NotFound().json(ErrorResponse {
+            error: ErrorDetail {
+                code: "POINTER_NOT_FOUND".to_string(),
+                message: format!("Nothing exists at JSON Pointer '{}'", query.pointer),
+            },
+        }),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/keys/{key}/metadata",
+    params(
+        ("key" = String, Path, description = "Unique key identifier (alphanumeric, hyphens, underscores, 1-255 chars)", example = "user-123")
+    ),
+    responses(
+        (status = 200, description = "Key name and metadata, without the value", body = KeyMetadataResponse),
+        (status = 404, description = "Key not found in the store", body = ErrorResponse)
+    ),
+    tag = "Keys - Read Operations",
+    summary = "Get metadata by key",
+    description = "Returns the key's timestamps (and expiry, when set) without transferring its value, which makes it cheap to check whether a large entry is stale. Returns 404 if the key does not exist."
+)]
+#[get("/keys/{key}/metadata")]
+pub async fn get_metadata_by_key(
+    key: web::Path<Key>,
+    storage: web::Data<Arc<dyn AsyncStorage>>,
+) -> impl Responder {
+    let key = key.into_inner();
+
+    match storage.get_metadata(&key).await {
+        Ok(metadata) => HttpResponse::Ok().json(KeyMetadataResponse { key, metadata }),
+        Err(storage_error) => storage_error.error_response(),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/keys/{key}/schema",
+    params(
+        ("key" = String, Path, description = "Unique key identifier (alphanumeric, hyphens, underscores, 1-255 chars)", example = "config-prod")
+    ),
+    responses(
+        (status = 200, description = "The JSON Schema attached to the key", body = Object, example = json!({"type": "object", "required": ["port"]})),
+        (status = 404, description = "Key not found (`KEY_NOT_FOUND`) or no schema attached (`SCHEMA_NOT_FOUND`)", body = ErrorResponse)
+    ),
+    tag = "Keys - Read Operations",
+    summary = "Get the schema attached to a key",
+    description = "Returns the JSON Schema document attached with `PUT /keys/{key}/schema`, exactly as it was sent. The same document is shown as `metadata.schema` in read responses. Returns 404 `SCHEMA_NOT_FOUND` when the key exists but has no schema."
+)]
+#[get("/keys/{key}/schema")]
+pub async fn get_schema(
+    key: web::Path<Key>,
+    storage: web::Data<Arc<dyn AsyncStorage>>,
+) -> impl Responder {
+    match storage.get_metadata(&key).await {
+        Ok(Metadata {
+            schema: Some(schema),
+            ..
+        }) => HttpResponse::Ok().json(schema),
+        Ok(_) => HttpResponse::NotFound().json(ErrorResponse {
+            error: ErrorDetail {
+                code: "SCHEMA_NOT_FOUND".to_string(),
+                message: format!("The key '{}' has no schema attached", key.as_str()),
+            },
+        }),
+        Err(storage_error) => storage_error.error_response(),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/keys/{key}/size",
+    params(
+        ("key" = String, Path, description = "Unique key identifier (alphanumeric, hyphens, underscores, 1-255 chars)", example = "user-123")
+    ),
+    responses(
+        (status = 200, description = "Size of the stored value in bytes", body = SizeResponse),
+        (status = 404, description = "Key not found in the store", body = ErrorResponse)
+    ),
+    tag = "Keys - Read Operations",
+    summary = "Get value size by key",
+    description = "Returns the UTF-8 byte length of the stored value without transferring it, for capacity planning. The same number is reported as `size_bytes` in every metadata object. Returns 404 if the key does not exist."
+)]
+#[get("/keys/{key}/size")]
+pub async fn get_size_by_key(
+    key: web::Path<Key>,
+    storage: web::Data<Arc<dyn AsyncStorage>>,
+) -> impl Responder {
+    let key = key.into_inner();
+
+    match storage.get_metadata(&key).await {
+        Ok(metadata) => HttpResponse::Ok().json(SizeResponse {
+            key,
+            size_bytes: metadata.size_bytes,
+        }),
+        Err(storage_error) => storage_error.error_response(),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/keys/{key}/ttl",
+    params(
+        ("key" = String, Path, description = "Unique key identifier (alphanumeric, hyphens, underscores, 1-255 chars)", example = "user-123")
+    ),
+    responses(
+        (status = 200, description = "Remaining time to live in seconds, or -1 when the key has no expiry", body = TtlResponse),
+        (status = 404, description = "Key not found in the store", body = ErrorResponse)
+    ),
+    tag = "Keys - Read Operations",
+    summary = "Get remaining time to live",
+    description = "Mirrors the Redis TTL command. Returns the number of seconds until the key expires (rounded up), or -1 when the key has no expiry. Returns 404 if the key does not exist or has already expired."
+)]
+#[get("/keys/{key}/ttl")]
+pub async fn get_ttl(
+    key: web::Path<Key>,
+    storage: web::Data<Arc<dyn AsyncStorage>>,
+) -> impl Responder {
+    match storage.get_ttl(&key).await {
+        Ok(remaining) => HttpResponse::Ok().json(TtlResponse::from_remaining(remaining)),
+        Err(storage_error) => storage_error.error_response(),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/keys/{key}/history",
+    params(
+        ("key" = String, Path, description = "Unique key identifier (alphanumeric, hyphens, underscores, 1-255 chars)", example = "user-123"),
+        HistoryQuery
+    ),
+    responses(
+        (status = 200, description = "Previous values of the key with their metadata, newest first", body = HistoryResponse),
+        (status = 404, description = "Key not found in the store", body = ErrorResponse)
+    ),
+    tag = "Keys - Read Operations",
+    summary = "Get previous values of a key",
+    description = "Returns the values the key held before its most recent writes, newest first, each with the metadata it had at the time (`updated_at` says when that value was written and `version` which write it was). The current value is not included; fetch it with `GET /keys/{key}`. History is only kept when the server runs with `KV_HISTORY_LIMIT` set to the number of past versions to retain per key; with the default of 0 the list is always empty. The oldest versions are dropped once the limit is reached, and deleting a key, letting it expire or creating it again discards its history. `limit` caps how many versions are returned (at most 1000). Returns 404 if the key does not exist."
+)]
+#[get("/keys/{key}/history")]
+pub async fn get_history(
+    key: web::Path<Key>,
+    query: web::Query<HistoryQuery>,
+    storage: web::Data<Arc<dyn AsyncStorage>>,
+) -> impl Responder {
+    let key = key.into_inner();
+    let limit = query
+        .limit
+        .unwrap_or(MAX_LISTED_ENTRIES)
+        .min(MAX_LISTED_ENTRIES);
+
+    match storage.history(key.clone(), limit).await {
+        Ok(versions) => HttpResponse::Ok().json(HistoryResponse { key, versions }),
+        Err(storage_error) => storage_error.error_response(),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/keys/{key}/list",
+    params(
+        ("key" = String, Path, description = "Key holding the list", example = "jobs"),
+        ListRangeQuery
+    ),
+    responses(
+        (status = 200, description = "The requested elements, left to right, and the list's total length", body = ListRangeResponse),
+        (status = 409, description = "The key holds something other than a list (`WRONG_TYPE`)", body = ErrorResponse)
+    ),
+    tag = "Keys - Read Operations",
+    summary = "Read a range of a list",
+    description = "Mirrors the Redis LRANGE command. Returns the elements from index `start` to index `end`, both inclusive, counting from 0 at the left end; negative indexes count from the right end, so the defaults `start=0&end=-1` return the whole list. Out-of-range indexes are clamped rather than rejected, and a range that selects nothing returns an empty array. `length` is the total number of elements. A missing key reads as an empty list, since popping the last element deletes the key."
+)]
+#[get("/keys/{key}/list")]
+pub async fn list_range(
+    key: web::Path<Key>,
+    query: web::Query<ListRangeQuery>,
+    storage: web::Data<Arc<dyn AsyncStorage>>,
+) -> impl Responder {
+    let key = key.into_inner();
+
+    match storage.list_slice(&key, query.start, query.end).await {
+        Ok((elements, length)) => HttpResponse::Ok().json(ListRangeResponse {
+            key,
+            elements,
+            length,
+        }),
+        Err(storage_error) => storage_error.error_response(),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/keys/{key}/set/members",
+    params(
+        ("key" = String, Path, description = "Key holding the set", example = "post-42-tags")
+    ),
+    responses(
+        (status = 200, description = "Every member of the set, sorted, and the set's size", body = SetMembersResponse),
+        (status = 409, description = "The key holds a different kind of value (`WRONG_TYPE`)", body = ErrorResponse)
+    ),
+    tag = "Keys - Read Operations",
+    summary = "Get the members of a set",
+    description = "Mirrors the Redis SMEMBERS command. Returns every member of the set held by the key, sorted so the order is stable between calls, with its `cardinality`. A missing key reads as an empty set, since removing the last member deletes the key."
+)]
+#[get("/keys/{key}/set/members")]
+pub async fn set_members(
+    key: web::Path<Key>,
+    storage: web::Data<Arc<dyn AsyncStorage>>,
+) -> impl Responder {
+    let key = key.into_inner();
+
+    match storage.members(&key).await {
+        Ok(members) => HttpResponse::Ok().json(SetMembersResponse {
+            key,
+            cardinality: members.len(),
+            members,
+        }),
+        Err(storage_error) => storage_error.error_response(),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/keys/{key}/set/contains/{member}",
+    params(
+        ("key" = String, Path, description = "Key holding the set", example = "post-42-tags"),
+        ("member" = String, Path, description = "Member to look for, percent-encoded", example = "rust")
+    ),
+    responses(
+        (status = 200, description = "Whether the set contains the member", body = SetContainsResponse),
+        (status = 409, description = "The key holds a different kind of value (`WRONG_TYPE`)", body = ErrorResponse)
+    ),
+    tag = "Keys - Read Operations",
+    summary = "Check set membership",
+    description = "Mirrors the Redis SISMEMBER command. Answers `contains: true` when the member is in the set held by the key, and `false` otherwise, including when the key does not exist."
+)]
+#[get("/keys/{key}/set/contains/{member}")]
+pub async fn set_contains(
+    path: web::Path<(Key, String)>,
+    storage: web::Data<Arc<dyn AsyncStorage>>,
+) -> impl Responder {
+    let (key, member) = path.into_inner();
+
+    match storage.is_member(&key, &member).await {
+        Ok(contains) => HttpResponse::Ok().json(SetContainsResponse {
+            key,
+            member,
+            contains,
+        }),
+        Err(storage_error) => storage_error.error_response(),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/keys/{key}/fields",
+    params(
+        ("key" = String, Path, description = "Key holding the hash", example = "user-123")
+    ),
+    responses(
+        (status = 200, description = "Every field of the hash with its value, sorted by field name", body = FieldsResponse),
+        (status = 404, description = "Key not found in the store", body = ErrorResponse),
+        (status = 409, description = "The key holds something other than a hash (`WRONG_TYPE`)", body = ErrorResponse)
+    ),
+    tag = "Keys - Read Operations",
+    summary = "Get all fields of a hash",
+    description = "Mirrors the Redis HGETALL command. Returns every field stored under the key by `PUT /keys/{key}/fields/{field}`, as an object mapping field names to their string values. A key holding a plain value is answered with 409 `WRONG_TYPE`."
+)]
+#[get("/keys/{key}/fields")]
+pub async fn get_fields(
+    key: web::Path<Key>,
+    storage: web::Data<Arc<dyn AsyncStorage>>,
+) -> impl Responder {
+    let key = key.into_inner();
+
+    match storage.get_fields(&key).await {
+        Ok(fields) => HttpResponse::Ok().json(FieldsResponse { key, fields }),
+        Err(storage_error) => storage_error.error_response(),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/keys/{key}/fields/{field}",
+    params(
+        ("key" = String, Path, description = "Key holding the hash", example = "user-123"),
+        ("field" = String, Path, description = "Field name, following the same rules as keys", example = "name")
+    ),
+    responses(
+        (status = 200, description = "The field's value", body = FieldResponse),
+        (status = 404, description = "Key not found (`KEY_NOT_FOUND`) or the hash has no such field (`FIELD_NOT_FOUND`)", body = ErrorResponse),
+        (status = 409, description = "The key holds something other than a hash (`WRONG_TYPE`)", body = ErrorResponse)
+    ),
+    tag = "Keys - Read Operations",
+    summary = "Get one field of a hash",
+    description = "Mirrors the Redis HGET command. Returns the value of a single field of the hash stored under the key. A missing key is 404 `KEY_NOT_FOUND`, a missing field 404 `FIELD_NOT_FOUND`, and a key holding a plain value 409 `WRONG_TYPE`."
+)]
+#[get("/keys/{key}/fields/{field}")]
+pub async fn get_field(
+    path: web::Path<(Key, Key)>,
+    storage: web::Data<Arc<dyn AsyncStorage>>,
+) -> impl Responder {
+    let (key, field) = path.into_inner();
+
+    match storage.get_field(&key, &field).await {
+        Ok(value) => HttpResponse::Ok().json(FieldResponse { key, field, value }),
+        Err(storage_error) => storage_error.error_response(),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/keys/batch-get",
+    request_body = BatchGetRequest,
+    responses(
+        (status = 200, description = "One result per requested key, in request order", body = BatchGetResponse),
+        (status = 422, description = "One or more keys are invalid (each listed in `errors`), or more than 1000 keys were requested", body = BatchValidationResponse)
+    ),
+    tag = "Keys - Read Operations",
+    summary = "Get many values in one request",
+    description = "Fetches up to 1000 keys in a single round trip. The response holds one entry per requested key, in request order, whose `status` is `ok` (with `value` and `metadata`), `not_found`, or `error` (with an `error` detail when the backend failed to read that key). The request still succeeds with 200 when individual keys are missing or fail. Every key is validated first and the whole request is rejected with 422 if any entry is invalid, with an `errors` array holding one `{index, field, code, message}` item per invalid key."
+)]
+#[post("/keys/batch-get")]
+pub async fn batch_get(
+    body: web::Json<BatchGetRequest>,
+    storage: web::Data<Arc<dyn AsyncStorage>>,
+) -> impl Responder {
+    let keys = match parse_key_list(body.into_inner().keys) {
+        Ok(keys) => keys,
+        Err(error) => return HttpResponse::UnprocessableEntity().json(error),
+    };
+
+    let results = storage.get_many(&keys).await;
+    let results = keys
+        .into_iter()
+        .zip(results)
+        .map(|(key, result)| match result {
+            Ok(ValueResponse {
+                value: StoredValue::Json(value),
+                metadata,
+            }) => BatchGetResult {
+                key,
+                status: BatchGetStatus::Ok,
+                value: Some(value),
+                metadata: Some(metadata),
+                error: None,
+            },
+            Err(StorageError::KeyNotFound(_)) => BatchGetResult {
+                key,
+                status: BatchGetStatus::NotFound,
+                value: None,
+                metadata: None,
+                error: None,
+            },
+            Ok(ValueResponse { value, metadata }) => {
+                let storage_error = not_json(key.clone(), &value);
+                BatchGetResult {
+                    key,
+                    status: BatchGetStatus::Error,
+                    value: None,
+                    metadata: Some(metadata),
+                    error: Some(ErrorDetail::from(&storage_error)),
+                }
+            }
+            Err(storage_error) => BatchGetResult {
+                key,
+                status: BatchGetStatus::Error,
+                value: None,
+                metadata: None,
+                error: Some(ErrorDetail::from(&storage_error)),
+            },
+        })
+        .collect();
+
+    HttpResponse::Ok().json(BatchGetResponse { results })
+}
+
+#[utoipa::path(
+    post,
+    path = "/keys/exists",
+    request_body = BatchGetRequest,
+    responses(
+        (status = 200, description = "Map of each requested key to whether it exists", body = ExistsResponse),
+        (status = 422, description = "One or more keys are invalid (each listed in `errors`), or more than 1000 keys were requested", body = BatchValidationResponse)
+    ),
+    tag = "Keys - Read Operations",
+    summary = "Check whether many keys exist",
+    description = "Answers existence for up to 1000 keys in one round trip without transferring any values, which is cheaper than issuing one HEAD request per key. Every key is validated first and the whole request is rejected with 422 if any entry is invalid."
+)]
+#[post("/keys/exists")]
+pub async fn bulk_exists(
+    body: web::Json<BatchGetRequest>,
+    storage: web::Data<Arc<dyn AsyncStorage>>,
+) -> impl Responder {
+    let keys = match parse_key_list(body.into_inner().keys) {
+        Ok(keys) => keys,
+        Err(error) => return HttpResponse::UnprocessableEntity().json(error),
+    };
+
+    let mut exists = HashMap::with_capacity(keys.len());
+    for key in keys {
+        let present = storage.exists(&key).await;
+        exists.insert(key, present);
+    }
+
+    HttpResponse::Ok().json(ExistsResponse(exists))
+}
+
+pub async fn lookup_many(storage: &dyn AsyncStorage, keys: Vec<Key>) -> MultiGetResponse {
+    let mut response = MultiGetResponse {
+        values: HashMap::new(),
+        missing: Vec::new(),
+        errors: HashMap::new(),
+    };
+
+    let results = storage.get_many(&keys).await;
+    for (key, result) in keys.into_iter().zip(results) {
+        match result {
+            Ok(value) if value.value.as_json().is_none() => {
+                let storage_error = not_json(key.clone(), &value.value);
+                response
+                    .errors
+                    .insert(key, ErrorDetail::from(&storage_error));
+            }
+            Ok(value) => {
+                response.values.insert(key, value);
+            }
+            Err(StorageError::KeyNotFound(_)) => response.missing.push(key),
+            Err(storage_error) => {
+                response
+                    .errors
+                    .insert(key, ErrorDetail::from(&storage_error));
+            }
         }
     }
+
+    response
+}
+
+pub const DEFAULT_SEARCH_LIMIT: usize = 100;
+
+#[utoipa::path(
+    get,
+    path = "/search",
+    params(SearchQuery),
+    responses(
+        (status = 200, description = "Keys whose values contain the substring, with their metadata", body = SearchResponse),
+        (status = 400, description = "`value_contains` is missing or empty", body = ErrorResponse)
+    ),
+    tag = "Keys - Read Operations",
+    summary = "Search entries by value substring",
+    description = "Scans stored values for a substring and returns the matching keys with their metadata, ordered by key. Matching is case-sensitive unless `case_sensitive=false`. This is an O(n) scan: at most `limit` matches are returned, and when more remain the response sets `truncated: true` and a `next_cursor` to pass back as `cursor` to resume."
+)]
+#[get("/search")]
+pub async fn search_values(
+    query: web::Query<SearchQuery>,
+    storage: web::Data<Arc<dyn AsyncStorage>>,
+) -> impl Responder {
+    let query = query.into_inner();
+    if query.value_contains.is_empty() {
+        return HttpResponse::BadRequest().json(ErrorResponse {
+            error: ErrorDetail {
+                code: "VALIDATION_ERROR".to_string(),
+                message: "value_contains must not be empty".to_string(),
+            },
+        });
+    }
+
+    let search = ValueSearch::new(&query.value_contains, query.case_sensitive.unwrap_or(true));
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_SEARCH_LIMIT)
+        .clamp(1, MAX_LISTED_ENTRIES);
+    let page = storage
+        .search_values(&search, query.cursor.as_deref(), limit)
+        .await;
+
+    HttpResponse::Ok().json(search_response(page))
+}
+
+#[utoipa::path(
+    post,
+    path = "/keys/by-value",
+    params(PageQuery),
+    request_body = ValueLookupRequest,
+    responses(
+        (status = 200, description = "Keys whose value equals the given value exactly, with their metadata", body = SearchResponse)
+    ),
+    tag = "Keys - Read Operations",
+    summary = "Find keys holding an exact value",
+    description = "Reverse lookup for deduplication: returns every key whose stored value is exactly equal to `value`, ordered by key. The value travels in the request body because it can be long and contain characters that are not allowed in URLs. Results are paginated like `/search`: at most `limit` matches per page, with `truncated` and `next_cursor` set when more remain. Without `KV_INDEX_VALUES` this scans every entry; with it the lookup uses a value index."
+)]
+#[post("/keys/by-value")]
+pub async fn find_by_value(
+    query: web::Query<PageQuery>,
+    body: web::Json<ValueLookupRequest>,
+    storage: web::Data<Arc<dyn AsyncStorage>>,
+) -> impl Responder {
+    let search = ValueSearch::exact(&value_text(&body.value));
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_SEARCH_LIMIT)
+        .clamp(1, MAX_LISTED_ENTRIES);
+    let page = storage
+        .search_values(&search, query.cursor.as_deref(), limit)
+        .await;
+
+    HttpResponse::Ok().json(search_response(page))
+}
+
+fn search_response(page: SearchPage) -> SearchResponse {
+    SearchResponse {
+        matches: page
+            .matches
+            .into_iter()
+            .map(|(key, metadata)| KeyMetadataResponse { key, metadata })
+            .collect(),
+        truncated: page.next_cursor.is_some(),
+        next_cursor: page.next_cursor,
+    }
 }
 
 pub fn configure(cfg: &mut web::ServiceConfig) {
-    cfg.service(get_value_by_key);
+    cfg.service(count_keys)
+        .service(random_entry)
+        .service(get_value_by_key)
+        .service(key_exists)
+        .service(get_raw_value)
+        .service(get_blob)
+        .service(get_json_pointer)
+        .service(get_metadata_by_key)
+        .service(get_size_by_key)
+        .service(get_schema)
+        .service(get_ttl)
+        .service(get_history)
+        .service(get_fields)
+        .service(get_field)
+        .service(list_range)
+        .service(set_members)
+        .service(set_contains)
+        .service(batch_get)
+        .service(bulk_exists)
+        .service(search_values)
+        .service(find_by_value);
 }
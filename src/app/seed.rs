@@ -0,0 +1,145 @@
+use crate::app::models::BatchUpsertEntry;
+use crate::app::validation::validate_batch_entry;
+use crate::service::AsyncStorage;
+use serde_json::{Map, Value};
+use std::path::Path;
+
+/// What loading a seed file did: how many keys were written, and which were
+/// skipped and why.
+#[derive(Debug, Default)]
+pub struct SeedReport {
+    pub seeded: usize,
+    pub skipped: Vec<(String, String)>,
+}
+
+/// Upserts every `{key: value}` pair in one `upsert_many`, validating each
+/// like a batch upsert entry. Invalid or rejected pairs are skipped and
+/// reported, not fatal.
+pub async fn seed(storage: &dyn AsyncStorage, entries: Map<String, Value>) -> SeedReport {
+    let mut report = SeedReport::default();
+    let mut keys = Vec::new();
+    let mut requests = Vec::new();
+    for (index, (key, value)) in entries.into_iter().enumerate() {
+        let entry = BatchUpsertEntry {
+            key: key.clone(),
+            value,
+            value_type: Default::default(),
+            encoding: Default::default(),
+            ttl_seconds: None,
+        };
+        let request = match validate_batch_entry(index, entry) {
+            Ok(request) => request,
+            Err(errors) => {
+                let reasons: Vec<String> = errors.into_iter().map(|error| error.message).collect();
+                report.skipped.push((key, reasons.join("; ")));
+                continue;
+            }
+        };
+        keys.push(key);
+        requests.push(request);
+    }
+
+    for (key, written) in keys.into_iter().zip(storage.upsert_many(requests).await) {
+        match written {
+            Ok(_) => report.seeded += 1,
+            Err(storage_error) => report.skipped.push((key, storage_error.to_string())),
+        }
+    }
+    report
+}
+
+/// Reads a JSON object of `{key: value}` pairs from `path` and seeds it. A
+/// missing file or one that is not a JSON object is an error, since the
+/// store would otherwise start without the data it was configured with.
+pub async fn seed_file(storage: &dyn AsyncStorage, path: &Path) -> std::io::Result<SeedReport> {
+    let contents = std::fs::read_to_string(path).map_err(|err| {
+        std::io::Error::new(
+            err.kind(),
+            format!("Cannot read seed file {}: {}", path.display(), err),
+        )
+    })?;
+    let entries: Map<String, Value> = serde_json::from_str(&contents).map_err(|err| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!(
+                "Seed file {} must hold a JSON object of key-value pairs: {}",
+                path.display(),
+                err
+            ),
+        )
+    })?;
+    Ok(seed(storage, entries).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::service::{InMemoryConfig, InMemoryStorage};
+    use crate::types::Key;
+
+    fn seed_path(name: &str, contents: &str) -> std::path::PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("kv-seed-{}-{}.json", std::process::id(), name));
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[actix_web::test]
+    async fn test_seed_skips_invalid_pairs() {
+        let storage = InMemoryStorage::new();
+        let entries = serde_json::json!({
+            "feature-flags": {"beta": true},
+            "greeting": "hello",
+            "bad key!": "x",
+            "empty": ""
+        });
+
+        let report = seed(&storage, entries.as_object().unwrap().clone()).await;
+        assert_eq!(report.seeded, 2);
+        let skipped: Vec<&str> = report.skipped.iter().map(|(key, _)| key.as_str()).collect();
+        assert_eq!(skipped, ["bad key!", "empty"]);
+        assert_eq!(
+            storage
+                .get(Key::new("greeting".to_string()).unwrap())
+                .await
+                .unwrap()
+                .value,
+            "hello"
+        );
+        assert_eq!(storage.len().await, 2);
+    }
+
+    #[actix_web::test]
+    async fn test_seed_reports_storage_rejections() {
+        let storage = InMemoryStorage::with_config(InMemoryConfig {
+            max_value_bytes: 4,
+            ..Default::default()
+        });
+        let entries = serde_json::json!({"short": "ok", "long": "far too long"});
+
+        let report = seed(&storage, entries.as_object().unwrap().clone()).await;
+        assert_eq!(report.seeded, 1);
+        assert_eq!(report.skipped.len(), 1);
+        assert_eq!(report.skipped[0].0, "long");
+        assert!(report.skipped[0].1.contains("over the limit"));
+    }
+
+    #[actix_web::test]
+    async fn test_seed_file_reads_a_json_object() {
+        let storage = InMemoryStorage::new();
+        let path = seed_path("object", r#"{"region": "eu-west-1", "retries": 3}"#);
+        let report = seed_file(&storage, &path).await.unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(report.seeded, 2);
+        assert!(report.skipped.is_empty());
+
+        let path = seed_path("array", r#"["region"]"#);
+        let err = seed_file(&storage, &path).await.unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+
+        let missing = std::env::temp_dir().join("kv-seed-does-not-exist.json");
+        let err = seed_file(&storage, &missing).await.unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::NotFound);
+    }
+}
@@ -0,0 +1,98 @@
+use crate::types::Key;
+use dashmap::DashMap;
+use rand::Rng;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_SNAPSHOT_TTL: Duration = Duration::from_secs(60);
+
+struct Snapshot {
+    keys: Arc<Vec<Key>>,
+    created_at: Instant,
+}
+
+pub struct SnapshotCache {
+    snapshots: DashMap<String, Snapshot>,
+    ttl: Duration,
+}
+
+impl Default for SnapshotCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_SNAPSHOT_TTL)
+    }
+}
+
+impl SnapshotCache {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            snapshots: DashMap::new(),
+            ttl,
+        }
+    }
+
+    pub fn create(&self, keys: Vec<Key>) -> (String, Arc<Vec<Key>>) {
+        let token = format!("{:032x}", rand::thread_rng().r#gen::<u128>());
+        let keys = Arc::new(keys);
+        self.snapshots.insert(
+            token.clone(),
+            Snapshot {
+                keys: keys.clone(),
+                created_at: Instant::now(),
+            },
+        );
+        (token, keys)
+    }
+
+    pub fn get(&self, token: &str) -> Option<Arc<Vec<Key>>> {
+        let snapshot = self.snapshots.get(token)?;
+        if snapshot.created_at.elapsed() < self.ttl {
+            return Some(snapshot.keys.clone());
+        }
+        drop(snapshot);
+        self.snapshots.remove_if(token, |_, snapshot| {
+            snapshot.created_at.elapsed() >= self.ttl
+        });
+        None
+    }
+
+    pub fn purge_expired(&self) -> usize {
+        let before = self.snapshots.len();
+        self.snapshots
+            .retain(|_, snapshot| snapshot.created_at.elapsed() < self.ttl);
+        before.saturating_sub(self.snapshots.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(names: &[&str]) -> Vec<Key> {
+        names
+            .iter()
+            .map(|name| Key::new(name.to_string()).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_snapshot_round_trip() {
+        let cache = SnapshotCache::default();
+        let (token, stored) = cache.create(keys(&["a", "b"]));
+        let (other, _) = cache.create(keys(&["c"]));
+
+        assert_ne!(token, other);
+        assert_eq!(cache.get(&token), Some(stored));
+        assert_eq!(cache.get(&other).unwrap().as_slice(), keys(&["c"]));
+        assert!(cache.get("unknown").is_none());
+    }
+
+    #[test]
+    fn test_expired_snapshots_are_gone() {
+        let cache = SnapshotCache::new(Duration::ZERO);
+        let (token, _) = cache.create(keys(&["a"]));
+
+        assert!(cache.get(&token).is_none());
+        cache.create(keys(&["b"]));
+        assert_eq!(cache.purge_expired(), 1);
+    }
+}
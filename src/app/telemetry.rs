@@ -0,0 +1,111 @@
+use crate::service::traced::{KEY_ATTRIBUTE, TRACER_NAME};
+use actix_web::Error;
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::HeaderMap;
+use actix_web::middleware::Next;
+use opentelemetry::context::FutureExt;
+use opentelemetry::propagation::Extractor;
+use opentelemetry::trace::{SpanKind, Status, TraceContextExt, Tracer};
+use opentelemetry::{KeyValue, global};
+use opentelemetry_otlp::{ExporterBuildError, SpanExporter};
+use opentelemetry_sdk::Resource;
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::SdkTracerProvider;
+
+/// The standard OpenTelemetry variable naming the collector. Tracing is only
+/// switched on when it is set.
+pub const ENDPOINT_VAR: &str = "OTEL_EXPORTER_OTLP_ENDPOINT";
+
+/// Exports spans in batches over OTLP/HTTP to the collector named by
+/// `OTEL_EXPORTER_OTLP_ENDPOINT` (the exporter appends `/v1/traces`), and
+/// installs the provider globally. Returns `None` when the variable is unset.
+/// Shut the provider down on exit so the last batch is flushed.
+pub fn init() -> Result<Option<SdkTracerProvider>, ExporterBuildError> {
+    if std::env::var_os(ENDPOINT_VAR).is_none_or(|url| url.is_empty()) {
+        return Ok(None);
+    }
+    let exporter = SpanExporter::builder().with_http().build()?;
+    let mut resource = Resource::builder();
+    if std::env::var_os("OTEL_SERVICE_NAME").is_none() {
+        resource = resource.with_service_name(TRACER_NAME);
+    }
+    let provider = SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(resource.build())
+        .build();
+    install(provider.clone());
+    Ok(Some(provider))
+}
+
+/// Makes `provider` the global one and accepts W3C `traceparent` headers, so
+/// a request joins its caller's trace.
+pub fn install(provider: SdkTracerProvider) {
+    global::set_text_map_propagator(TraceContextPropagator::new());
+    global::set_tracer_provider(provider);
+}
+
+struct HeaderExtractor<'a>(&'a HeaderMap);
+
+impl Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(|name| name.as_str()).collect()
+    }
+}
+
+/// Middleware opening a server span per request, named after the method and
+/// the matched route (`GET /keys/{key}`). It is current while the request is
+/// handled, so the `storage.*` spans of [`Traced`](crate::service::Traced)
+/// become its children. The key from the path is recorded as `kv.key`;
+/// bodies are not recorded. 5xx responses mark the span as failed.
+pub async fn trace_requests<B: MessageBody + 'static>(
+    req: ServiceRequest,
+    next: Next<B>,
+) -> Result<ServiceResponse<B>, Error> {
+    let parent = global::get_text_map_propagator(|propagator| {
+        propagator.extract(&HeaderExtractor(req.headers()))
+    });
+    let tracer = global::tracer(TRACER_NAME);
+    let method = req.method().to_string();
+    let span = tracer
+        .span_builder(method.clone())
+        .with_kind(SpanKind::Server)
+        .with_attributes([
+            KeyValue::new("http.request.method", method.clone()),
+            KeyValue::new("url.path", req.path().to_string()),
+        ])
+        .start_with_context(&tracer, &parent);
+    let cx = parent.with_span(span);
+
+    let result = next.call(req).with_context(cx.clone()).await;
+
+    let span = cx.span();
+    match &result {
+        Ok(response) => {
+            // Routing has run by now, so the request knows its route.
+            let request = response.request();
+            if let Some(route) = request.match_pattern() {
+                span.update_name(format!("{} {}", method, route));
+                span.set_attribute(KeyValue::new("http.route", route));
+            }
+            if let Some(key) = request.match_info().get("key") {
+                span.set_attribute(KeyValue::new(KEY_ATTRIBUTE, key.to_string()));
+            }
+            let status = response.status();
+            span.set_attribute(KeyValue::new(
+                "http.response.status_code",
+                i64::from(status.as_u16()),
+            ));
+            if status.is_server_error() {
+                span.set_status(Status::error(status.to_string()));
+            }
+        }
+        Err(error) => span.set_status(Status::error(error.to_string())),
+    }
+    span.end();
+    result
+}
@@ -0,0 +1,239 @@
+use crate::app::models::{
+    BatchUpsertEntry, BatchValidationResponse, CounterBounds, CreateKVRequest, ErrorDetail,
+    ErrorResponse, FieldError,
+};
+use crate::service::validation;
+use crate::types::Key;
+
+pub use crate::service::validation::{reject_blank_values, set_reject_blank_values};
+
+pub const MAX_BATCH_KEYS: usize = 1000;
+/// Distinct keys a `POST /transactions` request may touch.
+pub const MAX_TRANSACTION_KEYS: usize = 16;
+pub const MAX_SET_MEMBER_BYTES: usize = 1024;
+
+pub fn batch_too_large(count: usize) -> ErrorResponse {
+    ErrorResponse {
+        error: ErrorDetail {
+            code: "BATCH_TOO_LARGE".to_string(),
+            message: format!(
+                "Request contains {} keys, the maximum is {}",
+                count, MAX_BATCH_KEYS
+            ),
+        },
+    }
+}
+
+pub fn parse_key_csv(raw: &str) -> Result<Vec<Key>, ErrorResponse> {
+    let parts: Vec<&str> = raw.split(',').collect();
+    if parts.len() > MAX_BATCH_KEYS {
+        return Err(batch_too_large(parts.len()));
+    }
+
+    parts
+        .into_iter()
+        .map(|part| {
+            validation::key("keys", part.to_string()).map_err(|err| ErrorResponse {
+                error: ErrorDetail {
+                    code: err.code.to_string(),
+                    message: format!("Invalid key '{}': {}", part, err.reason),
+                },
+            })
+        })
+        .collect()
+}
+
+pub fn parse_key_list(raw_keys: Vec<String>) -> Result<Vec<Key>, BatchValidationResponse> {
+    if raw_keys.len() > MAX_BATCH_KEYS {
+        return Err(BatchValidationResponse {
+            error: batch_too_large(raw_keys.len()).error,
+            errors: Vec::new(),
+        });
+    }
+
+    let mut keys = Vec::with_capacity(raw_keys.len());
+    let mut failures = Vec::new();
+    let mut errors = Vec::new();
+
+    for (index, raw) in raw_keys.into_iter().enumerate() {
+        match validation::key("keys", raw.clone()) {
+            Ok(key) => keys.push(key),
+            Err(err) => {
+                failures.push(format!("[{}] '{}': {}", index, raw, err.reason));
+                errors.push(FieldError {
+                    index,
+                    field: err.field,
+                    code: err.code.to_string(),
+                    message: err.reason,
+                });
+            }
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(keys)
+    } else {
+        Err(BatchValidationResponse {
+            error: ErrorDetail {
+                code: "INVALID_KEY".to_string(),
+                message: format!("Invalid keys in request: {}", failures.join("; ")),
+            },
+            errors,
+        })
+    }
+}
+
+/// Runs [`validation::entry`] on one entry of a batch, tagging each problem
+/// with the entry's position.
+pub fn validate_batch_entry(
+    index: usize,
+    entry: BatchUpsertEntry,
+) -> Result<CreateKVRequest, Vec<FieldError>> {
+    validation::entry(entry).map_err(|errors| {
+        errors
+            .into_iter()
+            .map(|error| FieldError {
+                index,
+                field: error.field,
+                code: error.code.to_string(),
+                message: error.reason,
+            })
+            .collect()
+    })
+}
+
+pub fn validate_set_member(member: &str) -> Result<(), ErrorResponse> {
+    let message = if member.is_empty() {
+        "Set members must not be empty".to_string()
+    } else if member.len() > MAX_SET_MEMBER_BYTES {
+        format!(
+            "Set members must be at most {} bytes, got {}",
+            MAX_SET_MEMBER_BYTES,
+            member.len()
+        )
+    } else {
+        return Ok(());
+    };
+    Err(ErrorResponse {
+        error: ErrorDetail {
+            code: "INVALID_MEMBER".to_string(),
+            message,
+        },
+    })
+}
+
+pub fn validate_counter_bounds(bounds: &CounterBounds) -> Result<(), ErrorResponse> {
+    let message = if bounds.floor() > bounds.ceiling() {
+        format!(
+            "min ({}) must not be greater than max ({})",
+            bounds.floor(),
+            bounds.ceiling()
+        )
+    } else if !bounds.contains(bounds.initial) {
+        format!(
+            "initial ({}) must lie between min ({}) and max ({})",
+            bounds.initial,
+            bounds.floor(),
+            bounds.ceiling()
+        )
+    } else {
+        return Ok(());
+    };
+    Err(ErrorResponse {
+        error: ErrorDetail {
+            code: "INVALID_COUNTER_BOUNDS".to_string(),
+            message,
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::key::KeyError;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_key_list_valid() {
+        let keys = parse_key_list(vec!["a".to_string(), "b-1".to_string()]).unwrap();
+        assert_eq!(keys.len(), 2);
+        assert_eq!(keys[1].as_str(), "b-1");
+    }
+
+    #[test]
+    fn test_parse_key_list_reports_every_invalid_entry() {
+        let err = parse_key_list(vec![
+            "good".to_string(),
+            "bad key".to_string(),
+            "".to_string(),
+        ])
+        .unwrap_err();
+
+        assert_eq!(err.error.code, "INVALID_KEY");
+        assert!(err.error.message.contains("[1] 'bad key'"));
+        assert!(err.error.message.contains("[2] ''"));
+        assert!(!err.error.message.contains("good"));
+
+        let indexes: Vec<usize> = err.errors.iter().map(|failure| failure.index).collect();
+        assert_eq!(indexes, vec![1, 2]);
+        assert_eq!(err.errors[0].field, "keys");
+        assert_eq!(
+            err.errors[0].message,
+            KeyError::InvalidCharacters.to_string()
+        );
+        assert_eq!(err.errors[1].message, KeyError::Empty.to_string());
+    }
+
+    #[test]
+    fn test_validate_batch_entry_reports_every_field() {
+        let entry: BatchUpsertEntry =
+            serde_json::from_value(json!({ "key": "bad key", "ttl_seconds": 0 })).unwrap();
+        let errors = validate_batch_entry(4, entry).unwrap_err();
+        let fields: Vec<(&str, &str)> = errors
+            .iter()
+            .map(|failure| (failure.field.as_str(), failure.code.as_str()))
+            .collect();
+        assert_eq!(
+            fields,
+            vec![
+                ("key", "INVALID_KEY"),
+                ("value", "NULL_VALUE"),
+                ("ttl_seconds", "VALIDATION_ERROR")
+            ]
+        );
+        assert!(errors.iter().all(|failure| failure.index == 4));
+
+        let entry: BatchUpsertEntry =
+            serde_json::from_value(json!({ "key": "ok", "value": 1 })).unwrap();
+        assert_eq!(validate_batch_entry(0, entry).unwrap().key.as_str(), "ok");
+    }
+
+    #[test]
+    fn test_parse_key_list_too_many() {
+        let raw = (0..=MAX_BATCH_KEYS).map(|i| format!("k{}", i)).collect();
+        let err = parse_key_list(raw).unwrap_err();
+        assert_eq!(err.error.code, "BATCH_TOO_LARGE");
+    }
+
+    #[test]
+    fn test_parse_key_csv_valid() {
+        let keys = parse_key_csv("a,b-1,c_2").unwrap();
+        let names: Vec<&str> = keys.iter().map(|key| key.as_str()).collect();
+        assert_eq!(names, vec!["a", "b-1", "c_2"]);
+    }
+
+    #[test]
+    fn test_parse_key_csv_stops_at_first_invalid() {
+        let err = parse_key_csv("good,bad key,,also bad").unwrap_err();
+        assert_eq!(err.error.code, "INVALID_KEY");
+        assert!(err.error.message.contains("'bad key'"));
+        assert!(!err.error.message.contains("also bad"));
+    }
+
+    #[test]
+    fn test_parse_key_csv_too_many() {
+        let raw: Vec<String> = (0..=MAX_BATCH_KEYS).map(|i| format!("k{}", i)).collect();
+        let err = parse_key_csv(&raw.join(",")).unwrap_err();
+        assert_eq!(err.error.code, "BATCH_TOO_LARGE");
+    }
+}
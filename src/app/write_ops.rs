@@ -1,131 +1,2497 @@
+use crate::app::csv::{self, CsvReader, CsvRecord};
+use crate::app::cursor::{decode_cursor, encode_cursor};
+use crate::app::error_handler::payload_error_response;
+use crate::app::idempotency::{
+    IDEMPOTENCY_HEADER, IdempotencyCache, IdempotencyLookup, MAX_IDEMPOTENCY_KEY_LEN,
+};
 use crate::app::models::{
-    CreateKVRequest, ErrorDetail, ErrorResponse, KeyValueResponse, UpdateKVRequest, ValueResponse,
+    AppendRequest, AppendResponse, BatchDeleteQuery, BatchDeleteResponse, BatchDeleteResult,
+    BatchDeleteStatus, BatchGetRequest, BatchUpsertEntry, BatchUpsertQuery, BatchUpsertRequest,
+    BatchUpsertResponse, BatchUpsertResult, BatchUpsertStatus, BatchValidationResponse,
+    CasConflictResponse, CasRequest, CounterAddRequest, CounterBounds, CounterResponse,
+    CreateCounterRequest, CreateKVRequest, CreateQuery, DeleteQuery, DeleteResponse, ErrorDetail,
+    ErrorResponse, ExpireRequest, FieldError, ImportCsvQuery, ImportCsvResponse, ImportCsvRowError,
+    KeyListResponse, KeyMetadataResponse, KeyPageResponse, KeyValueResponse, ListInclude,
+    ListKeysQuery, ListPopQuery, ListPopResponse, ListPushRequest, ListPushResponse, OnConflict,
+    RestoreRequest, ReturnPreference, SchemaViolationResponse, SetAddResponse, SetFieldRequest,
+    SetMemberRequest, SetRemoveResponse, StoredValue, TtlResponse, TxErrorResponse, TxOpKind,
+    TxOpRequest, TxOpResult, TxOpStatus, TxRequest, TxResponse, UpdateKVRequest,
+    ValidationErrorResponse, ValueEncoding, ValueResponse, ValueType, VersionConflictResponse,
+    value_text,
+};
+use crate::app::read_ops::lookup_many;
+use crate::app::snapshot::SnapshotCache;
+use crate::app::validation::{
+    MAX_BATCH_KEYS, MAX_TRANSACTION_KEYS, batch_too_large, parse_key_csv, parse_key_list,
+    reject_blank_values, validate_batch_entry, validate_counter_bounds, validate_set_member,
+};
+use crate::service::{
+    AsyncStorage, CasOutcome, KeyFilter, KeyPattern, OpResult, RestoreTarget, StorageError,
+    TxCheck, TxError, TxOp, UpsertOutcome, VersionPrecondition, WriteOp, WriteOutcome, json_schema,
+    validation,
 };
-use crate::service::Storage;
 use crate::types::Key;
-use actix_web::{HttpResponse, Responder, delete, get, post, put, web};
+use crate::types::key::fold_case;
+use actix_web::http::header::{
+    Accept, CONTENT_TYPE, ETag, EntityTag, HttpDate, IfMatch, IfNoneMatch, IfUnmodifiedSince,
+    LOCATION, LastModified, VARY,
+};
+use actix_web::web::Bytes;
+use actix_web::{
+    HttpMessage, HttpRequest, HttpResponse, Responder, ResponseError, delete, get, mime, patch,
+    post, put, web,
+};
+use chrono::{DateTime, Utc};
+use futures_util::{Stream, StreamExt, stream};
+use std::collections::{BTreeSet, HashSet};
+use std::convert::Infallible;
+use std::ops::Bound;
 use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+pub const MAX_LISTED_ENTRIES: usize = 1000;
+/// Set to `true` on an `include=values` listing that stopped at
+/// [`MAX_LISTED_ENTRIES`] with more matching entries left.
+pub const TRUNCATED_HEADER: &str = "X-KV-Truncated";
+pub const MAX_TTL_SECONDS: u64 = 10 * 365 * 24 * 60 * 60;
+pub const STREAM_CHUNK_KEYS: usize = 1000;
+pub const IF_MATCH_VALUE_HEADER: &str = "If-Match-Value";
+pub const PREFER_HEADER: &str = "Prefer";
+pub const PREFERENCE_APPLIED_HEADER: &str = "Preference-Applied";
+pub const MERGE_PATCH_CONTENT_TYPE: &str = "application/merge-patch+json";
+pub const DEFAULT_BLOB_CONTENT_TYPE: &str = "application/octet-stream";
+pub const MAX_IMPORT_ERRORS: usize = 1000;
 
 #[utoipa::path(
     post,
     path = "/keys",
+    params(
+        CreateQuery,
+        ("Idempotency-Key" = Option<String>, Header, description = "Client-chosen token (1-255 chars) that makes retries of this create safe", example = "3f1c9a52-order-1")
+    ),
     request_body = CreateKVRequest,
     responses(
-        (status = 201, description = "Key-value pair created successfully", body = KeyValueResponse),
-        (status = 409, description = "Key already exists - use PUT to update", body = ErrorResponse),
-        (status = 400, description = "Invalid key format or empty value", body = ErrorResponse)
+        (status = 201, description = "Key-value pair created successfully, or the original response replayed for a repeated Idempotency-Key", body = KeyValueResponse),
+        (status = 200, description = "Key already existed and `on_conflict` was `replace` (returns the new entry) or `ignore` (returns the untouched existing entry)", body = KeyValueResponse),
+        (status = 409, description = "Key already exists and `on_conflict` is `fail` (the default) - use PUT to update, or an earlier request with the same Idempotency-Key is still being processed (`IDEMPOTENCY_IN_PROGRESS`, with `Retry-After`)", body = ErrorResponse),
+        (status = 507, description = "Storing the entry would exceed the configured memory budget (`KV_MAX_BYTES`)", body = ErrorResponse),
+        (status = 400, description = "The body is not valid JSON, the Idempotency-Key header is malformed, or a field failed validation: an invalid key, an empty or null value, a `json` or `number` value that does not parse, a `base64` value that does not decode, a value over the `KV_MAX_VALUE_BYTES_BY_PREFIX` limit for its key (`VALUE_TOO_LARGE`), or a `ttl_seconds` other than -1 or 1 to ten years (each naming the `field` and `reason`)", body = ValidationErrorResponse),
+        (status = 413, description = "Request body exceeds `KV_MAX_BODY_BYTES`, or the value exceeds `KV_MAX_VALUE_BYTES` (`VALUE_TOO_LARGE`, with the size and the limit in the message)", body = ErrorResponse),
+        (status = 422, description = "A required field is missing or has the wrong type, or the Idempotency-Key was already used with a different request body (`IDEMPOTENCY_CONFLICT`)", body = ErrorResponse)
     ),
     tag = "Keys - Write Operations",
     summary = "Create new key-value pair",
-    description = "Creates a new key-value pair in the store. The key must be unique and follow the naming constraints (alphanumeric, hyphens, underscores, 1-255 chars). Returns 409 if the key already exists. Use PUT to update existing keys."
+    description = "Creates a new key-value pair in the store. The key must be unique and follow the naming constraints (alphanumeric, hyphens, underscores, 1-255 chars). Returns 409 if the key already exists. Use PUT to update existing keys. To retry safely over an unreliable network, send an `Idempotency-Key` header: for 10 minutes after a successful create, repeating the request with the same header and the same body replays the original 201 response (marked with `Idempotent-Replayed: true`) instead of returning 409. Reusing the header with a different body returns 422 `IDEMPOTENCY_CONFLICT`, also while the first request is still running; repeating the same request before the first one finishes returns 409 `IDEMPOTENCY_IN_PROGRESS` with `Retry-After`, so the retry can be sent again for the replay. Failed creates are not remembered and can be retried as-is. The value can be any JSON value except null: objects, arrays, numbers and booleans are stored as-is and returned unescaped by GET, so structured data doesn't need to be double-encoded, and `null` is rejected with 400 `NULL_VALUE` (delete the key instead). An empty string is rejected with 400 `EMPTY_VALUE`, and so are whitespace-only strings when the server runs with `KV_REJECT_BLANK_VALUES`. String values are opaque by default; set `value_type` to `json` to have the value parsed before it is stored (400 `INVALID_JSON_VALUE` if it is not valid JSON), or to `number` to require a JSON number or a string holding one (400 `INVALID_NUMBER_VALUE` otherwise). `counter` requires a 64-bit integer (400 `INVALID_COUNTER_VALUE`) and creates an unbounded counter; use `POST /keys/{key}/counter` to give it bounds. `string` is accepted as another name for the default `text`. The type is kept in the entry's metadata so readers know how to interpret the value, and it sticks to the key: later writes must match it (see PUT). Binary data can be sent with `encoding` set to `base64`: the value must then be standard base64 (400 `INVALID_BASE64` otherwise), it is stored and returned by GET in that form with `metadata.encoding` recording it, and `GET /keys/{key}?raw=true` or `GET /keys/{key}/raw` serve the decoded bytes. Combined with `value_type: json`, the decoded bytes must be JSON. The `on_conflict` query parameter chooses what happens when the key already exists: `fail` (default) returns 409 as described above; `replace` overwrites the value like PUT, preserving `created_at` and responding 200; `ignore` leaves the stored entry untouched and returns it with 200. Only responses that actually created the key (201) are remembered for `Idempotency-Key` replays. `ttl_seconds` makes the entry expire that many seconds after the write; when it is omitted the deployment's `KV_DEFAULT_TTL_SECONDS` applies (no expiry if that is unset), and `-1` explicitly stores the entry without an expiry even when a default is configured."
 )]
 #[post("/keys")]
 pub async fn create_kv(
+    req: HttpRequest,
+    query: web::Query<CreateQuery>,
     body: web::Json<CreateKVRequest>,
-    storage: web::Data<Arc<dyn Storage + Send + Sync>>,
+    storage: web::Data<Arc<dyn AsyncStorage>>,
+    idempotency: web::Data<IdempotencyCache>,
 ) -> impl Responder {
     let request = body.into_inner();
 
-    match storage.insert(request) {
-        Ok(response) => HttpResponse::Created().json(response),
-        Err(storage_error) => {
-            let error = ErrorResponse {
+    let idempotency_key = match req.headers().get(IDEMPOTENCY_HEADER) {
+        None => None,
+        Some(value) => match value.to_str() {
+            Ok(value) if !value.is_empty() && value.len() <= MAX_IDEMPOTENCY_KEY_LEN => {
+                Some(value.to_string())
+            }
+            _ => {
+                return HttpResponse::BadRequest().json(ErrorResponse {
+                    error: ErrorDetail {
+                        code: "INVALID_IDEMPOTENCY_KEY".to_string(),
+                        message: format!(
+                            "{} must be 1-{} visible ASCII characters",
+                            IDEMPOTENCY_HEADER, MAX_IDEMPOTENCY_KEY_LEN
+                        ),
+                    },
+                });
+            }
+        },
+    };
+    if let Err(error) = validation::write(&request) {
+        return error.error_response();
+    }
+    let fingerprint = IdempotencyCache::fingerprint(&request);
+
+    let reservation = match idempotency_key.as_deref() {
+        None => None,
+        Some(idempotency_key) => match idempotency.reserve(idempotency_key, fingerprint) {
+            IdempotencyLookup::Reserved(reservation) => Some(reservation),
+            IdempotencyLookup::Replay(response) => {
+                return HttpResponse::Created()
+                    .insert_header(("Idempotent-Replayed", "true"))
+                    .json(response);
+            }
+            IdempotencyLookup::Conflict => {
+                return HttpResponse::UnprocessableEntity().json(ErrorResponse {
+                    error: ErrorDetail {
+                        code: "IDEMPOTENCY_CONFLICT".to_string(),
+                        message: format!(
+                            "{} '{}' was already used with a different request body",
+                            IDEMPOTENCY_HEADER, idempotency_key
+                        ),
+                    },
+                });
+            }
+            IdempotencyLookup::InProgress => {
+                return HttpResponse::Conflict()
+                    .insert_header(("Retry-After", "1"))
+                    .json(ErrorResponse {
+                        error: ErrorDetail {
+                            code: "IDEMPOTENCY_IN_PROGRESS".to_string(),
+                            message: format!(
+                                "A request with {} '{}' is still being processed",
+                                IDEMPOTENCY_HEADER, idempotency_key
+                            ),
+                        },
+                    });
+            }
+        },
+    };
+
+    let created = match query.on_conflict {
+        OnConflict::Fail => storage.insert(request).await,
+        OnConflict::Replace => match storage.upsert(request).await {
+            Ok((response, UpsertOutcome::Created)) => Ok(response),
+            Ok((response, UpsertOutcome::Updated)) => return HttpResponse::Ok().json(response),
+            Err(storage_error) => Err(storage_error),
+        },
+        OnConflict::Ignore => {
+            let key = request.key.clone();
+            match storage.insert(request).await {
+                Err(StorageError::KeyAlreadyExists(_)) => match storage.get(key.clone()).await {
+                    Ok(existing) => {
+                        return HttpResponse::Ok().json(KeyValueResponse {
+                            key,
+                            value: existing.value,
+                            metadata: existing.metadata,
+                        });
+                    }
+                    Err(storage_error) => Err(storage_error),
+                },
+                result => result,
+            }
+        }
+    };
+
+    match created {
+        Ok(response) => {
+            if let Some(reservation) = reservation {
+                reservation.complete(response.clone());
+            }
+            HttpResponse::Created().json(response)
+        }
+        Err(storage_error) => storage_error.error_response(),
+    }
+}
+
+/// Walks the whole keyspace in sorted order, `STREAM_CHUNK_KEYS` keys at a
+/// time, without holding anything between chunks.
+fn key_chunks(storage: Arc<dyn AsyncStorage>) -> impl Stream<Item = Vec<Key>> + 'static {
+    stream::unfold(Some(None::<Key>), move |cursor| {
+        let storage = storage.clone();
+        async move {
+            let after = cursor?;
+            let keys = storage
+                .list_keys_after(after.as_ref().map(Key::as_str), STREAM_CHUNK_KEYS)
+                .await;
+            let last = keys.last()?.clone();
+            Some((keys, Some(Some(last))))
+        }
+    })
+}
+
+fn stream_keys(
+    storage: Arc<dyn AsyncStorage>,
+) -> impl Stream<Item = Result<Bytes, Infallible>> + 'static {
+    key_chunks(storage).map(|keys| {
+        let mut chunk = Vec::new();
+        for key in &keys {
+            if serde_json::to_writer(&mut chunk, key).is_ok() {
+                chunk.push(b'\n');
+            }
+        }
+        Ok(Bytes::from(chunk))
+    })
+}
+
+/// Collects up to `limit` keys of the range that match `pattern`, reading it
+/// `STREAM_CHUNK_KEYS` keys at a time so a sparse pattern never needs the
+/// whole keyspace in memory.
+async fn matching_range(
+    storage: &dyn AsyncStorage,
+    start: Bound<&str>,
+    end: Bound<&str>,
+    pattern: &KeyPattern,
+    limit: usize,
+) -> Vec<Key> {
+    let mut keys = Vec::new();
+    let mut lower = start.map(str::to_string);
+    while keys.len() < limit {
+        let batch = storage
+            .list_range(lower.as_ref().map(String::as_str), end, STREAM_CHUNK_KEYS)
+            .await;
+        let exhausted = batch.len() < STREAM_CHUNK_KEYS;
+        if let Some(last) = batch.last() {
+            lower = Bound::Excluded(last.as_str().to_string());
+        }
+        keys.extend(
+            batch
+                .into_iter()
+                .filter(|key| pattern.matches(key.as_str())),
+        );
+        if exhausted {
+            break;
+        }
+    }
+    keys.truncate(limit);
+    keys
+}
+
+const CSV_HEADER: &str = "key,value,created_at,updated_at\r\n";
+
+/// Streams every entry as CSV rows, fetching the values of each chunk of
+/// keys in one `get_many`. Keys deleted between the listing and the fetch
+/// are left out.
+fn stream_csv(
+    storage: Arc<dyn AsyncStorage>,
+) -> impl Stream<Item = Result<Bytes, Infallible>> + 'static {
+    let rows = key_chunks(storage.clone()).then(move |keys| {
+        let storage = storage.clone();
+        async move {
+            let mut chunk = String::new();
+            for (key, result) in keys.iter().zip(storage.get_many(&keys).await) {
+                let Ok(entry) = result else { continue };
+                let value = serde_json::to_value(&entry.value).unwrap_or_default();
+                chunk.push_str(&csv::quote(key.as_str()));
+                chunk.push(',');
+                chunk.push_str(&csv::quote(&value_text(&value)));
+                chunk.push(',');
+                chunk.push_str(&entry.metadata.created_at.to_rfc3339());
+                chunk.push(',');
+                chunk.push_str(&entry.metadata.updated_at.to_rfc3339());
+                chunk.push_str("\r\n");
+            }
+            Ok(Bytes::from(chunk))
+        }
+    });
+    stream::once(async { Ok(Bytes::from_static(CSV_HEADER.as_bytes())) }).chain(rows)
+}
+
+fn prefers_csv(req: &HttpRequest) -> bool {
+    req.get_header::<Accept>()
+        .and_then(|accept| {
+            accept
+                .ranked()
+                .into_iter()
+                .find_map(|mime| match (mime.type_(), mime.subtype()) {
+                    (mime::TEXT, mime::CSV) => Some(true),
+                    (mime::APPLICATION, mime::JSON | mime::STAR) | (mime::STAR, _) => Some(false),
+                    _ => None,
+                })
+        })
+        .unwrap_or(false)
+}
+
+/// The apply phase of the batch and import endpoints: writes the planned
+/// ops or, on a dry run, asks the store what writing them would do. Both go
+/// through the same storage code, so a dry run reports what a real run
+/// would, as long as no one else writes those keys in between.
+async fn apply_ops(
+    storage: &dyn AsyncStorage,
+    ops: Vec<WriteOp>,
+    dry_run: bool,
+) -> Vec<Result<WriteOutcome, StorageError>> {
+    if dry_run {
+        storage.batch_plan(ops).await
+    } else {
+        storage.batch_apply(ops).await
+    }
+}
+
+fn upsert_result(
+    key: String,
+    written: Result<(KeyValueResponse, UpsertOutcome), StorageError>,
+) -> BatchUpsertResult {
+    match written {
+        Ok((response, outcome)) => BatchUpsertResult {
+            key,
+            status: match outcome {
+                UpsertOutcome::Created => BatchUpsertStatus::Created,
+                UpsertOutcome::Updated => BatchUpsertStatus::Updated,
+            },
+            metadata: Some(response.metadata),
+            error: None,
+        },
+        Err(storage_error) => BatchUpsertResult {
+            key,
+            status: BatchUpsertStatus::Error,
+            metadata: None,
+            error: Some(ErrorDetail::from(&storage_error)),
+        },
+    }
+}
+
+/// Fills the entries that were written, left as `None` in `results`, with
+/// the outcomes of `written`, which holds one per such entry in order.
+fn fill_results(
+    raw_keys: Vec<String>,
+    results: Vec<Option<BatchUpsertResult>>,
+    written: Vec<Result<(KeyValueResponse, UpsertOutcome), StorageError>>,
+) -> Vec<BatchUpsertResult> {
+    let mut written = written.into_iter();
+    raw_keys
+        .into_iter()
+        .zip(results)
+        .map(|(raw, result)| match result {
+            Some(result) => result,
+            None => {
+                let outcome = written.next().unwrap_or_else(|| {
+                    Err(StorageError::Unavailable(
+                        "the backend returned fewer results than entries".to_string(),
+                    ))
+                });
+                upsert_result(raw, outcome)
+            }
+        })
+        .collect()
+}
+
+/// Upserts `bodies` in one [`AsyncStorage::upsert_many`] call, or plans
+/// them on a dry run. Results come back in the order of `bodies`.
+async fn upsert_all(
+    storage: &dyn AsyncStorage,
+    bodies: Vec<CreateKVRequest>,
+    dry_run: bool,
+) -> Vec<Result<(KeyValueResponse, UpsertOutcome), StorageError>> {
+    if dry_run {
+        let ops = bodies.into_iter().map(WriteOp::Upsert).collect();
+        storage
+            .batch_plan(ops)
+            .await
+            .into_iter()
+            .map(|outcome| outcome?.into_upserted())
+            .collect()
+    } else {
+        storage.upsert_many(bodies).await
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/keys/batch",
+    params(BatchUpsertQuery),
+    request_body = BatchUpsertRequest,
+    responses(
+        (status = 200, description = "One result per entry, in request order", body = BatchUpsertResponse),
+        (status = 413, description = "Request body exceeds the configured size limit; entries whose value exceeds `KV_MAX_VALUE_BYTES` are reported as `VALUE_TOO_LARGE` errors instead", body = ErrorResponse),
+        (status = 422, description = "More than 1000 entries, or `atomic=true` and at least one entry is invalid; `errors` lists every problem", body = BatchValidationResponse)
+    ),
+    tag = "Keys - Write Operations",
+    summary = "Upsert many key-value pairs",
+    description = "Writes up to 1000 entries in a single round trip, each with the same semantics as `PUT /keys/{key}` (create or overwrite, `value_type`, `encoding` and `ttl_seconds` as on PUT). Entries are applied in request order, so if a key appears twice the later entry wins. The response holds one result per entry, in request order, whose `status` is `created` or `updated` (with the resulting `metadata`) or `error` (with an `error` detail). By default an invalid entry (bad key, null or empty value, malformed `json`/`base64` value or TTL) is reported as `error` and the remaining entries are still written, and the request succeeds with 200. With `atomic=true` every entry is validated before anything is written, and if any is invalid the whole batch is rejected with 422 `VALIDATION_ERROR` whose `errors` array lists every problem at once as `{index, field, code, message}`, so a client can fix all entries in one go (an entry with both a bad key and a null value yields two items). `atomic` only covers validation: a storage failure such as 507 `INSUFFICIENT_STORAGE` part-way through is reported on the affected entries while the earlier ones stay written. With `dry_run=true` nothing is written, but the response is the one the batch would get right now: every entry goes through the same validation and storage checks (conflicting types, schemas, value and memory limits) against a private copy of the keys involved, so entries report `created`, `updated` or `error` exactly as a real run would, with the metadata they would end up with."
+)]
+#[post("/keys/batch")]
+pub async fn batch_upsert(
+    query: web::Query<BatchUpsertQuery>,
+    body: web::Json<BatchUpsertRequest>,
+    storage: web::Data<Arc<dyn AsyncStorage>>,
+) -> impl Responder {
+    let entries = body.into_inner().entries;
+    if entries.len() > MAX_BATCH_KEYS {
+        return HttpResponse::UnprocessableEntity().json(batch_too_large(entries.len()));
+    }
+
+    let mut raw_keys = Vec::with_capacity(entries.len());
+    let mut validated = Vec::with_capacity(entries.len());
+    for (index, entry) in entries.into_iter().enumerate() {
+        raw_keys.push(entry.key.clone());
+        validated.push(validate_batch_entry(index, entry));
+    }
+
+    if query.atomic {
+        let errors: Vec<FieldError> = validated
+            .iter()
+            .filter_map(|result| result.as_ref().err())
+            .flatten()
+            .cloned()
+            .collect();
+        if !errors.is_empty() {
+            let invalid_entries = validated.iter().filter(|result| result.is_err()).count();
+            return HttpResponse::UnprocessableEntity().json(BatchValidationResponse {
                 error: ErrorDetail {
-                    code: storage_error.error_code().to_string(),
-                    message: storage_error.to_string(),
+                    code: "VALIDATION_ERROR".to_string(),
+                    message: format!(
+                        "{} of {} entries in the batch are invalid; nothing was written",
+                        invalid_entries,
+                        validated.len()
+                    ),
                 },
-            };
-            HttpResponse::Conflict().json(error)
+                errors,
+            });
+        }
+    }
+
+    let mut results: Vec<Option<BatchUpsertResult>> = Vec::with_capacity(validated.len());
+    let mut bodies = Vec::new();
+    for (raw, result) in raw_keys.iter().zip(validated) {
+        match result {
+            Ok(request) => {
+                bodies.push(request);
+                results.push(None);
+            }
+            Err(errors) => results.push(Some(BatchUpsertResult {
+                key: raw.clone(),
+                status: BatchUpsertStatus::Error,
+                metadata: None,
+                error: errors.into_iter().next().map(|failure| ErrorDetail {
+                    code: failure.code,
+                    message: failure.message,
+                }),
+            })),
+        }
+    }
+
+    let written = upsert_all(storage.get_ref().as_ref(), bodies, query.dry_run).await;
+    let results = fill_results(raw_keys, results, written);
+
+    HttpResponse::Ok().json(BatchUpsertResponse { results })
+}
+
+#[utoipa::path(
+    post,
+    path = "/keys/batch-upsert",
+    request_body = BatchUpsertRequest,
+    responses(
+        (status = 207, description = "One result per entry, in request order, each of which succeeded or failed on its own", body = [BatchUpsertResult]),
+        (status = 413, description = "Request body exceeds the configured size limit", body = ErrorResponse),
+        (status = 422, description = "More than 1000 entries", body = ErrorResponse)
+    ),
+    tag = "Keys - Write Operations",
+    summary = "Upsert many key-value pairs, best effort",
+    description = "Upserts up to 1000 entries, each independently and exactly as `PUT /keys/{key}` would, and reports every outcome with 207 Multi-Status as an array of `{key, status, error?}` in request order. `status` is `created` or `updated` (with `metadata`) or `error`. An entry that fails validation (bad key, null or empty value, malformed `json`/`base64` value or TTL) or that the store rejects (`TYPE_MISMATCH`, `VALUE_TOO_LARGE`, ...) gets an `error` detail and does not stop the others. If a key appears twice the later entry wins. Unlike `POST /keys/batch` there is no `atomic` or `dry_run` mode, and the response is the bare array."
+)]
+#[post("/keys/batch-upsert")]
+pub async fn bulk_upsert(
+    body: web::Json<BatchUpsertRequest>,
+    storage: web::Data<Arc<dyn AsyncStorage>>,
+) -> impl Responder {
+    let entries = body.into_inner().entries;
+    if entries.len() > MAX_BATCH_KEYS {
+        return HttpResponse::UnprocessableEntity().json(batch_too_large(entries.len()));
+    }
+
+    let mut raw_keys = Vec::with_capacity(entries.len());
+    let mut results = Vec::with_capacity(entries.len());
+    let mut bodies = Vec::new();
+    for entry in entries {
+        let key = entry.key.clone();
+        match validation::entry(entry) {
+            Ok(request) => {
+                bodies.push(request);
+                results.push(None);
+            }
+            Err(errors) => {
+                let error = StorageError::from(errors[0].clone());
+                results.push(Some(upsert_result(key.clone(), Err(error))));
+            }
         }
+        raw_keys.push(key);
     }
+
+    let written = storage.upsert_many(bodies).await;
+    let results = fill_results(raw_keys, results, written);
+
+    HttpResponse::MultiStatus().json(results)
 }
 
 #[utoipa::path(
     get,
     path = "/keys",
+    params(
+        ListKeysQuery,
+        ("Accept" = Option<String>, Header, description = "`text/csv` to export every entry as CSV; anything else returns JSON", example = "text/csv")
+    ),
     responses(
-        (status = 200, description = "List of all keys in the store", body = Vec<String>, example = json!(["user-123", "config-prod", "session-abc"]))
+        (status = 200, description = "Array of key strings by default, an array of full entries (at most 1000) when `include=values` is set, a `values`/`missing` object when `keys` is given, a `keys`/`next_cursor` page when `limit` or `cursor` is given without a range, or one JSON string per line (`application/x-ndjson`) when `stream=true`, or `key,value,created_at,updated_at` rows (`text/csv`) when `Accept` prefers `text/csv`", body = KeyListResponse,
+            headers(("X-KV-Truncated" = String, description = "`true` when an `include=values` listing stopped at 1000 entries and more entries match")),
+            example = json!(["user-123", "config-prod", "session-abc"])),
+        (status = 400, description = "Invalid query parameter, such as a malformed timestamp, an invalid `pattern` (`INVALID_PATTERN`), an invalid key in `keys`, more than 1000 keys, a range whose start is after its end, or an invalid cursor", body = ErrorResponse),
+        (status = 410, description = "The `snapshot` token is unknown or has expired; restart the listing with `consistent=true`", body = ErrorResponse)
     ),
     tag = "Keys - Read Operations",
     summary = "List all keys",
-    description = "Returns an array of all keys currently stored in the key-value store. Useful for discovering what data is available or for administrative purposes."
+    description = "Returns an array of all keys currently stored in the key-value store. Useful for discovering what data is available or for administrative purposes. Keys (and entries) are always returned in ascending byte-wise lexicographic order of the key string, so the ordering is stable across calls and safe to use for diffing and pagination cursors. Pass `include=values` to receive each entry's value and metadata in the same response instead of issuing one GET per key; because values can be large this variant returns at most 1000 entries, the first ones in key order. When more entries match, the response carries `X-KV-Truncated: true`; narrow the listing with `prefix` or `pattern`, or walk the keys with cursor pages, to see the rest. The listing can be narrowed with `prefix`, with a glob `pattern` such as `user-*-session` (`*` matches any run of characters, including none, and `?` exactly one; everything else matches itself, and the pattern must match the whole key), and with `updated_since` / `created_since` (RFC 3339, strictly greater than) for incremental syncs; invalid timestamps are rejected with 400, and so is a `pattern` that is empty, longer than 255 characters or holds a character no key can contain (400 `INVALID_PATTERN`). Unlike the other filters, `pattern` also applies to range queries and cursor pages, which then return up to `limit` matching keys and may read past many non-matching ones to fill a page. Passing `keys=a,b,c` instead fetches just those keys, returning their values in a map and listing absent keys under `missing` and keys the backend failed to read under `errors` (the GET equivalent of `POST /keys/batch-get`, friendlier to caches); the first invalid key rejects the request with 400 and the other listing parameters are ignored. Passing `start` and/or `end` returns the keys in the half-open range `[start, end)` in lexicographic order, at most `limit` (1000) of them; to fetch the next page, repeat the query with `start` set just past the last key returned. A `start` greater than `end` is rejected with 400 `INVALID_RANGE`. For walking the whole keyspace, pass `limit` (and no range) to get a page object `{keys, next_cursor}` holding at most `limit` (1000) keys; pass the opaque `next_cursor` back as `cursor` to fetch the following page, which costs the same regardless of how deep into the keyspace it is. `next_cursor` is omitted on the last page. Keys written or deleted between pages are reflected in later pages, so a key can be missed or seen twice if the keyspace changes mid-walk. To avoid that, add `consistent=true` to the first page request: the server then freezes the sorted key list and returns a `snapshot` token with each page, and passing `snapshot` (with `cursor`) on the following requests pages over exactly the keys that existed when the walk started. Snapshots live for 60 seconds from the first page; an unknown or expired token returns 410 `SNAPSHOT_EXPIRED`, and the client should restart the walk. Cursor pages cannot be combined with `prefix`, `updated_since`, `created_since` or `include` (400 `INVALID_QUERY`), and a cursor that was not issued by this API is rejected with 400 `INVALID_CURSOR`. For very large stores, `stream=true` returns every key as newline-delimited JSON (`application/x-ndjson`, one quoted key per line, in sorted order) without building the whole list in memory: the server reads 1000 keys at a time and writes each batch as it goes, holding no storage locks while the client consumes it. Like cursor pages, a stream reflects writes made while it is in progress. `stream=true` cannot be combined with any other listing parameter (400 `INVALID_QUERY`). To export the store as a spreadsheet, send `Accept: text/csv` (ranked ahead of `application/json` and `*/*`): the response streams the same way, 1000 entries at a time, as CSV with a `key,value,created_at,updated_at` header row and one row per entry, lines ending in CRLF. Values are written as `text/plain` GETs return them (structured values as compact JSON, binary values as base64), timestamps are RFC 3339, and any field holding a comma, a double quote or a line break is enclosed in double quotes with inner quotes doubled (RFC 4180). Keys deleted while the export runs are left out. Like `stream=true`, a CSV export cannot be combined with other listing parameters. When the server runs with `KV_CASE_INSENSITIVE`, keys are listed in their lowercased form and `prefix`, `pattern`, `start` and `end` are lowercased before matching."
 )]
 #[get("/keys")]
-pub async fn get_keys_list(storage: web::Data<Arc<dyn Storage + Send + Sync>>) -> impl Responder {
-    HttpResponse::Ok().json(storage.list_keys())
+pub async fn get_keys_list(
+    req: HttpRequest,
+    query: web::Query<ListKeysQuery>,
+    storage: web::Data<Arc<dyn AsyncStorage>>,
+    snapshots: web::Data<SnapshotCache>,
+) -> impl Responder {
+    let mut query = query.into_inner();
+    for text in [&mut query.prefix, &mut query.start, &mut query.end]
+        .into_iter()
+        .flatten()
+    {
+        *text = fold_case(text).into_owned();
+    }
+    let csv = prefers_csv(&req);
+
+    if query.stream == Some(true) || csv {
+        if query.keys.is_some()
+            || query.include.is_some()
+            || query.prefix.is_some()
+            || query.pattern.is_some()
+            || query.updated_since.is_some()
+            || query.created_since.is_some()
+            || query.start.is_some()
+            || query.end.is_some()
+            || query.limit.is_some()
+            || query.cursor.is_some()
+            || query.consistent.is_some()
+            || query.snapshot.is_some()
+        {
+            return HttpResponse::BadRequest().json(ErrorResponse {
+                error: ErrorDetail {
+                    code: "INVALID_QUERY".to_string(),
+                    message: "Streamed listings (stream=true or Accept: text/csv) cannot be combined with other listing parameters"
+                        .to_string(),
+                },
+            });
+        }
+        if csv {
+            return HttpResponse::Ok()
+                .insert_header((VARY, "Accept"))
+                .content_type("text/csv; charset=utf-8")
+                .streaming(stream_csv(storage.get_ref().clone()));
+        }
+        return HttpResponse::Ok()
+            .content_type("application/x-ndjson")
+            .streaming(stream_keys(storage.get_ref().clone()));
+    }
+
+    if let Some(raw_keys) = query.keys.as_deref() {
+        return match parse_key_csv(raw_keys) {
+            Ok(keys) => HttpResponse::Ok().json(KeyListResponse::Lookup(
+                lookup_many(storage.get_ref().as_ref(), keys).await,
+            )),
+            Err(error) => HttpResponse::BadRequest().json(error),
+        };
+    }
+
+    let pattern = match query.pattern.as_deref().map(KeyPattern::parse).transpose() {
+        Ok(pattern) => pattern,
+        Err(message) => {
+            return HttpResponse::BadRequest().json(ErrorResponse {
+                error: ErrorDetail {
+                    code: "INVALID_PATTERN".to_string(),
+                    message,
+                },
+            });
+        }
+    };
+
+    if query.start.is_some() || query.end.is_some() {
+        if let (Some(start), Some(end)) = (&query.start, &query.end)
+            && start > end
+        {
+            return HttpResponse::BadRequest().json(ErrorResponse {
+                error: ErrorDetail {
+                    code: "INVALID_RANGE".to_string(),
+                    message: format!("Range start '{}' is after end '{}'", start, end),
+                },
+            });
+        }
+
+        let start = query
+            .start
+            .as_deref()
+            .map_or(Bound::Unbounded, Bound::Included);
+        let end = query
+            .end
+            .as_deref()
+            .map_or(Bound::Unbounded, Bound::Excluded);
+        let limit = query
+            .limit
+            .unwrap_or(MAX_LISTED_ENTRIES)
+            .min(MAX_LISTED_ENTRIES);
+        let keys = match &pattern {
+            Some(pattern) => {
+                matching_range(storage.get_ref().as_ref(), start, end, pattern, limit).await
+            }
+            None => storage.list_range(start, end, limit).await,
+        };
+        return HttpResponse::Ok().json(KeyListResponse::Keys(keys));
+    }
+
+    let consistent = query.consistent == Some(true) || query.snapshot.is_some();
+    if query.limit.is_some() || query.cursor.is_some() || consistent {
+        if query.prefix.is_some()
+            || query.updated_since.is_some()
+            || query.created_since.is_some()
+            || query.include.is_some()
+        {
+            return HttpResponse::BadRequest().json(ErrorResponse {
+                error: ErrorDetail {
+                    code: "INVALID_QUERY".to_string(),
+                    message: "Cursor pagination cannot be combined with prefix, updated_since, created_since or include".to_string(),
+                },
+            });
+        }
+
+        let after = match query.cursor.as_deref().map(decode_cursor).transpose() {
+            Ok(after) => after,
+            Err(error) => return HttpResponse::BadRequest().json(error),
+        };
+        let limit = query
+            .limit
+            .unwrap_or(MAX_LISTED_ENTRIES)
+            .clamp(1, MAX_LISTED_ENTRIES);
+
+        let (mut keys, snapshot) = if consistent {
+            let (token, frozen) = match query.snapshot {
+                Some(token) => match snapshots.get(&token) {
+                    Some(frozen) => (token, frozen),
+                    None => {
+                        return HttpResponse::Gone().json(ErrorResponse {
+                            error: ErrorDetail {
+                                code: "SNAPSHOT_EXPIRED".to_string(),
+                                message: format!(
+                                    "Snapshot '{}' is unknown or has expired; restart the listing with consistent=true",
+                                    token
+                                ),
+                            },
+                        });
+                    }
+                },
+                None => {
+                    let mut keys = storage.list_keys().await;
+                    keys.sort();
+                    snapshots.create(keys)
+                }
+            };
+            let start = after.as_deref().map_or(0, |after| {
+                frozen.partition_point(|key| key.as_str() <= after)
+            });
+            let keys = frozen[start..]
+                .iter()
+                .filter(|key| {
+                    pattern
+                        .as_ref()
+                        .is_none_or(|pattern| pattern.matches(key.as_str()))
+                })
+                .take(limit + 1)
+                .cloned()
+                .collect();
+            (keys, Some(token))
+        } else {
+            let keys = match &pattern {
+                Some(pattern) => {
+                    let start = after.as_deref().map_or(Bound::Unbounded, Bound::Excluded);
+                    matching_range(
+                        storage.get_ref().as_ref(),
+                        start,
+                        Bound::Unbounded,
+                        pattern,
+                        limit + 1,
+                    )
+                    .await
+                }
+                None => storage.list_keys_after(after.as_deref(), limit + 1).await,
+            };
+            (keys, None)
+        };
+        let next_cursor = if keys.len() > limit {
+            keys.truncate(limit);
+            keys.last().map(encode_cursor)
+        } else {
+            None
+        };
+        return HttpResponse::Ok().json(KeyListResponse::Page(KeyPageResponse {
+            keys,
+            next_cursor,
+            snapshot,
+        }));
+    }
+
+    let filter = KeyFilter {
+        prefix: query.prefix,
+        pattern,
+        updated_since: query.updated_since,
+        created_since: query.created_since,
+    };
+
+    let response = match query.include {
+        Some(ListInclude::Values) => {
+            // One entry past the cap tells a full listing from a cut one.
+            let mut entries = storage.list_entries(&filter, MAX_LISTED_ENTRIES + 1).await;
+            if entries.len() > MAX_LISTED_ENTRIES {
+                entries.truncate(MAX_LISTED_ENTRIES);
+                return HttpResponse::Ok()
+                    .insert_header((TRUNCATED_HEADER, "true"))
+                    .json(KeyListResponse::Entries(entries));
+            }
+            KeyListResponse::Entries(entries)
+        }
+        None if filter.is_empty() => KeyListResponse::Keys(storage.list_keys().await),
+        None => KeyListResponse::Keys(storage.scan_keys(&filter).await),
+    };
+    HttpResponse::Ok().json(response)
 }
 
 #[utoipa::path(
     put,
     path = "/keys/{key}",
     params(
-        ("key" = String, Path, description = "Unique key identifier", example = "user-123")
+        ("key" = String, Path, description = "Unique key identifier", example = "user-123"),
+        ("If-Match" = Option<String>, Header, description = "ETag or `metadata.version` the client last read; the write only happens if the stored entry still has it. `*` requires only that the key exists", example = "\"3b9e6a1b...\""),
+        ("If-None-Match" = Option<String>, Header, description = "`*` makes the PUT create-only: it fails with 412 if the key already exists. Other values are ignored", example = "*"),
+        ("If-Unmodified-Since" = Option<String>, Header, description = "HTTP date; the write only happens if the key was not modified after it. Ignored when `If-Match` or `If-None-Match: *` is sent, or when the date is malformed", example = "Tue, 15 Oct 2024 08:12:31 GMT")
     ),
     request_body = UpdateKVRequest,
     responses(
-        (status = 200, description = "Key-value pair updated or created (idempotent upsert operation)", body = KeyValueResponse),
-        (status = 400, description = "Invalid key format or empty value", body = ErrorResponse)
+        (status = 200, description = "Existing key-value pair updated", body = KeyValueResponse,
+            headers(("ETag" = String, description = "ETag of the value just written"))),
+        (status = 201, description = "Key did not exist (or had expired) and was created", body = KeyValueResponse,
+            headers(
+                ("ETag" = String, description = "ETag of the value just written"),
+                ("Location" = String, description = "Path of the created key, `/keys/{key}`")
+            )),
+        (status = 400, description = "A field failed validation, with the same body `POST /keys` answers with (naming the `field` and `reason`): an invalid key in the path (`INVALID_KEY`), an empty or null value, a `json` or `number` value that does not parse, a `base64` value that does not decode, a value over the `KV_MAX_VALUE_BYTES_BY_PREFIX` limit for its key (`VALUE_TOO_LARGE`), or an invalid `ttl_seconds`. Also a value that does not match the key's stored type (`TYPE_MISMATCH`), or both `If-Match` and `If-None-Match: *`", body = ValidationErrorResponse),
+        (status = 412, description = "If-Match did not match the stored value's ETag, or the key does not exist, and the body carries the current version; or `If-None-Match: *` was sent and the key already exists (`KEY_ALREADY_EXISTS`); or the key was modified after `If-Unmodified-Since` (`MODIFIED_SINCE`, with `current_updated_at` and a `Last-Modified` header)", body = VersionConflictResponse),
+        (status = 507, description = "Storing the new value would exceed the configured memory budget (`KV_MAX_BYTES`)", body = ErrorResponse),
+        (status = 413, description = "Request body exceeds `KV_MAX_BODY_BYTES`, or the value exceeds `KV_MAX_VALUE_BYTES` (`VALUE_TOO_LARGE`)", body = ErrorResponse),
+        (status = 422, description = "The key has a schema attached (see `PUT /keys/{key}/schema`) and the new value does not satisfy it (`SCHEMA_VIOLATION`)", body = SchemaViolationResponse)
     ),
     tag = "Keys - Write Operations",
     summary = "Update or create key-value pair",
-    description = "Updates an existing key-value pair or creates it if it doesn't exist (upsert operation). This is an idempotent operation. If updating, preserves the original created_at timestamp and updates the updated_at timestamp."
+    description = "Updates an existing key-value pair or creates it if it doesn't exist (upsert operation). This is an idempotent operation. If updating, preserves the original created_at timestamp and updates the updated_at timestamp. Responds 201 with a `Location` header naming the key's path when the key was newly created, and 200 when an existing key was updated, so a client can tell whether its PUT overwrote someone else's data. Every write increments `metadata.version` (starting at 1 when the key is created, and again at 1 if it is deleted or expires and is recreated). For safe read-modify-write, send the `ETag` from a previous GET, or the `metadata.version` as a quoted tag such as `\"3\"`, in `If-Match`: the comparison and the write happen atomically in the storage layer, and if another client changed the value in between the request fails with 412 `VERSION_MISMATCH` and `current_version` set to the value's current ETag (omitted when the key no longer exists), so the client can re-read and retry. Conditional PUTs never create keys. Conversely, `If-None-Match: *` makes the PUT create-only, like `POST /keys` but with the key in the URL: if the key exists (and has not expired) nothing is written and the response is 412 `KEY_ALREADY_EXISTS`, otherwise the key is created with 201. The existence check and the creation are one atomic step, so of several clients racing to create the same key exactly one gets 201. `If-None-Match` values other than `*` are ignored, and combining `If-None-Match: *` with `If-Match` is rejected with 400 `VALIDATION_ERROR`, since no key can satisfy both. Clients that track `updated_at` rather than versions can send `If-Unmodified-Since` with the `Last-Modified` date of their last read instead: if the key was written after that date nothing is written and the response is 412 `MODIFIED_SINCE` with the key's `current_updated_at`, so the client can re-read. The check and the write are one atomic step. HTTP dates have whole-second precision, so the comparison uses the second `updated_at` falls in: a write later in the same second as the date is not detected. A missing key has no modification date, so it is created as usual; a malformed date is ignored, as RFC 9110 requires, and the header is ignored alongside `If-Match` or `If-None-Match: *`. As with POST, `value_type: json` or `number` validates the value (400 `INVALID_JSON_VALUE` or `INVALID_NUMBER_VALUE` otherwise) and records the type in metadata; omitting it stores an opaque `text` value. Once a key exists its type is fixed: a PUT that omits `value_type` keeps the stored type and the new value must conform to it, so writing `abc` to a `number` key fails with 400 `TYPE_MISMATCH`, and so does naming a different type. Delete the key to change its type. `encoding: base64` is accepted and validated the same way as on POST. Structured values (objects, arrays, numbers, booleans) are accepted too, and `null` or an empty string is rejected with 400 `NULL_VALUE` or `EMPTY_VALUE` (whitespace-only strings too under `KV_REJECT_BLANK_VALUES`). Every PUT sets the expiry afresh, exactly like POST: `ttl_seconds` if given, `-1` for none, otherwise `KV_DEFAULT_TTL_SECONDS`, so rewriting a key also refreshes its default TTL."
 )]
 #[put("/keys/{key}")]
 pub async fn update_kv(
+    req: HttpRequest,
     path: web::Path<Key>,
     body: web::Json<UpdateKVRequest>,
-    storage: web::Data<Arc<dyn Storage + Send + Sync>>,
+    storage: web::Data<Arc<dyn AsyncStorage>>,
 ) -> impl Responder {
     let key = path.into_inner();
     let update_request = body.into_inner();
 
     let request = CreateKVRequest {
         key,
-        value: update_request.value,
+        value: update_request.value.into(),
+        value_type: update_request.value_type,
+        encoding: update_request.encoding,
+        ttl_seconds: update_request.ttl_seconds,
     };
+    if let Err(error) = validation::write(&request) {
+        return error.error_response();
+    }
 
-    let response = storage.upsert(request);
-    HttpResponse::Ok().json(response)
+    let create_only = matches!(req.get_header::<IfNoneMatch>(), Some(IfNoneMatch::Any));
+    let written = match req.get_header::<IfMatch>() {
+        None if create_only => storage
+            .insert(request)
+            .await
+            .map(|response| (response, UpsertOutcome::Created)),
+        None => match if_unmodified_since(&req) {
+            Some(since) => storage.upsert_if_unmodified(request, since).await,
+            None => storage.upsert(request).await,
+        },
+        Some(_) if create_only => {
+            return HttpResponse::BadRequest().json(ErrorResponse {
+                error: ErrorDetail {
+                    code: "VALIDATION_ERROR".to_string(),
+                    message: "If-Match and If-None-Match: * cannot both hold; send only one"
+                        .to_string(),
+                },
+            });
+        }
+        Some(if_match) => {
+            let expected = match if_match {
+                IfMatch::Any => VersionPrecondition::Exists,
+                IfMatch::Items(tags) => VersionPrecondition::OneOf(
+                    tags.into_iter()
+                        .filter(|tag| !tag.weak)
+                        .map(|tag| tag.tag().to_string())
+                        .collect(),
+                ),
+            };
+            storage
+                .upsert_if_version(request, &expected)
+                .await
+                .map(|response| (response, UpsertOutcome::Updated))
+        }
+    };
+
+    match written {
+        Ok((response, outcome)) => {
+            let mut builder = match outcome {
+                UpsertOutcome::Created => {
+                    let mut builder = HttpResponse::Created();
+                    builder.insert_header((LOCATION, format!("/keys/{}", response.key.as_str())));
+                    builder
+                }
+                UpsertOutcome::Updated => HttpResponse::Ok(),
+            };
+            builder
+                .insert_header(ETag(EntityTag::new_strong(response.value.etag())))
+                .json(response)
+        }
+        Err(storage_error) => {
+            let error = ErrorDetail::from(&storage_error);
+            match storage_error {
+                StorageError::VersionMismatch { current, .. } => {
+                    let mut builder = HttpResponse::PreconditionFailed();
+                    if let Some(current) = &current {
+                        builder.insert_header(ETag(EntityTag::new_strong(current.clone())));
+                    }
+                    builder.json(VersionConflictResponse {
+                        error,
+                        current_version: current,
+                        current_updated_at: None,
+                    })
+                }
+                StorageError::KeyAlreadyExists(_) => {
+                    HttpResponse::PreconditionFailed().json(VersionConflictResponse {
+                        error,
+                        current_version: None,
+                        current_updated_at: None,
+                    })
+                }
+                StorageError::ModifiedSince { updated_at, .. } => {
+                    modified_since_response(error, updated_at)
+                }
+                storage_error => storage_error.error_response(),
+            }
+        }
+    }
 }
 
 #[utoipa::path(
     delete,
     path = "/keys/{key}",
     params(
-        ("key" = String, Path, description = "Unique key identifier", example = "user-123")
+        ("key" = String, Path, description = "Unique key identifier", example = "user-123"),
+        ("If-Match-Value" = Option<String>, Header, description = "Only delete if the current value equals this text", example = "pending"),
+        ("If-Unmodified-Since" = Option<String>, Header, description = "HTTP date; only delete if the key was not modified after it. Ignored alongside `If-Match-Value` / `if_value`, or when malformed", example = "Tue, 15 Oct 2024 08:12:31 GMT"),
+        ("Prefer" = Option<String>, Header, description = "`return=minimal` answers 204 No Content, like `quiet=true`", example = "return=minimal"),
+        DeleteQuery
     ),
     responses(
-        (status = 200, description = "Key-value pair deleted successfully, returns the deleted value", body = ValueResponse),
-        (status = 404, description = "Key not found - nothing to delete", body = ErrorResponse)
+        (status = 200, description = "Key-value pair deleted successfully, returns the deleted value, or `{\"deleted\": true}` with `return=minimal`",
+            content(
+                (ValueResponse = "application/json"),
+                (DeleteResponse = "application/json")
+            )),
+        (status = 204, description = "Key deleted and `quiet=true` or `Prefer: return=minimal` was sent; no body",
+            headers(("Preference-Applied" = String, description = "`return=minimal` when the `Prefer` header was honored"))),
+        (status = 400, description = "`If-Match-Value` is not valid UTF-8", body = ErrorResponse),
+        (status = 404, description = "Key not found - nothing to delete", body = ErrorResponse),
+        (status = 412, description = "The current value differs from `If-Match-Value` / `if_value`, or the key was modified after `If-Unmodified-Since` (`MODIFIED_SINCE`, with `current_updated_at` and a `Last-Modified` header); nothing was deleted", body = VersionConflictResponse)
     ),
     tag = "Keys - Write Operations",
     summary = "Delete key-value pair",
-    description = "Removes a key-value pair from the store and returns the deleted value with its metadata. Returns 404 if the key does not exist."
+    description = "Removes a key-value pair from the store and returns the deleted value with its metadata. Returns 404 if the key does not exist. Clients that don't need the old value can pass `return=minimal` to get just `{\"deleted\": true}`, which avoids sending large values back over the wire. To skip the body altogether, pass `quiet=true` or send `Prefer: return=minimal` (RFC 7240): a successful delete then answers 204 No Content, the deleted value is never copied out of the store, and `Preference-Applied: return=minimal` confirms the header was honored. Either one overrides `return`. Failures are unaffected, so a missing key is still 404 with an `ErrorResponse` body. To avoid deleting a key that changed since it was read, send the value you expect in the `If-Match-Value` header or the `if_value` query parameter (the header wins when both are present): the comparison and the removal happen atomically, and if the current value differs the key is left alone and the response is 412 `VERSION_MISMATCH` with the current value's ETag in `current_version`. String values are compared as-is, structured values as compact JSON. Alternatively, `If-Unmodified-Since` deletes only if the key was not written after the given HTTP date (compared to the second), atomically as well, and otherwise answers 412 `MODIFIED_SINCE` with the key's `current_updated_at`; a malformed date is ignored."
 )]
 #[delete("/keys/{key}")]
 pub async fn delete_kv(
+    req: HttpRequest,
     key: web::Path<Key>,
-    storage: web::Data<Arc<dyn Storage + Send + Sync>>,
+    query: web::Query<DeleteQuery>,
+    storage: web::Data<Arc<dyn AsyncStorage>>,
 ) -> impl Responder {
     let key = key.into_inner();
+    let query = query.into_inner();
+
+    let expected = match req.headers().get(IF_MATCH_VALUE_HEADER) {
+        None => query.if_value,
+        Some(value) => match std::str::from_utf8(value.as_bytes()) {
+            Ok(value) => Some(value.to_string()),
+            Err(_) => {
+                return HttpResponse::BadRequest().json(ErrorResponse {
+                    error: ErrorDetail {
+                        code: "VALIDATION_ERROR".to_string(),
+                        message: format!("{} must be valid UTF-8", IF_MATCH_VALUE_HEADER),
+                    },
+                });
+            }
+        },
+    };
+
+    let prefer_minimal = prefers_minimal_return(&req);
+    let quiet = query.quiet || prefer_minimal;
+    let preference = if quiet {
+        ReturnPreference::Minimal
+    } else {
+        query.return_preference
+    };
+    let minimal = || {
+        if !quiet {
+            return HttpResponse::Ok().json(DeleteResponse { deleted: true });
+        }
+        let mut builder = HttpResponse::NoContent();
+        if prefer_minimal {
+            builder.insert_header((PREFERENCE_APPLIED_HEADER, "return=minimal"));
+        }
+        builder.finish()
+    };
+    let respond = |value_response: ValueResponse| match preference {
+        ReturnPreference::Representation => HttpResponse::Ok().json(value_response),
+        ReturnPreference::Minimal => minimal(),
+    };
+    let deleted = match (expected, if_unmodified_since(&req), preference) {
+        (Some(expected), _, _) => storage.delete_if(key, &expected).await.map(respond),
+        (None, Some(since), _) => storage.delete_if_unmodified(key, since).await.map(respond),
+        (None, None, ReturnPreference::Representation) => storage
+            .delete(key)
+            .await
+            .map(|value_response| HttpResponse::Ok().json(value_response)),
+        (None, None, ReturnPreference::Minimal) => {
+            storage.delete_silent(key).await.map(|()| minimal())
+        }
+    };
 
-    match storage.delete(key) {
-        Ok(value_response) => HttpResponse::Ok().json(value_response),
+    match deleted {
+        Ok(response) => response,
         Err(storage_error) => {
-            let error = ErrorResponse {
+            let error = ErrorDetail::from(&storage_error);
+            match storage_error {
+                StorageError::VersionMismatch { current, .. } => {
+                    let mut builder = HttpResponse::PreconditionFailed();
+                    if let Some(current) = &current {
+                        builder.insert_header(ETag(EntityTag::new_strong(current.clone())));
+                    }
+                    builder.json(VersionConflictResponse {
+                        error,
+                        current_version: current,
+                        current_updated_at: None,
+                    })
+                }
+                StorageError::ModifiedSince { updated_at, .. } => {
+                    modified_since_response(error, updated_at)
+                }
+                storage_error => storage_error.error_response(),
+            }
+        }
+    }
+}
+
+/// Whether a `Prefer` header asks for `return=minimal` (RFC 7240). Other
+/// preferences and their parameters are ignored.
+fn prefers_minimal_return(req: &HttpRequest) -> bool {
+    req.headers()
+        .get_all(PREFER_HEADER)
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|preference| preference.split(';').next())
+        .any(|preference| {
+            preference
+                .trim()
+                .replace(' ', "")
+                .eq_ignore_ascii_case("return=minimal")
+        })
+}
+
+/// The `If-Unmodified-Since` date, if the request carries a valid one. A
+/// malformed date is ignored, as RFC 9110 asks, rather than rejected.
+fn if_unmodified_since(req: &HttpRequest) -> Option<DateTime<Utc>> {
+    req.get_header::<IfUnmodifiedSince>()
+        .map(|IfUnmodifiedSince(since)| DateTime::<Utc>::from(SystemTime::from(since)))
+}
+
+fn modified_since_response(error: ErrorDetail, updated_at: DateTime<Utc>) -> HttpResponse {
+    HttpResponse::PreconditionFailed()
+        .insert_header(LastModified(HttpDate::from(SystemTime::from(updated_at))))
+        .json(VersionConflictResponse {
+            error,
+            current_version: None,
+            current_updated_at: Some(updated_at),
+        })
+}
+
+#[utoipa::path(
+    patch,
+    path = "/keys/{key}",
+    params(
+        ("key" = String, Path, description = "Unique key identifier", example = "user-123")
+    ),
+    request_body(content = Object, content_type = "application/merge-patch+json", description = "RFC 7386 merge patch; `null` members remove fields", example = json!({"profile": {"nickname": null}, "theme": "dark"})),
+    responses(
+        (status = 200, description = "The patch was merged; returns the full updated entry", body = KeyValueResponse,
+            headers(("ETag" = String, description = "ETag of the value just written"))),
+        (status = 400, description = "The body is not valid JSON", body = ErrorResponse),
+        (status = 404, description = "Key not found - PATCH never creates keys", body = ErrorResponse),
+        (status = 415, description = "Content-Type is not `application/merge-patch+json` or `application/json`", body = ErrorResponse),
+        (status = 409, description = "The stored value is not JSON: a `text` or `base64` string (`VALUE_NOT_JSON`) or a blob (`BINARY_VALUE`)", body = ErrorResponse),
+        (status = 422, description = "The body is not a JSON object, the stored value is JSON but not an object (`VALUE_NOT_OBJECT`), or the patched value does not satisfy the key's schema (`SCHEMA_VIOLATION`, with `violations`)", body = SchemaViolationResponse),
+        (status = 413, description = "The patch exceeds `KV_MAX_BODY_BYTES`, or the patched value exceeds `KV_MAX_VALUE_BYTES` (`VALUE_TOO_LARGE`)", body = ErrorResponse),
+        (status = 507, description = "The patched value would exceed the configured memory budget (`KV_MAX_BYTES`)", body = ErrorResponse)
+    ),
+    tag = "Keys - Write Operations",
+    summary = "Merge-patch a JSON value",
+    description = "Applies a JSON Merge Patch (RFC 7386) to the stored document and returns the whole updated entry, so a single field can be changed without the GET, modify, PUT round trip. Members of the patch replace the matching members of the document, nested objects are merged recursively, and members set to `null` are removed; arrays are replaced, not merged. The patch is merged while the entry is locked, so concurrent patches that touch different fields all survive. The stored value must be a JSON object: either a structured value or a string with `value_type: json`, which stays a string holding the patched document. A stored value that is not JSON at all - a `text` or `base64` string, or a blob - is rejected with 409 `VALUE_NOT_JSON` (`BINARY_VALUE` for blobs), and JSON that is not an object (a number, array or boolean) with 422 `VALUE_NOT_OBJECT`. Missing keys are 404 - PATCH never creates keys. Send the patch with `Content-Type: application/merge-patch+json` (`application/json` is accepted too); the patch itself must be a JSON object. Like any write it bumps `metadata.version` and resets the expiry to `KV_DEFAULT_TTL_SECONDS` (none when unset)."
+)]
+#[patch("/keys/{key}")]
+pub async fn merge_patch_kv(
+    req: HttpRequest,
+    key: web::Path<Key>,
+    body: Result<web::Bytes, actix_web::Error>,
+    storage: web::Data<Arc<dyn AsyncStorage>>,
+) -> impl Responder {
+    let body = match body {
+        Ok(body) => body,
+        Err(err) => return payload_error_response(&err),
+    };
+    if !matches!(
+        req.content_type(),
+        MERGE_PATCH_CONTENT_TYPE | "application/json"
+    ) {
+        return HttpResponse::UnsupportedMediaType().json(ErrorResponse {
+            error: ErrorDetail {
+                code: "UNSUPPORTED_MEDIA_TYPE".to_string(),
+                message: format!(
+                    "PATCH expects Content-Type {} or application/json",
+                    MERGE_PATCH_CONTENT_TYPE
+                ),
+            },
+        });
+    }
+    let patch = match serde_json::from_slice::<serde_json::Value>(&body) {
+        Ok(patch) if patch.is_object() => patch,
+        Ok(_) => {
+            return HttpResponse::UnprocessableEntity().json(ErrorResponse {
+                error: ErrorDetail {
+                    code: "VALIDATION_ERROR".to_string(),
+                    message: "A merge patch must be a JSON object".to_string(),
+                },
+            });
+        }
+        Err(err) => {
+            return HttpResponse::BadRequest().json(ErrorResponse {
                 error: ErrorDetail {
-                    code: storage_error.error_code().to_string(),
-                    message: storage_error.to_string(),
+                    code: "MALFORMED_JSON".to_string(),
+                    message: format!("Request body is not valid JSON: {}", err),
                 },
+            });
+        }
+    };
+
+    match storage.merge_patch(key.into_inner(), &patch).await {
+        Ok(response) => HttpResponse::Ok()
+            .insert_header(ETag(EntityTag::new_strong(response.value.etag())))
+            .json(response),
+        Err(storage_error) => storage_error.error_response(),
+    }
+}
+
+#[utoipa::path(
+    put,
+    path = "/keys/{key}/blob",
+    params(
+        ("key" = String, Path, description = "Unique key identifier", example = "avatar-42"),
+        ("Content-Type" = Option<String>, Header, description = "Stored with the bytes and sent back on download; `application/octet-stream` when omitted", example = "image/png")
+    ),
+    request_body(content = Vec<u8>, description = "The bytes to store, sent as the raw request body", content_type = "application/octet-stream"),
+    responses(
+        (status = 201, description = "Blob stored under a new key; returns the key and its metadata", body = KeyMetadataResponse,
+            headers(("ETag" = String, description = "SHA-256 of the stored bytes"))),
+        (status = 200, description = "Existing key overwritten with the blob", body = KeyMetadataResponse,
+            headers(("ETag" = String, description = "SHA-256 of the stored bytes"))),
+        (status = 413, description = "Body exceeds `KV_MAX_BODY_BYTES` (`PAYLOAD_TOO_LARGE`) or `KV_MAX_VALUE_BYTES` (`VALUE_TOO_LARGE`)", body = ErrorResponse),
+        (status = 507, description = "Storing the blob would exceed the configured memory budget (`KV_MAX_BYTES`)", body = ErrorResponse)
+    ),
+    tag = "Keys - Write Operations",
+    summary = "Upload a binary value",
+    description = "Stores the raw request body under the key, byte for byte, without any JSON or base64 wrapping, so images or protobuf payloads can be uploaded with `curl --data-binary`. The request's `Content-Type` is recorded in `metadata.content_type` and `metadata.size_bytes` holds the length in bytes; read the bytes back with `GET /keys/{key}/blob`. Like PUT it creates the key (201) or overwrites it (200), bumping `metadata.version` and applying `KV_DEFAULT_TTL_SECONDS`. The body is limited by `KV_MAX_BODY_BYTES` and the blob counts against `KV_MAX_BYTES` like any other value. JSON endpoints refuse to render a blob as JSON: `GET /keys/{key}` and `GET /keys/{key}/json` answer 409 `BINARY_VALUE`, and batch reads report that code for the key. Listings and history show the blob base64-encoded, with `metadata.encoding` set to `base64`."
+)]
+#[put("/keys/{key}/blob")]
+pub async fn put_blob(
+    req: HttpRequest,
+    key: web::Path<Key>,
+    body: Result<web::Bytes, actix_web::Error>,
+    storage: web::Data<Arc<dyn AsyncStorage>>,
+) -> impl Responder {
+    let body = match body {
+        Ok(body) => body,
+        Err(err) => return payload_error_response(&err),
+    };
+    let content_type = req
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .filter(|value| !value.is_empty())
+        .unwrap_or(DEFAULT_BLOB_CONTENT_TYPE)
+        .to_string();
+    let request = CreateKVRequest {
+        key: key.into_inner(),
+        value: StoredValue::Binary {
+            bytes: body.to_vec(),
+            content_type,
+        },
+        value_type: ValueType::Text,
+        encoding: ValueEncoding::Base64,
+        ttl_seconds: None,
+    };
+
+    match storage.upsert(request).await {
+        Ok((response, outcome)) => {
+            let mut builder = match outcome {
+                UpsertOutcome::Created => HttpResponse::Created(),
+                UpsertOutcome::Updated => HttpResponse::Ok(),
             };
-            HttpResponse::NotFound().json(error)
+            builder
+                .insert_header(ETag(EntityTag::new_strong(response.value.etag())))
+                .json(KeyMetadataResponse {
+                    key: response.key,
+                    metadata: response.metadata,
+                })
         }
+        Err(storage_error) => storage_error.error_response(),
     }
 }
 
-pub fn configure(cfg: &mut web::ServiceConfig) {
-    cfg.service(create_kv)
-        .service(get_keys_list)
-        .service(update_kv)
-        .service(delete_kv);
+#[utoipa::path(
+    put,
+    path = "/keys/{key}/expire",
+    params(
+        ("key" = String, Path, description = "Unique key identifier", example = "user-123")
+    ),
+    request_body = ExpireRequest,
+    responses(
+        (status = 200, description = "Expiry set; returns the new time to live", body = TtlResponse),
+        (status = 404, description = "Key not found - nothing to expire", body = ErrorResponse),
+        (status = 422, description = "TTL is malformed or exceeds ten years", body = ErrorResponse)
+    ),
+    tag = "Keys - Write Operations",
+    summary = "Set or refresh a key's time to live",
+    description = "Mirrors the Redis EXPIRE command. Sets the key to expire `ttl_seconds` from now without changing its value or `updated_at`, replacing any previous expiry. A TTL of 0 expires the key immediately. Any later write to the key replaces the expiry with the write's own `ttl_seconds`, or with `KV_DEFAULT_TTL_SECONDS` when the write does not set one. Expired keys are treated as missing everywhere and reclaimed in the background."
+)]
+#[put("/keys/{key}/expire")]
+pub async fn expire_kv(
+    key: web::Path<Key>,
+    body: web::Json<ExpireRequest>,
+    storage: web::Data<Arc<dyn AsyncStorage>>,
+) -> impl Responder {
+    let ttl_seconds = body.into_inner().ttl_seconds;
+    if ttl_seconds > MAX_TTL_SECONDS {
+        return HttpResponse::UnprocessableEntity().json(ErrorResponse {
+            error: ErrorDetail {
+                code: "VALIDATION_ERROR".to_string(),
+                message: format!("ttl_seconds must not exceed {}", MAX_TTL_SECONDS),
+            },
+        });
+    }
+
+    let ttl = Duration::from_secs(ttl_seconds);
+    match storage.set_ttl(&key, ttl).await {
+        Ok(_) => HttpResponse::Ok().json(TtlResponse::from_remaining(Some(ttl))),
+        Err(storage_error) => storage_error.error_response(),
+    }
+}
+
+#[utoipa::path(
+    put,
+    path = "/keys/{key}/schema",
+    params(
+        ("key" = String, Path, description = "Unique key identifier", example = "config-prod")
+    ),
+    request_body(content = Object, description = "A JSON Schema document (draft 4 to 2020-12; 2020-12 unless `$schema` says otherwise)", example = json!({"type": "object", "required": ["port"], "properties": {"port": {"type": "integer", "minimum": 1, "maximum": 65535}}})),
+    responses(
+        (status = 200, description = "Schema attached; returns the key and its metadata, with the schema under `schema`", body = KeyMetadataResponse),
+        (status = 404, description = "Key not found - a schema can only be attached to an existing key", body = ErrorResponse),
+        (status = 409, description = "The key's value is not JSON: a base64 string (`VALUE_NOT_JSON`), a blob (`BINARY_VALUE`) or a hash, list or set (`WRONG_TYPE`)", body = ErrorResponse),
+        (status = 422, description = "The body is not a valid JSON Schema (`INVALID_SCHEMA`), or the current value does not satisfy it (`SCHEMA_VIOLATION`, where `violations` lists each problem)", body = SchemaViolationResponse)
+    ),
+    tag = "Keys - Write Operations",
+    summary = "Attach a JSON Schema to a key",
+    description = "Attaches a JSON Schema to an existing key so the server refuses malformed updates, which suits configuration documents. From then on every write that replaces the value - PUT, PATCH, batch upserts, CSV imports, transactions, restores - is validated against the schema first and rejected with 422 `SCHEMA_VIOLATION` when it does not match; the response's `violations` array gives the JSON Pointer `path` of each offending part of the value (empty for the value as a whole) and a `message`, up to 20 of them. The value is validated as the JSON that GET returns, so plain text is a JSON string, except that a string stored with `value_type` `json` (or `number` or `counter`) is parsed first and validated as the document it holds. Values that hold bytes rather than JSON cannot have a schema: base64 strings (409 `VALUE_NOT_JSON`), blobs (409 `BINARY_VALUE`) and hashes, lists and sets (409 `WRONG_TYPE`). The current value is validated when the schema is attached, and the schema is rejected with 422 if it does not match. Attaching a schema replaces any previous one and does not change the value, its `version` or `updated_at`. The schema is listed as `metadata.schema` and can be read back from `GET /keys/{key}/schema`. It lives with the key: deleting the key or letting it expire removes it, and a key created again starts without one. Remote `$ref`s are not fetched, so schemas must be self-contained."
+)]
+#[put("/keys/{key}/schema")]
+pub async fn put_schema(
+    key: web::Path<Key>,
+    body: web::Json<serde_json::Value>,
+    storage: web::Data<Arc<dyn AsyncStorage>>,
+) -> impl Responder {
+    let key = key.into_inner();
+    let schema = body.into_inner();
+    if let Err(message) = json_schema::compile(&schema) {
+        return HttpResponse::UnprocessableEntity().json(ErrorResponse {
+            error: ErrorDetail {
+                code: "INVALID_SCHEMA".to_string(),
+                message: format!("The body is not a valid JSON Schema: {}", message),
+            },
+        });
+    }
+
+    match storage.set_schema(&key, Some(schema)).await {
+        Ok(metadata) => HttpResponse::Ok().json(KeyMetadataResponse { key, metadata }),
+        Err(storage_error) => storage_error.error_response(),
+    }
+}
+
+#[utoipa::path(
+    delete,
+    path = "/keys/{key}/schema",
+    params(
+        ("key" = String, Path, description = "Unique key identifier", example = "config-prod")
+    ),
+    responses(
+        (status = 200, description = "Schema detached (or there was none); returns the key and its metadata", body = KeyMetadataResponse),
+        (status = 404, description = "Key not found", body = ErrorResponse)
+    ),
+    tag = "Keys - Write Operations",
+    summary = "Detach a key's JSON Schema",
+    description = "Removes the JSON Schema attached to the key, so later writes are no longer validated. The value is left as it is. Detaching from a key without a schema succeeds and changes nothing."
+)]
+#[delete("/keys/{key}/schema")]
+pub async fn delete_schema(
+    key: web::Path<Key>,
+    storage: web::Data<Arc<dyn AsyncStorage>>,
+) -> impl Responder {
+    let key = key.into_inner();
+    match storage.set_schema(&key, None).await {
+        Ok(metadata) => HttpResponse::Ok().json(KeyMetadataResponse { key, metadata }),
+        Err(storage_error) => storage_error.error_response(),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/keys/{key}/restore",
+    params(
+        ("key" = String, Path, description = "Unique key identifier", example = "user-123")
+    ),
+    request_body = RestoreRequest,
+    responses(
+        (status = 200, description = "The restored value, written as a new version", body = KeyValueResponse,
+            headers(
+                ("ETag" = String, description = "Strong validator of the restored value")
+            )),
+        (status = 404, description = "Key not found (`KEY_NOT_FOUND`) or no retained version matches (`VERSION_NOT_FOUND`)", body = ErrorResponse),
+        (status = 422, description = "Neither or both of `version` and `at` were given", body = ErrorResponse),
+        (status = 507, description = "Restoring the value would exceed the configured memory budget (`KV_MAX_BYTES`)", body = ErrorResponse)
+    ),
+    tag = "Keys - Write Operations",
+    summary = "Restore a previous value",
+    description = "Sets the key back to one of its retained values (see `GET /keys/{key}/history`). Pass either `version`, matching the `metadata.version` of the value to bring back, or `at`, an RFC 3339 instant, to bring back the value the key held at that moment. The restore is an ordinary write: the value and its `value_type` and `encoding` are copied into a new version, the value it replaces goes into the history, and `created_at` is kept. Returns 404 `VERSION_NOT_FOUND` when the version has been dropped from the history (or history is disabled with `KV_HISTORY_LIMIT=0`), or when the key did not exist yet at `at`."
+)]
+#[post("/keys/{key}/restore")]
+pub async fn restore_kv(
+    key: web::Path<Key>,
+    body: web::Json<RestoreRequest>,
+    storage: web::Data<Arc<dyn AsyncStorage>>,
+) -> impl Responder {
+    let target = match body.into_inner() {
+        RestoreRequest {
+            version: Some(version),
+            at: None,
+        } => RestoreTarget::Version(version),
+        RestoreRequest {
+            version: None,
+            at: Some(at),
+        } => RestoreTarget::At(at),
+        _ => {
+            return HttpResponse::UnprocessableEntity().json(ErrorResponse {
+                error: ErrorDetail {
+                    code: "VALIDATION_ERROR".to_string(),
+                    message: "Exactly one of 'version' and 'at' must be given".to_string(),
+                },
+            });
+        }
+    };
+
+    match storage.restore(key.into_inner(), target).await {
+        Ok(response) => HttpResponse::Ok()
+            .insert_header(ETag(EntityTag::new_strong(response.value.etag())))
+            .json(response),
+        Err(storage_error) => storage_error.error_response(),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/keys/{key}/append",
+    params(
+        ("key" = String, Path, description = "Unique key identifier", example = "job-42-log")
+    ),
+    request_body = AppendRequest,
+    responses(
+        (status = 200, description = "The text was appended; returns the new length in bytes and the metadata", body = AppendResponse),
+        (status = 201, description = "The key did not exist and was created with the text as its value", body = AppendResponse),
+        (status = 409, description = "The stored value is not plain text (a structured, `json` or `base64` value)", body = ErrorResponse),
+        (status = 413, description = "The longer value would exceed `KV_MAX_VALUE_BYTES`", body = ErrorResponse),
+        (status = 507, description = "The longer value would exceed the configured memory budget (`KV_MAX_BYTES`)", body = ErrorResponse)
+    ),
+    tag = "Keys - Write Operations",
+    summary = "Append text to a value",
+    description = "Atomically concatenates `value` onto the end of the key's current value, so log-style values can be built up without reading them back and without racing other writers: concurrent appends are applied one after another and none is lost. If the key does not exist (or has expired) it is created with `value` as its content and the response is 201. Like any write, an append bumps `metadata.version` and `updated_at`, resets the expiry to `KV_DEFAULT_TTL_SECONDS` (none when unset) and counts against `KV_MAX_BYTES` (507 when the longer value would not fit). The response carries the new `length` in bytes rather than the whole value. Only plain text values can be appended to; values that are structured JSON, have `value_type: json` or `encoding: base64` are rejected with 409 `VALUE_NOT_APPENDABLE`."
+)]
+#[post("/keys/{key}/append")]
+pub async fn append_kv(
+    key: web::Path<Key>,
+    body: web::Json<AppendRequest>,
+    storage: web::Data<Arc<dyn AsyncStorage>>,
+) -> impl Responder {
+    let key = key.into_inner();
+
+    match storage.append(key.clone(), &body.value).await {
+        Ok((metadata, outcome)) => {
+            let mut builder = match outcome {
+                UpsertOutcome::Created => HttpResponse::Created(),
+                UpsertOutcome::Updated => HttpResponse::Ok(),
+            };
+            builder.json(AppendResponse {
+                key,
+                length: metadata.size_bytes,
+                metadata,
+            })
+        }
+        Err(storage_error) => storage_error.error_response(),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/keys/{key}/cas",
+    params(
+        ("key" = String, Path, description = "Unique key identifier", example = "lock-nightly-report")
+    ),
+    request_body = CasRequest,
+    responses(
+        (status = 200, description = "The current value matched `expected` and was replaced by `new`", body = KeyValueResponse),
+        (status = 400, description = "Invalid `new` value or `ttl_seconds` (naming the `field` and `reason`), or a `new` value that does not match the key's stored type (`TYPE_MISMATCH`)", body = ValidationErrorResponse),
+        (status = 409, description = "The current value did not match `expected`; returns the current metadata (when the key exists) but not the value", body = CasConflictResponse),
+        (status = 413, description = "The new value exceeds `KV_MAX_VALUE_BYTES`", body = ErrorResponse),
+        (status = 507, description = "The new value would exceed the configured memory budget (`KV_MAX_BYTES`)", body = ErrorResponse)
+    ),
+    tag = "Keys - Write Operations",
+    summary = "Compare and swap a value",
+    description = "Atomically replaces the key's value with `new` only if its current value is equal to `expected`; no other write can slip in between the comparison and the replacement, so of several clients racing with the same `expected` exactly one wins. Values are compared as JSON, so `\"1\"` and `1` differ. Passing `expected: null` (or omitting it) means \"only if the key does not exist\", which together with a `DELETE` guarded by `If-Match-Value` gives a simple lock: acquire with `{\"expected\": null, \"new\": \"worker-7\"}` and release by deleting only if the value is still `worker-7`. On success the response is 200 with the stored value and metadata and an `ETag` header; like any write it bumps `metadata.version` and resets the expiry, to `ttl_seconds` if given (handy for leases that must lapse if the holder dies) or to `KV_DEFAULT_TTL_SECONDS` otherwise. On a mismatch nothing is written and the response is 409 `VALUE_MISMATCH` with the current `metadata` (omitted when the key does not exist). The current value itself is never returned, so a losing client cannot read a lock token it does not own."
+)]
+#[post("/keys/{key}/cas")]
+pub async fn compare_and_swap_kv(
+    key: web::Path<Key>,
+    body: web::Json<CasRequest>,
+    storage: web::Data<Arc<dyn AsyncStorage>>,
+) -> impl Responder {
+    let key = key.into_inner();
+    let body = body.into_inner();
+    let request = CreateKVRequest {
+        key: key.clone(),
+        value: body.new.into(),
+        value_type: body.value_type,
+        encoding: body.encoding,
+        ttl_seconds: body.ttl_seconds,
+    };
+    if let Err(error) = validation::write(&request) {
+        return error.error_response();
+    }
+
+    match storage
+        .compare_and_swap(body.expected.as_ref(), request)
+        .await
+    {
+        Ok(CasOutcome::Swapped(response)) => HttpResponse::Ok()
+            .insert_header(ETag(EntityTag::new_strong(response.value.etag())))
+            .json(response),
+        Ok(CasOutcome::Mismatch(current)) => {
+            let message = match (&body.expected, &current) {
+                (None, _) => format!("Key '{}' already exists", key.as_str()),
+                (Some(_), None) => format!("Key '{}' does not exist", key.as_str()),
+                (Some(_), Some(_)) => {
+                    format!(
+                        "The current value of '{}' does not match 'expected'",
+                        key.as_str()
+                    )
+                }
+            };
+            HttpResponse::Conflict().json(CasConflictResponse {
+                error: ErrorDetail {
+                    code: "VALUE_MISMATCH".to_string(),
+                    message,
+                },
+                current,
+            })
+        }
+        Err(storage_error) => storage_error.error_response(),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/tx",
+    request_body = TxRequest,
+    responses(
+        (status = 200, description = "Every op was applied; one result per op, in request order", body = TxResponse),
+        (status = 400, description = "A value does not match its key's stored type (`TYPE_MISMATCH`, shaped like the other op failures with an `index`)", body = TxErrorResponse),
+        (status = 404, description = "A `delete` op named a key that does not exist", body = TxErrorResponse),
+        (status = 409, description = "A `cas` op did not find its expected value (`VALUE_MISMATCH`, with the key's `current` metadata), a check did not hold (`CHECK_FAILED`, likewise), or an op hit a key of another kind (`WRONG_TYPE`) or a counter bound (`COUNTER_OUT_OF_RANGE`)", body = TxErrorResponse),
+        (status = 413, description = "A value exceeds `KV_MAX_VALUE_BYTES`", body = TxErrorResponse),
+        (status = 422, description = "More than 1000 ops, or at least one invalid op (`VALIDATION_ERROR`, with every problem listed in `errors`)", body = BatchValidationResponse),
+        (status = 507, description = "The transaction would exceed the configured memory budget (`KV_MAX_BYTES`)", body = TxErrorResponse)
+    ),
+    tag = "Keys - Write Operations",
+    summary = "Apply several writes atomically",
+    description = "Applies up to 1000 ops as one unit: either all of them take effect or none does. Each op is `{\"op\": \"set\"}` (same semantics as `PUT /keys/{key}`), `{\"op\": \"delete\"}` (fails with 404 if the key does not exist) or `{\"op\": \"cas\"}` (same semantics as `POST /keys/{key}/cas`, with the replacement in `value` and the required current value in `expected`; null or omitted `expected` means the key must not exist). The `put`, `check_value` and `check_version` ops of `POST /transactions` are accepted too. `value_type`, `encoding` and `ttl_seconds` work as on PUT. Ops run in request order and later ops see the effect of earlier ones, so a transfer can `cas` both balances, and a key may appear more than once. Every op is validated before anything runs; invalid ops reject the request with 422 `VALIDATION_ERROR` listing every problem as `{index, field, code, message}`. If an op fails while running, for example on a `cas` mismatch, the transaction is rolled back and the response carries the error and the `index` of the op that failed. Isolation: a transaction is serializable with respect to every other write, because other writes wait while it runs, and no write can land between two of its ops. Reads of entries wait while a transaction commits, so they see all of its writes or none: a single key, several keys through `GET /keys?keys=`, `include=values` listings, value searches, random entries and key counts. Listings of bare key names do not wait and may show some of the keys a transaction creates and not others yet. Backends without transaction support answer 503 `STORAGE_UNAVAILABLE`."
+)]
+#[post("/tx")]
+pub async fn transaction(
+    body: web::Json<TxRequest>,
+    storage: web::Data<Arc<dyn AsyncStorage>>,
+) -> impl Responder {
+    let requests = body.into_inner().ops;
+    if requests.len() > MAX_BATCH_KEYS {
+        return HttpResponse::UnprocessableEntity().json(batch_too_large(requests.len()));
+    }
+    match validate_tx_ops(requests) {
+        Ok(ops) => run_transaction(storage.get_ref(), ops).await,
+        Err(response) => HttpResponse::UnprocessableEntity().json(response),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/transactions",
+    request_body = TxRequest,
+    responses(
+        (status = 200, description = "Every check held and every write was applied; one result per op, in request order", body = TxResponse),
+        (status = 400, description = "A value does not match its key's stored type (`TYPE_MISMATCH`, with an `index`)", body = TxErrorResponse),
+        (status = 404, description = "A `delete` op named a key that does not exist", body = TxErrorResponse),
+        (status = 409, description = "A check did not hold (`CHECK_FAILED`) or a `cas` did not find its expected value (`VALUE_MISMATCH`); `index` names the op and `current` carries the key's metadata when it exists. Also `WRONG_TYPE` and `COUNTER_OUT_OF_RANGE`", body = TxErrorResponse),
+        (status = 413, description = "A value exceeds `KV_MAX_VALUE_BYTES`", body = TxErrorResponse),
+        (status = 422, description = "More than 1000 ops, more than 16 distinct keys (`TOO_MANY_KEYS`), or at least one invalid op (`VALIDATION_ERROR`, with every problem listed in `errors`)", body = BatchValidationResponse),
+        (status = 507, description = "The transaction would exceed the configured memory budget (`KV_MAX_BYTES`)", body = TxErrorResponse)
+    ),
+    tag = "Keys - Write Operations",
+    summary = "Check and update a few keys atomically",
+    description = "Runs an ordered list of ops over at most 16 distinct keys as one unit. `{\"op\": \"put\"}` writes `value` like `PUT /keys/{key}`, `{\"op\": \"delete\"}` removes a key (404 if it does not exist), `{\"op\": \"check_value\"}` requires the key to hold `expected` (null or omitted: the key must not exist) and `{\"op\": \"check_version\"}` requires the key to be at `version`. The `set` and `cas` ops of `POST /tx` are accepted too. Ops run in order and see the effect of earlier ops, so a check placed after a write checks the written value. Either every check holds and every write is applied, or nothing is and the response names the first failing op by `index`; a failed check answers 409 `CHECK_FAILED` with the key's `current` metadata. Other writes wait while a transaction runs, and reads of a single key or of several keys through `GET /keys?keys=` wait while it commits, so no reader sees one key updated without the others. Key listings and searches do not wait. Backends without transaction support answer 503 `STORAGE_UNAVAILABLE`."
+)]
+#[post("/transactions")]
+pub async fn transactions(
+    body: web::Json<TxRequest>,
+    storage: web::Data<Arc<dyn AsyncStorage>>,
+) -> impl Responder {
+    let requests = body.into_inner().ops;
+    if requests.len() > MAX_BATCH_KEYS {
+        return HttpResponse::UnprocessableEntity().json(batch_too_large(requests.len()));
+    }
+    let ops = match validate_tx_ops(requests) {
+        Ok(ops) => ops,
+        Err(response) => return HttpResponse::UnprocessableEntity().json(response),
+    };
+    let keys: BTreeSet<&Key> = ops.iter().map(TxOp::key).collect();
+    if keys.len() > MAX_TRANSACTION_KEYS {
+        return HttpResponse::UnprocessableEntity().json(ErrorResponse {
+            error: ErrorDetail {
+                code: "TOO_MANY_KEYS".to_string(),
+                message: format!(
+                    "Transaction touches {} keys, the maximum is {}",
+                    keys.len(),
+                    MAX_TRANSACTION_KEYS
+                ),
+            },
+        });
+    }
+    run_transaction(storage.get_ref(), ops).await
+}
+
+/// Turns transaction ops into [`TxOp`]s, or lists every invalid op.
+fn validate_tx_ops(requests: Vec<TxOpRequest>) -> Result<Vec<TxOp>, BatchValidationResponse> {
+    let count = requests.len();
+    let mut ops = Vec::with_capacity(count);
+    let mut errors = Vec::new();
+    let mut invalid_ops = 0;
+    for (index, request) in requests.into_iter().enumerate() {
+        let TxOpRequest {
+            op,
+            key,
+            value,
+            expected,
+            version,
+            value_type,
+            encoding,
+            ttl_seconds,
+        } = request;
+        let field_error = |field: &str, code: &str, message: String| {
+            vec![FieldError {
+                index,
+                field: field.to_string(),
+                code: code.to_string(),
+                message,
+            }]
+        };
+        let entry = BatchUpsertEntry {
+            key,
+            value,
+            value_type,
+            encoding,
+            ttl_seconds,
+        };
+        let validated = match op {
+            TxOpKind::Set | TxOpKind::Put => validate_batch_entry(index, entry).map(TxOp::Set),
+            TxOpKind::Cas => {
+                validate_batch_entry(index, entry).map(|body| TxOp::Cas { expected, body })
+            }
+            TxOpKind::Delete => validation::key("key", entry.key)
+                .map(TxOp::Delete)
+                .map_err(|err| field_error(&err.field, err.code, err.reason)),
+            TxOpKind::CheckValue => validation::key("key", entry.key)
+                .map(|key| TxOp::Check {
+                    key,
+                    expected: TxCheck::Value(expected),
+                })
+                .map_err(|err| field_error(&err.field, err.code, err.reason)),
+            TxOpKind::CheckVersion => match (validation::key("key", entry.key), version) {
+                (Err(err), _) => Err(field_error(&err.field, err.code, err.reason)),
+                (Ok(_), None) => Err(field_error(
+                    "version",
+                    "MISSING_VERSION",
+                    "check_version needs the expected `version`".to_string(),
+                )),
+                (Ok(key), Some(version)) => Ok(TxOp::Check {
+                    key,
+                    expected: TxCheck::Version(version),
+                }),
+            },
+        };
+        match validated {
+            Ok(op) => ops.push(op),
+            Err(found) => {
+                invalid_ops += 1;
+                errors.extend(found);
+            }
+        }
+    }
+    if !errors.is_empty() {
+        return Err(BatchValidationResponse {
+            error: ErrorDetail {
+                code: "VALIDATION_ERROR".to_string(),
+                message: format!(
+                    "{} of {} ops in the transaction are invalid; nothing was written",
+                    invalid_ops, count
+                ),
+            },
+            errors,
+        });
+    }
+    Ok(ops)
+}
+
+async fn run_transaction(storage: &Arc<dyn AsyncStorage>, ops: Vec<TxOp>) -> HttpResponse {
+    let targets: Vec<(Key, bool)> = ops
+        .iter()
+        .map(|op| (op.key().clone(), op.writes()))
+        .collect();
+    match storage.transaction(ops).await {
+        Ok(results) => {
+            let results = targets
+                .into_iter()
+                .zip(results)
+                .map(|((key, _), result)| {
+                    let (status, metadata) = match result {
+                        OpResult::Set(response, UpsertOutcome::Created) => {
+                            (TxOpStatus::Created, Some(response.metadata))
+                        }
+                        OpResult::Set(response, UpsertOutcome::Updated) => {
+                            (TxOpStatus::Updated, Some(response.metadata))
+                        }
+                        OpResult::Deleted(response) => {
+                            (TxOpStatus::Deleted, Some(response.metadata))
+                        }
+                        OpResult::Swapped(response) => {
+                            (TxOpStatus::Swapped, Some(response.metadata))
+                        }
+                        OpResult::Checked(metadata) => (TxOpStatus::Checked, metadata),
+                    };
+                    TxOpResult {
+                        key,
+                        status,
+                        metadata,
+                    }
+                })
+                .collect();
+            HttpResponse::Ok().json(TxResponse { results })
+        }
+        Err(TxError::Mismatch { index, current }) => {
+            let (key, writes) = &targets[index];
+            let (code, message) = if *writes {
+                (
+                    "VALUE_MISMATCH",
+                    format!(
+                        "Op {} on '{}' did not find its expected value; nothing was written",
+                        index,
+                        key.as_str()
+                    ),
+                )
+            } else {
+                (
+                    "CHECK_FAILED",
+                    format!(
+                        "Check {} on '{}' did not hold; nothing was written",
+                        index,
+                        key.as_str()
+                    ),
+                )
+            };
+            HttpResponse::Conflict().json(TxErrorResponse {
+                error: ErrorDetail {
+                    code: code.to_string(),
+                    message,
+                },
+                index: Some(index),
+                current: current.map(|metadata| *metadata),
+            })
+        }
+        Err(TxError::Failed { index, error }) => {
+            let detail = ErrorDetail::from(&error);
+            let response = TxErrorResponse {
+                error: ErrorDetail {
+                    code: detail.code,
+                    message: format!(
+                        "Op {} failed, nothing was written: {}",
+                        index, detail.message
+                    ),
+                },
+                index: Some(index),
+                current: None,
+            };
+            HttpResponse::build(error.status_code()).json(response)
+        }
+        Err(TxError::Storage(storage_error)) => HttpResponse::build(storage_error.status_code())
+            .json(TxErrorResponse {
+                error: ErrorDetail::from(&storage_error),
+                index: None,
+                current: None,
+            }),
+    }
+}
+
+#[utoipa::path(
+    put,
+    path = "/keys/{key}/fields/{field}",
+    params(
+        ("key" = String, Path, description = "Key holding the hash", example = "user-123"),
+        ("field" = String, Path, description = "Field name, following the same rules as keys", example = "name")
+    ),
+    request_body = SetFieldRequest,
+    responses(
+        (status = 201, description = "The field is new (and the hash too, when the key did not exist); returns the hash's metadata", body = KeyMetadataResponse),
+        (status = 200, description = "An existing field was overwritten; returns the hash's metadata", body = KeyMetadataResponse),
+        (status = 409, description = "The key holds something other than a hash (`WRONG_TYPE`)", body = ErrorResponse),
+        (status = 413, description = "The hash would exceed `KV_MAX_VALUE_BYTES`", body = ErrorResponse),
+        (status = 400, description = "The value is empty (or blank under `KV_REJECT_BLANK_VALUES`)", body = ValidationErrorResponse),
+        (status = 507, description = "The hash would exceed the configured memory budget (`KV_MAX_BYTES`)", body = ErrorResponse)
+    ),
+    tag = "Keys - Write Operations",
+    summary = "Set one field of a hash",
+    description = "Mirrors the Redis HSET command. Stores `value` under `field` in the hash held by the key, creating the hash when the key does not exist. Each field write is atomic, so concurrent writers touching different fields of the same hash never lose each other's updates. A hash is a separate kind of value: it is only reachable through `/keys/{key}/fields`, and mixing plain and hash operations on one key is rejected with 409 `WRONG_TYPE` (for example `GET /keys/{key}` on a hash, or setting a field on a key holding a plain value). The hash counts as a single value for `KV_MAX_VALUE_BYTES` and `KV_MAX_BYTES`, sized as its JSON object form. Like any write it bumps `metadata.version` and resets the expiry to `KV_DEFAULT_TTL_SECONDS` (none when unset)."
+)]
+#[put("/keys/{key}/fields/{field}")]
+pub async fn set_field(
+    path: web::Path<(Key, Key)>,
+    body: web::Json<SetFieldRequest>,
+    storage: web::Data<Arc<dyn AsyncStorage>>,
+) -> impl Responder {
+    let (key, field) = path.into_inner();
+    if let Err(error) = validation::not_empty("value", &body.value, reject_blank_values()) {
+        return error.error_response();
+    }
+
+    match storage.set_field(key.clone(), &field, &body.value).await {
+        Ok((metadata, outcome)) => {
+            let mut builder = match outcome {
+                UpsertOutcome::Created => HttpResponse::Created(),
+                UpsertOutcome::Updated => HttpResponse::Ok(),
+            };
+            builder.json(KeyMetadataResponse { key, metadata })
+        }
+        Err(storage_error) => storage_error.error_response(),
+    }
+}
+
+#[utoipa::path(
+    delete,
+    path = "/keys/{key}/fields/{field}",
+    params(
+        ("key" = String, Path, description = "Key holding the hash", example = "user-123"),
+        ("field" = String, Path, description = "Field name, following the same rules as keys", example = "name")
+    ),
+    responses(
+        (status = 200, description = "The field was removed", body = DeleteResponse),
+        (status = 404, description = "Key not found (`KEY_NOT_FOUND`) or the hash has no such field (`FIELD_NOT_FOUND`)", body = ErrorResponse),
+        (status = 409, description = "The key holds something other than a hash (`WRONG_TYPE`)", body = ErrorResponse)
+    ),
+    tag = "Keys - Write Operations",
+    summary = "Delete one field of a hash",
+    description = "Mirrors the Redis HDEL command. Removes a single field from the hash held by the key. Removing the last field deletes the key itself, as in Redis, so a hash is never left empty."
+)]
+#[delete("/keys/{key}/fields/{field}")]
+pub async fn delete_field(
+    path: web::Path<(Key, Key)>,
+    storage: web::Data<Arc<dyn AsyncStorage>>,
+) -> impl Responder {
+    let (key, field) = path.into_inner();
+
+    match storage.delete_field(key, &field).await {
+        Ok(()) => HttpResponse::Ok().json(DeleteResponse { deleted: true }),
+        Err(storage_error) => storage_error.error_response(),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/keys/{key}/list/push",
+    params(
+        ("key" = String, Path, description = "Key holding the list", example = "jobs")
+    ),
+    request_body = ListPushRequest,
+    responses(
+        (status = 201, description = "The key did not exist and was created as a one-element list", body = ListPushResponse),
+        (status = 200, description = "The element was pushed; returns the new length", body = ListPushResponse),
+        (status = 409, description = "The key holds something other than a list (`WRONG_TYPE`)", body = ErrorResponse),
+        (status = 413, description = "The list would exceed `KV_MAX_VALUE_BYTES`", body = ErrorResponse),
+        (status = 400, description = "The value is empty (or blank under `KV_REJECT_BLANK_VALUES`)", body = ValidationErrorResponse),
+        (status = 507, description = "The list would exceed the configured memory budget (`KV_MAX_BYTES`)", body = ErrorResponse)
+    ),
+    tag = "Keys - Write Operations",
+    summary = "Push an element onto a list",
+    description = "Mirrors the Redis LPUSH and RPUSH commands. Adds `value` to the `left` or `right` (the default) end of the list held by the key, creating the list when the key does not exist, and returns the new `length`. Pushes are atomic, so concurrent producers never lose elements. Lists are a separate kind of value, only reachable through `/keys/{key}/list`: pushing onto a key holding a plain value or a hash, or reading a list through the plain-value routes, is rejected with 409 `WRONG_TYPE`. The list counts as a single value for `KV_MAX_VALUE_BYTES` and `KV_MAX_BYTES`, sized as its JSON array form. Like any write a push bumps `metadata.version` and resets the expiry to `KV_DEFAULT_TTL_SECONDS` (none when unset)."
+)]
+#[post("/keys/{key}/list/push")]
+pub async fn list_push(
+    key: web::Path<Key>,
+    body: web::Json<ListPushRequest>,
+    storage: web::Data<Arc<dyn AsyncStorage>>,
+) -> impl Responder {
+    let key = key.into_inner();
+    if let Err(error) = validation::not_empty("value", &body.value, reject_blank_values()) {
+        return error.error_response();
+    }
+
+    match storage.list_push(key.clone(), body.side, &body.value).await {
+        Ok((metadata, length, outcome)) => {
+            let mut builder = match outcome {
+                UpsertOutcome::Created => HttpResponse::Created(),
+                UpsertOutcome::Updated => HttpResponse::Ok(),
+            };
+            builder.json(ListPushResponse {
+                key,
+                length,
+                metadata,
+            })
+        }
+        Err(storage_error) => storage_error.error_response(),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/keys/{key}/list/pop",
+    params(
+        ("key" = String, Path, description = "Key holding the list", example = "jobs"),
+        ListPopQuery
+    ),
+    responses(
+        (status = 200, description = "The popped element and the number of elements left", body = ListPopResponse),
+        (status = 404, description = "The list is empty or the key does not exist (`LIST_EMPTY`)", body = ErrorResponse),
+        (status = 409, description = "The key holds something other than a list (`WRONG_TYPE`)", body = ErrorResponse)
+    ),
+    tag = "Keys - Write Operations",
+    summary = "Pop an element off a list",
+    description = "Mirrors the Redis LPOP and RPOP commands. Removes the element at the `left` (the default) or `right` end of the list and returns it with the remaining `length`; pushing right and popping left makes the list a FIFO queue. Pops are atomic, so when several consumers pop concurrently every element is handed to exactly one of them. As in Redis, popping the last element deletes the key, so an empty list and a missing key are the same thing: popping either returns 404 `LIST_EMPTY`."
+)]
+#[post("/keys/{key}/list/pop")]
+pub async fn list_pop(
+    key: web::Path<Key>,
+    query: web::Query<ListPopQuery>,
+    storage: web::Data<Arc<dyn AsyncStorage>>,
+) -> impl Responder {
+    let key = key.into_inner();
+
+    match storage.list_pop(key.clone(), query.side).await {
+        Ok((element, length)) => HttpResponse::Ok().json(ListPopResponse {
+            key,
+            element,
+            length,
+        }),
+        Err(storage_error) => storage_error.error_response(),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/keys/{key}/set/add",
+    params(
+        ("key" = String, Path, description = "Key holding the set", example = "post-42-tags")
+    ),
+    request_body = SetMemberRequest,
+    responses(
+        (status = 200, description = "Whether the member was added and the set's size", body = SetAddResponse),
+        (status = 409, description = "The key holds a different kind of value (`WRONG_TYPE`), or the set already has `KV_MAX_SET_MEMBERS` members (`TOO_MANY_MEMBERS`)", body = ErrorResponse),
+        (status = 413, description = "The set would exceed `KV_MAX_VALUE_BYTES`", body = ErrorResponse),
+        (status = 422, description = "The member is empty or longer than 1024 bytes (`INVALID_MEMBER`)", body = ErrorResponse),
+        (status = 507, description = "The set would exceed the configured memory budget (`KV_MAX_BYTES`)", body = ErrorResponse)
+    ),
+    tag = "Keys - Write Operations",
+    summary = "Add a member to a set",
+    description = "Mirrors the Redis SADD command. Adds `member` to the set held by the key, creating the set when the key does not exist, and returns the set's `cardinality`. Adding a member that is already present is a no-op reported as `added: false`: nothing is written, so `metadata.version` and the expiry are left alone. Otherwise, like any write, it bumps `metadata.version` and resets the expiry to `KV_DEFAULT_TTL_SECONDS` (none when unset). Adds are atomic, so concurrent writers never lose members. Members are strings of 1 to 1024 bytes. When the server runs with `KV_MAX_SET_MEMBERS`, adding a new member to a full set is rejected with 409 `TOO_MANY_MEMBERS`. Sets are a separate kind of value, only reachable through `/keys/{key}/set`; their size is reported as `metadata.member_count`, and they count against `KV_MAX_VALUE_BYTES` and `KV_MAX_BYTES` sized as a JSON array of their members."
+)]
+#[post("/keys/{key}/set/add")]
+pub async fn set_add(
+    key: web::Path<Key>,
+    body: web::Json<SetMemberRequest>,
+    storage: web::Data<Arc<dyn AsyncStorage>>,
+) -> impl Responder {
+    let key = key.into_inner();
+    let member = body.into_inner().member;
+    if let Err(error) = validate_set_member(&member) {
+        return HttpResponse::UnprocessableEntity().json(error);
+    }
+
+    match storage.add_member(key.clone(), &member).await {
+        Ok((_, added, cardinality)) => HttpResponse::Ok().json(SetAddResponse {
+            key,
+            member,
+            added,
+            cardinality,
+        }),
+        Err(storage_error) => storage_error.error_response(),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/keys/{key}/set/remove",
+    params(
+        ("key" = String, Path, description = "Key holding the set", example = "post-42-tags")
+    ),
+    request_body = SetMemberRequest,
+    responses(
+        (status = 200, description = "Whether the member was removed and the set's remaining size", body = SetRemoveResponse),
+        (status = 409, description = "The key holds a different kind of value (`WRONG_TYPE`)", body = ErrorResponse)
+    ),
+    tag = "Keys - Write Operations",
+    summary = "Remove a member from a set",
+    description = "Mirrors the Redis SREM command. Removes `member` from the set held by the key and returns the remaining `cardinality`. Removing a member that is not in the set, or from a key that does not exist, is not an error: the response says `removed: false`. As in Redis, removing the last member deletes the key."
+)]
+#[post("/keys/{key}/set/remove")]
+pub async fn set_remove(
+    key: web::Path<Key>,
+    body: web::Json<SetMemberRequest>,
+    storage: web::Data<Arc<dyn AsyncStorage>>,
+) -> impl Responder {
+    let key = key.into_inner();
+    let member = body.into_inner().member;
+
+    match storage.remove_member(key.clone(), &member).await {
+        Ok((removed, cardinality)) => HttpResponse::Ok().json(SetRemoveResponse {
+            key,
+            member,
+            removed,
+            cardinality,
+        }),
+        Err(storage_error) => storage_error.error_response(),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/keys/{key}/counter",
+    params(
+        ("key" = String, Path, description = "Key to hold the counter", example = "page-views")
+    ),
+    request_body = CreateCounterRequest,
+    responses(
+        (status = 201, description = "The counter was created holding `initial`", body = KeyValueResponse),
+        (status = 409, description = "The key already exists (`KEY_ALREADY_EXISTS`)", body = ErrorResponse),
+        (status = 422, description = "`min` is greater than `max`, or `initial` lies outside them (`INVALID_COUNTER_BOUNDS`)", body = ErrorResponse),
+        (status = 507, description = "The counter would exceed the configured memory budget (`KV_MAX_BYTES`)", body = ErrorResponse)
+    ),
+    tag = "Keys - Write Operations",
+    summary = "Create a counter",
+    description = "Creates an integer counter holding `initial` (0 by default), optionally held between `min` and `max` (both inclusive). The counter is an ordinary value with `value_type: counter`, so `GET /keys/{key}` returns its number, and its bounds are kept in `metadata.counter`. Change it with `POST /keys/{key}/counter/add` and `POST /keys/{key}/counter/reset`; a PUT may also write it, but only integers within its bounds (400 `TYPE_MISMATCH` or 409 `COUNTER_OUT_OF_RANGE` otherwise). Like POST /keys, this never overwrites: an existing key is 409 `KEY_ALREADY_EXISTS`."
+)]
+#[post("/keys/{key}/counter")]
+pub async fn create_counter(
+    key: web::Path<Key>,
+    body: web::Json<CreateCounterRequest>,
+    storage: web::Data<Arc<dyn AsyncStorage>>,
+) -> impl Responder {
+    let body = body.into_inner();
+    let bounds = CounterBounds {
+        initial: body.initial,
+        min: body.min,
+        max: body.max,
+    };
+    if let Err(error) = validate_counter_bounds(&bounds) {
+        return HttpResponse::UnprocessableEntity().json(error);
+    }
+
+    match storage.create_counter(key.into_inner(), bounds).await {
+        Ok(response) => HttpResponse::Created().json(response),
+        Err(storage_error) => storage_error.error_response(),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/keys/{key}/counter/add",
+    params(
+        ("key" = String, Path, description = "Key holding the counter", example = "page-views")
+    ),
+    request_body = CounterAddRequest,
+    responses(
+        (status = 200, description = "The counter's new value", body = CounterResponse),
+        (status = 404, description = "The key does not exist", body = ErrorResponse),
+        (status = 409, description = "The key is not a counter (`VALUE_NOT_COUNTER`), or the result would leave its bounds and `overflow` is `error` (`COUNTER_OUT_OF_RANGE`)", body = ErrorResponse)
+    ),
+    tag = "Keys - Write Operations",
+    summary = "Add to a counter",
+    description = "Atomically adds `delta`, which may be negative, to the counter and returns its new `value`; concurrent adds are applied one after another and none is lost. When the result would pass `min` or `max`, or the limits of a 64-bit integer, the counter never wraps: with `overflow: error` (the default) the change is rejected with 409 `COUNTER_OUT_OF_RANGE` and the counter is left as it was, and with `overflow: clamp` it stops at the bound it crossed and the response says `clamped: true`. Like any write it bumps `metadata.version` and resets the expiry to `KV_DEFAULT_TTL_SECONDS` (none when unset)."
+)]
+#[post("/keys/{key}/counter/add")]
+pub async fn counter_add(
+    key: web::Path<Key>,
+    body: web::Json<CounterAddRequest>,
+    storage: web::Data<Arc<dyn AsyncStorage>>,
+) -> impl Responder {
+    let key = key.into_inner();
+    let body = body.into_inner();
+
+    match storage
+        .counter_add(key.clone(), body.delta, body.overflow)
+        .await
+    {
+        Ok((metadata, value, clamped)) => HttpResponse::Ok().json(CounterResponse {
+            key,
+            value,
+            clamped,
+            metadata,
+        }),
+        Err(storage_error) => storage_error.error_response(),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/keys/{key}/counter/reset",
+    params(
+        ("key" = String, Path, description = "Key holding the counter", example = "page-views")
+    ),
+    responses(
+        (status = 200, description = "The counter is back at its initial value", body = CounterResponse),
+        (status = 404, description = "The key does not exist", body = ErrorResponse),
+        (status = 409, description = "The key is not a counter (`VALUE_NOT_COUNTER`)", body = ErrorResponse)
+    ),
+    tag = "Keys - Write Operations",
+    summary = "Reset a counter",
+    description = "Atomically sets the counter back to the `initial` value it was created with (`metadata.counter.initial`). Resets and adds are serialized, so an add racing with a reset lands either before it, and is wiped out, or after it, and counts from the initial value."
+)]
+#[post("/keys/{key}/counter/reset")]
+pub async fn counter_reset(
+    key: web::Path<Key>,
+    storage: web::Data<Arc<dyn AsyncStorage>>,
+) -> impl Responder {
+    let key = key.into_inner();
+
+    match storage.counter_reset(key.clone()).await {
+        Ok((metadata, value)) => HttpResponse::Ok().json(CounterResponse {
+            key,
+            value,
+            clamped: false,
+            metadata,
+        }),
+        Err(storage_error) => storage_error.error_response(),
+    }
+}
+
+fn batch_delete_result(
+    key: String,
+    outcome: Result<ValueResponse, StorageError>,
+) -> BatchDeleteResult {
+    match outcome {
+        Ok(value) => BatchDeleteResult {
+            key,
+            status: BatchDeleteStatus::Deleted,
+            metadata: Some(value.metadata),
+            error: None,
+        },
+        Err(StorageError::KeyNotFound(_)) => BatchDeleteResult {
+            key,
+            status: BatchDeleteStatus::NotFound,
+            metadata: None,
+            error: None,
+        },
+        Err(storage_error) => BatchDeleteResult {
+            key,
+            status: BatchDeleteStatus::Error,
+            metadata: None,
+            error: Some(ErrorDetail::from(&storage_error)),
+        },
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/keys/batch-delete",
+    params(BatchDeleteQuery),
+    request_body = BatchGetRequest,
+    responses(
+        (status = 200, description = "One result per listed key, in request order", body = BatchDeleteResponse),
+        (status = 404, description = "`strict=true` and at least one key does not exist; nothing was deleted", body = ErrorResponse),
+        (status = 422, description = "More than 1000 keys, or `strict=true` and at least one key is invalid; `errors` lists every invalid key", body = BatchValidationResponse)
+    ),
+    tag = "Keys - Write Operations",
+    summary = "Delete many keys",
+    description = "Deletes up to 1000 keys in a single round trip. The response holds one result per listed key, in request order, whose `status` is `deleted` (with the removed entry's `metadata`), `not_found`, or `error` (with an `error` detail, e.g. `INVALID_KEY` for a malformed key, which is skipped while the others are still deleted). The request succeeds with 200 however many keys were missing. With `strict=true` the batch is all-or-nothing: every key must be valid (422 otherwise) and exist, and if any is missing the response is 404 `KEY_NOT_FOUND` naming all missing keys and nothing is deleted. The existence check happens in the storage layer right before the deletes; a key removed by another client in between is reported as `not_found` rather than failing the batch. With `dry_run=true` nothing is deleted, and the response (including the 404 of a strict batch) is the one the request would get right now."
+)]
+#[post("/keys/batch-delete")]
+pub async fn batch_delete(
+    query: web::Query<BatchDeleteQuery>,
+    body: web::Json<BatchGetRequest>,
+    storage: web::Data<Arc<dyn AsyncStorage>>,
+) -> impl Responder {
+    let raw_keys = body.into_inner().keys;
+    if raw_keys.len() > MAX_BATCH_KEYS {
+        return HttpResponse::UnprocessableEntity().json(batch_too_large(raw_keys.len()));
+    }
+
+    if query.strict {
+        let keys = match parse_key_list(raw_keys) {
+            Ok(keys) => keys,
+            Err(error) => return HttpResponse::UnprocessableEntity().json(error),
+        };
+        let deleted = if query.dry_run {
+            let ops = keys.iter().cloned().map(WriteOp::Delete).collect();
+            let planned: Vec<_> = storage
+                .batch_plan(ops)
+                .await
+                .into_iter()
+                .map(|result| result.and_then(WriteOutcome::into_deleted))
+                .collect();
+            let mut seen = HashSet::new();
+            let missing: Vec<Key> = keys
+                .iter()
+                .zip(&planned)
+                .filter(|(key, result)| {
+                    seen.insert(*key) && matches!(result, Err(StorageError::KeyNotFound(_)))
+                })
+                .map(|(key, _)| key.clone())
+                .collect();
+            if missing.is_empty() {
+                Ok(planned)
+            } else {
+                Err(StorageError::KeysNotFound(missing))
+            }
+        } else {
+            storage.delete_many_strict(keys.clone()).await
+        };
+        return match deleted {
+            Ok(deleted) => {
+                let results = keys
+                    .into_iter()
+                    .zip(deleted)
+                    .map(|(key, outcome)| batch_delete_result(key.as_str().to_string(), outcome))
+                    .collect();
+                HttpResponse::Ok().json(BatchDeleteResponse { results })
+            }
+            Err(storage_error) => storage_error.error_response(),
+        };
+    }
+
+    let parsed: Vec<Result<Key, ErrorDetail>> = raw_keys
+        .iter()
+        .map(|raw| {
+            Key::new(raw.clone()).map_err(|err| ErrorDetail {
+                code: "INVALID_KEY".to_string(),
+                message: format!("Invalid key '{}': {}", raw, err),
+            })
+        })
+        .collect();
+    let ops = parsed
+        .iter()
+        .filter_map(|key| key.as_ref().ok().cloned().map(WriteOp::Delete))
+        .collect();
+    let mut deleted = apply_ops(storage.get_ref().as_ref(), ops, query.dry_run)
+        .await
+        .into_iter();
+
+    let results = raw_keys
+        .into_iter()
+        .zip(parsed)
+        .map(|(raw, parsed)| match parsed {
+            Ok(_) => batch_delete_result(
+                raw,
+                deleted
+                    .next()
+                    .unwrap_or_else(|| {
+                        Err(StorageError::Unavailable(
+                            "the backend returned fewer results than keys".to_string(),
+                        ))
+                    })
+                    .and_then(WriteOutcome::into_deleted),
+            ),
+            Err(error) => BatchDeleteResult {
+                key: raw,
+                status: BatchDeleteStatus::Error,
+                metadata: None,
+                error: Some(error),
+            },
+        })
+        .collect();
+
+    HttpResponse::Ok().json(BatchDeleteResponse { results })
+}
+
+/// The state of one CSV import: the reader and the record in progress,
+/// which columns hold the key and the value once the header row has been
+/// read, the rows planned but not yet applied, and the running summary.
+struct CsvImport {
+    on_conflict: OnConflict,
+    dry_run: bool,
+    reader: CsvReader,
+    records: Vec<CsvRecord>,
+    columns: Option<(usize, usize, usize)>,
+    pending: Vec<PlannedRow>,
+    summary: ImportCsvResponse,
+}
+
+/// A row after the plan phase: the write it asks for, or why it is
+/// rejected without reaching the store.
+struct PlannedRow {
+    line: usize,
+    key: Option<String>,
+    request: Result<CreateKVRequest, ErrorDetail>,
+}
+
+impl CsvImport {
+    fn new(on_conflict: OnConflict, dry_run: bool) -> Self {
+        CsvImport {
+            on_conflict,
+            dry_run,
+            reader: CsvReader::new(),
+            records: Vec::new(),
+            columns: None,
+            pending: Vec::new(),
+            summary: ImportCsvResponse::default(),
+        }
+    }
+
+    /// Parses one chunk of the body and, unless this is a dry run, writes
+    /// the rows it completed before returning, so only one chunk's rows are
+    /// held at a time. A dry run plans every row in one go at the end, so
+    /// rows that repeat a key see the earlier row's outcome.
+    async fn feed(
+        &mut self,
+        chunk: &[u8],
+        storage: &dyn AsyncStorage,
+    ) -> Result<(), ErrorResponse> {
+        let mut records = std::mem::take(&mut self.records);
+        self.reader.feed(chunk, &mut records);
+        for record in records.drain(..) {
+            self.record(record)?;
+        }
+        self.records = records;
+        if !self.dry_run {
+            self.apply(storage).await;
+        }
+        Ok(())
+    }
+
+    /// Reads the last record and applies whatever is still pending.
+    async fn finish(&mut self, storage: &dyn AsyncStorage) -> Result<(), ErrorResponse> {
+        if let Some(record) = std::mem::take(&mut self.reader).finish() {
+            self.record(record)?;
+        }
+        if self.columns.is_none() {
+            return Err(invalid_csv(
+                "The body is empty; expected a header row naming a `key` and a `value` column"
+                    .to_string(),
+            ));
+        }
+        self.apply(storage).await;
+        Ok(())
+    }
+
+    fn record(&mut self, record: CsvRecord) -> Result<(), ErrorResponse> {
+        match self.columns {
+            None => self.header(record),
+            Some(columns) => {
+                let row = self.plan(columns, record);
+                self.pending.push(row);
+                Ok(())
+            }
+        }
+    }
+
+    /// Reads the header row, locating the `key` and `value` columns by name.
+    fn header(&mut self, record: CsvRecord) -> Result<(), ErrorResponse> {
+        let names = record
+            .fields
+            .map_err(|err| invalid_csv(format!("Header row on line {}: {}", record.line, err)))?;
+        let column = |name: &str| {
+            names
+                .iter()
+                .position(|field| field.trim_start_matches('\u{feff}') == name)
+        };
+        match (column("key"), column("value")) {
+            (Some(key), Some(value)) => {
+                self.columns = Some((key, value, names.len()));
+                Ok(())
+            }
+            _ => Err(invalid_csv(
+                "The first row must be a header naming a `key` and a `value` column".to_string(),
+            )),
+        }
+    }
+
+    /// Validates a row like a batch entry and turns it into the write that
+    /// `on_conflict` asks for.
+    fn plan(&self, columns: (usize, usize, usize), record: CsvRecord) -> PlannedRow {
+        let line = record.line;
+        let rejected = |key: Option<String>, code: &str, message: String| PlannedRow {
+            line,
+            key,
+            request: Err(ErrorDetail {
+                code: code.to_string(),
+                message,
+            }),
+        };
+        let (key_column, value_column, width) = columns;
+        let mut fields = match record.fields {
+            Ok(fields) if fields.len() == width => fields,
+            Ok(fields) => {
+                let message = format!(
+                    "Row has {} fields but the header names {} columns",
+                    fields.len(),
+                    width
+                );
+                let key = fields.into_iter().nth(key_column);
+                return rejected(key, "INVALID_CSV", message);
+            }
+            Err(err) => return rejected(None, "INVALID_CSV", err.to_string()),
+        };
+        let value = std::mem::take(&mut fields[value_column]);
+        let key = std::mem::take(&mut fields[key_column]);
+        let entry = BatchUpsertEntry {
+            key: key.clone(),
+            value: serde_json::Value::String(value),
+            value_type: Default::default(),
+            encoding: Default::default(),
+            ttl_seconds: None,
+        };
+        let request = match validate_batch_entry(line, entry) {
+            Ok(request) => request,
+            Err(errors) => {
+                let (code, message) = errors
+                    .into_iter()
+                    .next()
+                    .map(|failure| (failure.code, failure.message))
+                    .unwrap_or_else(|| ("VALIDATION_ERROR".to_string(), "Invalid row".to_string()));
+                return rejected(Some(key), &code, message);
+            }
+        };
+        PlannedRow {
+            line,
+            key: Some(key),
+            request: Ok(request),
+        }
+    }
+
+    /// Applies the pending rows (or plans them, on a dry run) and adds them
+    /// to the summary in file order.
+    async fn apply(&mut self, storage: &dyn AsyncStorage) {
+        let mut requests = Vec::new();
+        let rows: Vec<(usize, Option<String>, Option<ErrorDetail>)> =
+            std::mem::take(&mut self.pending)
+                .into_iter()
+                .map(|row| match row.request {
+                    Ok(request) => {
+                        requests.push(request);
+                        (row.line, row.key, None)
+                    }
+                    Err(error) => (row.line, row.key, Some(error)),
+                })
+                .collect();
+        let outcomes = match self.on_conflict {
+            OnConflict::Replace => upsert_all(storage, requests, self.dry_run).await,
+            OnConflict::Fail | OnConflict::Ignore => {
+                let ops = requests.into_iter().map(WriteOp::Insert).collect();
+                apply_ops(storage, ops, self.dry_run)
+                    .await
+                    .into_iter()
+                    .map(|outcome| outcome?.into_upserted())
+                    .collect()
+            }
+        };
+        let mut outcomes = outcomes.into_iter();
+        for (line, key, rejected) in rows {
+            let error = match rejected {
+                Some(error) => error,
+                None => match self.count(outcomes.next()) {
+                    Ok(()) => continue,
+                    Err(error) => error,
+                },
+            };
+            self.reject(line, key, error);
+        }
+    }
+
+    fn count(
+        &mut self,
+        outcome: Option<Result<(KeyValueResponse, UpsertOutcome), StorageError>>,
+    ) -> Result<(), ErrorDetail> {
+        let outcome = outcome.unwrap_or_else(|| {
+            Err(StorageError::Unavailable(
+                "the backend returned fewer results than rows".to_string(),
+            ))
+        });
+        match outcome {
+            Ok((_, UpsertOutcome::Created)) => self.summary.created += 1,
+            Ok((_, UpsertOutcome::Updated)) => self.summary.updated += 1,
+            Err(StorageError::KeyAlreadyExists(_)) if self.on_conflict == OnConflict::Ignore => {
+                self.summary.ignored += 1
+            }
+            Err(storage_error) => return Err(ErrorDetail::from(&storage_error)),
+        }
+        self.summary.accepted += 1;
+        Ok(())
+    }
+
+    fn reject(&mut self, line: usize, key: Option<String>, error: ErrorDetail) {
+        self.summary.rejected += 1;
+        if self.summary.errors.len() < MAX_IMPORT_ERRORS {
+            self.summary
+                .errors
+                .push(ImportCsvRowError { line, key, error });
+        }
+    }
+}
+
+fn invalid_csv(message: String) -> ErrorResponse {
+    ErrorResponse {
+        error: ErrorDetail {
+            code: "INVALID_CSV".to_string(),
+            message,
+        },
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/import/csv",
+    params(ImportCsvQuery),
+    request_body(content = String, content_type = "text/csv", description = "A header row naming a `key` and a `value` column, then one row per entry", example = "key,value\r\ngreeting,hello\r\naddress,\"1 Main St,\nSpringfield\"\r\n"),
+    responses(
+        (status = 200, description = "The file was read to the end; counts of accepted and rejected rows, and the line and reason of each rejected row", body = ImportCsvResponse),
+        (status = 400, description = "The body is empty or its first row is not a header naming `key` and `value` columns (`INVALID_CSV`)", body = ErrorResponse),
+        (status = 415, description = "Content-Type is not `text/csv`", body = ErrorResponse)
+    ),
+    tag = "Keys - Write Operations",
+    summary = "Import key-value pairs from CSV",
+    description = "Loads key-value pairs from a CSV file (RFC 4180) sent as the request body with `Content-Type: text/csv`. The first row is a header, and the `key` and `value` columns are found by name, so other columns are ignored and a `GET /keys` CSV export can be imported as-is. Fields holding commas, double quotes or line breaks must be enclosed in double quotes, with inner quotes doubled; lines may end in CRLF or LF, and blank lines are skipped. The body is parsed as it arrives and the rows of each received chunk are written before the next chunk is read, so files of any size can be imported without the server holding them in memory: `KV_MAX_BODY_BYTES` does not apply, but a single row may be at most 2 MiB. Each row is validated like a `POST /keys/batch` entry and stored as a `text` value with the default TTL. A bad key, an empty value, a value over `KV_MAX_VALUE_BYTES`, a row with a different number of fields than the header, or a row that is not valid UTF-8 is rejected, and the import carries on with the next row. `on_conflict` chooses what happens to rows whose key already exists: `replace` (the default) overwrites the value, `fail` rejects the row with `KEY_ALREADY_EXISTS`, and `ignore` keeps the stored value. The response counts the rows `accepted` (broken down into `created`, `updated` and `ignored`) and `rejected`, and lists the first 1000 rejected rows with the line each starts on (the header is line 1, and line breaks inside quoted fields count), the key when it could be read, and the error. Rows written before a failure stay written; the import is not atomic. With `dry_run=true` the whole file is read and checked, including against the stored keys and earlier rows of the same file, and the response reports what the import would do, but nothing is written. A dry run keeps the parsed rows until the end of the file, so it needs as much memory as the imported values."
+)]
+#[post("/import/csv")]
+pub async fn import_csv(
+    req: HttpRequest,
+    query: web::Query<ImportCsvQuery>,
+    mut payload: web::Payload,
+    storage: web::Data<Arc<dyn AsyncStorage>>,
+) -> impl Responder {
+    if req.content_type() != mime::TEXT_CSV.essence_str() {
+        return HttpResponse::UnsupportedMediaType().json(ErrorResponse {
+            error: ErrorDetail {
+                code: "UNSUPPORTED_MEDIA_TYPE".to_string(),
+                message: "CSV imports expect Content-Type text/csv".to_string(),
+            },
+        });
+    }
+    let mut import = CsvImport::new(
+        query.on_conflict.unwrap_or(OnConflict::Replace),
+        query.dry_run,
+    );
+    let storage = storage.get_ref().as_ref();
+    while let Some(chunk) = payload.next().await {
+        let chunk = match chunk {
+            Ok(chunk) => chunk,
+            Err(err) => return payload_error_response(&err.into()),
+        };
+        if let Err(error) = import.feed(&chunk, storage).await {
+            return HttpResponse::BadRequest().json(error);
+        }
+    }
+    if let Err(error) = import.finish(storage).await {
+        return HttpResponse::BadRequest().json(error);
+    }
+
+    HttpResponse::Ok().json(import.summary)
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(create_kv)
+        .service(batch_upsert)
+        .service(bulk_upsert)
+        .service(get_keys_list)
+        .service(update_kv)
+        .service(merge_patch_kv)
+        .service(put_blob)
+        .service(expire_kv)
+        .service(put_schema)
+        .service(delete_schema)
+        .service(restore_kv)
+        .service(append_kv)
+        .service(compare_and_swap_kv)
+        .service(transaction)
+        .service(transactions)
+        .service(set_field)
+        .service(delete_field)
+        .service(list_push)
+        .service(list_pop)
+        .service(set_add)
+        .service(set_remove)
+        .service(create_counter)
+        .service(counter_add)
+        .service(counter_reset)
+        .service(batch_delete)
+        .service(import_csv)
+        .service(delete_kv);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::service::InMemoryStorage;
+
+    #[actix_web::test]
+    async fn test_csv_import_holds_one_chunk_of_rows_at_a_time() {
+        let storage = InMemoryStorage::new();
+        let rows = 30_000;
+        let mut body = String::from("key,value\r\n");
+        for i in 0..rows {
+            body.push_str(&format!("row-{:05},\"{:0>64}, quoted\"\r\n", i, i));
+        }
+        assert!(body.len() > crate::app::DEFAULT_MAX_BODY_BYTES);
+
+        let mut import = CsvImport::new(OnConflict::Replace, false);
+        let mut most_records = 0;
+        for chunk in body.as_bytes().chunks(1024) {
+            import.feed(chunk, &storage).await.unwrap();
+            assert!(import.pending.is_empty());
+            assert!(import.records.is_empty());
+            most_records = most_records.max(import.records.capacity());
+        }
+        import.finish(&storage).await.unwrap();
+
+        // A 1 KiB chunk completes at most a dozen of these rows.
+        assert!(most_records <= 16, "{}", most_records);
+        assert_eq!(import.summary.accepted, rows);
+        assert_eq!(AsyncStorage::len(&storage).await, rows);
+    }
 }
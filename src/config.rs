@@ -0,0 +1,737 @@
+//! Server configuration: an optional TOML file named by `KV_CONFIG`, with
+//! the `KV_*` environment variables overriding whatever it sets. Without
+//! either, every setting keeps its default.
+
+use crate::app::DEFAULT_MAX_BODY_BYTES;
+use crate::service::{DEFAULT_MAX_VALUE_BYTES, EvictionPolicy, InMemoryConfig, TransformChain};
+use crate::types::TimestampFormat;
+use serde::{Deserialize, Deserializer};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigError(String);
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// Which storage the server runs on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Backend {
+    /// One in-memory store.
+    #[default]
+    Memory,
+    /// `storage.shards` in-memory stores, keys spread by consistent hashing.
+    Sharded,
+}
+
+impl FromStr for Backend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "memory" => Ok(Backend::Memory),
+            "sharded" => Ok(Backend::Sharded),
+            other => Err(format!(
+                "Unknown backend '{}' (expected 'memory' or 'sharded')",
+                other
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub storage: StorageConfig,
+    pub limits: LimitsConfig,
+    pub ttl: TtlConfig,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    /// `KV_BIND`
+    pub bind: SocketAddr,
+    /// `KV_MAX_BODY_BYTES`
+    pub max_body_bytes: usize,
+    /// `KV_TIMESTAMP_FORMAT`
+    #[serde(deserialize_with = "parsed")]
+    pub timestamp_format: TimestampFormat,
+    /// `KV_REJECT_BLANK_VALUES`
+    pub reject_blank_values: bool,
+    /// `KV_CASE_INSENSITIVE`
+    pub case_insensitive_keys: bool,
+    /// `KV_SEED_FILE`
+    pub seed_file: Option<PathBuf>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            bind: SocketAddr::from(([0, 0, 0, 0], 8080)),
+            max_body_bytes: DEFAULT_MAX_BODY_BYTES,
+            timestamp_format: TimestampFormat::default(),
+            reject_blank_values: false,
+            case_insensitive_keys: false,
+            seed_file: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StorageConfig {
+    /// `KV_BACKEND`
+    #[serde(deserialize_with = "parsed")]
+    pub backend: Backend,
+    /// `KV_STORAGE_SHARDS`: number of stores behind the sharded backend.
+    pub shards: usize,
+    /// `KV_SHARDS`: number of internal map shards of each store.
+    pub map_shards: Option<usize>,
+    /// `KV_EVICTION_POLICY`
+    #[serde(deserialize_with = "parsed")]
+    pub eviction_policy: EvictionPolicy,
+    /// `KV_INDEX_VALUES`
+    pub index_values: bool,
+    /// `KV_HISTORY_LIMIT`
+    pub history_limit: usize,
+    /// `KV_COMPRESS_THRESHOLD_BYTES`; 0 turns compression off.
+    pub compress_threshold_bytes: Option<usize>,
+    /// `KV_TRACK_ACCESS`
+    pub track_access: bool,
+    /// `KV_NEGATIVE_CACHE_MS`; 0 turns the cache off.
+    pub negative_cache_ms: Option<u64>,
+    /// `KV_VALUE_TRANSFORMS`, comma-separated: built-in transforms applied
+    /// in order to every text value written.
+    pub value_transforms: Vec<String>,
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        Self {
+            backend: Backend::default(),
+            shards: 1,
+            map_shards: None,
+            eviction_policy: EvictionPolicy::default(),
+            index_values: false,
+            history_limit: 0,
+            compress_threshold_bytes: None,
+            track_access: false,
+            negative_cache_ms: None,
+            value_transforms: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LimitsConfig {
+    /// `KV_MAX_KEYS`
+    pub max_keys: Option<usize>,
+    /// `KV_MAX_BYTES`
+    pub max_bytes: Option<u64>,
+    /// `KV_MAX_VALUE_BYTES`
+    pub max_value_bytes: usize,
+    /// `KV_MAX_VALUE_BYTES_BY_PREFIX`, as `prefix=bytes` pairs separated by
+    /// commas. Narrows `max_value_bytes` for matching keys.
+    pub max_value_bytes_by_prefix: BTreeMap<String, usize>,
+    /// `KV_MAX_SET_MEMBERS`; 0 means unlimited.
+    pub max_set_members: Option<usize>,
+}
+
+impl Default for LimitsConfig {
+    fn default() -> Self {
+        Self {
+            max_keys: None,
+            max_bytes: None,
+            max_value_bytes: DEFAULT_MAX_VALUE_BYTES,
+            max_value_bytes_by_prefix: BTreeMap::new(),
+            max_set_members: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TtlConfig {
+    /// `KV_DEFAULT_TTL_SECONDS`; 0 means writes never expire by default.
+    pub default_seconds: Option<u64>,
+    /// `KV_EXPIRY_SWEEP_SECS`
+    pub sweep_seconds: u64,
+}
+
+impl Default for TtlConfig {
+    fn default() -> Self {
+        Self {
+            default_seconds: None,
+            sweep_seconds: 1,
+        }
+    }
+}
+
+impl Config {
+    /// Loads the file named by `KV_CONFIG`, if any, applies the environment
+    /// on top and validates the result.
+    pub fn load() -> Result<Config, ConfigError> {
+        Self::load_from(|name| std::env::var(name).ok())
+    }
+
+    /// Like [`Config::load`], reading variables through `var` instead of the
+    /// process environment.
+    pub fn load_from(var: impl Fn(&str) -> Option<String>) -> Result<Config, ConfigError> {
+        let mut config = match var("KV_CONFIG") {
+            Some(path) => Config::from_file(Path::new(&path))?,
+            None => Config::default(),
+        };
+        config.apply_env(var)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn from_file(path: &Path) -> Result<Config, ConfigError> {
+        let contents = std::fs::read_to_string(path).map_err(|err| {
+            ConfigError(format!(
+                "Cannot read config file {}: {}",
+                path.display(),
+                err
+            ))
+        })?;
+        Config::from_toml(&contents)
+            .map_err(|err| ConfigError(format!("Config file {}: {}", path.display(), err)))
+    }
+
+    pub fn from_toml(contents: &str) -> Result<Config, ConfigError> {
+        toml::from_str(contents).map_err(|err| ConfigError(err.to_string().trim_end().to_string()))
+    }
+
+    /// Overrides every setting whose `KV_*` variable is set. A value that
+    /// does not parse is an error rather than silently ignored.
+    pub fn apply_env(&mut self, var: impl Fn(&str) -> Option<String>) -> Result<(), ConfigError> {
+        let env = Env(var);
+        let server = &mut self.server;
+        env.parse(
+            "KV_BIND",
+            &mut server.bind,
+            "an address such as 0.0.0.0:8080",
+        )?;
+        env.parse("KV_MAX_BODY_BYTES", &mut server.max_body_bytes, INTEGER)?;
+        env.parse_str("KV_TIMESTAMP_FORMAT", &mut server.timestamp_format)?;
+        env.flag("KV_REJECT_BLANK_VALUES", &mut server.reject_blank_values)?;
+        env.flag("KV_CASE_INSENSITIVE", &mut server.case_insensitive_keys)?;
+        if let Some(path) = env.get("KV_SEED_FILE") {
+            server.seed_file = Some(PathBuf::from(path));
+        }
+
+        let storage = &mut self.storage;
+        env.parse("KV_STORAGE_SHARDS", &mut storage.shards, INTEGER)?;
+        if env.get("KV_STORAGE_SHARDS").is_some() {
+            storage.backend = if storage.shards > 1 {
+                Backend::Sharded
+            } else {
+                Backend::Memory
+            };
+        }
+        env.parse_str("KV_BACKEND", &mut storage.backend)?;
+        env.optional("KV_SHARDS", &mut storage.map_shards)?;
+        env.parse_str("KV_EVICTION_POLICY", &mut storage.eviction_policy)?;
+        env.flag("KV_INDEX_VALUES", &mut storage.index_values)?;
+        env.parse("KV_HISTORY_LIMIT", &mut storage.history_limit, INTEGER)?;
+        env.optional(
+            "KV_COMPRESS_THRESHOLD_BYTES",
+            &mut storage.compress_threshold_bytes,
+        )?;
+        env.flag("KV_TRACK_ACCESS", &mut storage.track_access)?;
+        env.optional("KV_NEGATIVE_CACHE_MS", &mut storage.negative_cache_ms)?;
+        if let Some(names) = env.get("KV_VALUE_TRANSFORMS") {
+            storage.value_transforms = names
+                .split(',')
+                .map(str::trim)
+                .filter(|name| !name.is_empty())
+                .map(str::to_string)
+                .collect();
+        }
+
+        let limits = &mut self.limits;
+        env.optional("KV_MAX_KEYS", &mut limits.max_keys)?;
+        env.optional("KV_MAX_BYTES", &mut limits.max_bytes)?;
+        env.parse("KV_MAX_VALUE_BYTES", &mut limits.max_value_bytes, INTEGER)?;
+        if let Some(pairs) = env.get("KV_MAX_VALUE_BYTES_BY_PREFIX") {
+            limits.max_value_bytes_by_prefix = pairs
+                .split(',')
+                .map(str::trim)
+                .filter(|pair| !pair.is_empty())
+                .map(|pair| {
+                    let (prefix, bytes) = pair.split_once('=').ok_or_else(|| {
+                        ConfigError(format!(
+                            "KV_MAX_VALUE_BYTES_BY_PREFIX must hold prefix=bytes pairs, got '{}'",
+                            pair
+                        ))
+                    })?;
+                    let bytes = parse_value("KV_MAX_VALUE_BYTES_BY_PREFIX", bytes, INTEGER)?;
+                    Ok((prefix.trim().to_string(), bytes))
+                })
+                .collect::<Result<_, ConfigError>>()?;
+        }
+        env.optional("KV_MAX_SET_MEMBERS", &mut limits.max_set_members)?;
+
+        let ttl = &mut self.ttl;
+        env.optional("KV_DEFAULT_TTL_SECONDS", &mut ttl.default_seconds)?;
+        env.parse("KV_EXPIRY_SWEEP_SECS", &mut ttl.sweep_seconds, INTEGER)?;
+        Ok(())
+    }
+
+    /// Checks the settings against each other, reporting every problem at
+    /// once.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let mut problems = Vec::new();
+        match self.storage.backend {
+            Backend::Memory if self.storage.shards != 1 => problems.push(format!(
+                "storage.shards is {} but only the sharded backend uses more than one store",
+                self.storage.shards
+            )),
+            Backend::Sharded if self.storage.shards < 2 => problems.push(format!(
+                "storage.shards must be at least 2 for the sharded backend, got {}",
+                self.storage.shards
+            )),
+            _ => {}
+        }
+        if let Some(shards) = self.storage.map_shards
+            && !(shards > 1 && shards.is_power_of_two())
+        {
+            problems.push(format!(
+                "storage.map_shards must be a power of two greater than 1, got {}",
+                shards
+            ));
+        }
+        if let Err(err) = TransformChain::from_names(&self.storage.value_transforms) {
+            problems.push(format!("storage.value_transforms: {}", err));
+        }
+        if self.server.max_body_bytes == 0 {
+            problems.push("server.max_body_bytes must be greater than 0".to_string());
+        }
+        if self.limits.max_value_bytes == 0 {
+            problems.push("limits.max_value_bytes must be greater than 0".to_string());
+        }
+        for (prefix, bytes) in &self.limits.max_value_bytes_by_prefix {
+            if prefix.is_empty() {
+                problems.push(
+                    "limits.max_value_bytes_by_prefix: use max_value_bytes for the limit of \
+                     every key instead of an empty prefix"
+                        .to_string(),
+                );
+            } else if *bytes == 0 {
+                problems.push(format!(
+                    "limits.max_value_bytes_by_prefix: the limit for '{}' must be greater than 0",
+                    prefix
+                ));
+            } else if *bytes > self.limits.max_value_bytes {
+                problems.push(format!(
+                    "limits.max_value_bytes_by_prefix: the limit for '{}' ({} bytes) is over \
+                     limits.max_value_bytes ({} bytes), which still applies to every key",
+                    prefix, bytes, self.limits.max_value_bytes
+                ));
+            } else if *bytes > self.server.max_body_bytes {
+                problems.push(format!(
+                    "limits.max_value_bytes_by_prefix: the limit for '{}' ({} bytes) is over \
+                     server.max_body_bytes ({} bytes), so no request could reach it",
+                    prefix, bytes, self.server.max_body_bytes
+                ));
+            }
+        }
+        if self.ttl.sweep_seconds == 0 {
+            problems.push("ttl.sweep_seconds must be greater than 0".to_string());
+        }
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(ConfigError(problems.join("; ")))
+        }
+    }
+
+    pub fn in_memory_config(&self) -> InMemoryConfig {
+        InMemoryConfig {
+            max_keys: self.limits.max_keys,
+            eviction_policy: self.storage.eviction_policy,
+            index_values: self.storage.index_values,
+            shards: self.storage.map_shards,
+            max_bytes: self.limits.max_bytes,
+            history_limit: self.storage.history_limit,
+            default_ttl: self
+                .ttl
+                .default_seconds
+                .filter(|seconds| *seconds > 0)
+                .map(Duration::from_secs),
+            max_value_bytes: self.limits.max_value_bytes,
+            compress_threshold: self
+                .storage
+                .compress_threshold_bytes
+                .filter(|bytes| *bytes > 0),
+            max_set_members: self.limits.max_set_members.filter(|members| *members > 0),
+            track_access: self.storage.track_access,
+        }
+    }
+
+    /// The chain built from `storage.value_transforms`; empty when none are
+    /// configured or a name is unknown (which `validate` reports).
+    pub fn value_transforms(&self) -> TransformChain {
+        TransformChain::from_names(&self.storage.value_transforms).unwrap_or_default()
+    }
+
+    pub fn negative_cache_ttl(&self) -> Option<Duration> {
+        self.storage
+            .negative_cache_ms
+            .filter(|millis| *millis > 0)
+            .map(Duration::from_millis)
+    }
+}
+
+const INTEGER: &str = "a non-negative integer";
+
+/// Deserializes a setting from its string form, so the file accepts the same
+/// spellings as the environment.
+fn parsed<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr<Err = String>,
+{
+    let text = String::deserialize(deserializer)?;
+    text.parse().map_err(serde::de::Error::custom)
+}
+
+fn parse_value<T: FromStr>(name: &str, value: &str, expected: &str) -> Result<T, ConfigError> {
+    value
+        .trim()
+        .parse()
+        .map_err(|_| ConfigError(format!("{} must be {}, got '{}'", name, expected, value)))
+}
+
+struct Env<F>(F);
+
+impl<F: Fn(&str) -> Option<String>> Env<F> {
+    fn get(&self, name: &str) -> Option<String> {
+        (self.0)(name)
+    }
+
+    fn parse<T: FromStr>(
+        &self,
+        name: &str,
+        field: &mut T,
+        expected: &str,
+    ) -> Result<(), ConfigError> {
+        if let Some(value) = self.get(name) {
+            *field = parse_value(name, &value, expected)?;
+        }
+        Ok(())
+    }
+
+    fn optional<T: FromStr>(&self, name: &str, field: &mut Option<T>) -> Result<(), ConfigError> {
+        if let Some(value) = self.get(name) {
+            *field = Some(parse_value(name, &value, INTEGER)?);
+        }
+        Ok(())
+    }
+
+    fn parse_str<T: FromStr<Err = String>>(
+        &self,
+        name: &str,
+        field: &mut T,
+    ) -> Result<(), ConfigError> {
+        if let Some(value) = self.get(name) {
+            *field = value
+                .parse()
+                .map_err(|err| ConfigError(format!("{}: {}", name, err)))?;
+        }
+        Ok(())
+    }
+
+    fn flag(&self, name: &str, field: &mut bool) -> Result<(), ConfigError> {
+        if let Some(value) = self.get(name) {
+            *field = match value.trim().to_ascii_lowercase().as_str() {
+                "1" | "true" => true,
+                "0" | "false" | "" => false,
+                _ => {
+                    return Err(ConfigError(format!(
+                        "{} must be true or false, got '{}'",
+                        name, value
+                    )));
+                }
+            };
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn env(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        move |name| vars.get(name).cloned()
+    }
+
+    fn config_path(name: &str, contents: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("kv-config-{}-{}.toml", std::process::id(), name));
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_defaults_without_file_or_env() {
+        let config = Config::load_from(env(&[])).unwrap();
+        assert_eq!(config.server.bind, SocketAddr::from(([0, 0, 0, 0], 8080)));
+        assert_eq!(config.storage.backend, Backend::Memory);
+        assert_eq!(config.ttl.sweep_seconds, 1);
+        assert_eq!(config.negative_cache_ttl(), None);
+
+        let storage = config.in_memory_config();
+        let defaults = InMemoryConfig::default();
+        assert_eq!(storage.max_keys, defaults.max_keys);
+        assert_eq!(storage.max_value_bytes, defaults.max_value_bytes);
+        assert_eq!(storage.default_ttl, defaults.default_ttl);
+        assert_eq!(storage.shards, defaults.shards);
+    }
+
+    #[test]
+    fn test_env_overrides_file() {
+        let path = config_path(
+            "override",
+            r#"
+            [server]
+            bind = "127.0.0.1:9000"
+            timestamp_format = "epoch_millis"
+
+            [storage]
+            backend = "sharded"
+            shards = 4
+            eviction_policy = "fifo"
+
+            [limits]
+            max_keys = 1000
+
+            [ttl]
+            default_seconds = 60
+            "#,
+        );
+        let path_text = path.to_str().unwrap().to_string();
+        let config = Config::load_from(env(&[
+            ("KV_CONFIG", &path_text),
+            ("KV_MAX_KEYS", "50"),
+            ("KV_TRACK_ACCESS", "true"),
+        ]))
+        .unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(config.server.bind, SocketAddr::from(([127, 0, 0, 1], 9000)));
+        assert_eq!(config.server.timestamp_format, TimestampFormat::EpochMillis);
+        assert_eq!(config.storage.backend, Backend::Sharded);
+        assert_eq!(config.storage.shards, 4);
+        let storage = config.in_memory_config();
+        assert_eq!(storage.eviction_policy, EvictionPolicy::Fifo);
+        assert_eq!(storage.max_keys, Some(50));
+        assert!(storage.track_access);
+        assert_eq!(storage.default_ttl, Some(Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn test_value_transforms_from_file_and_env() {
+        let config = Config::from_toml("[storage]\nvalue_transforms = [\"trim\"]\n").unwrap();
+        assert_eq!(config.value_transforms().len(), 1);
+
+        let config = Config::load_from(env(&[(
+            "KV_VALUE_TRANSFORMS",
+            "trim, reject-control-characters",
+        )]))
+        .unwrap();
+        assert_eq!(
+            config.storage.value_transforms,
+            ["trim", "reject-control-characters"]
+        );
+        assert_eq!(config.value_transforms().len(), 2);
+        assert!(
+            Config::load_from(env(&[("KV_VALUE_TRANSFORMS", "")]))
+                .unwrap()
+                .value_transforms()
+                .is_empty()
+        );
+
+        let err = Config::load_from(env(&[("KV_VALUE_TRANSFORMS", "trim,upper")])).unwrap_err();
+        assert!(
+            err.to_string()
+                .starts_with("storage.value_transforms: Unknown value transform 'upper'"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_value_limits_by_prefix_from_file_and_env() {
+        let config = Config::from_toml(
+            "[limits.max_value_bytes_by_prefix]\n\"session-\" = 256\n\"cache-\" = 65536\n",
+        )
+        .unwrap();
+        assert_eq!(
+            config.limits.max_value_bytes_by_prefix,
+            BTreeMap::from([("cache-".to_string(), 65536), ("session-".to_string(), 256)])
+        );
+
+        let config = Config::load_from(env(&[(
+            "KV_MAX_VALUE_BYTES_BY_PREFIX",
+            "session-=256, session-admin-=1024,",
+        )]))
+        .unwrap();
+        assert_eq!(
+            config.limits.max_value_bytes_by_prefix,
+            BTreeMap::from([
+                ("session-".to_string(), 256),
+                ("session-admin-".to_string(), 1024)
+            ])
+        );
+
+        let err =
+            Config::load_from(env(&[("KV_MAX_VALUE_BYTES_BY_PREFIX", "session-")])).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "KV_MAX_VALUE_BYTES_BY_PREFIX must hold prefix=bytes pairs, got 'session-'"
+        );
+        let err = Config::load_from(env(&[("KV_MAX_VALUE_BYTES_BY_PREFIX", "a=big")])).unwrap_err();
+        assert!(
+            err.to_string()
+                .starts_with("KV_MAX_VALUE_BYTES_BY_PREFIX must be a non-negative integer"),
+            "{}",
+            err
+        );
+        let err =
+            Config::load_from(env(&[("KV_MAX_VALUE_BYTES_BY_PREFIX", "a=0,=10")])).unwrap_err();
+        let message = err.to_string();
+        assert!(
+            message.contains("the limit for 'a' must be greater than 0"),
+            "{}",
+            message
+        );
+        assert!(
+            message.contains("instead of an empty prefix"),
+            "{}",
+            message
+        );
+
+        let err = Config::load_from(env(&[
+            ("KV_MAX_VALUE_BYTES", "4096"),
+            ("KV_MAX_VALUE_BYTES_BY_PREFIX", "cache-=8192"),
+        ]))
+        .unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("the limit for 'cache-' (8192 bytes) is over limits.max_value_bytes"),
+            "{}",
+            err
+        );
+        let err = Config::load_from(env(&[
+            ("KV_MAX_BODY_BYTES", "1024"),
+            ("KV_MAX_VALUE_BYTES_BY_PREFIX", "cache-=2048"),
+        ]))
+        .unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("the limit for 'cache-' (2048 bytes) is over server.max_body_bytes"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_storage_shards_env_picks_the_backend() {
+        let config = Config::load_from(env(&[("KV_STORAGE_SHARDS", "8")])).unwrap();
+        assert_eq!(config.storage.backend, Backend::Sharded);
+        assert_eq!(config.storage.shards, 8);
+
+        let config = Config::load_from(env(&[("KV_STORAGE_SHARDS", "1")])).unwrap();
+        assert_eq!(config.storage.backend, Backend::Memory);
+    }
+
+    #[test]
+    fn test_invalid_settings_are_reported() {
+        let err = Config::from_toml("[storage]\nbackend = \"disk\"\n").unwrap_err();
+        assert!(
+            err.to_string().contains("Unknown backend 'disk'"),
+            "{}",
+            err
+        );
+
+        let err = Config::from_toml("[limits]\nmax_key = 10\n").unwrap_err();
+        assert!(
+            err.to_string().contains("unknown field `max_key`"),
+            "{}",
+            err
+        );
+
+        let err = Config::load_from(env(&[("KV_MAX_KEYS", "lots")])).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "KV_MAX_KEYS must be a non-negative integer, got 'lots'"
+        );
+
+        let err = Config::load_from(env(&[("KV_INDEX_VALUES", "yes")])).unwrap_err();
+        assert!(
+            err.to_string()
+                .starts_with("KV_INDEX_VALUES must be true or false")
+        );
+
+        let err = Config::load_from(env(&[
+            ("KV_BACKEND", "sharded"),
+            ("KV_SHARDS", "3"),
+            ("KV_EXPIRY_SWEEP_SECS", "0"),
+        ]))
+        .unwrap_err();
+        let message = err.to_string();
+        assert!(
+            message.contains("storage.shards must be at least 2"),
+            "{}",
+            message
+        );
+        assert!(
+            message.contains("storage.map_shards must be a power of two"),
+            "{}",
+            message
+        );
+        assert!(
+            message.contains("ttl.sweep_seconds must be greater than 0"),
+            "{}",
+            message
+        );
+
+        let missing = std::env::temp_dir().join("kv-config-does-not-exist.toml");
+        let err = Config::load_from(env(&[("KV_CONFIG", missing.to_str().unwrap())])).unwrap_err();
+        assert!(err.to_string().starts_with("Cannot read config file"));
+    }
+
+    #[test]
+    fn test_zero_turns_optional_settings_off() {
+        let config = Config::load_from(env(&[
+            ("KV_DEFAULT_TTL_SECONDS", "0"),
+            ("KV_NEGATIVE_CACHE_MS", "0"),
+            ("KV_COMPRESS_THRESHOLD_BYTES", "0"),
+        ]))
+        .unwrap();
+        let storage = config.in_memory_config();
+        assert_eq!(storage.default_ttl, None);
+        assert_eq!(storage.compress_threshold, None);
+        assert_eq!(config.negative_cache_ttl(), None);
+    }
+}
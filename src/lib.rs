@@ -1,3 +1,4 @@
 pub mod app;
+pub mod config;
 pub mod service;
 pub mod types;
@@ -1,37 +1,127 @@
+#[cfg(feature = "otel")]
+use actix_web::middleware::Condition;
+use actix_web::middleware::from_fn;
 use actix_web::{App, HttpServer, web};
 use std::sync::Arc;
+use std::time::Duration;
 use utoipa::OpenApi;
 use utoipa_rapidoc::RapiDoc;
 use utoipa_redoc::{Redoc, Servable};
 use utoipa_scalar::{Scalar, Servable as ScalarServable};
 use utoipa_swagger_ui::SwaggerUi;
 
-mod app;
-mod service;
-mod types;
-
-use app::openapi::ApiDoc;
-use service::{InMemoryStorage, Storage};
+use kv_rust::app::{self, openapi::ApiDoc};
+use kv_rust::config::{Backend, Config};
+use kv_rust::service::{
+    AsyncStorage, InMemoryStorage, NegativeCache, ShardedStorage, Storage, TransformChain,
+    Transforming, validation,
+};
+use kv_rust::types::{key, timestamp};
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
-    println!("🚀 Server starting on http://localhost:8080");
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+
+    let config = match Config::load() {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("Invalid configuration: {}", err);
+            std::process::exit(2);
+        }
+    };
+
+    let base = format!("http://{}", config.server.bind);
+    println!("🚀 Server starting on {}", base);
     println!("📚 API Documentation:");
-    println!("   • Swagger UI: http://localhost:8080/swagger-ui");
-    println!("   • Redoc:      http://localhost:8080/redoc");
-    println!("   • RapiDoc:    http://localhost:8080/rapidoc");
-    println!("   • Scalar:     http://localhost:8080/scalar");
-    println!("📄 OpenAPI Spec: http://localhost:8080/api-docs/openapi.json");
+    println!("   • Swagger UI: {}/swagger-ui", base);
+    println!("   • Redoc:      {}/redoc", base);
+    println!("   • RapiDoc:    {}/rapidoc", base);
+    println!("   • Scalar:     {}/scalar", base);
+    println!("📄 OpenAPI Spec: {}/api-docs/openapi.json", base);
+
+    #[cfg(feature = "otel")]
+    let tracer_provider = match app::telemetry::init() {
+        Ok(provider) => provider,
+        Err(err) => {
+            eprintln!("Invalid OpenTelemetry configuration: {}", err);
+            std::process::exit(2);
+        }
+    };
+    #[cfg(feature = "otel")]
+    let traced = tracer_provider.is_some();
+    #[cfg(not(feature = "otel"))]
+    let traced = false;
+
+    timestamp::set_timestamp_format(config.server.timestamp_format);
+    app::validation::set_reject_blank_values(config.server.reject_blank_values);
+    key::set_case_insensitive(config.server.case_insensitive_keys);
+    validation::set_value_limits_by_prefix(&config.limits.max_value_bytes_by_prefix);
+
+    let storage_config = config.in_memory_config();
+    let negative_cache_ttl = config.negative_cache_ttl();
+    let transforms = config.value_transforms();
+    let storage = match config.storage.backend {
+        Backend::Sharded => with_wrappers(
+            ShardedStorage::with_config(config.storage.shards, storage_config),
+            transforms,
+            negative_cache_ttl,
+            traced,
+        ),
+        Backend::Memory => with_wrappers(
+            InMemoryStorage::with_config(storage_config),
+            transforms,
+            negative_cache_ttl,
+            traced,
+        ),
+    };
+    if let Some(path) = &config.server.seed_file {
+        let report = app::seed::seed_file(storage.as_ref(), path).await?;
+        for (key, reason) in &report.skipped {
+            log::warn!("Skipped seed key '{}': {}", key, reason);
+        }
+        log::info!(
+            "Seeded {} keys from {} ({} skipped)",
+            report.seeded,
+            path.display(),
+            report.skipped.len()
+        );
+    }
+    let sweep_interval = config.ttl.sweep_seconds;
+    let idempotency = web::Data::new(app::idempotency::IdempotencyCache::default());
+    let snapshots = web::Data::new(app::snapshot::SnapshotCache::default());
+    let sweeper_storage = storage.clone();
+    let sweeper_idempotency = idempotency.clone();
+    let sweeper_snapshots = snapshots.clone();
+    actix_web::rt::spawn(async move {
+        let mut interval = actix_web::rt::time::interval(Duration::from_secs(sweep_interval));
+        loop {
+            interval.tick().await;
+            let purged = sweeper_storage.purge_expired().await;
+            if purged > 0 {
+                log::debug!("Purged {} expired keys", purged);
+            }
+            sweeper_idempotency.purge_expired();
+            sweeper_snapshots.purge_expired();
+        }
+    });
 
-    let storage: Arc<dyn Storage + Send + Sync> = Arc::new(InMemoryStorage::new());
     let storage_data = web::Data::new(storage);
+    let server_info = web::Data::new(app::health::ServerInfo::new());
+
+    let max_body_bytes = config.server.max_body_bytes;
 
-    HttpServer::new(move || {
-        App::new()
+    #[cfg_attr(not(feature = "otel"), allow(clippy::let_and_return))]
+    let server = HttpServer::new(move || {
+        let app = App::new()
             .app_data(storage_data.clone())
-            .app_data(
-                web::JsonConfig::default().error_handler(app::error_handler::json_error_handler),
-            )
+            .app_data(server_info.clone())
+            .app_data(idempotency.clone())
+            .app_data(snapshots.clone())
+            .app_data(app::json_config(max_body_bytes))
+            .app_data(app::payload_config(max_body_bytes))
+            .app_data(app::path_config())
+            .app_data(app::query_config())
+            .wrap(from_fn(app::msgpack::negotiate))
             .service(
                 SwaggerUi::new("/swagger-ui/{_:.*}")
                     .url("/api-docs/openapi.json", ApiDoc::openapi()),
@@ -40,10 +130,75 @@ async fn main() -> std::io::Result<()> {
             .service(RapiDoc::new("/api-docs/openapi.json").path("/rapidoc"))
             .service(Scalar::with_url("/scalar", ApiDoc::openapi()))
             .service(app::health::health)
+            .service(app::health::ready)
+            .service(app::health::stats)
             .configure(app::read_ops::configure)
             .configure(app::write_ops::configure)
+            .configure(app::admin::configure);
+        #[cfg(feature = "otel")]
+        let app = app.wrap(Condition::new(
+            traced,
+            from_fn(app::telemetry::trace_requests),
+        ));
+        app
     })
-    .bind(("0.0.0.0", 8080))?
+    .bind(config.server.bind)?
     .run()
-    .await
+    .await;
+
+    #[cfg(feature = "otel")]
+    if let Some(provider) = tracer_provider
+        && let Err(err) = provider.shutdown()
+    {
+        log::warn!("Failed to flush traces: {}", err);
+    }
+    server
+}
+
+fn with_wrappers<S: Storage + Send + Sync + 'static>(
+    storage: S,
+    transforms: TransformChain,
+    negative_cache_ttl: Option<Duration>,
+    traced: bool,
+) -> Arc<dyn AsyncStorage> {
+    if transforms.is_empty() {
+        with_negative_cache(storage, negative_cache_ttl, traced)
+    } else {
+        with_negative_cache(
+            Transforming::new(storage, transforms),
+            negative_cache_ttl,
+            traced,
+        )
+    }
+}
+
+fn with_negative_cache<S: Storage + Send + Sync + 'static>(
+    storage: S,
+    ttl: Option<Duration>,
+    traced: bool,
+) -> Arc<dyn AsyncStorage> {
+    match ttl {
+        Some(ttl) => with_tracing(NegativeCache::new(storage, ttl), traced),
+        None => with_tracing(storage, traced),
+    }
+}
+
+#[cfg(feature = "otel")]
+fn with_tracing<S: Storage + Send + Sync + 'static>(
+    storage: S,
+    traced: bool,
+) -> Arc<dyn AsyncStorage> {
+    if traced {
+        Arc::new(kv_rust::service::Traced::new(storage))
+    } else {
+        Arc::new(storage)
+    }
+}
+
+#[cfg(not(feature = "otel"))]
+fn with_tracing<S: Storage + Send + Sync + 'static>(
+    storage: S,
+    _traced: bool,
+) -> Arc<dyn AsyncStorage> {
+    Arc::new(storage)
 }
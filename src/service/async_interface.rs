@@ -0,0 +1,486 @@
+use crate::app::models::{
+    CounterBounds, CounterOverflow, CreateKVRequest, KeyValueResponse, ListSide, Metadata,
+    ValueResponse,
+};
+use crate::service::{
+    CasOutcome, KeyFilter, MemoryUsage, OpResult, RestoreTarget, SearchPage, Storage, StorageError,
+    TxError, TxOp, UpsertOutcome, ValueSearch, VersionPrecondition, WriteOp, WriteOutcome,
+};
+use crate::types::Key;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::ops::Bound;
+use std::time::Duration;
+
+#[async_trait]
+pub trait AsyncStorage: Send + Sync {
+    async fn get(&self, key: Key) -> Result<ValueResponse, StorageError>;
+    async fn insert(&self, body: CreateKVRequest) -> Result<KeyValueResponse, StorageError>;
+    async fn upsert(
+        &self,
+        body: CreateKVRequest,
+    ) -> Result<(KeyValueResponse, UpsertOutcome), StorageError>;
+    async fn upsert_if_version(
+        &self,
+        body: CreateKVRequest,
+        expected: &VersionPrecondition,
+    ) -> Result<KeyValueResponse, StorageError>;
+    async fn delete(&self, key: Key) -> Result<ValueResponse, StorageError>;
+    async fn delete_silent(&self, key: Key) -> Result<(), StorageError>;
+    async fn delete_if(&self, key: Key, expected: &str) -> Result<ValueResponse, StorageError>;
+    async fn upsert_if_unmodified(
+        &self,
+        body: CreateKVRequest,
+        since: DateTime<Utc>,
+    ) -> Result<(KeyValueResponse, UpsertOutcome), StorageError>;
+    async fn delete_if_unmodified(
+        &self,
+        key: Key,
+        since: DateTime<Utc>,
+    ) -> Result<ValueResponse, StorageError>;
+    async fn history(&self, key: Key, limit: usize) -> Result<Vec<ValueResponse>, StorageError>;
+    async fn append(
+        &self,
+        key: Key,
+        suffix: &str,
+    ) -> Result<(Metadata, UpsertOutcome), StorageError>;
+    async fn merge_patch(&self, key: Key, patch: &Value) -> Result<KeyValueResponse, StorageError>;
+    async fn get_fields(&self, key: &Key) -> Result<BTreeMap<String, String>, StorageError>;
+    async fn get_field(&self, key: &Key, field: &Key) -> Result<String, StorageError>;
+    async fn set_field(
+        &self,
+        key: Key,
+        field: &Key,
+        value: &str,
+    ) -> Result<(Metadata, UpsertOutcome), StorageError>;
+    async fn delete_field(&self, key: Key, field: &Key) -> Result<(), StorageError>;
+    async fn list_push(
+        &self,
+        key: Key,
+        side: ListSide,
+        value: &str,
+    ) -> Result<(Metadata, usize, UpsertOutcome), StorageError>;
+    async fn list_pop(&self, key: Key, side: ListSide) -> Result<(String, usize), StorageError>;
+    async fn add_member(
+        &self,
+        key: Key,
+        member: &str,
+    ) -> Result<(Metadata, bool, usize), StorageError>;
+    async fn remove_member(&self, key: Key, member: &str) -> Result<(bool, usize), StorageError>;
+    async fn members(&self, key: &Key) -> Result<Vec<String>, StorageError>;
+    async fn is_member(&self, key: &Key, member: &str) -> Result<bool, StorageError>;
+    async fn create_counter(
+        &self,
+        key: Key,
+        bounds: CounterBounds,
+    ) -> Result<KeyValueResponse, StorageError>;
+    async fn counter_add(
+        &self,
+        key: Key,
+        delta: i64,
+        overflow: CounterOverflow,
+    ) -> Result<(Metadata, i64, bool), StorageError>;
+    async fn counter_reset(&self, key: Key) -> Result<(Metadata, i64), StorageError>;
+    async fn list_slice(
+        &self,
+        key: &Key,
+        start: i64,
+        end: i64,
+    ) -> Result<(Vec<String>, usize), StorageError>;
+    async fn compare_and_swap(
+        &self,
+        expected: Option<&Value>,
+        body: CreateKVRequest,
+    ) -> Result<CasOutcome, StorageError>;
+    async fn restore(
+        &self,
+        key: Key,
+        target: RestoreTarget,
+    ) -> Result<KeyValueResponse, StorageError>;
+    async fn list_keys(&self) -> Vec<Key>;
+    async fn list_entries(&self, filter: &KeyFilter, limit: usize) -> Vec<KeyValueResponse>;
+    async fn len(&self) -> usize;
+    async fn is_empty(&self) -> bool;
+    async fn set_ttl(&self, key: &Key, ttl: Duration) -> Result<Metadata, StorageError>;
+    async fn set_schema(&self, key: &Key, schema: Option<Value>) -> Result<Metadata, StorageError>;
+    async fn count_prefix(&self, prefix: &str) -> usize;
+    async fn scan_keys(&self, filter: &KeyFilter) -> Vec<Key>;
+    async fn list_range(&self, start: Bound<&str>, end: Bound<&str>, limit: usize) -> Vec<Key>;
+    async fn list_keys_after(&self, cursor: Option<&str>, limit: usize) -> Vec<Key>;
+    async fn search_values(
+        &self,
+        search: &ValueSearch,
+        after: Option<&str>,
+        limit: usize,
+    ) -> SearchPage;
+    async fn exists(&self, key: &Key) -> bool;
+    async fn random_entry(&self, prefix: Option<&str>) -> Option<KeyValueResponse>;
+    async fn largest_entries(&self, limit: usize) -> Vec<(Key, Metadata)>;
+    async fn get_metadata(&self, key: &Key) -> Result<Metadata, StorageError>;
+    async fn get_ttl(&self, key: &Key) -> Result<Option<Duration>, StorageError>;
+    async fn purge_expired(&self) -> usize;
+    async fn clear(&self) -> usize;
+    async fn memory_usage(&self) -> Option<MemoryUsage>;
+    async fn negative_cache_hits(&self) -> Option<u64>;
+    async fn ping(&self) -> Result<(), StorageError>;
+    async fn get_many(&self, keys: &[Key]) -> Vec<Result<ValueResponse, StorageError>>;
+    async fn get_stale(&self, key: Key) -> Result<ValueResponse, StorageError>;
+    async fn upsert_many(
+        &self,
+        bodies: Vec<CreateKVRequest>,
+    ) -> Vec<Result<(KeyValueResponse, UpsertOutcome), StorageError>>;
+    async fn delete_many(&self, keys: Vec<Key>) -> Vec<Result<ValueResponse, StorageError>>;
+    async fn delete_many_strict(
+        &self,
+        keys: Vec<Key>,
+    ) -> Result<Vec<Result<ValueResponse, StorageError>>, StorageError>;
+    async fn batch_apply(&self, ops: Vec<WriteOp>) -> Vec<Result<WriteOutcome, StorageError>>;
+    async fn batch_plan(&self, ops: Vec<WriteOp>) -> Vec<Result<WriteOutcome, StorageError>>;
+    async fn transaction(&self, ops: Vec<TxOp>) -> Result<Vec<OpResult>, TxError>;
+}
+
+#[async_trait]
+impl<S: Storage + Send + Sync + ?Sized> AsyncStorage for S {
+    async fn get(&self, key: Key) -> Result<ValueResponse, StorageError> {
+        Storage::get(self, key)
+    }
+
+    async fn insert(&self, body: CreateKVRequest) -> Result<KeyValueResponse, StorageError> {
+        Storage::insert(self, body)
+    }
+
+    async fn upsert(
+        &self,
+        body: CreateKVRequest,
+    ) -> Result<(KeyValueResponse, UpsertOutcome), StorageError> {
+        Storage::upsert(self, body)
+    }
+
+    async fn upsert_if_version(
+        &self,
+        body: CreateKVRequest,
+        expected: &VersionPrecondition,
+    ) -> Result<KeyValueResponse, StorageError> {
+        Storage::upsert_if_version(self, body, expected)
+    }
+
+    async fn delete(&self, key: Key) -> Result<ValueResponse, StorageError> {
+        Storage::delete(self, key)
+    }
+
+    async fn delete_silent(&self, key: Key) -> Result<(), StorageError> {
+        Storage::delete_silent(self, key)
+    }
+
+    async fn delete_if(&self, key: Key, expected: &str) -> Result<ValueResponse, StorageError> {
+        Storage::delete_if(self, key, expected)
+    }
+
+    async fn upsert_if_unmodified(
+        &self,
+        body: CreateKVRequest,
+        since: DateTime<Utc>,
+    ) -> Result<(KeyValueResponse, UpsertOutcome), StorageError> {
+        Storage::upsert_if_unmodified(self, body, since)
+    }
+
+    async fn delete_if_unmodified(
+        &self,
+        key: Key,
+        since: DateTime<Utc>,
+    ) -> Result<ValueResponse, StorageError> {
+        Storage::delete_if_unmodified(self, key, since)
+    }
+
+    async fn history(&self, key: Key, limit: usize) -> Result<Vec<ValueResponse>, StorageError> {
+        Storage::history(self, key, limit)
+    }
+
+    async fn append(
+        &self,
+        key: Key,
+        suffix: &str,
+    ) -> Result<(Metadata, UpsertOutcome), StorageError> {
+        Storage::append(self, key, suffix)
+    }
+
+    async fn merge_patch(&self, key: Key, patch: &Value) -> Result<KeyValueResponse, StorageError> {
+        Storage::merge_patch(self, key, patch)
+    }
+
+    async fn get_fields(&self, key: &Key) -> Result<BTreeMap<String, String>, StorageError> {
+        Storage::get_fields(self, key)
+    }
+
+    async fn get_field(&self, key: &Key, field: &Key) -> Result<String, StorageError> {
+        Storage::get_field(self, key, field)
+    }
+
+    async fn set_field(
+        &self,
+        key: Key,
+        field: &Key,
+        value: &str,
+    ) -> Result<(Metadata, UpsertOutcome), StorageError> {
+        Storage::set_field(self, key, field, value)
+    }
+
+    async fn delete_field(&self, key: Key, field: &Key) -> Result<(), StorageError> {
+        Storage::delete_field(self, key, field)
+    }
+
+    async fn list_push(
+        &self,
+        key: Key,
+        side: ListSide,
+        value: &str,
+    ) -> Result<(Metadata, usize, UpsertOutcome), StorageError> {
+        Storage::list_push(self, key, side, value)
+    }
+
+    async fn list_pop(&self, key: Key, side: ListSide) -> Result<(String, usize), StorageError> {
+        Storage::list_pop(self, key, side)
+    }
+
+    async fn add_member(
+        &self,
+        key: Key,
+        member: &str,
+    ) -> Result<(Metadata, bool, usize), StorageError> {
+        Storage::add_member(self, key, member)
+    }
+
+    async fn remove_member(&self, key: Key, member: &str) -> Result<(bool, usize), StorageError> {
+        Storage::remove_member(self, key, member)
+    }
+
+    async fn members(&self, key: &Key) -> Result<Vec<String>, StorageError> {
+        Storage::members(self, key)
+    }
+
+    async fn is_member(&self, key: &Key, member: &str) -> Result<bool, StorageError> {
+        Storage::is_member(self, key, member)
+    }
+
+    async fn create_counter(
+        &self,
+        key: Key,
+        bounds: CounterBounds,
+    ) -> Result<KeyValueResponse, StorageError> {
+        Storage::create_counter(self, key, bounds)
+    }
+
+    async fn counter_add(
+        &self,
+        key: Key,
+        delta: i64,
+        overflow: CounterOverflow,
+    ) -> Result<(Metadata, i64, bool), StorageError> {
+        Storage::counter_add(self, key, delta, overflow)
+    }
+
+    async fn counter_reset(&self, key: Key) -> Result<(Metadata, i64), StorageError> {
+        Storage::counter_reset(self, key)
+    }
+
+    async fn list_slice(
+        &self,
+        key: &Key,
+        start: i64,
+        end: i64,
+    ) -> Result<(Vec<String>, usize), StorageError> {
+        Storage::list_slice(self, key, start, end)
+    }
+
+    async fn compare_and_swap(
+        &self,
+        expected: Option<&Value>,
+        body: CreateKVRequest,
+    ) -> Result<CasOutcome, StorageError> {
+        Storage::compare_and_swap(self, expected, body)
+    }
+
+    async fn restore(
+        &self,
+        key: Key,
+        target: RestoreTarget,
+    ) -> Result<KeyValueResponse, StorageError> {
+        Storage::restore(self, key, target)
+    }
+
+    async fn list_keys(&self) -> Vec<Key> {
+        Storage::list_keys(self)
+    }
+
+    async fn list_entries(&self, filter: &KeyFilter, limit: usize) -> Vec<KeyValueResponse> {
+        Storage::list_entries(self, filter, limit)
+    }
+
+    async fn len(&self) -> usize {
+        Storage::len(self)
+    }
+
+    async fn is_empty(&self) -> bool {
+        Storage::is_empty(self)
+    }
+
+    async fn set_ttl(&self, key: &Key, ttl: Duration) -> Result<Metadata, StorageError> {
+        Storage::set_ttl(self, key, ttl)
+    }
+
+    async fn set_schema(&self, key: &Key, schema: Option<Value>) -> Result<Metadata, StorageError> {
+        Storage::set_schema(self, key, schema)
+    }
+
+    async fn count_prefix(&self, prefix: &str) -> usize {
+        Storage::count_prefix(self, prefix)
+    }
+
+    async fn scan_keys(&self, filter: &KeyFilter) -> Vec<Key> {
+        Storage::scan_keys(self, filter)
+    }
+
+    async fn list_range(&self, start: Bound<&str>, end: Bound<&str>, limit: usize) -> Vec<Key> {
+        Storage::list_range(self, start, end, limit)
+    }
+
+    async fn list_keys_after(&self, cursor: Option<&str>, limit: usize) -> Vec<Key> {
+        Storage::list_keys_after(self, cursor, limit)
+    }
+
+    async fn search_values(
+        &self,
+        search: &ValueSearch,
+        after: Option<&str>,
+        limit: usize,
+    ) -> SearchPage {
+        Storage::search_values(self, search, after, limit)
+    }
+
+    async fn exists(&self, key: &Key) -> bool {
+        Storage::exists(self, key)
+    }
+
+    async fn random_entry(&self, prefix: Option<&str>) -> Option<KeyValueResponse> {
+        Storage::random_entry(self, prefix)
+    }
+
+    async fn largest_entries(&self, limit: usize) -> Vec<(Key, Metadata)> {
+        Storage::largest_entries(self, limit)
+    }
+
+    async fn get_metadata(&self, key: &Key) -> Result<Metadata, StorageError> {
+        Storage::get_metadata(self, key)
+    }
+
+    async fn get_ttl(&self, key: &Key) -> Result<Option<Duration>, StorageError> {
+        Storage::get_ttl(self, key)
+    }
+
+    async fn purge_expired(&self) -> usize {
+        Storage::purge_expired(self)
+    }
+
+    async fn clear(&self) -> usize {
+        Storage::clear(self)
+    }
+
+    async fn memory_usage(&self) -> Option<MemoryUsage> {
+        Storage::memory_usage(self)
+    }
+
+    async fn negative_cache_hits(&self) -> Option<u64> {
+        Storage::negative_cache_hits(self)
+    }
+
+    async fn ping(&self) -> Result<(), StorageError> {
+        Storage::ping(self)
+    }
+
+    async fn get_many(&self, keys: &[Key]) -> Vec<Result<ValueResponse, StorageError>> {
+        Storage::get_many(self, keys)
+    }
+
+    async fn get_stale(&self, key: Key) -> Result<ValueResponse, StorageError> {
+        Storage::get_stale(self, key)
+    }
+
+    async fn upsert_many(
+        &self,
+        bodies: Vec<CreateKVRequest>,
+    ) -> Vec<Result<(KeyValueResponse, UpsertOutcome), StorageError>> {
+        Storage::upsert_many(self, bodies)
+    }
+
+    async fn delete_many(&self, keys: Vec<Key>) -> Vec<Result<ValueResponse, StorageError>> {
+        Storage::delete_many(self, keys)
+    }
+
+    async fn delete_many_strict(
+        &self,
+        keys: Vec<Key>,
+    ) -> Result<Vec<Result<ValueResponse, StorageError>>, StorageError> {
+        Storage::delete_many_strict(self, keys)
+    }
+
+    async fn batch_apply(&self, ops: Vec<WriteOp>) -> Vec<Result<WriteOutcome, StorageError>> {
+        Storage::batch_apply(self, ops)
+    }
+
+    async fn batch_plan(&self, ops: Vec<WriteOp>) -> Vec<Result<WriteOutcome, StorageError>> {
+        Storage::batch_plan(self, ops)
+    }
+
+    async fn transaction(&self, ops: Vec<TxOp>) -> Result<Vec<OpResult>, TxError> {
+        Storage::transaction(self, ops)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::models::{ValueEncoding, ValueType};
+    use crate::service::InMemoryStorage;
+    use std::sync::Arc;
+
+    fn request(key: &str, value: &str) -> CreateKVRequest {
+        CreateKVRequest {
+            key: Key::new(key.to_string()).unwrap(),
+            value: value.into(),
+            value_type: ValueType::Text,
+            encoding: ValueEncoding::Utf8,
+            ttl_seconds: None,
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_sync_storage_is_usable_as_async_storage() {
+        let storage: Arc<dyn AsyncStorage> = Arc::new(InMemoryStorage::new());
+        let key = Key::new("user-123".to_string()).unwrap();
+
+        assert!(storage.is_empty().await);
+        storage
+            .insert(request("user-123", "John Doe"))
+            .await
+            .unwrap();
+        let (updated, outcome) = storage
+            .upsert(request("user-123", "Jane Doe"))
+            .await
+            .unwrap();
+        assert_eq!(outcome, UpsertOutcome::Updated);
+        assert_eq!(updated.metadata.version, 2);
+
+        assert_eq!(storage.get(key.clone()).await.unwrap().value, "Jane Doe");
+        assert!(storage.exists(&key).await);
+        assert_eq!(storage.list_keys().await, vec![key.clone()]);
+        assert_eq!(storage.len().await, 1);
+
+        storage.delete(key.clone()).await.unwrap();
+        assert_eq!(
+            storage.get(key.clone()).await.unwrap_err(),
+            StorageError::KeyNotFound(key)
+        );
+    }
+}
@@ -0,0 +1,499 @@
+use crate::app::models::{
+    CounterBounds, CounterOverflow, CreateKVRequest, KeyValueResponse, ListSide, Metadata,
+    ValueResponse,
+};
+use crate::service::{
+    AsyncStorage, CasOutcome, KeyFilter, MemoryUsage, OpResult, RestoreTarget, SearchPage, Storage,
+    StorageError, TxError, TxOp, UpsertOutcome, ValueSearch, VersionPrecondition, WriteOp,
+    WriteOutcome,
+};
+use crate::types::Key;
+use actix_web::web;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::ops::Bound;
+use std::sync::Arc;
+use std::time::Duration;
+
+pub struct BlockingStorage<S> {
+    inner: Arc<S>,
+}
+
+impl<S: Storage + Send + Sync + 'static> BlockingStorage<S> {
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner: Arc::new(inner),
+        }
+    }
+
+    async fn run<T, F>(&self, f: F) -> Result<T, StorageError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&S) -> T + Send + 'static,
+    {
+        let inner = self.inner.clone();
+        web::block(move || f(&inner)).await.map_err(|_| {
+            StorageError::Unavailable("the blocking storage task did not complete".to_string())
+        })
+    }
+
+    async fn run_infallible<T, F>(&self, f: F) -> T
+    where
+        T: Default + Send + 'static,
+        F: FnOnce(&S) -> T + Send + 'static,
+    {
+        self.run(f).await.unwrap_or_else(|storage_error| {
+            log::error!("{}", storage_error);
+            T::default()
+        })
+    }
+}
+
+fn owned_bound(bound: Bound<&str>) -> Bound<String> {
+    bound.map(str::to_string)
+}
+
+fn borrowed_bound(bound: &Bound<String>) -> Bound<&str> {
+    bound.as_ref().map(String::as_str)
+}
+
+#[async_trait]
+impl<S: Storage + Send + Sync + 'static> AsyncStorage for BlockingStorage<S> {
+    async fn get(&self, key: Key) -> Result<ValueResponse, StorageError> {
+        self.run(move |storage| storage.get(key)).await?
+    }
+
+    async fn insert(&self, body: CreateKVRequest) -> Result<KeyValueResponse, StorageError> {
+        self.run(move |storage| storage.insert(body)).await?
+    }
+
+    async fn upsert(
+        &self,
+        body: CreateKVRequest,
+    ) -> Result<(KeyValueResponse, UpsertOutcome), StorageError> {
+        self.run(move |storage| storage.upsert(body)).await?
+    }
+
+    async fn upsert_if_version(
+        &self,
+        body: CreateKVRequest,
+        expected: &VersionPrecondition,
+    ) -> Result<KeyValueResponse, StorageError> {
+        let expected = expected.clone();
+        self.run(move |storage| storage.upsert_if_version(body, &expected))
+            .await?
+    }
+
+    async fn delete(&self, key: Key) -> Result<ValueResponse, StorageError> {
+        self.run(move |storage| storage.delete(key)).await?
+    }
+
+    async fn delete_silent(&self, key: Key) -> Result<(), StorageError> {
+        self.run(move |storage| storage.delete_silent(key)).await?
+    }
+
+    async fn delete_if(&self, key: Key, expected: &str) -> Result<ValueResponse, StorageError> {
+        let expected = expected.to_string();
+        self.run(move |storage| storage.delete_if(key, &expected))
+            .await?
+    }
+
+    async fn upsert_if_unmodified(
+        &self,
+        body: CreateKVRequest,
+        since: DateTime<Utc>,
+    ) -> Result<(KeyValueResponse, UpsertOutcome), StorageError> {
+        self.run(move |storage| storage.upsert_if_unmodified(body, since))
+            .await?
+    }
+
+    async fn delete_if_unmodified(
+        &self,
+        key: Key,
+        since: DateTime<Utc>,
+    ) -> Result<ValueResponse, StorageError> {
+        self.run(move |storage| storage.delete_if_unmodified(key, since))
+            .await?
+    }
+
+    async fn history(&self, key: Key, limit: usize) -> Result<Vec<ValueResponse>, StorageError> {
+        self.run(move |storage| storage.history(key, limit)).await?
+    }
+
+    async fn append(
+        &self,
+        key: Key,
+        suffix: &str,
+    ) -> Result<(Metadata, UpsertOutcome), StorageError> {
+        let suffix = suffix.to_string();
+        self.run(move |storage| storage.append(key, &suffix))
+            .await?
+    }
+
+    async fn merge_patch(&self, key: Key, patch: &Value) -> Result<KeyValueResponse, StorageError> {
+        let patch = patch.clone();
+        self.run(move |storage| storage.merge_patch(key, &patch))
+            .await?
+    }
+
+    async fn get_fields(&self, key: &Key) -> Result<BTreeMap<String, String>, StorageError> {
+        let key = key.clone();
+        self.run(move |storage| storage.get_fields(&key)).await?
+    }
+
+    async fn get_field(&self, key: &Key, field: &Key) -> Result<String, StorageError> {
+        let (key, field) = (key.clone(), field.clone());
+        self.run(move |storage| storage.get_field(&key, &field))
+            .await?
+    }
+
+    async fn set_field(
+        &self,
+        key: Key,
+        field: &Key,
+        value: &str,
+    ) -> Result<(Metadata, UpsertOutcome), StorageError> {
+        let (field, value) = (field.clone(), value.to_string());
+        self.run(move |storage| storage.set_field(key, &field, &value))
+            .await?
+    }
+
+    async fn delete_field(&self, key: Key, field: &Key) -> Result<(), StorageError> {
+        let field = field.clone();
+        self.run(move |storage| storage.delete_field(key, &field))
+            .await?
+    }
+
+    async fn list_push(
+        &self,
+        key: Key,
+        side: ListSide,
+        value: &str,
+    ) -> Result<(Metadata, usize, UpsertOutcome), StorageError> {
+        let value = value.to_string();
+        self.run(move |storage| storage.list_push(key, side, &value))
+            .await?
+    }
+
+    async fn list_pop(&self, key: Key, side: ListSide) -> Result<(String, usize), StorageError> {
+        self.run(move |storage| storage.list_pop(key, side)).await?
+    }
+
+    async fn add_member(
+        &self,
+        key: Key,
+        member: &str,
+    ) -> Result<(Metadata, bool, usize), StorageError> {
+        let member = member.to_string();
+        self.run(move |storage| storage.add_member(key, &member))
+            .await?
+    }
+
+    async fn remove_member(&self, key: Key, member: &str) -> Result<(bool, usize), StorageError> {
+        let member = member.to_string();
+        self.run(move |storage| storage.remove_member(key, &member))
+            .await?
+    }
+
+    async fn members(&self, key: &Key) -> Result<Vec<String>, StorageError> {
+        let key = key.clone();
+        self.run(move |storage| storage.members(&key)).await?
+    }
+
+    async fn is_member(&self, key: &Key, member: &str) -> Result<bool, StorageError> {
+        let (key, member) = (key.clone(), member.to_string());
+        self.run(move |storage| storage.is_member(&key, &member))
+            .await?
+    }
+
+    async fn create_counter(
+        &self,
+        key: Key,
+        bounds: CounterBounds,
+    ) -> Result<KeyValueResponse, StorageError> {
+        self.run(move |storage| storage.create_counter(key, bounds))
+            .await?
+    }
+
+    async fn counter_add(
+        &self,
+        key: Key,
+        delta: i64,
+        overflow: CounterOverflow,
+    ) -> Result<(Metadata, i64, bool), StorageError> {
+        self.run(move |storage| storage.counter_add(key, delta, overflow))
+            .await?
+    }
+
+    async fn counter_reset(&self, key: Key) -> Result<(Metadata, i64), StorageError> {
+        self.run(move |storage| storage.counter_reset(key)).await?
+    }
+
+    async fn list_slice(
+        &self,
+        key: &Key,
+        start: i64,
+        end: i64,
+    ) -> Result<(Vec<String>, usize), StorageError> {
+        let key = key.clone();
+        self.run(move |storage| storage.list_slice(&key, start, end))
+            .await?
+    }
+
+    async fn compare_and_swap(
+        &self,
+        expected: Option<&Value>,
+        body: CreateKVRequest,
+    ) -> Result<CasOutcome, StorageError> {
+        let expected = expected.cloned();
+        self.run(move |storage| storage.compare_and_swap(expected.as_ref(), body))
+            .await?
+    }
+
+    async fn restore(
+        &self,
+        key: Key,
+        target: RestoreTarget,
+    ) -> Result<KeyValueResponse, StorageError> {
+        self.run(move |storage| storage.restore(key, target))
+            .await?
+    }
+
+    async fn list_keys(&self) -> Vec<Key> {
+        self.run_infallible(|storage| storage.list_keys()).await
+    }
+
+    async fn list_entries(&self, filter: &KeyFilter, limit: usize) -> Vec<KeyValueResponse> {
+        let filter = filter.clone();
+        self.run_infallible(move |storage| storage.list_entries(&filter, limit))
+            .await
+    }
+
+    async fn len(&self) -> usize {
+        self.run_infallible(|storage| storage.len()).await
+    }
+
+    async fn is_empty(&self) -> bool {
+        self.len().await == 0
+    }
+
+    async fn set_ttl(&self, key: &Key, ttl: Duration) -> Result<Metadata, StorageError> {
+        let key = key.clone();
+        self.run(move |storage| storage.set_ttl(&key, ttl)).await?
+    }
+
+    async fn set_schema(&self, key: &Key, schema: Option<Value>) -> Result<Metadata, StorageError> {
+        let key = key.clone();
+        self.run(move |storage| storage.set_schema(&key, schema))
+            .await?
+    }
+
+    async fn count_prefix(&self, prefix: &str) -> usize {
+        let prefix = prefix.to_string();
+        self.run_infallible(move |storage| storage.count_prefix(&prefix))
+            .await
+    }
+
+    async fn scan_keys(&self, filter: &KeyFilter) -> Vec<Key> {
+        let filter = filter.clone();
+        self.run_infallible(move |storage| storage.scan_keys(&filter))
+            .await
+    }
+
+    async fn list_range(&self, start: Bound<&str>, end: Bound<&str>, limit: usize) -> Vec<Key> {
+        let (start, end) = (owned_bound(start), owned_bound(end));
+        self.run_infallible(move |storage| {
+            storage.list_range(borrowed_bound(&start), borrowed_bound(&end), limit)
+        })
+        .await
+    }
+
+    async fn list_keys_after(&self, cursor: Option<&str>, limit: usize) -> Vec<Key> {
+        let cursor = cursor.map(str::to_string);
+        self.run_infallible(move |storage| storage.list_keys_after(cursor.as_deref(), limit))
+            .await
+    }
+
+    async fn search_values(
+        &self,
+        search: &ValueSearch,
+        after: Option<&str>,
+        limit: usize,
+    ) -> SearchPage {
+        let search = search.clone();
+        let after = after.map(str::to_string);
+        self.run_infallible(move |storage| storage.search_values(&search, after.as_deref(), limit))
+            .await
+    }
+
+    async fn exists(&self, key: &Key) -> bool {
+        let key = key.clone();
+        self.run_infallible(move |storage| storage.exists(&key))
+            .await
+    }
+
+    async fn random_entry(&self, prefix: Option<&str>) -> Option<KeyValueResponse> {
+        let prefix = prefix.map(str::to_string);
+        self.run_infallible(move |storage| storage.random_entry(prefix.as_deref()))
+            .await
+    }
+
+    async fn largest_entries(&self, limit: usize) -> Vec<(Key, Metadata)> {
+        self.run_infallible(move |storage| storage.largest_entries(limit))
+            .await
+    }
+
+    async fn get_metadata(&self, key: &Key) -> Result<Metadata, StorageError> {
+        let key = key.clone();
+        self.run(move |storage| storage.get_metadata(&key)).await?
+    }
+
+    async fn get_ttl(&self, key: &Key) -> Result<Option<Duration>, StorageError> {
+        let key = key.clone();
+        self.run(move |storage| storage.get_ttl(&key)).await?
+    }
+
+    async fn purge_expired(&self) -> usize {
+        self.run_infallible(|storage| storage.purge_expired()).await
+    }
+
+    async fn clear(&self) -> usize {
+        self.run_infallible(|storage| storage.clear()).await
+    }
+
+    async fn memory_usage(&self) -> Option<MemoryUsage> {
+        self.inner.memory_usage()
+    }
+
+    async fn negative_cache_hits(&self) -> Option<u64> {
+        self.inner.negative_cache_hits()
+    }
+
+    async fn ping(&self) -> Result<(), StorageError> {
+        self.run(|storage| storage.ping()).await?
+    }
+
+    async fn get_many(&self, keys: &[Key]) -> Vec<Result<ValueResponse, StorageError>> {
+        let keys = keys.to_vec();
+        let count = keys.len();
+        match self.run(move |storage| storage.get_many(&keys)).await {
+            Ok(results) => results,
+            Err(storage_error) => vec![Err(storage_error); count],
+        }
+    }
+
+    async fn get_stale(&self, key: Key) -> Result<ValueResponse, StorageError> {
+        self.run(move |storage| storage.get_stale(key)).await?
+    }
+
+    async fn upsert_many(
+        &self,
+        bodies: Vec<CreateKVRequest>,
+    ) -> Vec<Result<(KeyValueResponse, UpsertOutcome), StorageError>> {
+        let count = bodies.len();
+        match self.run(move |storage| storage.upsert_many(bodies)).await {
+            Ok(results) => results,
+            Err(storage_error) => vec![Err(storage_error); count],
+        }
+    }
+
+    async fn delete_many(&self, keys: Vec<Key>) -> Vec<Result<ValueResponse, StorageError>> {
+        let count = keys.len();
+        match self.run(move |storage| storage.delete_many(keys)).await {
+            Ok(results) => results,
+            Err(storage_error) => vec![Err(storage_error); count],
+        }
+    }
+
+    async fn delete_many_strict(
+        &self,
+        keys: Vec<Key>,
+    ) -> Result<Vec<Result<ValueResponse, StorageError>>, StorageError> {
+        self.run(move |storage| storage.delete_many_strict(keys))
+            .await?
+    }
+
+    async fn batch_apply(&self, ops: Vec<WriteOp>) -> Vec<Result<WriteOutcome, StorageError>> {
+        let count = ops.len();
+        match self.run(move |storage| storage.batch_apply(ops)).await {
+            Ok(results) => results,
+            Err(storage_error) => vec![Err(storage_error); count],
+        }
+    }
+
+    async fn batch_plan(&self, ops: Vec<WriteOp>) -> Vec<Result<WriteOutcome, StorageError>> {
+        let count = ops.len();
+        match self.run(move |storage| storage.batch_plan(ops)).await {
+            Ok(results) => results,
+            Err(storage_error) => vec![Err(storage_error); count],
+        }
+    }
+
+    async fn transaction(&self, ops: Vec<TxOp>) -> Result<Vec<OpResult>, TxError> {
+        self.run(move |storage| storage.transaction(ops)).await?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::models::{ValueEncoding, ValueType};
+    use crate::service::InMemoryStorage;
+
+    fn key(name: &str) -> Key {
+        Key::new(name.to_string()).unwrap()
+    }
+
+    fn request(name: &str, value: &str) -> CreateKVRequest {
+        CreateKVRequest {
+            key: key(name),
+            value: value.into(),
+            value_type: ValueType::Text,
+            encoding: ValueEncoding::Utf8,
+            ttl_seconds: None,
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_blocking_storage_delegates_to_inner_storage() {
+        let storage: Arc<dyn AsyncStorage> = Arc::new(BlockingStorage::new(InMemoryStorage::new()));
+
+        storage.insert(request("order-2", "b")).await.unwrap();
+        storage.insert(request("order-1", "a")).await.unwrap();
+        assert_eq!(
+            storage.insert(request("order-1", "a")).await.unwrap_err(),
+            StorageError::KeyAlreadyExists(key("order-1"))
+        );
+        let (_, outcome) = storage.upsert(request("order-3", "ccc")).await.unwrap();
+        assert_eq!(outcome, UpsertOutcome::Created);
+
+        assert_eq!(storage.get(key("order-1")).await.unwrap().value, "a");
+        assert_eq!(
+            storage.list_keys().await,
+            vec![key("order-1"), key("order-2"), key("order-3")]
+        );
+        assert_eq!(
+            storage
+                .list_range(Bound::Excluded("order-1"), Bound::Unbounded, 1)
+                .await,
+            vec![key("order-2")]
+        );
+        assert_eq!(
+            storage.list_keys_after(Some("order-2"), 10).await,
+            vec![key("order-3")]
+        );
+        assert_eq!(storage.count_prefix("order-").await, 3);
+        assert_eq!(storage.largest_entries(1).await[0].0, key("order-3"));
+
+        let results = storage.get_many(&[key("order-2"), key("missing")]).await;
+        assert_eq!(results[0].as_ref().unwrap().value, "b");
+        assert!(results[1].is_err());
+
+        storage.delete(key("order-2")).await.unwrap();
+        assert!(!storage.exists(&key("order-2")).await);
+        assert_eq!(storage.len().await, 2);
+        assert!(storage.ping().await.is_ok());
+    }
+}
@@ -0,0 +1,37 @@
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use std::io::{Read, Write};
+
+pub fn gzip(bytes: &[u8]) -> Vec<u8> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::fast());
+    encoder
+        .write_all(bytes)
+        .and_then(|_| encoder.finish())
+        .expect("writing to a Vec cannot fail")
+}
+
+pub fn gunzip(bytes: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut decoded = Vec::new();
+    GzDecoder::new(bytes).read_to_end(&mut decoded)?;
+    Ok(decoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let text = r#"{"name": "Ada", "tags": ["a", "b"]}"#.repeat(50);
+        let compressed = gzip(text.as_bytes());
+        assert!(compressed.len() < text.len() / 5);
+        assert_eq!(gunzip(&compressed).unwrap(), text.as_bytes());
+        assert_eq!(gunzip(&gzip(b"")).unwrap(), b"");
+    }
+
+    #[test]
+    fn test_corrupt_input_is_an_error() {
+        assert!(gunzip(b"not gzip").is_err());
+    }
+}
@@ -1,9 +1,72 @@
+use crate::app::models::{SchemaViolation, ValueType};
+use crate::service::validation::ValidationError;
 use crate::types::Key;
+use chrono::{DateTime, Utc};
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum StorageError {
     KeyNotFound(Key),
+    KeysNotFound(Vec<Key>),
     KeyAlreadyExists(Key),
+    Unavailable(String),
+    VersionMismatch {
+        key: Key,
+        current: Option<String>,
+    },
+    ModifiedSince {
+        key: Key,
+        updated_at: DateTime<Utc>,
+    },
+    InsufficientStorage {
+        key: Key,
+        limit_bytes: u64,
+    },
+    ValueTooLarge {
+        key: Key,
+        size_bytes: usize,
+        limit_bytes: usize,
+    },
+    VersionNotFound(Key),
+    NotAppendable(Key),
+    NotMergeable(Key),
+    NotJson(Key),
+    BinaryValue(Key),
+    NotBinary(Key),
+    WrongType(Key),
+    FieldNotFound {
+        key: Key,
+        field: Key,
+    },
+    ListEmpty(Key),
+    TooManyMembers {
+        key: Key,
+        limit: usize,
+    },
+    TypeMismatch {
+        key: Key,
+        expected: ValueType,
+    },
+    NotCounter(Key),
+    CounterOutOfRange {
+        key: Key,
+        min: i64,
+        max: i64,
+    },
+    SchemaViolation {
+        key: Key,
+        violations: Vec<SchemaViolation>,
+    },
+    TransformFailed {
+        key: Key,
+        message: String,
+    },
+    /// A field of the request is invalid. Raised before the backend is
+    /// reached, by the checks in [`crate::service::validation`].
+    Validation(ValidationError),
+    /// The backend failed in a way the client cannot fix: an I/O error, a
+    /// lost connection, a corrupt record. The message is for the server's
+    /// logs; clients only see [`StorageError::public_message`].
+    Internal(String),
 }
 
 impl std::fmt::Display for StorageError {
@@ -12,20 +75,212 @@ impl std::fmt::Display for StorageError {
             StorageError::KeyNotFound(key) => {
                 write!(f, "The key '{}' does not exist in the store", key.as_str())
             }
+            StorageError::KeysNotFound(keys) => write!(
+                f,
+                "The keys {} do not exist in the store",
+                keys.iter()
+                    .map(|key| format!("'{}'", key.as_str()))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
             StorageError::KeyAlreadyExists(key) => {
                 write!(f, "The key '{}' already exists in the store", key.as_str())
             }
+            StorageError::Unavailable(reason) => {
+                write!(f, "The storage backend is unavailable: {}", reason)
+            }
+            StorageError::VersionMismatch { key, current: None } => write!(
+                f,
+                "The key '{}' does not exist, so no version can match",
+                key.as_str()
+            ),
+            StorageError::VersionMismatch {
+                key,
+                current: Some(current),
+            } => write!(
+                f,
+                "The key '{}' has changed; its current version is \"{}\"",
+                key.as_str(),
+                current
+            ),
+            StorageError::ModifiedSince { key, updated_at } => write!(
+                f,
+                "The key '{}' was modified at {}, after the If-Unmodified-Since date",
+                key.as_str(),
+                updated_at.to_rfc3339()
+            ),
+            StorageError::InsufficientStorage { key, limit_bytes } => write!(
+                f,
+                "Writing '{}' would exceed the memory budget of {} bytes",
+                key.as_str(),
+                limit_bytes
+            ),
+            StorageError::ValueTooLarge {
+                key,
+                size_bytes,
+                limit_bytes,
+            } => write!(
+                f,
+                "The value for '{}' is {} bytes, over the limit of {} bytes per value",
+                key.as_str(),
+                size_bytes,
+                limit_bytes
+            ),
+            StorageError::VersionNotFound(key) => write!(
+                f,
+                "No stored version of '{}' matches the restore request",
+                key.as_str()
+            ),
+            StorageError::NotAppendable(key) => write!(
+                f,
+                "The value of '{}' is not plain text and cannot be appended to",
+                key.as_str()
+            ),
+            StorageError::NotMergeable(key) => write!(
+                f,
+                "The value of '{}' is not a JSON object and cannot be merge-patched",
+                key.as_str()
+            ),
+            StorageError::NotJson(key) => write!(
+                f,
+                "The value of '{}' is not a JSON document; store it as a structured value or \
+                 with value_type 'json'",
+                key.as_str()
+            ),
+            StorageError::BinaryValue(key) => write!(
+                f,
+                "The value of '{}' is binary; read it from /keys/{}/blob",
+                key.as_str(),
+                key.as_str()
+            ),
+            StorageError::NotBinary(key) => write!(
+                f,
+                "The value of '{}' is not binary; read it from /keys/{}",
+                key.as_str(),
+                key.as_str()
+            ),
+            StorageError::WrongType(key) => write!(
+                f,
+                "The key '{}' holds a different kind of value; hashes are only reachable \
+                 through /keys/{}/fields, lists through /keys/{}/list, sets through \
+                 /keys/{}/set and plain values only outside them",
+                key.as_str(),
+                key.as_str(),
+                key.as_str(),
+                key.as_str()
+            ),
+            StorageError::FieldNotFound { key, field } => write!(
+                f,
+                "The hash '{}' has no field '{}'",
+                key.as_str(),
+                field.as_str()
+            ),
+            StorageError::ListEmpty(key) => {
+                write!(f, "The list '{}' is empty", key.as_str())
+            }
+            StorageError::TooManyMembers { key, limit } => write!(
+                f,
+                "The set '{}' already holds the maximum of {} members",
+                key.as_str(),
+                limit
+            ),
+            StorageError::TypeMismatch { key, expected } => write!(
+                f,
+                "The key '{}' has type '{}' and the new value does not match it (delete the key \
+                 to change its type)",
+                key.as_str(),
+                expected.as_str()
+            ),
+            StorageError::NotCounter(key) => write!(
+                f,
+                "The value of '{}' is not a counter; create one with POST /keys/{}/counter",
+                key.as_str(),
+                key.as_str()
+            ),
+            StorageError::CounterOutOfRange { key, min, max } => write!(
+                f,
+                "The counter '{}' must stay between {} and {}",
+                key.as_str(),
+                min,
+                max
+            ),
+            StorageError::SchemaViolation { key, violations } => {
+                write!(
+                    f,
+                    "The value for '{}' does not match the key's schema",
+                    key.as_str()
+                )?;
+                if let Some(first) = violations.first() {
+                    let at = if first.path.is_empty() {
+                        "/"
+                    } else {
+                        &first.path
+                    };
+                    write!(f, ": {} at {}", first.message, at)?;
+                }
+                match violations.len() {
+                    0 | 1 => Ok(()),
+                    count => write!(f, " (and {} more)", count - 1),
+                }
+            }
+            StorageError::TransformFailed { key, message } => write!(
+                f,
+                "The value for '{}' was rejected by a value transform: {}",
+                key.as_str(),
+                message
+            ),
+            StorageError::Validation(error) => error.fmt(f),
+            StorageError::Internal(detail) => write!(f, "Internal storage error: {}", detail),
         }
     }
 }
 
 impl std::error::Error for StorageError {}
 
+impl From<ValidationError> for StorageError {
+    fn from(error: ValidationError) -> Self {
+        StorageError::Validation(error)
+    }
+}
+
 impl StorageError {
     pub fn error_code(&self) -> &'static str {
         match self {
-            StorageError::KeyNotFound(_) => "KEY_NOT_FOUND",
+            StorageError::KeyNotFound(_) | StorageError::KeysNotFound(_) => "KEY_NOT_FOUND",
             StorageError::KeyAlreadyExists(_) => "KEY_ALREADY_EXISTS",
+            StorageError::Unavailable(_) => "STORAGE_UNAVAILABLE",
+            StorageError::VersionMismatch { .. } => "VERSION_MISMATCH",
+            StorageError::ModifiedSince { .. } => "MODIFIED_SINCE",
+            StorageError::InsufficientStorage { .. } => "INSUFFICIENT_STORAGE",
+            StorageError::ValueTooLarge { .. } => "VALUE_TOO_LARGE",
+            StorageError::VersionNotFound(_) => "VERSION_NOT_FOUND",
+            StorageError::NotAppendable(_) => "VALUE_NOT_APPENDABLE",
+            StorageError::NotMergeable(_) => "VALUE_NOT_OBJECT",
+            StorageError::NotJson(_) => "VALUE_NOT_JSON",
+            StorageError::BinaryValue(_) => "BINARY_VALUE",
+            StorageError::NotBinary(_) => "VALUE_NOT_BINARY",
+            StorageError::WrongType(_) => "WRONG_TYPE",
+            StorageError::FieldNotFound { .. } => "FIELD_NOT_FOUND",
+            StorageError::ListEmpty(_) => "LIST_EMPTY",
+            StorageError::TooManyMembers { .. } => "TOO_MANY_MEMBERS",
+            StorageError::TypeMismatch { .. } => "TYPE_MISMATCH",
+            StorageError::NotCounter(_) => "VALUE_NOT_COUNTER",
+            StorageError::CounterOutOfRange { .. } => "COUNTER_OUT_OF_RANGE",
+            StorageError::SchemaViolation { .. } => "SCHEMA_VIOLATION",
+            StorageError::TransformFailed { .. } => "TRANSFORM_FAILED",
+            StorageError::Validation(error) => error.code,
+            StorageError::Internal(_) => "INTERNAL_ERROR",
+        }
+    }
+
+    /// The message shown to clients. Internal errors can carry file paths or
+    /// connection strings, so they are replaced by a generic message.
+    pub fn public_message(&self) -> String {
+        match self {
+            StorageError::Internal(_) => {
+                "The storage backend failed unexpectedly; the error has been logged".to_string()
+            }
+            _ => self.to_string(),
         }
     }
 }
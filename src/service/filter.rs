@@ -0,0 +1,232 @@
+use crate::app::models::Metadata;
+use crate::types::Key;
+use crate::types::key::fold_case;
+use chrono::{DateTime, Utc};
+
+/// Longest pattern accepted; as long as the longest key.
+pub const MAX_PATTERN_LEN: usize = 255;
+
+/// A glob matched against whole keys: `*` matches any run of characters
+/// (including none) and `?` exactly one. Every other character matches
+/// itself. Keys cannot contain `*` or `?`, so no escaping is needed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyPattern(String);
+
+impl KeyPattern {
+    /// Parses `pattern`, rejecting it when it is empty, too long, or holds a
+    /// character no key can contain (it could never match). Lowercased when
+    /// keys are case-insensitive.
+    pub fn parse(pattern: &str) -> Result<Self, String> {
+        if pattern.is_empty() {
+            return Err("Pattern must not be empty".to_string());
+        }
+        if pattern.len() > MAX_PATTERN_LEN {
+            return Err(format!(
+                "Pattern exceeds maximum length of {} characters",
+                MAX_PATTERN_LEN
+            ));
+        }
+        if let Some(invalid) = pattern
+            .chars()
+            .find(|c| !(c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '*' | '?')))
+        {
+            return Err(format!(
+                "Pattern contains '{}'; only a-z, A-Z, 0-9, _, - and the wildcards * and ? are allowed",
+                invalid
+            ));
+        }
+        Ok(KeyPattern(fold_case(pattern).into_owned()))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Matches greedily, backtracking only to the most recent `*`, so the
+    /// cost stays linear in practice rather than exponential in the stars.
+    pub fn matches(&self, key: &str) -> bool {
+        let (pattern, key) = (self.0.as_bytes(), key.as_bytes());
+        let (mut p, mut k) = (0, 0);
+        let mut star: Option<(usize, usize)> = None;
+        while k < key.len() {
+            match pattern.get(p) {
+                Some(b'*') => {
+                    star = Some((p, k));
+                    p += 1;
+                }
+                Some(&c) if c == b'?' || c == key[k] => {
+                    p += 1;
+                    k += 1;
+                }
+                _ => match star {
+                    Some((star_p, star_k)) => {
+                        p = star_p + 1;
+                        k = star_k + 1;
+                        star = Some((star_p, star_k + 1));
+                    }
+                    None => return false,
+                },
+            }
+        }
+        pattern[p..].iter().all(|&c| c == b'*')
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct KeyFilter {
+    pub prefix: Option<String>,
+    pub pattern: Option<KeyPattern>,
+    pub updated_since: Option<DateTime<Utc>>,
+    pub created_since: Option<DateTime<Utc>>,
+}
+
+impl KeyFilter {
+    pub fn is_empty(&self) -> bool {
+        self == &KeyFilter::default()
+    }
+
+    pub fn needs_metadata(&self) -> bool {
+        self.updated_since.is_some() || self.created_since.is_some()
+    }
+
+    pub fn matches_key(&self, key: &Key) -> bool {
+        self.prefix
+            .as_deref()
+            .is_none_or(|prefix| key.as_str().starts_with(prefix))
+            && self
+                .pattern
+                .as_ref()
+                .is_none_or(|pattern| pattern.matches(key.as_str()))
+    }
+
+    pub fn matches(&self, key: &Key, metadata: &Metadata) -> bool {
+        self.matches_key(key)
+            && self
+                .updated_since
+                .is_none_or(|since| metadata.updated_at > since)
+            && self
+                .created_since
+                .is_none_or(|since| metadata.created_at > since)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::models::{ValueEncoding, ValueType};
+    use chrono::Duration;
+
+    fn metadata(created_at: DateTime<Utc>, updated_at: DateTime<Utc>) -> Metadata {
+        Metadata {
+            created_at,
+            updated_at,
+            accessed_at: None,
+            expires_at: None,
+            size_bytes: 0,
+            value_type: ValueType::Text,
+            encoding: ValueEncoding::Utf8,
+            content_type: None,
+            compressed_size_bytes: None,
+            member_count: None,
+            counter: None,
+            schema: None,
+            version: 1,
+        }
+    }
+
+    #[test]
+    fn test_empty_filter_matches_everything() {
+        let filter = KeyFilter::default();
+        let now = Utc::now();
+        assert!(filter.is_empty());
+        assert!(filter.matches(&Key::new("any".to_string()).unwrap(), &metadata(now, now)));
+    }
+
+    #[test]
+    fn test_updated_since_is_strict() {
+        let now = Utc::now();
+        let filter = KeyFilter {
+            updated_since: Some(now),
+            ..Default::default()
+        };
+        let key = Key::new("k".to_string()).unwrap();
+
+        assert!(!filter.matches(&key, &metadata(now, now)));
+        assert!(filter.matches(&key, &metadata(now, now + Duration::milliseconds(1))));
+    }
+
+    #[test]
+    fn test_created_since_and_prefix_combine() {
+        let now = Utc::now();
+        let later = now + Duration::seconds(1);
+        let filter = KeyFilter {
+            prefix: Some("user-".to_string()),
+            created_since: Some(now),
+            ..Default::default()
+        };
+
+        let user = Key::new("user-1".to_string()).unwrap();
+        let order = Key::new("order-1".to_string()).unwrap();
+        assert!(filter.matches(&user, &metadata(later, later)));
+        assert!(!filter.matches(&order, &metadata(later, later)));
+        assert!(!filter.matches(&user, &metadata(now, later)));
+    }
+
+    fn pattern(pattern: &str) -> KeyPattern {
+        KeyPattern::parse(pattern).unwrap()
+    }
+
+    #[test]
+    fn test_star_matches_any_run() {
+        let glob = pattern("user-*-session");
+        assert!(glob.matches("user-42-session"));
+        assert!(glob.matches("user--session"));
+        assert!(glob.matches("user-a-b-session"));
+        assert!(!glob.matches("user-42-session-old"));
+        assert!(!glob.matches("admin-42-session"));
+
+        assert!(pattern("*").matches("anything"));
+        assert!(pattern("a**b").matches("ab"));
+        assert!(pattern("*-old").matches("cache-old"));
+        assert!(!pattern("*-old").matches("cache-old-1"));
+    }
+
+    #[test]
+    fn test_question_mark_matches_one_character() {
+        let glob = pattern("order-???");
+        assert!(glob.matches("order-001"));
+        assert!(!glob.matches("order-01"));
+        assert!(!glob.matches("order-0001"));
+        assert!(pattern("?*").matches("x"));
+        assert!(!pattern("?*").matches(""));
+    }
+
+    #[test]
+    fn test_literal_pattern_matches_only_itself() {
+        let glob = pattern("config-prod");
+        assert!(glob.matches("config-prod"));
+        assert!(!glob.matches("config-prod2"));
+        assert!(!glob.matches("config-pro"));
+    }
+
+    #[test]
+    fn test_invalid_patterns_are_rejected() {
+        assert!(KeyPattern::parse("").is_err());
+        assert!(KeyPattern::parse("user.*").is_err());
+        assert!(KeyPattern::parse("[ab]*").is_err());
+        assert!(KeyPattern::parse(&"*".repeat(MAX_PATTERN_LEN + 1)).is_err());
+    }
+
+    #[test]
+    fn test_pattern_combines_with_prefix() {
+        let filter = KeyFilter {
+            prefix: Some("user-".to_string()),
+            pattern: Some(pattern("*-session")),
+            ..Default::default()
+        };
+        let key = |name: &str| Key::new(name.to_string()).unwrap();
+        assert!(filter.matches_key(&key("user-1-session")));
+        assert!(!filter.matches_key(&key("admin-1-session")));
+        assert!(!filter.matches_key(&key("user-1-token")));
+    }
+}
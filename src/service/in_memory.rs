@@ -1,10 +1,251 @@
-use crate::app::models::{CreateKVRequest, KeyValueResponse, Metadata, ValueResponse};
-use crate::service::{Storage, StorageError};
+use crate::app::models::{
+    CounterBounds, CounterOverflow, CreateKVRequest, KeyValueResponse, ListSide, Metadata,
+    StoredValue, ValueEncoding, ValueKind, ValueResponse, ValueType, content_etag_bytes,
+    sorted_members, value_text,
+};
+use crate::app::write_ops::STREAM_CHUNK_KEYS;
+use crate::service::compression::{gunzip, gzip};
+use crate::service::interface::{
+    apply_tx_op, counter_sum, modified_since, new_counter_bounds, pop_element, push_element,
+};
+use crate::service::{
+    CasOutcome, KeyFilter, LargestEntries, MemoryUsage, OpResult, RestoreTarget, SearchPage,
+    Storage, StorageError, TxError, TxOp, UpsertOutcome, ValueSearch, VersionPrecondition, WriteOp,
+    WriteOutcome, appended_value, counter_request, counter_state, hash_fields, hash_request,
+    json_document, json_schema, list_elements, list_request, list_window, patched_value,
+    set_contents, set_request,
+};
 use crate::types::Key;
-use chrono::Utc;
+use chrono::{DateTime, TimeDelta, Utc};
+use dashmap::mapref::entry::Entry as MapEntry;
+use rand::Rng;
+use serde_json::Value;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::ops::Bound;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::sync::{PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EvictionPolicy {
+    #[default]
+    Lru,
+    Fifo,
+}
+
+impl FromStr for EvictionPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "lru" => Ok(EvictionPolicy::Lru),
+            "fifo" => Ok(EvictionPolicy::Fifo),
+            other => Err(format!(
+                "Unknown eviction policy '{}' (expected 'lru' or 'fifo')",
+                other
+            )),
+        }
+    }
+}
+
+/// Largest value accepted by a single write unless configured otherwise.
+pub const DEFAULT_MAX_VALUE_BYTES: usize = 1024 * 1024;
+
+#[derive(Debug, Clone)]
+pub struct InMemoryConfig {
+    pub max_keys: Option<usize>,
+    pub eviction_policy: EvictionPolicy,
+    pub index_values: bool,
+    pub shards: Option<usize>,
+    pub max_bytes: Option<u64>,
+    pub history_limit: usize,
+    pub default_ttl: Option<Duration>,
+    pub max_value_bytes: usize,
+    pub compress_threshold: Option<usize>,
+    pub max_set_members: Option<usize>,
+    /// Stamp `metadata.accessed_at` on every successful `get`. Off by
+    /// default: it turns each read into a write to the entry.
+    pub track_access: bool,
+}
+
+impl Default for InMemoryConfig {
+    fn default() -> Self {
+        Self {
+            max_keys: None,
+            eviction_policy: EvictionPolicy::default(),
+            index_values: false,
+            shards: None,
+            max_bytes: None,
+            history_limit: 0,
+            default_ttl: None,
+            max_value_bytes: DEFAULT_MAX_VALUE_BYTES,
+            compress_threshold: None,
+            max_set_members: None,
+            track_access: false,
+        }
+    }
+}
+
+/// A value as held in the map. Values of at least `compress_threshold` bytes
+/// are kept gzip-compressed and inflated on every read, so nothing outside
+/// this module sees the compressed form.
+#[derive(Clone)]
+enum PackedValue {
+    Plain(StoredValue),
+    Gzip {
+        bytes: Vec<u8>,
+        content_type: Option<String>,
+    },
+}
+
+#[derive(Clone)]
+struct Record {
+    value: PackedValue,
+    metadata: Metadata,
+}
+
+impl Record {
+    fn value(&self) -> Cow<'_, StoredValue> {
+        match &self.value {
+            PackedValue::Plain(value) => Cow::Borrowed(value),
+            PackedValue::Gzip {
+                bytes,
+                content_type,
+            } => {
+                let bytes = gunzip(bytes).expect("compressed values are written by this store");
+                Cow::Owned(match content_type {
+                    Some(content_type) => StoredValue::Binary {
+                        bytes,
+                        content_type: content_type.clone(),
+                    },
+                    None => StoredValue::Json(
+                        serde_json::from_slice(&bytes)
+                            .expect("compressed JSON values are written by this store"),
+                    ),
+                })
+            }
+        }
+    }
+
+    fn kind(&self) -> ValueKind {
+        match &self.value {
+            PackedValue::Plain(value) => value.kind(),
+            PackedValue::Gzip { .. } => ValueKind::Plain,
+        }
+    }
+
+    fn unpack(&self) -> ValueResponse {
+        ValueResponse {
+            value: self.value().into_owned(),
+            metadata: self.metadata.clone(),
+        }
+    }
+}
+
+struct Entry {
+    data: Record,
+    history: VecDeque<Record>,
+    inserted_tick: u64,
+    accessed_tick: AtomicU64,
+    /// Microseconds since the epoch of the last tracked `get`, or
+    /// [`NEVER_ACCESSED`]. Kept beside the record so a read can stamp it
+    /// through the shared map guard without copying the value.
+    accessed_at: AtomicI64,
+}
+
+const NEVER_ACCESSED: i64 = i64::MIN;
+
+/// A transaction's writes, applied to a scratch copy of the keys it touches
+/// but not yet to the store.
+pub(crate) struct StagedTx {
+    scratch: InMemoryStorage,
+    keys: BTreeSet<Key>,
+    last: Option<Key>,
+}
+
+impl Clone for Entry {
+    fn clone(&self) -> Self {
+        Self {
+            data: self.data.clone(),
+            history: self.history.clone(),
+            inserted_tick: self.inserted_tick,
+            accessed_tick: AtomicU64::new(self.accessed_tick.load(Ordering::Relaxed)),
+            accessed_at: AtomicI64::new(self.accessed_at.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+impl Entry {
+    fn new(data: Record, tick: u64) -> Self {
+        Self {
+            data,
+            history: VecDeque::new(),
+            inserted_tick: tick,
+            accessed_tick: AtomicU64::new(tick),
+            accessed_at: AtomicI64::new(NEVER_ACCESSED),
+        }
+    }
+
+    fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        self.data
+            .metadata
+            .expires_at
+            .is_some_and(|expires_at| expires_at <= now)
+    }
+
+    fn touch(&self, tick: u64) {
+        self.accessed_tick.store(tick, Ordering::Relaxed);
+    }
+
+    fn record_access(&self, at: DateTime<Utc>) {
+        self.accessed_at
+            .store(at.timestamp_micros(), Ordering::Relaxed);
+    }
+
+    /// The record's metadata with the last access filled in.
+    fn metadata(&self) -> Metadata {
+        let accessed_at = match self.accessed_at.load(Ordering::Relaxed) {
+            NEVER_ACCESSED => None,
+            micros => DateTime::from_timestamp_micros(micros),
+        };
+        Metadata {
+            accessed_at,
+            ..self.data.metadata.clone()
+        }
+    }
+
+    fn unpack(&self) -> ValueResponse {
+        ValueResponse {
+            value: self.data.value().into_owned(),
+            metadata: self.metadata(),
+        }
+    }
+
+    fn eviction_rank(&self, policy: EvictionPolicy) -> u64 {
+        match policy {
+            EvictionPolicy::Lru => self.accessed_tick.load(Ordering::Relaxed),
+            EvictionPolicy::Fifo => self.inserted_tick,
+        }
+    }
+}
 
 pub struct InMemoryStorage {
-    map: dashmap::DashMap<Key, ValueResponse>,
+    map: dashmap::DashMap<Key, Entry>,
+    index: RwLock<BTreeSet<Key>>,
+    value_index: Option<RwLock<HashMap<u64, BTreeSet<Key>>>>,
+    config: InMemoryConfig,
+    clock: AtomicU64,
+    memory_bytes: AtomicU64,
+    /// Single-key writes hold this shared and transactions hold it
+    /// exclusively, so a transaction never interleaves with another write.
+    tx_gate: RwLock<()>,
+    /// Reads of entries (point reads, listings with values, searches and
+    /// counts) hold this shared and transactions hold it exclusively while
+    /// they commit, so a read sees all of a transaction or none of it.
+    commit_gate: RwLock<()>,
 }
 
 impl Default for InMemoryStorage {
@@ -15,140 +256,2651 @@ impl Default for InMemoryStorage {
 
 impl InMemoryStorage {
     pub fn new() -> Self {
+        Self::with_config(InMemoryConfig::default())
+    }
+
+    pub fn with_shards(shards: usize) -> Self {
+        Self::with_config(InMemoryConfig {
+            shards: Some(shards),
+            ..Default::default()
+        })
+    }
+
+    pub fn with_config(config: InMemoryConfig) -> Self {
+        let map = match config.shards {
+            Some(shards) => dashmap::DashMap::with_shard_amount(shards.max(2).next_power_of_two()),
+            None => dashmap::DashMap::new(),
+        };
+
         Self {
-            map: dashmap::DashMap::new(),
+            map,
+            index: RwLock::new(BTreeSet::new()),
+            value_index: config.index_values.then(|| RwLock::new(HashMap::new())),
+            config,
+            clock: AtomicU64::new(0),
+            memory_bytes: AtomicU64::new(0),
+            tx_gate: RwLock::new(()),
+            commit_gate: RwLock::new(()),
         }
     }
-}
 
-impl Storage for InMemoryStorage {
-    fn get(&self, key: Key) -> Result<ValueResponse, StorageError> {
-        self.map
-            .get(&key)
-            .map(|entry| entry.value().clone())
-            .ok_or(StorageError::KeyNotFound(key))
+    fn tick(&self) -> u64 {
+        self.clock.fetch_add(1, Ordering::Relaxed)
     }
 
-    fn insert(&self, body: CreateKVRequest) -> Result<KeyValueResponse, StorageError> {
-        if self.map.contains_key(&body.key) {
-            return Err(StorageError::KeyAlreadyExists(body.key));
+    fn write_gate(&self) -> RwLockReadGuard<'_, ()> {
+        self.tx_gate.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub(crate) fn read_gate(&self) -> RwLockReadGuard<'_, ()> {
+        self.commit_gate
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn index_read(&self) -> RwLockReadGuard<'_, BTreeSet<Key>> {
+        self.index.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn index_write(&self) -> RwLockWriteGuard<'_, BTreeSet<Key>> {
+        self.index.write().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn track_insert(&self, key: &Key, value: &StoredValue) {
+        self.index_write().insert(key.clone());
+        self.track_value(key, value);
+    }
+
+    fn track_remove(&self, key: &Key, record: &Record) {
+        self.index_write().remove(key);
+        if self.value_index.is_some() {
+            self.untrack_value(key, &record.value());
         }
+        self.memory_bytes
+            .fetch_sub(record_bytes(key, record), Ordering::Relaxed);
+    }
 
-        let now = Utc::now();
-        let value_response = ValueResponse {
-            value: body.value.clone(),
-            metadata: Metadata {
-                created_at: now,
-                updated_at: now,
+    fn reserve_bytes(&self, key: &Key, released: u64, needed: u64) -> Result<(), StorageError> {
+        self.memory_bytes
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |used| {
+                let next = used.saturating_sub(released).saturating_add(needed);
+                match self.config.max_bytes {
+                    Some(limit) if needed > released && next > limit => None,
+                    _ => Some(next),
+                }
+            })
+            .map(|_| ())
+            .map_err(|_| StorageError::InsufficientStorage {
+                key: key.clone(),
+                limit_bytes: self.config.max_bytes.unwrap_or_default(),
+            })
+    }
+
+    fn check_value_limits(&self, key: &Key, value: &StoredValue) -> Result<(), StorageError> {
+        if let (Some(limit), Some(members)) = (self.config.max_set_members, value.member_count())
+            && members > limit
+        {
+            return Err(StorageError::TooManyMembers {
+                key: key.clone(),
+                limit,
+            });
+        }
+        let size_bytes = value.size_bytes();
+        if size_bytes > self.config.max_value_bytes {
+            return Err(StorageError::ValueTooLarge {
+                key: key.clone(),
+                size_bytes,
+                limit_bytes: self.config.max_value_bytes,
+            });
+        }
+        Ok(())
+    }
+
+    fn track_value_change(&self, key: &Key, old: &Record, new: &StoredValue) {
+        if self.value_index.is_none() {
+            return;
+        }
+        let old = old.value();
+        if *old != *new {
+            self.untrack_value(key, &old);
+            self.track_value(key, new);
+        }
+    }
+
+    fn pack(&self, value: &StoredValue, mut metadata: Metadata) -> Record {
+        let compressed = self
+            .config
+            .compress_threshold
+            .filter(|threshold| value.size_bytes() >= *threshold)
+            .and_then(|_| match value {
+                StoredValue::Json(json) => Some((gzip(json.to_string().as_bytes()), None)),
+                StoredValue::Binary {
+                    bytes,
+                    content_type,
+                } => Some((gzip(bytes), Some(content_type.clone()))),
+                // Field and member writes rewrite the whole collection, so
+                // collections stay plain.
+                StoredValue::Hash(_) | StoredValue::List(_) | StoredValue::Set(_) => None,
+            })
+            .filter(|(bytes, _)| bytes.len() < value.size_bytes());
+
+        metadata.compressed_size_bytes = compressed.as_ref().map(|(bytes, _)| bytes.len());
+        let value = match compressed {
+            Some((bytes, content_type)) => PackedValue::Gzip {
+                bytes,
+                content_type,
             },
+            None => PackedValue::Plain(value.clone()),
+        };
+        Record { value, metadata }
+    }
+
+    /// Inserts a key that must not exist yet, recording `counter` as its
+    /// counter bounds.
+    fn create(
+        &self,
+        body: CreateKVRequest,
+        counter: Option<CounterBounds>,
+    ) -> Result<KeyValueResponse, StorageError> {
+        self.check_value_limits(&body.key, &body.value)?;
+        let now = Utc::now();
+        let metadata = Metadata {
+            created_at: now,
+            updated_at: now,
+            accessed_at: None,
+            expires_at: self.expires_at(body.ttl_seconds, now),
+            size_bytes: body.value.size_bytes(),
+            value_type: body.value_type,
+            encoding: body.encoding,
+            content_type: body.value.content_type().map(str::to_string),
+            compressed_size_bytes: None,
+            member_count: body.value.member_count(),
+            counter,
+            schema: None,
+            version: 1,
         };
+        let record = self.pack(&body.value, metadata);
+        let metadata = record.metadata.clone();
+        let entry = Entry::new(record, self.tick());
+
+        match self.map.entry(body.key.clone()) {
+            MapEntry::Occupied(occupied) if !occupied.get().is_expired(now) => {
+                return Err(StorageError::KeyAlreadyExists(body.key));
+            }
+            MapEntry::Occupied(mut occupied) => {
+                self.reserve_bytes(
+                    &body.key,
+                    record_bytes(&body.key, &occupied.get().data),
+                    entry_bytes(&body.key, &body.value),
+                )?;
+                self.track_value_change(&body.key, &occupied.get().data, &body.value);
+                occupied.insert(entry);
+            }
+            MapEntry::Vacant(vacant) => {
+                self.reserve_bytes(&body.key, 0, entry_bytes(&body.key, &body.value))?;
+                self.track_insert(&body.key, &body.value);
+                vacant.insert(entry);
+            }
+        }
 
-        self.map.insert(body.key.clone(), value_response.clone());
+        self.evict_if_needed(&body.key);
 
         Ok(KeyValueResponse {
             key: body.key,
             value: body.value,
-            metadata: value_response.metadata,
+            metadata,
         })
     }
 
-    fn upsert(&self, body: CreateKVRequest) -> KeyValueResponse {
+    fn overwrite(
+        &self,
+        existing: &mut Entry,
+        body: &CreateKVRequest,
+        now: DateTime<Utc>,
+        tick: u64,
+    ) -> Result<(), StorageError> {
+        let mut value_type = body.value_type;
+        let mut counter = new_counter_bounds(body);
+        let mut schema = None;
+        if !existing.is_expired(now) {
+            if existing.data.kind() != body.value.kind() {
+                return Err(StorageError::WrongType(body.key.clone()));
+            }
+            value_type = kept_value_type(existing.data.metadata.value_type, body)?;
+            counter = existing.data.metadata.counter;
+            if let Some(bounds) = counter
+                && !body
+                    .value
+                    .as_counter(body.encoding)
+                    .is_some_and(|value| bounds.contains(value))
+            {
+                return Err(StorageError::CounterOutOfRange {
+                    key: body.key.clone(),
+                    min: bounds.floor(),
+                    max: bounds.ceiling(),
+                });
+            }
+            schema = existing.data.metadata.schema.clone();
+            if let Some(schema) = &schema {
+                let document = json_document(&body.key, &body.value, value_type, body.encoding)?;
+                json_schema::validate(&body.key, schema, &document)?;
+            }
+        }
+        self.check_value_limits(&body.key, &body.value)?;
+        self.reserve_bytes(
+            &body.key,
+            record_bytes(&body.key, &existing.data),
+            entry_bytes(&body.key, &body.value),
+        )?;
+        self.track_value_change(&body.key, &existing.data, &body.value);
+        if self.config.history_limit > 0 {
+            if existing.history.len() >= self.config.history_limit {
+                existing.history.pop_front();
+            }
+            existing.history.push_back(existing.data.clone());
+        }
+        let metadata = Metadata {
+            updated_at: now,
+            expires_at: self.expires_at(body.ttl_seconds, now),
+            size_bytes: body.value.size_bytes(),
+            value_type,
+            encoding: body.encoding,
+            content_type: body.value.content_type().map(str::to_string),
+            member_count: body.value.member_count(),
+            counter,
+            schema,
+            version: existing.data.metadata.version + 1,
+            ..existing.data.metadata.clone()
+        };
+        existing.data = self.pack(&body.value, metadata);
+        existing.touch(tick);
+        Ok(())
+    }
+
+    fn expires_at(&self, ttl_seconds: Option<i64>, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let ttl = match ttl_seconds {
+            None => self.config.default_ttl?,
+            Some(seconds) => Duration::from_secs(u64::try_from(seconds).ok()?),
+        };
+        let expires_at = TimeDelta::from_std(ttl)
+            .ok()
+            .and_then(|ttl| now.checked_add_signed(ttl))
+            .unwrap_or(DateTime::<Utc>::MAX_UTC);
+        Some(expires_at)
+    }
+
+    fn track_value(&self, key: &Key, value: &StoredValue) {
+        if let Some(value_index) = &self.value_index
+            && let Some(value) = value.as_json()
+        {
+            value_index
+                .write()
+                .unwrap_or_else(PoisonError::into_inner)
+                .entry(value_hash(&value_text(value)))
+                .or_default()
+                .insert(key.clone());
+        }
+    }
+
+    fn untrack_value(&self, key: &Key, value: &StoredValue) {
+        if let Some(value_index) = &self.value_index
+            && let Some(value) = value.as_json()
+        {
+            let mut value_index = value_index.write().unwrap_or_else(PoisonError::into_inner);
+            let hash = value_hash(&value_text(value));
+            if let Some(keys) = value_index.get_mut(&hash) {
+                keys.remove(key);
+                if keys.is_empty() {
+                    value_index.remove(&hash);
+                }
+            }
+        }
+    }
+
+    fn remove_where(&self, key: &Key, predicate: impl FnOnce(&Entry) -> bool) -> Option<Entry> {
+        match self.map.entry(key.clone()) {
+            MapEntry::Occupied(occupied) if predicate(occupied.get()) => {
+                self.track_remove(key, &occupied.get().data);
+                Some(occupied.remove())
+            }
+            _ => None,
+        }
+    }
+
+    /// The body of `upsert`, for callers holding the write gate. With
+    /// `since`, a live key modified after that date is left alone.
+    fn upsert_unless_modified(
+        &self,
+        body: CreateKVRequest,
+        since: Option<DateTime<Utc>>,
+    ) -> Result<(KeyValueResponse, UpsertOutcome), StorageError> {
         let now = Utc::now();
+        let tick = self.tick();
 
-        let value_response = self
-            .map
-            .entry(body.key.clone())
-            .and_modify(|existing| {
-                existing.value = body.value.clone();
-                existing.metadata.updated_at = now;
-            })
-            .or_insert_with(|| ValueResponse {
-                value: body.value.clone(),
-                metadata: Metadata {
+        let (metadata, outcome) = match self.map.entry(body.key.clone()) {
+            MapEntry::Occupied(mut occupied) => {
+                let existing = occupied.get_mut();
+                let expired = existing.is_expired(now);
+                if !expired
+                    && let Some(since) = since
+                    && modified_since(&existing.data.metadata, since)
+                {
+                    return Err(StorageError::ModifiedSince {
+                        key: body.key,
+                        updated_at: existing.data.metadata.updated_at,
+                    });
+                }
+                self.overwrite(existing, &body, now, tick)?;
+                let outcome = if expired {
+                    existing.history.clear();
+                    existing
+                        .accessed_at
+                        .store(NEVER_ACCESSED, Ordering::Relaxed);
+                    existing.data.metadata.created_at = now;
+                    existing.data.metadata.version = 1;
+                    UpsertOutcome::Created
+                } else {
+                    UpsertOutcome::Updated
+                };
+                (existing.data.metadata.clone(), outcome)
+            }
+            MapEntry::Vacant(vacant) => {
+                let metadata = Metadata {
                     created_at: now,
                     updated_at: now,
-                },
-            });
+                    accessed_at: None,
+                    expires_at: self.expires_at(body.ttl_seconds, now),
+                    size_bytes: body.value.size_bytes(),
+                    value_type: body.value_type,
+                    encoding: body.encoding,
+                    content_type: body.value.content_type().map(str::to_string),
+                    compressed_size_bytes: None,
+                    member_count: body.value.member_count(),
+                    counter: new_counter_bounds(&body),
+                    schema: None,
+                    version: 1,
+                };
+                self.check_value_limits(&body.key, &body.value)?;
+                self.reserve_bytes(&body.key, 0, entry_bytes(&body.key, &body.value))?;
+                self.track_insert(&body.key, &body.value);
+                let record = self.pack(&body.value, metadata);
+                let metadata = record.metadata.clone();
+                vacant.insert(Entry::new(record, tick));
+                (metadata, UpsertOutcome::Created)
+            }
+        };
 
-        KeyValueResponse {
-            key: body.key,
-            value: body.value,
-            metadata: value_response.value().metadata.clone(),
+        self.evict_if_needed(&body.key);
+
+        Ok((
+            KeyValueResponse {
+                key: body.key,
+                value: body.value,
+                metadata,
+            },
+            outcome,
+        ))
+    }
+
+    fn with_live<R>(&self, key: &Key, f: impl FnOnce(&Entry) -> R) -> Option<R> {
+        let _gate = self.read_gate();
+        self.peek_live(key, f)
+    }
+
+    /// [`with_live`](Self::with_live) for callers already holding the read
+    /// gate; taking it twice can deadlock behind a waiting transaction.
+    fn peek_live<R>(&self, key: &Key, f: impl FnOnce(&Entry) -> R) -> Option<R> {
+        let now = Utc::now();
+        {
+            let entry = self.map.get(key)?;
+            if !entry.is_expired(now) {
+                return Some(f(&entry));
+            }
         }
+        self.remove_where(key, |entry| entry.is_expired(now));
+        None
     }
 
-    fn delete(&self, key: Key) -> Result<ValueResponse, StorageError> {
-        self.map
-            .remove(&key)
-            .map(|(_, value)| value)
-            .ok_or(StorageError::KeyNotFound(key))
+    fn evict_if_needed(&self, protected: &Key) {
+        let Some(max_keys) = self.config.max_keys else {
+            return;
+        };
+
+        while self.map.len() > max_keys {
+            let victim = self
+                .map
+                .iter()
+                .filter(|entry| entry.key() != protected)
+                .min_by_key(|entry| entry.value().eviction_rank(self.config.eviction_policy))
+                .map(|entry| entry.key().clone());
+
+            let Some(victim) = victim else {
+                return;
+            };
+
+            if self.remove_where(&victim, |_| true).is_some() {
+                log::info!(
+                    "Evicted key '{}' ({:?} policy, limit {} keys)",
+                    victim.as_str(),
+                    self.config.eviction_policy,
+                    max_keys
+                );
+            }
+        }
     }
 
-    fn list_keys(&self) -> Vec<Key> {
-        self.map.iter().map(|entry| entry.key().clone()).collect()
+    /// Blocks every other write until the guard is dropped; transactions
+    /// hold it from [`stage`](Self::stage) to [`commit`](Self::commit).
+    pub(crate) fn lock_writes(&self) -> RwLockWriteGuard<'_, ()> {
+        self.tx_gate.write().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Blocks point reads until the guard is dropped; transactions hold it
+    /// while they [`commit`](Self::commit).
+    pub(crate) fn lock_reads(&self) -> RwLockWriteGuard<'_, ()> {
+        self.commit_gate
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Reads `key` for a caller holding the read gate, so several keys can
+    /// be read from the same state.
+    pub(crate) fn get_gated(&self, key: &Key) -> Result<ValueResponse, StorageError> {
+        let tick = self.tick();
+        self.peek_live(key, |entry| {
+            entry.touch(tick);
+            if self.config.track_access {
+                entry.record_access(Utc::now());
+            }
+            entry.unpack()
+        })
+        .ok_or_else(|| StorageError::KeyNotFound(key.clone()))
+    }
+
+    /// [`Storage::search_values`] for a caller holding the read gate.
+    pub(crate) fn search_values_gated(
+        &self,
+        search: &ValueSearch,
+        after: Option<&str>,
+        limit: usize,
+    ) -> SearchPage {
+        let now = Utc::now();
+
+        if let (Some(value), Some(value_index)) = (search.exact_value(), &self.value_index) {
+            let candidates: Vec<Key> = value_index
+                .read()
+                .unwrap_or_else(PoisonError::into_inner)
+                .get(&value_hash(value))
+                .map(|keys| {
+                    keys.iter()
+                        .filter(|key| after.is_none_or(|after| key.as_str() > after))
+                        .cloned()
+                        .collect()
+                })
+                .unwrap_or_default();
+
+            let mut matches = Vec::new();
+            for key in candidates {
+                if matches.len() > limit {
+                    break;
+                }
+                if let Some(entry) = self.map.get(&key)
+                    && !entry.is_expired(now)
+                    && entry
+                        .data
+                        .value()
+                        .as_json()
+                        .is_some_and(|json| value_text(json) == value)
+                {
+                    let metadata = entry.data.metadata.clone();
+                    drop(entry);
+                    matches.push((key, metadata));
+                }
+            }
+            return SearchPage::from_sorted(matches, limit);
+        }
+
+        let mut matches: Vec<(Key, Metadata)> = self
+            .map
+            .iter()
+            .filter(|entry| {
+                !entry.is_expired(now)
+                    && after.is_none_or(|after| entry.key().as_str() > after)
+                    && entry
+                        .data
+                        .value()
+                        .as_json()
+                        .is_some_and(|json| search.matches(&value_text(json)))
+            })
+            .map(|entry| (entry.key().clone(), entry.data.metadata.clone()))
+            .collect();
+        matches.sort_by(|a, b| a.0.cmp(&b.0));
+
+        SearchPage::from_sorted(matches, limit)
+    }
+
+    /// [`Storage::largest_entries`] for a caller holding the read gate.
+    pub(crate) fn largest_entries_gated(&self, limit: usize) -> Vec<(Key, Metadata)> {
+        let now = Utc::now();
+        let mut largest = LargestEntries::new(limit);
+        for entry in self.map.iter() {
+            if !entry.is_expired(now) {
+                largest.offer(entry.key(), &entry.data.metadata);
+            }
+        }
+        largest.into_sorted()
+    }
+
+    /// [`Storage::count_prefix`] for a caller holding the read gate.
+    pub(crate) fn count_prefix_gated(&self, prefix: &str) -> usize {
+        let now = Utc::now();
+        let candidates: Vec<Key> = self
+            .index_read()
+            .range::<str, _>((Bound::Included(prefix), Bound::Unbounded))
+            .take_while(|key| key.as_str().starts_with(prefix))
+            .cloned()
+            .collect();
+        candidates
+            .iter()
+            .filter(|key| {
+                self.map
+                    .get(*key)
+                    .is_some_and(|entry| !entry.is_expired(now))
+            })
+            .count()
+    }
+
+    /// [`Storage::random_entry`] for a caller holding the read gate.
+    pub(crate) fn random_entry_gated(&self, prefix: Option<&str>) -> Option<KeyValueResponse> {
+        let now = Utc::now();
+        let mut rng = rand::thread_rng();
+        let mut chosen = None;
+        let mut seen = 0u64;
+
+        for entry in self.map.iter() {
+            if entry.is_expired(now)
+                || prefix.is_some_and(|prefix| !entry.key().as_str().starts_with(prefix))
+            {
+                continue;
+            }
+            seen += 1;
+            if rng.gen_range(0..seen) == 0 {
+                chosen = Some(KeyValueResponse {
+                    key: entry.key().clone(),
+                    value: entry.data.value().into_owned(),
+                    metadata: entry.metadata(),
+                });
+            }
+        }
+
+        chosen
+    }
+
+    /// [`Storage::list_entries`] for a caller holding the read gate. Reads the
+    /// index `STREAM_CHUNK_KEYS` keys at a time and stops once `limit` entries
+    /// are found, so a small page never copies the whole keyspace.
+    pub(crate) fn list_entries_gated(
+        &self,
+        filter: &KeyFilter,
+        limit: usize,
+    ) -> Vec<KeyValueResponse> {
+        let now = Utc::now();
+        let prefix = filter.prefix.as_deref().unwrap_or("");
+        let mut entries = Vec::new();
+        let mut lower = Bound::Included(prefix.to_string());
+
+        while entries.len() < limit {
+            let batch: Vec<Key> = self
+                .index_read()
+                .range::<str, _>((lower.as_ref().map(String::as_str), Bound::Unbounded))
+                .take_while(|key| key.as_str().starts_with(prefix))
+                .filter(|key| filter.matches_key(key))
+                .take(STREAM_CHUNK_KEYS)
+                .cloned()
+                .collect();
+            let Some(last) = batch.last() else {
+                break;
+            };
+            lower = Bound::Excluded(last.as_str().to_string());
+            let exhausted = batch.len() < STREAM_CHUNK_KEYS;
+
+            entries.extend(
+                batch
+                    .into_iter()
+                    .filter_map(|key| {
+                        let entry = self.map.get(&key)?;
+                        (!entry.is_expired(now) && filter.matches(&key, &entry.data.metadata)).then(
+                            || KeyValueResponse {
+                                value: entry.data.value().into_owned(),
+                                metadata: entry.metadata(),
+                                key,
+                            },
+                        )
+                    })
+                    .take(limit - entries.len()),
+            );
+            if exhausted {
+                break;
+            }
+        }
+
+        entries
+    }
+
+    /// A private store holding a copy of the live entries among `keys`, with
+    /// the same limits as this one and its memory budget already used up as
+    /// far as this one's. Writes to it never reach the store.
+    fn scratch<'a>(&self, keys: impl IntoIterator<Item = &'a Key>) -> InMemoryStorage {
+        let scratch = InMemoryStorage::with_config(InMemoryConfig {
+            max_keys: None,
+            index_values: false,
+            shards: Some(2),
+            ..self.config.clone()
+        });
+        scratch
+            .memory_bytes
+            .store(self.memory_bytes.load(Ordering::Relaxed), Ordering::Relaxed);
+        let now = Utc::now();
+        for key in keys {
+            if let Some(entry) = self.map.get(key)
+                && !entry.is_expired(now)
+            {
+                scratch.index_write().insert(key.clone());
+                scratch.map.insert(key.clone(), entry.clone());
+            }
+        }
+        scratch
+    }
+
+    /// Runs transaction ops, tagged with their position in the transaction,
+    /// against a [`scratch`](Self::scratch) copy of the keys they touch.
+    /// Nothing reaches the store until `commit`, so dropping the result
+    /// rolls the transaction back.
+    pub(crate) fn stage(
+        &self,
+        ops: Vec<(usize, TxOp)>,
+    ) -> Result<(StagedTx, Vec<OpResult>), TxError> {
+        let scratch = self.scratch(ops.iter().map(|(_, op)| op.key()));
+        let keys: BTreeSet<Key> = ops
+            .iter()
+            .filter(|(_, op)| op.writes())
+            .map(|(_, op)| op.key().clone())
+            .collect();
+
+        let last = ops
+            .iter()
+            .rev()
+            .find(|(_, op)| op.writes())
+            .map(|(_, op)| op.key().clone());
+        let results = ops
+            .into_iter()
+            .map(|(index, op)| apply_tx_op(&scratch, index, op))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok((
+            StagedTx {
+                scratch,
+                keys,
+                last,
+            },
+            results,
+        ))
+    }
+
+    /// Moves what a transaction staged into the map, key by key. Keys the
+    /// transaction deleted are absent from the scratch store.
+    pub(crate) fn commit(&self, staged: StagedTx) {
+        let StagedTx {
+            scratch,
+            keys,
+            last,
+        } = staged;
+        let tick = self.tick();
+        for key in keys {
+            let staged = scratch.map.remove(&key).map(|(_, entry)| entry);
+            match (self.map.entry(key.clone()), staged) {
+                (MapEntry::Occupied(mut occupied), Some(mut entry)) => {
+                    let old = &occupied.get().data;
+                    self.track_value_change(&key, old, &entry.data.value());
+                    self.memory_bytes
+                        .fetch_sub(record_bytes(&key, old), Ordering::Relaxed);
+                    self.memory_bytes
+                        .fetch_add(record_bytes(&key, &entry.data), Ordering::Relaxed);
+                    entry.inserted_tick = occupied.get().inserted_tick;
+                    entry.touch(tick);
+                    occupied.insert(entry);
+                }
+                (MapEntry::Occupied(occupied), None) => {
+                    self.track_remove(&key, &occupied.get().data);
+                    occupied.remove();
+                }
+                (MapEntry::Vacant(vacant), Some(mut entry)) => {
+                    self.memory_bytes
+                        .fetch_add(record_bytes(&key, &entry.data), Ordering::Relaxed);
+                    self.track_insert(&key, &entry.data.value());
+                    entry.inserted_tick = tick;
+                    entry.touch(tick);
+                    vacant.insert(entry);
+                }
+                (MapEntry::Vacant(_), None) => {}
+            }
+        }
+        if let Some(last) = last {
+            self.evict_if_needed(&last);
+        }
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::service::tests::*;
+fn entry_bytes(key: &Key, value: &StoredValue) -> u64 {
+    (key.as_str().len() + value.size_bytes()) as u64
+}
 
-    fn create_storage() -> InMemoryStorage {
-        InMemoryStorage::new()
+/// Budget accounting always uses the logical size, so compression never
+/// changes what fits under `max_bytes`.
+fn record_bytes(key: &Key, record: &Record) -> u64 {
+    (key.as_str().len() + record.metadata.size_bytes) as u64
+}
+
+/// A key keeps the type it was created with. Untyped (`text`) writes inherit
+/// it as long as the new value conforms; naming another type is a mismatch.
+fn kept_value_type(stored: ValueType, body: &CreateKVRequest) -> Result<ValueType, StorageError> {
+    let conforms = body.value_type == stored
+        || (body.value_type == ValueType::Text && stored.admits(&body.value, body.encoding));
+    if conforms {
+        Ok(stored)
+    } else {
+        Err(StorageError::TypeMismatch {
+            key: body.key.clone(),
+            expected: stored,
+        })
     }
+}
 
-    #[test]
-    fn test_in_memory_get_nonexistent() {
-        let storage = create_storage();
-        test_get_nonexistent_key(&storage);
+fn value_hash(value: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+fn range_is_empty(start: Bound<&str>, end: Bound<&str>) -> bool {
+    match (start, end) {
+        (Bound::Included(start), Bound::Included(end)) => start > end,
+        (Bound::Included(start), Bound::Excluded(end))
+        | (Bound::Excluded(start), Bound::Included(end))
+        | (Bound::Excluded(start), Bound::Excluded(end)) => start >= end,
+        _ => false,
     }
+}
 
-    #[test]
-    fn test_in_memory_upsert_new() {
-        let storage = create_storage();
-        test_upsert_new_key(&storage);
+impl Storage for InMemoryStorage {
+    fn get(&self, key: Key) -> Result<ValueResponse, StorageError> {
+        let _gate = self.read_gate();
+        self.get_gated(&key)
     }
 
-    #[test]
-    fn test_in_memory_upsert_preserves_created_at() {
-        let storage = create_storage();
-        test_upsert_existing_key_preserves_created_at(&storage);
+    fn get_many(&self, keys: &[Key]) -> Vec<Result<ValueResponse, StorageError>> {
+        let _gate = self.read_gate();
+        keys.iter().map(|key| self.get_gated(key)).collect()
     }
 
-    #[test]
-    fn test_in_memory_get_existing() {
-        let storage = create_storage();
-        test_get_existing_key(&storage);
+    fn insert(&self, body: CreateKVRequest) -> Result<KeyValueResponse, StorageError> {
+        let _gate = self.write_gate();
+        let counter = new_counter_bounds(&body);
+        self.create(body, counter)
     }
 
-    #[test]
-    fn test_in_memory_delete_existing() {
-        let storage = create_storage();
-        test_delete_existing_key(&storage);
+    fn upsert(
+        &self,
+        body: CreateKVRequest,
+    ) -> Result<(KeyValueResponse, UpsertOutcome), StorageError> {
+        let _gate = self.write_gate();
+        self.upsert_unless_modified(body, None)
     }
 
-    #[test]
-    fn test_in_memory_delete_nonexistent() {
-        let storage = create_storage();
-        test_delete_nonexistent_key(&storage);
+    /// Takes the write gate once for the whole batch rather than once per
+    /// body, so a transaction commits before or after it, not in between.
+    fn upsert_many(
+        &self,
+        bodies: Vec<CreateKVRequest>,
+    ) -> Vec<Result<(KeyValueResponse, UpsertOutcome), StorageError>> {
+        let _gate = self.write_gate();
+        bodies
+            .into_iter()
+            .map(|body| self.upsert_unless_modified(body, None))
+            .collect()
     }
 
-    #[test]
-    fn test_in_memory_list_keys_empty() {
-        let storage = create_storage();
-        test_list_keys_empty(&storage);
+    fn upsert_if_unmodified(
+        &self,
+        body: CreateKVRequest,
+        since: DateTime<Utc>,
+    ) -> Result<(KeyValueResponse, UpsertOutcome), StorageError> {
+        let _gate = self.write_gate();
+        self.upsert_unless_modified(body, Some(since))
     }
 
-    #[test]
-    fn test_in_memory_list_keys_multiple() {
-        let storage = create_storage();
-        test_list_keys_multiple(&storage);
+    fn upsert_if_version(
+        &self,
+        body: CreateKVRequest,
+        expected: &VersionPrecondition,
+    ) -> Result<KeyValueResponse, StorageError> {
+        let _gate = self.write_gate();
+        let now = Utc::now();
+        let tick = self.tick();
+
+        let metadata = match self.map.get_mut(&body.key) {
+            Some(mut existing) if !existing.is_expired(now) => {
+                let current = existing.data.value().etag();
+                if !expected.matches(&current, existing.data.metadata.version) {
+                    return Err(StorageError::VersionMismatch {
+                        key: body.key,
+                        current: Some(current),
+                    });
+                }
+                self.overwrite(&mut existing, &body, now, tick)?;
+                existing.data.metadata.clone()
+            }
+            _ => {
+                return Err(StorageError::VersionMismatch {
+                    key: body.key,
+                    current: None,
+                });
+            }
+        };
+
+        Ok(KeyValueResponse {
+            key: body.key,
+            value: body.value,
+            metadata,
+        })
     }
 
-    #[test]
-    fn test_in_memory_concurrent_access() {
-        let storage = create_storage();
-        test_concurrent_upserts(storage);
+    fn delete(&self, key: Key) -> Result<ValueResponse, StorageError> {
+        let _gate = self.write_gate();
+        let now = Utc::now();
+        self.remove_where(&key, |_| true)
+            .filter(|entry| !entry.is_expired(now))
+            .map(|entry| entry.data.unpack())
+            .ok_or(StorageError::KeyNotFound(key))
+    }
+
+    fn delete_if(&self, key: Key, expected: &str) -> Result<ValueResponse, StorageError> {
+        let _gate = self.write_gate();
+        let now = Utc::now();
+        match self.map.entry(key.clone()) {
+            MapEntry::Occupied(occupied) if !occupied.get().is_expired(now) => {
+                {
+                    let value = occupied.get().data.value();
+                    let current = value.as_bytes();
+                    if *current != *expected.as_bytes() {
+                        return Err(StorageError::VersionMismatch {
+                            key,
+                            current: Some(content_etag_bytes(&current)),
+                        });
+                    }
+                }
+                self.track_remove(&key, &occupied.get().data);
+                Ok(occupied.remove().data.unpack())
+            }
+            _ => Err(StorageError::KeyNotFound(key)),
+        }
+    }
+
+    fn delete_if_unmodified(
+        &self,
+        key: Key,
+        since: DateTime<Utc>,
+    ) -> Result<ValueResponse, StorageError> {
+        let _gate = self.write_gate();
+        let now = Utc::now();
+        match self.map.entry(key.clone()) {
+            MapEntry::Occupied(occupied) if !occupied.get().is_expired(now) => {
+                let metadata = &occupied.get().data.metadata;
+                if modified_since(metadata, since) {
+                    return Err(StorageError::ModifiedSince {
+                        key,
+                        updated_at: metadata.updated_at,
+                    });
+                }
+                self.track_remove(&key, &occupied.get().data);
+                Ok(occupied.remove().data.unpack())
+            }
+            _ => Err(StorageError::KeyNotFound(key)),
+        }
+    }
+
+    fn list_keys(&self) -> Vec<Key> {
+        self.list_range(Bound::Unbounded, Bound::Unbounded, usize::MAX)
+    }
+
+    fn list_range(&self, start: Bound<&str>, end: Bound<&str>, limit: usize) -> Vec<Key> {
+        let now = Utc::now();
+        let mut keys = Vec::new();
+        let mut lower = start.map(str::to_string);
+
+        while keys.len() < limit && !range_is_empty(lower.as_ref().map(String::as_str), end) {
+            let batch: Vec<Key> = self
+                .index_read()
+                .range::<str, _>((lower.as_ref().map(String::as_str), end))
+                .take(limit - keys.len())
+                .cloned()
+                .collect();
+            let Some(last) = batch.last() else {
+                break;
+            };
+            lower = Bound::Excluded(last.as_str().to_string());
+
+            keys.extend(batch.into_iter().filter(|key| {
+                self.map
+                    .get(key)
+                    .is_some_and(|entry| !entry.is_expired(now))
+            }));
+        }
+
+        keys
+    }
+
+    fn exists(&self, key: &Key) -> bool {
+        self.with_live(key, |_| ()).is_some()
+    }
+
+    fn search_values(&self, search: &ValueSearch, after: Option<&str>, limit: usize) -> SearchPage {
+        let _gate = self.read_gate();
+        self.search_values_gated(search, after, limit)
+    }
+
+    fn history(&self, key: Key, limit: usize) -> Result<Vec<ValueResponse>, StorageError> {
+        self.with_live(&key, |entry| {
+            entry
+                .history
+                .iter()
+                .rev()
+                .take(limit)
+                .map(Record::unpack)
+                .collect()
+        })
+        .ok_or(StorageError::KeyNotFound(key))
+    }
+
+    fn append(&self, key: Key, suffix: &str) -> Result<(Metadata, UpsertOutcome), StorageError> {
+        let _gate = self.write_gate();
+        loop {
+            let now = Utc::now();
+            if let Some(mut existing) = self.map.get_mut(&key)
+                && !existing.is_expired(now)
+            {
+                let body = CreateKVRequest {
+                    key: key.clone(),
+                    value: appended_value(&key, &existing.data.unpack(), suffix)?.into(),
+                    value_type: ValueType::Text,
+                    encoding: ValueEncoding::Utf8,
+                    ttl_seconds: None,
+                };
+                self.overwrite(&mut existing, &body, now, self.tick())?;
+                return Ok((existing.data.metadata.clone(), UpsertOutcome::Updated));
+            }
+
+            let created = self.create(
+                CreateKVRequest {
+                    key: key.clone(),
+                    value: suffix.into(),
+                    value_type: ValueType::Text,
+                    encoding: ValueEncoding::Utf8,
+                    ttl_seconds: None,
+                },
+                None,
+            );
+            match created {
+                Ok(response) => return Ok((response.metadata, UpsertOutcome::Created)),
+                Err(StorageError::KeyAlreadyExists(_)) => continue,
+                Err(storage_error) => return Err(storage_error),
+            }
+        }
+    }
+
+    fn merge_patch(&self, key: Key, patch: &Value) -> Result<KeyValueResponse, StorageError> {
+        let _gate = self.write_gate();
+        let now = Utc::now();
+        match self.map.get_mut(&key) {
+            Some(mut existing) if !existing.is_expired(now) => {
+                let body = CreateKVRequest {
+                    key: key.clone(),
+                    value: patched_value(&key, &existing.data.unpack(), patch)?.into(),
+                    value_type: existing.data.metadata.value_type,
+                    encoding: existing.data.metadata.encoding,
+                    ttl_seconds: None,
+                };
+                self.overwrite(&mut existing, &body, now, self.tick())?;
+                Ok(KeyValueResponse {
+                    key,
+                    value: body.value,
+                    metadata: existing.data.metadata.clone(),
+                })
+            }
+            _ => Err(StorageError::KeyNotFound(key)),
+        }
+    }
+
+    fn get_fields(&self, key: &Key) -> Result<BTreeMap<String, String>, StorageError> {
+        self.with_live(key, |entry| hash_fields(key, &entry.data.value()))
+            .ok_or_else(|| StorageError::KeyNotFound(key.clone()))?
+    }
+
+    fn get_field(&self, key: &Key, field: &Key) -> Result<String, StorageError> {
+        let found = self
+            .with_live(key, |entry| match &entry.data.value {
+                PackedValue::Plain(StoredValue::Hash(fields)) => {
+                    Ok(fields.get(field.as_str()).cloned())
+                }
+                _ => Err(StorageError::WrongType(key.clone())),
+            })
+            .ok_or_else(|| StorageError::KeyNotFound(key.clone()))?;
+        found?.ok_or_else(|| StorageError::FieldNotFound {
+            key: key.clone(),
+            field: field.clone(),
+        })
+    }
+
+    fn set_field(
+        &self,
+        key: Key,
+        field: &Key,
+        value: &str,
+    ) -> Result<(Metadata, UpsertOutcome), StorageError> {
+        let _gate = self.write_gate();
+        loop {
+            let now = Utc::now();
+            if let Some(mut existing) = self.map.get_mut(&key)
+                && !existing.is_expired(now)
+            {
+                let mut fields = hash_fields(&key, &existing.data.value())?;
+                let outcome = match fields.insert(field.as_str().to_string(), value.to_string()) {
+                    Some(_) => UpsertOutcome::Updated,
+                    None => UpsertOutcome::Created,
+                };
+                let body = hash_request(key.clone(), fields);
+                self.overwrite(&mut existing, &body, now, self.tick())?;
+                return Ok((existing.data.metadata.clone(), outcome));
+            }
+
+            let fields = BTreeMap::from([(field.as_str().to_string(), value.to_string())]);
+            match self.create(hash_request(key.clone(), fields), None) {
+                Ok(response) => return Ok((response.metadata, UpsertOutcome::Created)),
+                Err(StorageError::KeyAlreadyExists(_)) => continue,
+                Err(storage_error) => return Err(storage_error),
+            }
+        }
+    }
+
+    fn delete_field(&self, key: Key, field: &Key) -> Result<(), StorageError> {
+        let _gate = self.write_gate();
+        let now = Utc::now();
+        match self.map.entry(key.clone()) {
+            MapEntry::Occupied(mut occupied) if !occupied.get().is_expired(now) => {
+                let mut fields = hash_fields(&key, &occupied.get().data.value())?;
+                if fields.remove(field.as_str()).is_none() {
+                    return Err(StorageError::FieldNotFound {
+                        key,
+                        field: field.clone(),
+                    });
+                }
+                if fields.is_empty() {
+                    self.track_remove(&key, &occupied.get().data);
+                    occupied.remove();
+                } else {
+                    let body = hash_request(key, fields);
+                    self.overwrite(occupied.get_mut(), &body, now, self.tick())?;
+                }
+                Ok(())
+            }
+            _ => Err(StorageError::KeyNotFound(key)),
+        }
+    }
+
+    fn list_push(
+        &self,
+        key: Key,
+        side: ListSide,
+        value: &str,
+    ) -> Result<(Metadata, usize, UpsertOutcome), StorageError> {
+        let _gate = self.write_gate();
+        loop {
+            let now = Utc::now();
+            if let Some(mut existing) = self.map.get_mut(&key)
+                && !existing.is_expired(now)
+            {
+                let mut elements = list_elements(&key, &existing.data.value())?;
+                push_element(&mut elements, side, value);
+                let length = elements.len();
+                let body = list_request(key.clone(), elements);
+                self.overwrite(&mut existing, &body, now, self.tick())?;
+                return Ok((
+                    existing.data.metadata.clone(),
+                    length,
+                    UpsertOutcome::Updated,
+                ));
+            }
+
+            let elements = VecDeque::from([value.to_string()]);
+            match self.create(list_request(key.clone(), elements), None) {
+                Ok(response) => return Ok((response.metadata, 1, UpsertOutcome::Created)),
+                Err(StorageError::KeyAlreadyExists(_)) => continue,
+                Err(storage_error) => return Err(storage_error),
+            }
+        }
+    }
+
+    fn list_pop(&self, key: Key, side: ListSide) -> Result<(String, usize), StorageError> {
+        let _gate = self.write_gate();
+        let now = Utc::now();
+        match self.map.entry(key.clone()) {
+            MapEntry::Occupied(mut occupied) if !occupied.get().is_expired(now) => {
+                let mut elements = list_elements(&key, &occupied.get().data.value())?;
+                let Some(element) = pop_element(&mut elements, side) else {
+                    return Err(StorageError::ListEmpty(key));
+                };
+                let length = elements.len();
+                if elements.is_empty() {
+                    self.track_remove(&key, &occupied.get().data);
+                    occupied.remove();
+                } else {
+                    let body = list_request(key, elements);
+                    self.overwrite(occupied.get_mut(), &body, now, self.tick())?;
+                }
+                Ok((element, length))
+            }
+            _ => Err(StorageError::ListEmpty(key)),
+        }
+    }
+
+    fn list_slice(
+        &self,
+        key: &Key,
+        start: i64,
+        end: i64,
+    ) -> Result<(Vec<String>, usize), StorageError> {
+        self.with_live(key, |entry| match &entry.data.value {
+            PackedValue::Plain(StoredValue::List(elements)) => {
+                let window = list_window(elements.len(), start, end);
+                Ok((elements.range(window).cloned().collect(), elements.len()))
+            }
+            _ => Err(StorageError::WrongType(key.clone())),
+        })
+        .unwrap_or(Ok((Vec::new(), 0)))
+    }
+
+    fn add_member(&self, key: Key, member: &str) -> Result<(Metadata, bool, usize), StorageError> {
+        let _gate = self.write_gate();
+        loop {
+            let now = Utc::now();
+            if let Some(mut existing) = self.map.get_mut(&key)
+                && !existing.is_expired(now)
+            {
+                let mut members = set_contents(&key, &existing.data.value())?;
+                if !members.insert(member.to_string()) {
+                    return Ok((existing.data.metadata.clone(), false, members.len()));
+                }
+                let count = members.len();
+                let body = set_request(key.clone(), members);
+                self.overwrite(&mut existing, &body, now, self.tick())?;
+                return Ok((existing.data.metadata.clone(), true, count));
+            }
+
+            let members = HashSet::from([member.to_string()]);
+            match self.create(set_request(key.clone(), members), None) {
+                Ok(response) => return Ok((response.metadata, true, 1)),
+                Err(StorageError::KeyAlreadyExists(_)) => continue,
+                Err(storage_error) => return Err(storage_error),
+            }
+        }
+    }
+
+    fn remove_member(&self, key: Key, member: &str) -> Result<(bool, usize), StorageError> {
+        let _gate = self.write_gate();
+        let now = Utc::now();
+        match self.map.entry(key.clone()) {
+            MapEntry::Occupied(mut occupied) if !occupied.get().is_expired(now) => {
+                let mut members = set_contents(&key, &occupied.get().data.value())?;
+                if !members.remove(member) {
+                    return Ok((false, members.len()));
+                }
+                let count = members.len();
+                if members.is_empty() {
+                    self.track_remove(&key, &occupied.get().data);
+                    occupied.remove();
+                } else {
+                    let body = set_request(key, members);
+                    self.overwrite(occupied.get_mut(), &body, now, self.tick())?;
+                }
+                Ok((true, count))
+            }
+            _ => Ok((false, 0)),
+        }
+    }
+
+    fn members(&self, key: &Key) -> Result<Vec<String>, StorageError> {
+        self.with_live(key, |entry| match &entry.data.value {
+            PackedValue::Plain(StoredValue::Set(members)) => Ok(sorted_members(members)
+                .into_iter()
+                .map(str::to_string)
+                .collect()),
+            _ => Err(StorageError::WrongType(key.clone())),
+        })
+        .unwrap_or(Ok(Vec::new()))
+    }
+
+    fn is_member(&self, key: &Key, member: &str) -> Result<bool, StorageError> {
+        self.with_live(key, |entry| match &entry.data.value {
+            PackedValue::Plain(StoredValue::Set(members)) => Ok(members.contains(member)),
+            _ => Err(StorageError::WrongType(key.clone())),
+        })
+        .unwrap_or(Ok(false))
+    }
+
+    fn create_counter(
+        &self,
+        key: Key,
+        bounds: CounterBounds,
+    ) -> Result<KeyValueResponse, StorageError> {
+        let _gate = self.write_gate();
+        self.create(counter_request(key, bounds.initial), Some(bounds))
+    }
+
+    fn counter_add(
+        &self,
+        key: Key,
+        delta: i64,
+        overflow: CounterOverflow,
+    ) -> Result<(Metadata, i64, bool), StorageError> {
+        let _gate = self.write_gate();
+        let now = Utc::now();
+        let mut existing = self
+            .map
+            .get_mut(&key)
+            .filter(|existing| !existing.is_expired(now))
+            .ok_or_else(|| StorageError::KeyNotFound(key.clone()))?;
+        let (value, bounds) = counter_state(&key, &existing.data.unpack())?;
+        let (sum, clamped) = counter_sum(&key, &bounds, value, delta, overflow)?;
+        self.overwrite(&mut existing, &counter_request(key, sum), now, self.tick())?;
+        Ok((existing.data.metadata.clone(), sum, clamped))
+    }
+
+    fn counter_reset(&self, key: Key) -> Result<(Metadata, i64), StorageError> {
+        let _gate = self.write_gate();
+        let now = Utc::now();
+        let mut existing = self
+            .map
+            .get_mut(&key)
+            .filter(|existing| !existing.is_expired(now))
+            .ok_or_else(|| StorageError::KeyNotFound(key.clone()))?;
+        let (_, bounds) = counter_state(&key, &existing.data.unpack())?;
+        self.overwrite(
+            &mut existing,
+            &counter_request(key, bounds.initial),
+            now,
+            self.tick(),
+        )?;
+        Ok((existing.data.metadata.clone(), bounds.initial))
+    }
+
+    fn compare_and_swap(
+        &self,
+        expected: Option<&Value>,
+        body: CreateKVRequest,
+    ) -> Result<CasOutcome, StorageError> {
+        let _gate = self.write_gate();
+        let Some(expected) = expected else {
+            loop {
+                match self.create(body.clone(), new_counter_bounds(&body)) {
+                    Ok(response) => return Ok(CasOutcome::Swapped(response)),
+                    Err(StorageError::KeyAlreadyExists(key)) => {
+                        if let Ok(metadata) = self.get_metadata(&key) {
+                            return Ok(CasOutcome::Mismatch(Some(metadata)));
+                        }
+                    }
+                    Err(storage_error) => return Err(storage_error),
+                }
+            }
+        };
+
+        let now = Utc::now();
+        match self.map.get_mut(&body.key) {
+            Some(mut existing) if !existing.is_expired(now) => {
+                if *existing.data.value() != *expected {
+                    return Ok(CasOutcome::Mismatch(Some(existing.data.metadata.clone())));
+                }
+                self.overwrite(&mut existing, &body, now, self.tick())?;
+                Ok(CasOutcome::Swapped(KeyValueResponse {
+                    key: body.key.clone(),
+                    value: body.value.clone(),
+                    metadata: existing.data.metadata.clone(),
+                }))
+            }
+            _ => Ok(CasOutcome::Mismatch(None)),
+        }
+    }
+
+    fn restore(&self, key: Key, target: RestoreTarget) -> Result<KeyValueResponse, StorageError> {
+        let _gate = self.write_gate();
+        let now = Utc::now();
+        let tick = self.tick();
+
+        let (body, metadata) = match self.map.get_mut(&key) {
+            Some(mut existing) if !existing.is_expired(now) => {
+                let candidates: Vec<ValueResponse> = std::iter::once(&existing.data)
+                    .chain(existing.history.iter().rev())
+                    .map(Record::unpack)
+                    .collect();
+                let chosen = target
+                    .select(&candidates)
+                    .ok_or_else(|| StorageError::VersionNotFound(key.clone()))?;
+                let body = CreateKVRequest {
+                    key: key.clone(),
+                    value: chosen.value.clone(),
+                    value_type: chosen.metadata.value_type,
+                    encoding: chosen.metadata.encoding,
+                    ttl_seconds: None,
+                };
+                self.overwrite(&mut existing, &body, now, tick)?;
+                (body, existing.data.metadata.clone())
+            }
+            _ => return Err(StorageError::KeyNotFound(key)),
+        };
+
+        Ok(KeyValueResponse {
+            key: body.key,
+            value: body.value,
+            metadata,
+        })
+    }
+
+    fn set_schema(&self, key: &Key, schema: Option<Value>) -> Result<Metadata, StorageError> {
+        let _gate = self.write_gate();
+        let now = Utc::now();
+        match self.map.entry(key.clone()) {
+            MapEntry::Occupied(occupied) if occupied.get().is_expired(now) => {
+                self.track_remove(key, &occupied.get().data);
+                occupied.remove();
+                Err(StorageError::KeyNotFound(key.clone()))
+            }
+            MapEntry::Occupied(mut occupied) => {
+                let entry = occupied.get_mut();
+                if let Some(schema) = &schema {
+                    let metadata = &entry.data.metadata;
+                    let value = entry.data.value();
+                    let document =
+                        json_document(key, &value, metadata.value_type, metadata.encoding)?;
+                    json_schema::validate(key, schema, &document)?;
+                }
+                entry.data.metadata.schema = schema;
+                Ok(entry.metadata())
+            }
+            MapEntry::Vacant(_) => Err(StorageError::KeyNotFound(key.clone())),
+        }
+    }
+
+    fn get_metadata(&self, key: &Key) -> Result<Metadata, StorageError> {
+        self.with_live(key, Entry::metadata)
+            .ok_or_else(|| StorageError::KeyNotFound(key.clone()))
+    }
+
+    fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    fn largest_entries(&self, limit: usize) -> Vec<(Key, Metadata)> {
+        let _gate = self.read_gate();
+        self.largest_entries_gated(limit)
+    }
+
+    fn memory_usage(&self) -> Option<MemoryUsage> {
+        Some(MemoryUsage {
+            used_bytes: self.memory_bytes.load(Ordering::Relaxed),
+            limit_bytes: self.config.max_bytes,
+        })
+    }
+
+    fn set_ttl(&self, key: &Key, ttl: Duration) -> Result<Metadata, StorageError> {
+        let _gate = self.write_gate();
+        let now = Utc::now();
+        let expires_at = TimeDelta::from_std(ttl)
+            .ok()
+            .and_then(|ttl| now.checked_add_signed(ttl))
+            .unwrap_or(DateTime::<Utc>::MAX_UTC);
+
+        match self.map.entry(key.clone()) {
+            MapEntry::Occupied(occupied) if occupied.get().is_expired(now) => {
+                self.track_remove(key, &occupied.get().data);
+                occupied.remove();
+                Err(StorageError::KeyNotFound(key.clone()))
+            }
+            MapEntry::Occupied(mut occupied) => {
+                let metadata = &mut occupied.get_mut().data.metadata;
+                metadata.expires_at = Some(expires_at);
+                Ok(metadata.clone())
+            }
+            MapEntry::Vacant(_) => Err(StorageError::KeyNotFound(key.clone())),
+        }
+    }
+
+    fn purge_expired(&self) -> usize {
+        let _gate = self.write_gate();
+        let now = Utc::now();
+        let before = self.map.len();
+        self.map.retain(|key, entry| {
+            let expired = entry.is_expired(now);
+            if expired {
+                self.track_remove(key, &entry.data);
+            }
+            !expired
+        });
+        before.saturating_sub(self.map.len())
+    }
+
+    fn clear(&self) -> usize {
+        let _gate = self.write_gate();
+        let now = Utc::now();
+        let mut cleared = 0;
+        self.map.retain(|key, entry| {
+            if !entry.is_expired(now) {
+                cleared += 1;
+            }
+            self.track_remove(key, &entry.data);
+            false
+        });
+        cleared
+    }
+
+    /// Stages the ops and only commits them if every op succeeded. The
+    /// exclusive write gate is held throughout, so transactions are
+    /// serializable with respect to all other writes. The commit itself
+    /// holds the read gate exclusively, so point reads (`get`, `get_many`),
+    /// listings with values, searches and counts wait for it and see all of
+    /// a commit or none of it.
+    fn transaction(&self, ops: Vec<TxOp>) -> Result<Vec<OpResult>, TxError> {
+        let _exclusive = self.lock_writes();
+        let (staged, results) = self.stage(ops.into_iter().enumerate().collect())?;
+        let _readers = self.lock_reads();
+        self.commit(staged);
+        Ok(results)
+    }
+
+    /// Applies the ops to a [`scratch`](InMemoryStorage::scratch) copy of the
+    /// keys they touch and throws it away. Holding the write gate keeps a
+    /// transaction from committing while the copy is taken.
+    fn batch_plan(&self, ops: Vec<WriteOp>) -> Vec<Result<WriteOutcome, StorageError>> {
+        let scratch = {
+            let _gate = self.write_gate();
+            self.scratch(ops.iter().map(WriteOp::key))
+        };
+        scratch.batch_apply(ops)
+    }
+
+    /// Walks only the keys under `prefix` in the sorted index, so counting a
+    /// small tenant costs the same however large the store is.
+    fn count_prefix(&self, prefix: &str) -> usize {
+        let _gate = self.read_gate();
+        self.count_prefix_gated(prefix)
+    }
+
+    fn random_entry(&self, prefix: Option<&str>) -> Option<KeyValueResponse> {
+        let _gate = self.read_gate();
+        self.random_entry_gated(prefix)
+    }
+
+    fn scan_keys(&self, filter: &KeyFilter) -> Vec<Key> {
+        let now = Utc::now();
+        let mut keys: Vec<Key> = self
+            .map
+            .iter()
+            .filter(|entry| {
+                !entry.is_expired(now) && filter.matches(entry.key(), &entry.data.metadata)
+            })
+            .map(|entry| entry.key().clone())
+            .collect();
+        keys.sort();
+        keys
+    }
+
+    fn list_entries(&self, filter: &KeyFilter, limit: usize) -> Vec<KeyValueResponse> {
+        let _gate = self.read_gate();
+        self.list_entries_gated(filter, limit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::models::{ValueEncoding, ValueType};
+    use crate::service::tests::*;
+
+    fn create_storage() -> InMemoryStorage {
+        InMemoryStorage::new()
+    }
+
+    #[test]
+    fn test_in_memory_get_nonexistent() {
+        let storage = create_storage();
+        test_get_nonexistent_key(&storage);
+    }
+
+    #[test]
+    fn test_in_memory_upsert_new() {
+        let storage = create_storage();
+        test_upsert_new_key(&storage);
+    }
+
+    #[test]
+    fn test_in_memory_upsert_preserves_created_at() {
+        let storage = create_storage();
+        test_upsert_existing_key_preserves_created_at(&storage);
+    }
+
+    #[test]
+    fn test_in_memory_get_existing() {
+        let storage = create_storage();
+        test_get_existing_key(&storage);
+    }
+
+    #[test]
+    fn test_in_memory_delete_existing() {
+        let storage = create_storage();
+        test_delete_existing_key(&storage);
+    }
+
+    #[test]
+    fn test_in_memory_delete_nonexistent() {
+        let storage = create_storage();
+        test_delete_nonexistent_key(&storage);
+    }
+
+    #[test]
+    fn test_in_memory_list_keys_empty() {
+        let storage = create_storage();
+        test_list_keys_empty(&storage);
+    }
+
+    #[test]
+    fn test_in_memory_list_keys_multiple() {
+        let storage = create_storage();
+        test_list_keys_multiple(&storage);
+    }
+
+    #[test]
+    fn test_in_memory_listing_is_sorted() {
+        let storage = create_storage();
+        test_listing_is_sorted(&storage);
+    }
+
+    #[test]
+    fn test_in_memory_list_entries_respects_limit() {
+        let storage = create_storage();
+        test_list_entries_respects_limit(&storage);
+    }
+
+    #[test]
+    fn test_in_memory_list_entries_spans_index_batches() {
+        let storage = create_storage();
+        test_list_entries_spans_index_batches(&storage);
+    }
+
+    #[test]
+    fn test_in_memory_get_many_mixed() {
+        let storage = create_storage();
+        test_get_many_mixed(&storage);
+    }
+
+    #[test]
+    fn test_in_memory_len_tracks_writes() {
+        let storage = create_storage();
+        test_len_tracks_writes(&storage);
+    }
+
+    #[test]
+    fn test_in_memory_count_prefix() {
+        let storage = create_storage();
+        test_count_prefix(&storage);
+    }
+
+    #[test]
+    fn test_in_memory_exists() {
+        let storage = create_storage();
+        test_exists(&storage);
+    }
+
+    #[test]
+    fn test_in_memory_random_entry_empty() {
+        let storage = create_storage();
+        test_random_entry_empty(&storage);
+    }
+
+    #[test]
+    fn test_in_memory_random_entry_distribution() {
+        let storage = create_storage();
+        test_random_entry_distribution(&storage);
+    }
+
+    #[test]
+    fn test_in_memory_random_entry_prefix() {
+        let storage = create_storage();
+        test_random_entry_prefix(&storage);
+    }
+
+    #[test]
+    fn test_in_memory_scan_keys_by_update_time() {
+        let storage = create_storage();
+        test_scan_keys_by_update_time(&storage);
+    }
+
+    #[test]
+    fn test_in_memory_list_range_boundaries() {
+        let storage = create_storage();
+        test_list_range_boundaries(&storage);
+    }
+
+    #[test]
+    fn test_in_memory_list_range_pages() {
+        let storage = create_storage();
+        test_list_range_pages(&storage);
+    }
+
+    #[test]
+    fn test_in_memory_list_keys_after_cursor() {
+        let storage = create_storage();
+        test_list_keys_after_cursor(&storage);
+    }
+
+    #[test]
+    fn test_in_memory_list_range_after_deletes() {
+        let storage = create_storage();
+        test_list_range_after_deletes(&storage);
+    }
+
+    #[test]
+    fn test_in_memory_list_range_skips_expired_and_evicted() {
+        let storage = capped_storage(3, EvictionPolicy::Fifo);
+        put(&storage, "a");
+        put(&storage, "b");
+        put(&storage, "c");
+        storage.set_ttl(&key("b"), Duration::ZERO).unwrap();
+        put(&storage, "d");
+
+        let keys = storage.list_range(Bound::Unbounded, Bound::Unbounded, 10);
+        assert_eq!(keys, vec![key("c"), key("d")]);
+
+        storage.purge_expired();
+        assert_eq!(storage.index_read().len(), storage.len());
+    }
+
+    #[test]
+    fn test_in_memory_search_values_pages() {
+        let storage = create_storage();
+        test_search_values_pages(&storage);
+    }
+
+    #[test]
+    fn test_in_memory_search_values_case_insensitive() {
+        let storage = create_storage();
+        test_search_values_case_insensitive(&storage);
+    }
+
+    #[test]
+    fn test_in_memory_get_metadata() {
+        let storage = create_storage();
+        test_get_metadata(&storage);
+    }
+
+    #[test]
+    fn test_in_memory_size_bytes_tracks_value() {
+        let storage = create_storage();
+        test_size_bytes_tracks_value(&storage);
+    }
+
+    #[test]
+    fn test_in_memory_set_and_get_ttl() {
+        let storage = create_storage();
+        test_set_and_get_ttl(&storage);
+    }
+
+    #[test]
+    fn test_in_memory_ttl_missing_key() {
+        let storage = create_storage();
+        test_ttl_missing_key(&storage);
+    }
+
+    #[test]
+    fn test_in_memory_expired_key_is_absent() {
+        let storage = create_storage();
+        test_expired_key_is_absent(&storage);
+    }
+
+    #[test]
+    fn test_in_memory_write_clears_ttl() {
+        let storage = create_storage();
+        test_write_clears_ttl(&storage);
+    }
+
+    #[test]
+    fn test_in_memory_purge_expired() {
+        let storage = create_storage();
+        put(&storage, "keep");
+        put(&storage, "drop");
+        storage.set_ttl(&key("drop"), Duration::ZERO).unwrap();
+        assert_eq!(storage.len(), 2);
+
+        assert_eq!(storage.purge_expired(), 1);
+        assert_eq!(storage.len(), 1);
+        assert!(storage.exists(&key("keep")));
+    }
+
+    #[test]
+    fn test_in_memory_upsert_if_version() {
+        let storage = create_storage();
+        test_upsert_if_version(&storage);
+    }
+
+    #[test]
+    fn test_in_memory_upsert_if_version_race() {
+        let storage = create_storage();
+        test_upsert_if_version_race(&storage);
+    }
+
+    #[test]
+    fn test_in_memory_value_type_is_kept_across_writes() {
+        let storage = create_storage();
+        test_value_type_is_kept_across_writes(&storage);
+    }
+
+    #[test]
+    fn test_in_memory_version_counter() {
+        let storage = create_storage();
+        test_version_counter(&storage);
+    }
+
+    #[test]
+    fn test_in_memory_concurrent_upserts_produce_unique_versions() {
+        let storage = create_storage();
+        test_concurrent_upserts_produce_unique_versions(&storage);
+    }
+
+    #[test]
+    fn test_in_memory_largest_entries() {
+        let storage = create_storage();
+        test_largest_entries(&storage);
+    }
+
+    #[test]
+    fn test_in_memory_listing_sorted_after_shuffled_concurrent_inserts() {
+        let storage = create_storage();
+        test_listing_sorted_after_shuffled_concurrent_inserts(&storage);
+    }
+
+    #[test]
+    fn test_in_memory_delete_silent() {
+        let storage = create_storage();
+        test_delete_silent(&storage);
+    }
+
+    #[test]
+    fn test_in_memory_delete_if_value_matches() {
+        let storage = create_storage();
+        test_delete_if_value_matches(&storage);
+    }
+
+    #[test]
+    fn test_in_memory_unmodified_since_guards_writes() {
+        let storage = create_storage();
+        test_unmodified_since_guards_writes(&storage);
+    }
+
+    #[test]
+    fn test_in_memory_stale_reads_match_strong_reads() {
+        let storage = create_storage();
+        test_stale_reads_match_strong_reads(&storage);
+    }
+
+    #[test]
+    fn test_in_memory_schema_guards_writes() {
+        let storage = create_storage();
+        test_schema_guards_writes(&storage);
+    }
+
+    #[test]
+    fn test_in_memory_structured_values_round_trip() {
+        let storage = create_storage();
+        test_structured_values_round_trip(&storage);
+    }
+
+    #[test]
+    fn test_in_memory_concurrent_appends_keep_every_fragment() {
+        let storage = create_storage();
+        test_concurrent_appends_keep_every_fragment(&storage);
+    }
+
+    #[test]
+    fn test_in_memory_upsert_many() {
+        let storage = create_storage();
+        test_upsert_many(&storage);
+    }
+
+    #[test]
+    fn test_in_memory_upsert_many_matches_single_upserts() {
+        test_upsert_many_matches_single_upserts(&create_storage(), &create_storage());
+    }
+
+    #[test]
+    fn test_in_memory_batch_apply() {
+        let storage = create_storage();
+        test_batch_apply(&storage);
+    }
+
+    #[test]
+    fn test_in_memory_batch_plan() {
+        let storage = create_storage();
+        test_batch_plan(&storage);
+    }
+
+    #[test]
+    fn test_in_memory_delete_many_strict() {
+        let storage = create_storage();
+        test_delete_many_strict(&storage);
+    }
+
+    #[test]
+    fn test_in_memory_concurrent_merge_patches_keep_every_field() {
+        let storage = create_storage();
+        test_concurrent_merge_patches_keep_every_field(&storage);
+    }
+
+    #[test]
+    fn test_in_memory_binary_values_round_trip() {
+        let storage = create_storage();
+        test_binary_values_round_trip(&storage);
+    }
+
+    #[test]
+    fn test_in_memory_clear_removes_every_key() {
+        let storage = InMemoryStorage::with_config(InMemoryConfig {
+            index_values: true,
+            ..Default::default()
+        });
+        test_clear_removes_every_key(&storage);
+        assert_eq!(storage.memory_usage().unwrap().used_bytes, 0);
+    }
+
+    #[test]
+    fn test_in_memory_hash_fields_and_type_conflicts() {
+        let storage = create_storage();
+        test_hash_fields_and_type_conflicts(&storage);
+    }
+
+    #[test]
+    fn test_in_memory_concurrent_field_writes_keep_every_field() {
+        let storage = create_storage();
+        test_concurrent_field_writes_keep_every_field(&storage);
+    }
+
+    #[test]
+    fn test_in_memory_list_push_pop_and_range() {
+        let storage = create_storage();
+        test_list_push_pop_and_range(&storage);
+    }
+
+    #[test]
+    fn test_in_memory_list_type_conflicts() {
+        let storage = create_storage();
+        test_list_type_conflicts(&storage);
+    }
+
+    #[test]
+    fn test_in_memory_concurrent_list_pops_consume_each_element_once() {
+        let storage = create_storage();
+        test_concurrent_list_pops_consume_each_element_once(&storage);
+    }
+
+    #[test]
+    fn test_in_memory_set_membership_operations() {
+        let storage = create_storage();
+        test_set_membership_operations(&storage);
+    }
+
+    #[test]
+    fn test_in_memory_counter_add_reset_and_bounds() {
+        let storage = create_storage();
+        test_counter_add_reset_and_bounds(&storage);
+    }
+
+    #[test]
+    fn test_in_memory_counter_arithmetic_never_wraps() {
+        let storage = create_storage();
+        test_counter_arithmetic_never_wraps(&storage);
+    }
+
+    #[test]
+    fn test_in_memory_counter_errors_on_other_values() {
+        let storage = create_storage();
+        test_counter_errors_on_other_values(&storage);
+    }
+
+    #[test]
+    fn test_in_memory_concurrent_counter_adds_stop_at_bounds() {
+        let storage = create_storage();
+        test_concurrent_counter_adds_stop_at_bounds(&storage);
+    }
+
+    #[test]
+    fn test_in_memory_counter_reset_racing_with_adds() {
+        let storage = create_storage();
+        test_counter_reset_racing_with_adds(&storage);
+    }
+
+    #[test]
+    fn test_in_memory_transaction_applies_all_or_nothing() {
+        let storage = create_storage();
+        test_transaction_applies_all_or_nothing(&storage);
+    }
+
+    #[test]
+    fn test_in_memory_concurrent_transfers_conserve_total() {
+        let storage = create_storage();
+        test_concurrent_transfers_conserve_total(&storage);
+    }
+
+    #[test]
+    fn test_in_memory_transaction_checks_gate_the_writes() {
+        let storage = create_storage();
+        test_transaction_checks_gate_the_writes(&storage);
+    }
+
+    #[test]
+    fn test_in_memory_overlapping_transactions_stay_consistent() {
+        let storage = create_storage();
+        test_overlapping_transactions_stay_consistent(&storage);
+    }
+
+    #[test]
+    fn test_in_memory_overlapping_transactions_stay_consistent_in_listings() {
+        let storage = create_storage();
+        test_overlapping_transactions_stay_consistent_in_listings(&storage);
+    }
+
+    #[test]
+    fn test_in_memory_compare_and_swap() {
+        let storage = create_storage();
+        test_compare_and_swap(&storage);
+    }
+
+    #[test]
+    fn test_in_memory_concurrent_compare_and_swap_has_one_winner() {
+        let storage = create_storage();
+        test_concurrent_compare_and_swap_has_one_winner(&storage);
+    }
+
+    #[test]
+    fn test_in_memory_with_shards() {
+        for shards in [1, 2, 3, 64] {
+            let storage = InMemoryStorage::with_shards(shards);
+            test_list_keys_multiple(&storage);
+        }
+        test_concurrent_upserts(InMemoryStorage::with_shards(4));
+    }
+
+    #[test]
+    fn test_in_memory_concurrent_access() {
+        let storage = create_storage();
+        test_concurrent_upserts(storage);
+    }
+
+    fn capped_storage(max_keys: usize, eviction_policy: EvictionPolicy) -> InMemoryStorage {
+        InMemoryStorage::with_config(InMemoryConfig {
+            max_keys: Some(max_keys),
+            eviction_policy,
+            ..Default::default()
+        })
+    }
+
+    fn value_indexed_storage() -> InMemoryStorage {
+        InMemoryStorage::with_config(InMemoryConfig {
+            index_values: true,
+            ..Default::default()
+        })
+    }
+
+    fn indexed_keys(storage: &InMemoryStorage, value: &str) -> Vec<Key> {
+        storage
+            .value_index
+            .as_ref()
+            .unwrap()
+            .read()
+            .unwrap()
+            .get(&value_hash(value))
+            .map(|keys| keys.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn test_value_index_follows_upserts_and_deletes() {
+        let storage = value_indexed_storage();
+        put(&storage, "a");
+        storage
+            .upsert(CreateKVRequest {
+                key: key("b"),
+                value: "a-value".into(),
+                value_type: ValueType::Text,
+                encoding: ValueEncoding::Utf8,
+                ttl_seconds: None,
+            })
+            .unwrap();
+        assert_eq!(indexed_keys(&storage, "a-value"), vec![key("a"), key("b")]);
+
+        storage
+            .upsert(CreateKVRequest {
+                key: key("a"),
+                value: "changed".into(),
+                value_type: ValueType::Text,
+                encoding: ValueEncoding::Utf8,
+                ttl_seconds: None,
+            })
+            .unwrap();
+        assert_eq!(indexed_keys(&storage, "a-value"), vec![key("b")]);
+        assert_eq!(indexed_keys(&storage, "changed"), vec![key("a")]);
+
+        storage.delete(key("b")).unwrap();
+        assert!(indexed_keys(&storage, "a-value").is_empty());
+        assert!(
+            !storage
+                .value_index
+                .as_ref()
+                .unwrap()
+                .read()
+                .unwrap()
+                .contains_key(&value_hash("a-value"))
+        );
+    }
+
+    #[test]
+    fn test_value_index_follows_transactions() {
+        let storage = value_indexed_storage();
+        put(&storage, "a");
+        put(&storage, "c");
+        let text = |name: &str, value: &str| CreateKVRequest {
+            key: key(name),
+            value: value.into(),
+            value_type: ValueType::Text,
+            encoding: ValueEncoding::Utf8,
+            ttl_seconds: None,
+        };
+
+        storage
+            .transaction(vec![
+                TxOp::Set(text("b", "a-value")),
+                TxOp::Set(text("a", "changed")),
+                TxOp::Delete(key("c")),
+            ])
+            .unwrap();
+        assert_eq!(indexed_keys(&storage, "a-value"), vec![key("b")]);
+        assert_eq!(indexed_keys(&storage, "changed"), vec![key("a")]);
+        assert!(indexed_keys(&storage, "c-value").is_empty());
+
+        assert!(
+            storage
+                .transaction(vec![
+                    TxOp::Set(text("a", "rolled-back")),
+                    TxOp::Delete(key("c")),
+                ])
+                .is_err()
+        );
+        assert_eq!(indexed_keys(&storage, "changed"), vec![key("a")]);
+        assert!(indexed_keys(&storage, "rolled-back").is_empty());
+    }
+
+    #[test]
+    fn test_value_index_follows_expiry_and_eviction() {
+        let storage = InMemoryStorage::with_config(InMemoryConfig {
+            max_keys: Some(2),
+            eviction_policy: EvictionPolicy::Fifo,
+            index_values: true,
+            ..Default::default()
+        });
+        put(&storage, "a");
+        put(&storage, "b");
+        put(&storage, "c");
+        assert!(indexed_keys(&storage, "a-value").is_empty());
+
+        storage.set_ttl(&key("b"), Duration::ZERO).unwrap();
+        assert_eq!(indexed_keys(&storage, "b-value"), vec![key("b")]);
+        storage.purge_expired();
+        assert!(indexed_keys(&storage, "b-value").is_empty());
+        assert_eq!(indexed_keys(&storage, "c-value"), vec![key("c")]);
+    }
+
+    #[test]
+    fn test_in_memory_find_by_value() {
+        let storage = create_storage();
+        test_find_by_value(&storage);
+    }
+
+    #[test]
+    fn test_in_memory_find_by_value_indexed() {
+        let storage = value_indexed_storage();
+        test_find_by_value(&storage);
+    }
+
+    fn put(storage: &InMemoryStorage, name: &str) {
+        storage
+            .upsert(CreateKVRequest {
+                key: key(name),
+                value: format!("{}-value", name).into(),
+                value_type: ValueType::Text,
+                encoding: ValueEncoding::Utf8,
+                ttl_seconds: None,
+            })
+            .unwrap();
+    }
+
+    fn key(name: &str) -> Key {
+        Key::new(name.to_string()).unwrap()
+    }
+
+    #[test]
+    fn test_lru_evicts_least_recently_used() {
+        let storage = capped_storage(3, EvictionPolicy::Lru);
+        put(&storage, "a");
+        put(&storage, "b");
+        put(&storage, "c");
+
+        storage.get(key("a")).unwrap();
+        put(&storage, "d");
+
+        assert_eq!(storage.len(), 3);
+        assert!(!storage.exists(&key("b")), "b was least recently used");
+        assert!(storage.exists(&key("a")));
+        assert!(storage.exists(&key("c")));
+        assert!(storage.exists(&key("d")));
+    }
+
+    #[test]
+    fn test_lru_counts_writes_as_access() {
+        let storage = capped_storage(2, EvictionPolicy::Lru);
+        put(&storage, "a");
+        put(&storage, "b");
+        put(&storage, "a");
+        put(&storage, "c");
+
+        assert!(storage.exists(&key("a")));
+        assert!(!storage.exists(&key("b")));
+    }
+
+    #[test]
+    fn test_fifo_ignores_reads() {
+        let storage = capped_storage(3, EvictionPolicy::Fifo);
+        put(&storage, "a");
+        put(&storage, "b");
+        put(&storage, "c");
+
+        storage.get(key("a")).unwrap();
+        put(&storage, "d");
+
+        assert!(!storage.exists(&key("a")), "a was inserted first");
+        assert!(storage.exists(&key("b")));
+    }
+
+    #[test]
+    fn test_reads_stamp_accessed_at_only_when_tracked() {
+        let untracked = InMemoryStorage::new();
+        put(&untracked, "a");
+        untracked.get(key("a")).unwrap();
+        assert_eq!(untracked.get(key("a")).unwrap().metadata.accessed_at, None);
+
+        let storage = InMemoryStorage::with_config(InMemoryConfig {
+            track_access: true,
+            ..Default::default()
+        });
+        put(&storage, "a");
+        put(&storage, "b");
+        assert_eq!(storage.get_metadata(&key("a")).unwrap().accessed_at, None);
+
+        let before = Utc::now();
+        let read = storage.get(key("a")).unwrap().metadata.accessed_at.unwrap();
+        assert!(read >= before - TimeDelta::microseconds(1));
+        assert_eq!(
+            storage.get_metadata(&key("a")).unwrap().accessed_at,
+            Some(read)
+        );
+
+        put(&storage, "a");
+        let listed = storage.list_entries(&KeyFilter::default(), 10);
+        assert_eq!(
+            listed[0].metadata.accessed_at,
+            Some(read),
+            "writes are not reads"
+        );
+        assert_eq!(listed[1].metadata.accessed_at, None);
+
+        storage.delete(key("a")).unwrap();
+        put(&storage, "a");
+        assert_eq!(storage.get_metadata(&key("a")).unwrap().accessed_at, None);
+    }
+
+    #[test]
+    fn test_updating_existing_key_at_capacity_does_not_evict() {
+        let storage = capped_storage(2, EvictionPolicy::Lru);
+        put(&storage, "a");
+        put(&storage, "b");
+        put(&storage, "b");
+
+        assert_eq!(storage.len(), 2);
+        assert!(storage.exists(&key("a")));
+    }
+
+    #[test]
+    fn test_insert_past_capacity_evicts() {
+        let storage = capped_storage(1, EvictionPolicy::Lru);
+        put(&storage, "a");
+        storage
+            .insert(CreateKVRequest {
+                key: key("b"),
+                value: "b-value".into(),
+                value_type: ValueType::Text,
+                encoding: ValueEncoding::Utf8,
+                ttl_seconds: None,
+            })
+            .unwrap();
+
+        assert_eq!(storage.list_keys(), vec![key("b")]);
+    }
+
+    #[test]
+    fn test_eviction_policy_from_str() {
+        assert_eq!("lru".parse::<EvictionPolicy>(), Ok(EvictionPolicy::Lru));
+        assert_eq!("FIFO".parse::<EvictionPolicy>(), Ok(EvictionPolicy::Fifo));
+        assert!("random".parse::<EvictionPolicy>().is_err());
+    }
+
+    fn used_bytes(storage: &InMemoryStorage) -> u64 {
+        storage.memory_usage().unwrap().used_bytes
+    }
+
+    fn write(storage: &InMemoryStorage, name: &str, value: &str) -> Result<(), StorageError> {
+        storage
+            .upsert(CreateKVRequest {
+                key: key(name),
+                value: value.into(),
+                value_type: ValueType::Text,
+                encoding: ValueEncoding::Utf8,
+                ttl_seconds: None,
+            })
+            .map(|_| ())
+    }
+
+    #[test]
+    fn test_memory_usage_follows_writes_and_removals() {
+        let storage = create_storage();
+        assert_eq!(used_bytes(&storage), 0);
+
+        write(&storage, "a", "12345").unwrap();
+        write(&storage, "bb", "123").unwrap();
+        assert_eq!(used_bytes(&storage), 6 + 5);
+
+        write(&storage, "a", "1234567890").unwrap();
+        assert_eq!(used_bytes(&storage), 11 + 5);
+        write(&storage, "a", "1").unwrap();
+        assert_eq!(used_bytes(&storage), 2 + 5);
+
+        storage.delete(key("bb")).unwrap();
+        assert_eq!(used_bytes(&storage), 2);
+
+        storage.set_ttl(&key("a"), Duration::ZERO).unwrap();
+        storage.purge_expired();
+        assert_eq!(used_bytes(&storage), 0);
+    }
+
+    #[test]
+    fn test_memory_usage_follows_eviction() {
+        let storage = capped_storage(1, EvictionPolicy::Lru);
+        put(&storage, "a");
+        put(&storage, "bb");
+
+        assert_eq!(used_bytes(&storage), 2 + "bb-value".len() as u64);
+    }
+
+    #[test]
+    fn test_max_bytes_rejects_writes_over_budget() {
+        let storage = InMemoryStorage::with_config(InMemoryConfig {
+            max_bytes: Some(20),
+            ..Default::default()
+        });
+        assert_eq!(storage.memory_usage().unwrap().limit_bytes, Some(20));
+
+        write(&storage, "a", "123456789").unwrap();
+        assert_eq!(
+            write(&storage, "b", "1234567890"),
+            Err(StorageError::InsufficientStorage {
+                key: key("b"),
+                limit_bytes: 20,
+            })
+        );
+        assert!(
+            storage
+                .insert(CreateKVRequest {
+                    key: key("b"),
+                    value: "1234567890".into(),
+                    value_type: ValueType::Text,
+                    encoding: ValueEncoding::Utf8,
+                    ttl_seconds: None,
+                })
+                .is_err()
+        );
+        assert!(!storage.exists(&key("b")));
+        assert_eq!(used_bytes(&storage), 10);
+
+        write(&storage, "b", "123456789").unwrap();
+        assert_eq!(used_bytes(&storage), 20);
+
+        assert!(write(&storage, "a", "1234567890").is_err());
+        assert_eq!(storage.get(key("a")).unwrap().value, "123456789");
+        assert_eq!(storage.get(key("a")).unwrap().metadata.version, 1);
+
+        write(&storage, "a", "1").unwrap();
+        assert_eq!(used_bytes(&storage), 12);
+    }
+
+    #[test]
+    fn test_transaction_stays_within_memory_budget() {
+        let storage = InMemoryStorage::with_config(InMemoryConfig {
+            max_bytes: Some(20),
+            ..Default::default()
+        });
+        let text = |name: &str, value: &str| {
+            TxOp::Set(CreateKVRequest {
+                key: key(name),
+                value: value.into(),
+                value_type: ValueType::Text,
+                encoding: ValueEncoding::Utf8,
+                ttl_seconds: None,
+            })
+        };
+        write(&storage, "a", "123456789").unwrap();
+
+        let error = storage
+            .transaction(vec![text("b", "12345"), text("c", "1234567")])
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            TxError::Failed {
+                index: 1,
+                error: StorageError::InsufficientStorage { .. }
+            }
+        ));
+        assert!(!storage.exists(&key("b")));
+        assert_eq!(used_bytes(&storage), 10);
+
+        storage
+            .transaction(vec![TxOp::Delete(key("a")), text("c", "123456789012345")])
+            .unwrap();
+        assert!(!storage.exists(&key("a")));
+        assert_eq!(used_bytes(&storage), 16);
+    }
+
+    #[test]
+    fn test_compression_round_trips_large_values() {
+        let storage = InMemoryStorage::with_config(InMemoryConfig {
+            compress_threshold: Some(64),
+            max_bytes: Some(10_000),
+            history_limit: 2,
+            ..Default::default()
+        });
+        let document = serde_json::json!({"items": vec!["verbose entry"; 40]});
+        let text = document.to_string().repeat(2);
+        let blob = StoredValue::Binary {
+            bytes: vec![7u8; 500],
+            content_type: "application/x-test".to_string(),
+        };
+        for (name, value) in [
+            ("document", StoredValue::from(document.clone())),
+            ("text", StoredValue::from(text.clone())),
+            ("blob", blob.clone()),
+        ] {
+            let size = value.size_bytes();
+            let (response, _) = storage
+                .upsert(CreateKVRequest {
+                    key: key(name),
+                    value: value.clone(),
+                    value_type: ValueType::Text,
+                    encoding: ValueEncoding::Utf8,
+                    ttl_seconds: None,
+                })
+                .unwrap();
+            assert_eq!(response.value, value);
+            assert_eq!(response.metadata.size_bytes, size);
+            let compressed = response.metadata.compressed_size_bytes.unwrap();
+            assert!(compressed < size / 4, "{} -> {}", size, compressed);
+
+            let stored = storage.get(key(name)).unwrap();
+            assert_eq!(stored.value, value);
+            assert_eq!(stored.metadata.compressed_size_bytes, Some(compressed));
+        }
+
+        let listed = storage.list_entries(&KeyFilter::default(), 10);
+        assert_eq!(listed[0].value, blob);
+        assert_eq!(listed[1].value, document);
+        assert_eq!(listed[2].value, text.as_str());
+        let logical: usize = listed
+            .iter()
+            .map(|entry| entry.key.as_str().len() + entry.metadata.size_bytes)
+            .sum();
+        assert_eq!(used_bytes(&storage), logical as u64);
+
+        write(&storage, "text", "short").unwrap();
+        let stored = storage.get(key("text")).unwrap();
+        assert_eq!(stored.metadata.compressed_size_bytes, None);
+        assert_eq!(
+            storage.history(key("text"), 1).unwrap()[0].value,
+            text.as_str()
+        );
+        let restored = storage
+            .restore(key("text"), RestoreTarget::Version(1))
+            .unwrap();
+        assert_eq!(restored.value, text.as_str());
+        assert!(restored.metadata.compressed_size_bytes.is_some());
+        assert!(storage.delete_if(key("text"), &text).is_ok());
+        assert_eq!(storage.delete(key("document")).unwrap().value, document);
+    }
+
+    #[test]
+    fn test_small_or_incompressible_values_skip_compression() {
+        let storage = InMemoryStorage::with_config(InMemoryConfig {
+            compress_threshold: Some(64),
+            ..Default::default()
+        });
+        write(&storage, "small", "tiny").unwrap();
+        write(&storage, "at-threshold", &"a".repeat(63)).unwrap();
+        let noise = StoredValue::Binary {
+            bytes: (0..200).map(|_| rand::random::<u8>()).collect(),
+            content_type: "application/octet-stream".to_string(),
+        };
+        storage
+            .upsert(CreateKVRequest {
+                key: key("noise"),
+                value: noise.clone(),
+                value_type: ValueType::Text,
+                encoding: ValueEncoding::Base64,
+                ttl_seconds: None,
+            })
+            .unwrap();
+
+        for name in ["small", "at-threshold", "noise"] {
+            let metadata = storage.get_metadata(&key(name)).unwrap();
+            assert_eq!(metadata.compressed_size_bytes, None, "{}", name);
+        }
+        assert_eq!(storage.get(key("noise")).unwrap().value, noise);
+    }
+
+    #[test]
+    fn test_max_value_bytes_rejects_oversized_values() {
+        let storage = InMemoryStorage::with_config(InMemoryConfig {
+            max_value_bytes: 8,
+            ..Default::default()
+        });
+
+        write(&storage, "a", "1234567").unwrap();
+        write(&storage, "b", "12345678").unwrap();
+        assert_eq!(
+            write(&storage, "c", "123456789"),
+            Err(StorageError::ValueTooLarge {
+                key: key("c"),
+                size_bytes: 9,
+                limit_bytes: 8,
+            })
+        );
+        assert!(!storage.exists(&key("c")));
+
+        assert!(write(&storage, "a", "123456789").is_err());
+        assert!(storage.append(key("b"), "9").is_err());
+        assert_eq!(storage.get(key("a")).unwrap().value, "1234567");
+        assert_eq!(storage.get(key("b")).unwrap().value, "12345678");
+    }
+
+    #[test]
+    fn test_max_set_members_caps_new_members() {
+        let storage = InMemoryStorage::with_config(InMemoryConfig {
+            max_set_members: Some(3),
+            ..Default::default()
+        });
+
+        for member in ["a", "b", "c"] {
+            storage.add_member(key("tags"), member).unwrap();
+        }
+        assert_eq!(
+            storage.add_member(key("tags"), "d").unwrap_err(),
+            StorageError::TooManyMembers {
+                key: key("tags"),
+                limit: 3,
+            }
+        );
+        assert_eq!(storage.add_member(key("tags"), "a").unwrap().2, 3);
+
+        storage.remove_member(key("tags"), "a").unwrap();
+        assert_eq!(storage.add_member(key("tags"), "d").unwrap().2, 3);
+        assert_eq!(storage.members(&key("tags")).unwrap(), vec!["b", "c", "d"]);
+    }
+
+    #[test]
+    fn test_history_keeps_last_versions_newest_first() {
+        let storage = InMemoryStorage::with_config(InMemoryConfig {
+            history_limit: 2,
+            ..Default::default()
+        });
+        for value in ["v1", "v2", "v3", "v4"] {
+            write(&storage, "a", value).unwrap();
+        }
+
+        let history = storage.history(key("a"), 10).unwrap();
+        let values: Vec<_> = history.iter().map(|past| past.value.clone()).collect();
+        assert_eq!(values, ["v3", "v2"]);
+        assert_eq!(history[0].metadata.version, 3);
+        assert_eq!(storage.history(key("a"), 1).unwrap().len(), 1);
+        assert_eq!(storage.get(key("a")).unwrap().value, "v4");
+
+        storage.set_ttl(&key("a"), Duration::ZERO).unwrap();
+        write(&storage, "a", "fresh").unwrap();
+        assert!(storage.history(key("a"), 10).unwrap().is_empty());
+
+        storage.delete(key("a")).unwrap();
+        assert_eq!(
+            storage.history(key("a"), 10).unwrap_err(),
+            StorageError::KeyNotFound(key("a"))
+        );
+    }
+
+    #[test]
+    fn test_history_disabled_by_default() {
+        let storage = InMemoryStorage::new();
+        write(&storage, "a", "v1").unwrap();
+        write(&storage, "a", "v2").unwrap();
+        assert!(storage.history(key("a"), 10).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_restore_writes_a_past_value_as_a_new_version() {
+        let storage = InMemoryStorage::with_config(InMemoryConfig {
+            history_limit: 3,
+            ..Default::default()
+        });
+        for value in ["v1", "v2", "v3"] {
+            write(&storage, "a", value).unwrap();
+        }
+        let created_at = storage.get(key("a")).unwrap().metadata.created_at;
+        let v2_written_at = storage.history(key("a"), 1).unwrap()[0].metadata.updated_at;
+
+        let restored = storage
+            .restore(key("a"), RestoreTarget::Version(1))
+            .unwrap();
+        assert_eq!(restored.value, "v1");
+        assert_eq!(restored.metadata.version, 4);
+        assert_eq!(restored.metadata.created_at, created_at);
+        assert_eq!(storage.history(key("a"), 1).unwrap()[0].value, "v3");
+
+        let restored = storage
+            .restore(key("a"), RestoreTarget::At(v2_written_at))
+            .unwrap();
+        assert_eq!(restored.value, "v2");
+        assert_eq!(storage.get(key("a")).unwrap().metadata.version, 5);
+
+        assert_eq!(
+            storage
+                .restore(key("a"), RestoreTarget::Version(1))
+                .unwrap_err(),
+            StorageError::VersionNotFound(key("a"))
+        );
+        assert_eq!(
+            storage
+                .restore(key("missing"), RestoreTarget::Version(1))
+                .unwrap_err(),
+            StorageError::KeyNotFound(key("missing"))
+        );
+    }
+
+    #[test]
+    fn test_default_ttl_applies_unless_overridden() {
+        let storage = InMemoryStorage::with_config(InMemoryConfig {
+            default_ttl: Some(Duration::from_secs(60)),
+            ..Default::default()
+        });
+        let write_with_ttl = |name: &str, ttl_seconds: Option<i64>| {
+            storage
+                .upsert(CreateKVRequest {
+                    key: key(name),
+                    value: "v".into(),
+                    value_type: ValueType::Text,
+                    encoding: ValueEncoding::Utf8,
+                    ttl_seconds,
+                })
+                .unwrap()
+                .0
+                .metadata
+                .expires_at
+        };
+        let remaining =
+            |expires_at: Option<DateTime<Utc>>| (expires_at.unwrap() - Utc::now()).num_seconds();
+
+        assert!((55..=60).contains(&remaining(write_with_ttl("a", None))));
+        assert!((5..=10).contains(&remaining(write_with_ttl("b", Some(10)))));
+        assert_eq!(write_with_ttl("c", Some(-1)), None);
+
+        storage.set_ttl(&key("a"), Duration::from_secs(5)).unwrap();
+        assert!((55..=60).contains(&remaining(write_with_ttl("a", None))));
+        assert_eq!(write_with_ttl("b", Some(-1)), None);
+        assert!((55..=60).contains(&remaining(write_with_ttl("c", None))));
+
+        let inserted = storage
+            .insert(CreateKVRequest {
+                key: key("d"),
+                value: "v".into(),
+                value_type: ValueType::Text,
+                encoding: ValueEncoding::Utf8,
+                ttl_seconds: None,
+            })
+            .unwrap();
+        assert!((55..=60).contains(&remaining(inserted.metadata.expires_at)));
     }
 }
@@ -1,11 +1,1183 @@
-use crate::app::models::{CreateKVRequest, KeyValueResponse, ValueResponse};
-use crate::service::StorageError;
+use crate::app::models::{
+    CounterBounds, CounterOverflow, CreateKVRequest, KeyValueResponse, ListSide, Metadata,
+    StoredValue, ValueEncoding, ValueResponse, ValueType, content_etag_bytes, value_text,
+};
+use crate::service::{
+    KeyFilter, LargestEntries, SearchPage, StorageError, ValueSearch, merge_patch,
+};
 use crate::types::Key;
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashSet, VecDeque};
+use std::ops::{Bound, Range, RangeBounds};
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpsertOutcome {
+    Created,
+    Updated,
+}
+
+#[derive(Debug, Clone)]
+pub enum CasOutcome {
+    Swapped(KeyValueResponse),
+    Mismatch(Option<Metadata>),
+}
+
+/// One write of a [`Storage::batch_apply`]. `Insert` fails with
+/// `KeyAlreadyExists` on a live key, like `insert`.
+#[derive(Debug, Clone)]
+pub enum WriteOp {
+    Upsert(CreateKVRequest),
+    Insert(CreateKVRequest),
+    Delete(Key),
+}
+
+impl WriteOp {
+    pub fn key(&self) -> &Key {
+        match self {
+            WriteOp::Upsert(body) | WriteOp::Insert(body) => &body.key,
+            WriteOp::Delete(key) => key,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum WriteOutcome {
+    Upserted(KeyValueResponse, UpsertOutcome),
+    Deleted(ValueResponse),
+}
+
+impl WriteOutcome {
+    pub fn into_upserted(self) -> Result<(KeyValueResponse, UpsertOutcome), StorageError> {
+        match self {
+            WriteOutcome::Upserted(response, outcome) => Ok((response, outcome)),
+            WriteOutcome::Deleted(_) => Err(StorageError::Unavailable(
+                "batch_apply answered an upsert with a delete".to_string(),
+            )),
+        }
+    }
+
+    pub fn into_deleted(self) -> Result<ValueResponse, StorageError> {
+        match self {
+            WriteOutcome::Deleted(deleted) => Ok(deleted),
+            WriteOutcome::Upserted(..) => Err(StorageError::Unavailable(
+                "batch_apply answered a delete with an upsert".to_string(),
+            )),
+        }
+    }
+}
+
+/// One step of a [`Storage::transaction`]. `Set` and `Cas` behave like
+/// `upsert` and `compare_and_swap`, and `Delete` fails on a missing key.
+/// `Check` writes nothing and aborts the transaction unless its key is in
+/// the expected state.
+#[derive(Debug, Clone)]
+pub enum TxOp {
+    Set(CreateKVRequest),
+    Delete(Key),
+    Cas {
+        expected: Option<Value>,
+        body: CreateKVRequest,
+    },
+    Check {
+        key: Key,
+        expected: TxCheck,
+    },
+}
+
+impl TxOp {
+    pub fn key(&self) -> &Key {
+        match self {
+            TxOp::Set(body) | TxOp::Cas { body, .. } => &body.key,
+            TxOp::Delete(key) | TxOp::Check { key, .. } => key,
+        }
+    }
+
+    pub fn writes(&self) -> bool {
+        !matches!(self, TxOp::Check { .. })
+    }
+}
+
+/// What a [`TxOp::Check`] requires of its key. `Value(None)` means the key
+/// must not exist.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TxCheck {
+    Value(Option<Value>),
+    Version(u64),
+}
+
+impl TxCheck {
+    pub fn holds(&self, current: Option<&ValueResponse>) -> bool {
+        match (self, current) {
+            (TxCheck::Value(None), current) => current.is_none(),
+            (TxCheck::Value(Some(expected)), Some(current)) => current.value == *expected,
+            (TxCheck::Version(version), Some(current)) => current.metadata.version == *version,
+            (_, None) => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum OpResult {
+    Set(KeyValueResponse, UpsertOutcome),
+    Deleted(ValueResponse),
+    Swapped(KeyValueResponse),
+    /// The check held; carries the key's metadata when it exists
+    Checked(Option<Metadata>),
+}
+
+/// Why a transaction was rolled back. `index` points at the op that failed.
+#[derive(Debug, Clone)]
+pub enum TxError {
+    Failed {
+        index: usize,
+        error: StorageError,
+    },
+    Mismatch {
+        index: usize,
+        current: Option<Box<Metadata>>,
+    },
+    Storage(StorageError),
+}
+
+impl TxError {
+    pub fn index(&self) -> Option<usize> {
+        match self {
+            TxError::Failed { index, .. } | TxError::Mismatch { index, .. } => Some(*index),
+            TxError::Storage(_) => None,
+        }
+    }
+}
+
+impl From<StorageError> for TxError {
+    fn from(storage_error: StorageError) -> Self {
+        TxError::Storage(storage_error)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VersionPrecondition {
+    Exists,
+    OneOf(Vec<String>),
+}
+
+impl VersionPrecondition {
+    pub fn matches(&self, current_etag: &str, current_version: u64) -> bool {
+        match self {
+            VersionPrecondition::Exists => true,
+            VersionPrecondition::OneOf(versions) => versions.iter().any(|version| {
+                version == current_etag || version.parse::<u64>() == Ok(current_version)
+            }),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RestoreTarget {
+    Version(u64),
+    At(DateTime<Utc>),
+}
+
+impl RestoreTarget {
+    pub fn select<'a>(
+        &self,
+        newest_first: impl IntoIterator<Item = &'a ValueResponse>,
+    ) -> Option<&'a ValueResponse> {
+        newest_first.into_iter().find(|candidate| match self {
+            RestoreTarget::Version(version) => candidate.metadata.version == *version,
+            RestoreTarget::At(at) => candidate.metadata.updated_at <= *at,
+        })
+    }
+}
+
+pub fn appended_value(
+    key: &Key,
+    current: &ValueResponse,
+    suffix: &str,
+) -> Result<Value, StorageError> {
+    match &current.value {
+        StoredValue::Json(Value::String(text))
+            if current.metadata.value_type == ValueType::Text
+                && current.metadata.encoding == ValueEncoding::Utf8 =>
+        {
+            Ok(Value::String(format!("{}{}", text, suffix)))
+        }
+        StoredValue::Hash(_) | StoredValue::List(_) | StoredValue::Set(_) => {
+            Err(StorageError::WrongType(key.clone()))
+        }
+        _ => Err(StorageError::NotAppendable(key.clone())),
+    }
+}
+
+pub fn patched_value(
+    key: &Key,
+    current: &ValueResponse,
+    patch: &Value,
+) -> Result<Value, StorageError> {
+    match &current.value {
+        StoredValue::Json(document @ Value::Object(_)) => {
+            let mut document = document.clone();
+            merge_patch::apply(&mut document, patch);
+            Ok(document)
+        }
+        StoredValue::Json(Value::String(text))
+            if current.metadata.value_type == ValueType::Json
+                && current.metadata.encoding == ValueEncoding::Utf8 =>
+        {
+            match serde_json::from_str::<Value>(text) {
+                Ok(mut document) if document.is_object() => {
+                    merge_patch::apply(&mut document, patch);
+                    Ok(Value::String(document.to_string()))
+                }
+                Ok(_) => Err(StorageError::NotMergeable(key.clone())),
+                Err(_) => Err(StorageError::NotJson(key.clone())),
+            }
+        }
+        StoredValue::Json(Value::String(_)) => Err(StorageError::NotJson(key.clone())),
+        StoredValue::Binary { .. } => Err(StorageError::BinaryValue(key.clone())),
+        StoredValue::Hash(_) | StoredValue::List(_) | StoredValue::Set(_) => {
+            Err(StorageError::WrongType(key.clone()))
+        }
+        _ => Err(StorageError::NotMergeable(key.clone())),
+    }
+}
+
+/// The JSON document a value holds, as schema validation sees it: values as
+/// the API returns them, except that strings stored with a `json`, `number`
+/// or `counter` type are parsed. Base64 strings and blobs hold bytes, not
+/// JSON, and collections are not documents.
+pub fn json_document<'a>(
+    key: &Key,
+    value: &'a StoredValue,
+    value_type: ValueType,
+    encoding: ValueEncoding,
+) -> Result<Cow<'a, Value>, StorageError> {
+    match value {
+        StoredValue::Json(Value::String(_)) if encoding != ValueEncoding::Utf8 => {
+            Err(StorageError::NotJson(key.clone()))
+        }
+        StoredValue::Json(Value::String(text)) if value_type != ValueType::Text => {
+            serde_json::from_str(text)
+                .map(Cow::Owned)
+                .map_err(|_| StorageError::NotJson(key.clone()))
+        }
+        StoredValue::Json(document) => Ok(Cow::Borrowed(document)),
+        StoredValue::Binary { .. } => Err(StorageError::BinaryValue(key.clone())),
+        StoredValue::Hash(_) | StoredValue::List(_) | StoredValue::Set(_) => {
+            Err(StorageError::WrongType(key.clone()))
+        }
+    }
+}
+
+pub fn hash_fields(
+    key: &Key,
+    value: &StoredValue,
+) -> Result<BTreeMap<String, String>, StorageError> {
+    match value {
+        StoredValue::Hash(fields) => Ok(fields.clone()),
+        _ => Err(StorageError::WrongType(key.clone())),
+    }
+}
+
+pub fn hash_request(key: Key, fields: BTreeMap<String, String>) -> CreateKVRequest {
+    CreateKVRequest {
+        key,
+        value: StoredValue::Hash(fields),
+        value_type: ValueType::Text,
+        encoding: ValueEncoding::Utf8,
+        ttl_seconds: None,
+    }
+}
+
+pub fn set_contents(key: &Key, value: &StoredValue) -> Result<HashSet<String>, StorageError> {
+    match value {
+        StoredValue::Set(members) => Ok(members.clone()),
+        _ => Err(StorageError::WrongType(key.clone())),
+    }
+}
+
+pub fn set_request(key: Key, members: HashSet<String>) -> CreateKVRequest {
+    CreateKVRequest {
+        key,
+        value: StoredValue::Set(members),
+        value_type: ValueType::Text,
+        encoding: ValueEncoding::Utf8,
+        ttl_seconds: None,
+    }
+}
+
+/// Runs one transaction op against `storage`, turning a CAS mismatch into
+/// an error so the caller can stop at the first op that did not apply.
+pub(crate) fn apply_tx_op(
+    storage: &impl Storage,
+    index: usize,
+    op: TxOp,
+) -> Result<OpResult, TxError> {
+    let failed = |error| TxError::Failed { index, error };
+    match op {
+        TxOp::Set(body) => storage
+            .upsert(body)
+            .map(|(response, outcome)| OpResult::Set(response, outcome))
+            .map_err(failed),
+        TxOp::Delete(key) => storage.delete(key).map(OpResult::Deleted).map_err(failed),
+        TxOp::Cas { expected, body } => {
+            match storage
+                .compare_and_swap(expected.as_ref(), body)
+                .map_err(failed)?
+            {
+                CasOutcome::Swapped(response) => Ok(OpResult::Swapped(response)),
+                CasOutcome::Mismatch(current) => Err(TxError::Mismatch {
+                    index,
+                    current: current.map(Box::new),
+                }),
+            }
+        }
+        TxOp::Check { key, expected } => {
+            let current = match storage.get(key) {
+                Ok(current) => Some(current),
+                Err(StorageError::KeyNotFound(_)) => None,
+                Err(storage_error) => return Err(failed(storage_error)),
+            };
+            if expected.holds(current.as_ref()) {
+                Ok(OpResult::Checked(current.map(|current| current.metadata)))
+            } else {
+                Err(TxError::Mismatch {
+                    index,
+                    current: current.map(|current| Box::new(current.metadata)),
+                })
+            }
+        }
+    }
+}
+
+/// Whether `metadata` was updated after `since`. HTTP dates carry whole
+/// seconds, so the comparison ignores the sub-second part of `updated_at`.
+pub(crate) fn modified_since(metadata: &Metadata, since: DateTime<Utc>) -> bool {
+    metadata.updated_at.timestamp() > since.timestamp()
+}
+
+pub fn counter_request(key: Key, value: i64) -> CreateKVRequest {
+    CreateKVRequest {
+        key,
+        value: StoredValue::Json(value.into()),
+        value_type: ValueType::Counter,
+        encoding: ValueEncoding::Utf8,
+        ttl_seconds: None,
+    }
+}
+
+/// The bounds a write creating a key should record: counters written without
+/// going through `create_counter` start where they are and are unbounded.
+pub(crate) fn new_counter_bounds(body: &CreateKVRequest) -> Option<CounterBounds> {
+    if body.value_type != ValueType::Counter {
+        return None;
+    }
+    body.value
+        .as_counter(body.encoding)
+        .map(|initial| CounterBounds {
+            initial,
+            ..CounterBounds::default()
+        })
+}
+
+/// The current value and bounds of the counter stored under `key`.
+pub fn counter_state(
+    key: &Key,
+    current: &ValueResponse,
+) -> Result<(i64, CounterBounds), StorageError> {
+    if current.metadata.value_type != ValueType::Counter {
+        return Err(StorageError::NotCounter(key.clone()));
+    }
+    let value = current
+        .value
+        .as_counter(current.metadata.encoding)
+        .ok_or_else(|| StorageError::NotCounter(key.clone()))?;
+    Ok((value, current.metadata.counter.unwrap_or_default()))
+}
+
+/// `value + delta` under `bounds`, and whether it had to be clamped.
+pub(crate) fn counter_sum(
+    key: &Key,
+    bounds: &CounterBounds,
+    value: i64,
+    delta: i64,
+    overflow: CounterOverflow,
+) -> Result<(i64, bool), StorageError> {
+    match bounds.checked_add(value, delta) {
+        Some(sum) => Ok((sum, false)),
+        None if overflow == CounterOverflow::Clamp => {
+            Ok((bounds.saturating_add(value, delta), true))
+        }
+        None => Err(StorageError::CounterOutOfRange {
+            key: key.clone(),
+            min: bounds.floor(),
+            max: bounds.ceiling(),
+        }),
+    }
+}
+
+pub fn list_elements(key: &Key, value: &StoredValue) -> Result<VecDeque<String>, StorageError> {
+    match value {
+        StoredValue::List(elements) => Ok(elements.clone()),
+        _ => Err(StorageError::WrongType(key.clone())),
+    }
+}
+
+pub fn list_request(key: Key, elements: VecDeque<String>) -> CreateKVRequest {
+    CreateKVRequest {
+        key,
+        value: StoredValue::List(elements),
+        value_type: ValueType::Text,
+        encoding: ValueEncoding::Utf8,
+        ttl_seconds: None,
+    }
+}
+
+/// The elements `start..=end` of a list of `len` elements, with negative
+/// indexes counting from the end and out-of-range indexes clamped, as in
+/// Redis LRANGE.
+pub fn list_window(len: usize, start: i64, end: i64) -> Range<usize> {
+    let resolve = |index: i64| {
+        if index < 0 { len as i64 + index } else { index }
+    };
+    let start = resolve(start).max(0);
+    let end = resolve(end).min(len as i64 - 1);
+    if start > end {
+        return 0..0;
+    }
+    start as usize..end as usize + 1
+}
+
+pub(crate) fn push_element(elements: &mut VecDeque<String>, side: ListSide, value: &str) {
+    match side {
+        ListSide::Left => elements.push_front(value.to_string()),
+        ListSide::Right => elements.push_back(value.to_string()),
+    }
+}
+
+pub(crate) fn pop_element(elements: &mut VecDeque<String>, side: ListSide) -> Option<String> {
+    match side {
+        ListSide::Left => elements.pop_front(),
+        ListSide::Right => elements.pop_back(),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryUsage {
+    pub used_bytes: u64,
+    pub limit_bytes: Option<u64>,
+}
 
 pub trait Storage {
     fn get(&self, key: Key) -> Result<ValueResponse, StorageError>;
     fn insert(&self, body: CreateKVRequest) -> Result<KeyValueResponse, StorageError>;
-    fn upsert(&self, body: CreateKVRequest) -> KeyValueResponse;
+    fn upsert(
+        &self,
+        body: CreateKVRequest,
+    ) -> Result<(KeyValueResponse, UpsertOutcome), StorageError>;
+    fn upsert_if_version(
+        &self,
+        body: CreateKVRequest,
+        expected: &VersionPrecondition,
+    ) -> Result<KeyValueResponse, StorageError>;
     fn delete(&self, key: Key) -> Result<ValueResponse, StorageError>;
     fn list_keys(&self) -> Vec<Key>;
+    fn list_entries(&self, filter: &KeyFilter, limit: usize) -> Vec<KeyValueResponse>;
+    fn len(&self) -> usize;
+    fn set_ttl(&self, key: &Key, ttl: Duration) -> Result<Metadata, StorageError>;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn delete_silent(&self, key: Key) -> Result<(), StorageError> {
+        self.delete(key).map(|_| ())
+    }
+
+    fn delete_if(&self, key: Key, expected: &str) -> Result<ValueResponse, StorageError> {
+        let current = self.get(key.clone())?;
+        let current_bytes = current.value.as_bytes();
+        if *current_bytes != *expected.as_bytes() {
+            return Err(StorageError::VersionMismatch {
+                key,
+                current: Some(content_etag_bytes(&current_bytes)),
+            });
+        }
+        self.delete(key)
+    }
+
+    /// Upserts unless the key exists and was modified after `since`. A
+    /// missing key is written, since it has no modification date to check.
+    fn upsert_if_unmodified(
+        &self,
+        body: CreateKVRequest,
+        since: DateTime<Utc>,
+    ) -> Result<(KeyValueResponse, UpsertOutcome), StorageError> {
+        match self.get_metadata(&body.key) {
+            Ok(current) if modified_since(&current, since) => Err(StorageError::ModifiedSince {
+                key: body.key,
+                updated_at: current.updated_at,
+            }),
+            Ok(_) | Err(StorageError::KeyNotFound(_)) => self.upsert(body),
+            Err(storage_error) => Err(storage_error),
+        }
+    }
+
+    fn delete_if_unmodified(
+        &self,
+        key: Key,
+        since: DateTime<Utc>,
+    ) -> Result<ValueResponse, StorageError> {
+        let current = self.get_metadata(&key)?;
+        if modified_since(&current, since) {
+            return Err(StorageError::ModifiedSince {
+                key,
+                updated_at: current.updated_at,
+            });
+        }
+        self.delete(key)
+    }
+
+    fn history(&self, key: Key, _limit: usize) -> Result<Vec<ValueResponse>, StorageError> {
+        self.get(key).map(|_| Vec::new())
+    }
+
+    fn append(&self, key: Key, suffix: &str) -> Result<(Metadata, UpsertOutcome), StorageError> {
+        let value = match self.get(key.clone()) {
+            Ok(current) => appended_value(&key, &current, suffix)?.into(),
+            Err(StorageError::KeyNotFound(_)) => suffix.into(),
+            Err(storage_error) => return Err(storage_error),
+        };
+        self.upsert(CreateKVRequest {
+            key,
+            value,
+            value_type: ValueType::Text,
+            encoding: ValueEncoding::Utf8,
+            ttl_seconds: None,
+        })
+        .map(|(response, outcome)| (response.metadata, outcome))
+    }
+
+    fn compare_and_swap(
+        &self,
+        expected: Option<&Value>,
+        body: CreateKVRequest,
+    ) -> Result<CasOutcome, StorageError> {
+        let key = body.key.clone();
+        let Some(expected) = expected else {
+            return match self.insert(body) {
+                Ok(response) => Ok(CasOutcome::Swapped(response)),
+                Err(StorageError::KeyAlreadyExists(_)) => {
+                    Ok(CasOutcome::Mismatch(self.get_metadata(&key).ok()))
+                }
+                Err(storage_error) => Err(storage_error),
+            };
+        };
+
+        let current = match self.get(key.clone()) {
+            Ok(current) => current,
+            Err(StorageError::KeyNotFound(_)) => return Ok(CasOutcome::Mismatch(None)),
+            Err(storage_error) => return Err(storage_error),
+        };
+        if current.value != *expected {
+            return Ok(CasOutcome::Mismatch(Some(current.metadata)));
+        }
+        let precondition = VersionPrecondition::OneOf(vec![current.metadata.version.to_string()]);
+        match self.upsert_if_version(body, &precondition) {
+            Ok(response) => Ok(CasOutcome::Swapped(response)),
+            Err(StorageError::VersionMismatch { .. } | StorageError::KeyNotFound(_)) => {
+                Ok(CasOutcome::Mismatch(self.get_metadata(&key).ok()))
+            }
+            Err(storage_error) => Err(storage_error),
+        }
+    }
+
+    fn merge_patch(&self, key: Key, patch: &Value) -> Result<KeyValueResponse, StorageError> {
+        loop {
+            let current = self.get(key.clone())?;
+            let request = CreateKVRequest {
+                key: key.clone(),
+                value: patched_value(&key, &current, patch)?.into(),
+                value_type: current.metadata.value_type,
+                encoding: current.metadata.encoding,
+                ttl_seconds: None,
+            };
+            let unchanged = VersionPrecondition::OneOf(vec![current.metadata.version.to_string()]);
+            match self.upsert_if_version(request, &unchanged) {
+                Err(StorageError::VersionMismatch {
+                    current: Some(_), ..
+                }) => continue,
+                Err(StorageError::VersionMismatch { current: None, .. }) => {
+                    return Err(StorageError::KeyNotFound(key));
+                }
+                written => return written,
+            }
+        }
+    }
+
+    fn get_fields(&self, key: &Key) -> Result<BTreeMap<String, String>, StorageError> {
+        hash_fields(key, &self.get(key.clone())?.value)
+    }
+
+    fn get_field(&self, key: &Key, field: &Key) -> Result<String, StorageError> {
+        self.get_fields(key)?
+            .remove(field.as_str())
+            .ok_or_else(|| StorageError::FieldNotFound {
+                key: key.clone(),
+                field: field.clone(),
+            })
+    }
+
+    /// Sets one field of the hash stored under `key`, creating the hash if
+    /// the key is missing. The outcome says whether the field is new.
+    fn set_field(
+        &self,
+        key: Key,
+        field: &Key,
+        value: &str,
+    ) -> Result<(Metadata, UpsertOutcome), StorageError> {
+        loop {
+            let current = match self.get(key.clone()) {
+                Ok(current) => Some(current),
+                Err(StorageError::KeyNotFound(_)) => None,
+                Err(storage_error) => return Err(storage_error),
+            };
+            let mut fields = match &current {
+                Some(current) => hash_fields(&key, &current.value)?,
+                None => BTreeMap::new(),
+            };
+            let outcome = match fields.insert(field.as_str().to_string(), value.to_string()) {
+                Some(_) => UpsertOutcome::Updated,
+                None => UpsertOutcome::Created,
+            };
+            let request = hash_request(key.clone(), fields);
+            let written = match current {
+                Some(current) => self.upsert_if_version(
+                    request,
+                    &VersionPrecondition::OneOf(vec![current.metadata.version.to_string()]),
+                ),
+                None => self.insert(request),
+            };
+            match written {
+                Ok(response) => return Ok((response.metadata, outcome)),
+                Err(StorageError::VersionMismatch { .. } | StorageError::KeyAlreadyExists(_)) => {
+                    continue;
+                }
+                Err(storage_error) => return Err(storage_error),
+            }
+        }
+    }
+
+    /// Removes one field; removing the last field deletes the key.
+    fn delete_field(&self, key: Key, field: &Key) -> Result<(), StorageError> {
+        loop {
+            let current = self.get(key.clone())?;
+            let mut fields = hash_fields(&key, &current.value)?;
+            if fields.remove(field.as_str()).is_none() {
+                return Err(StorageError::FieldNotFound {
+                    key,
+                    field: field.clone(),
+                });
+            }
+            let written = if fields.is_empty() {
+                let text = String::from_utf8_lossy(&current.value.as_bytes()).into_owned();
+                self.delete_if(key.clone(), &text).map(|_| ())
+            } else {
+                self.upsert_if_version(
+                    hash_request(key.clone(), fields),
+                    &VersionPrecondition::OneOf(vec![current.metadata.version.to_string()]),
+                )
+                .map(|_| ())
+            };
+            match written {
+                Err(StorageError::VersionMismatch {
+                    current: Some(_), ..
+                }) => continue,
+                Err(StorageError::VersionMismatch { current: None, .. }) => {
+                    return Err(StorageError::KeyNotFound(key));
+                }
+                written => return written,
+            }
+        }
+    }
+
+    /// Pushes `value` onto one end of the list stored under `key`, creating
+    /// the list if the key is missing. Returns the new length.
+    fn list_push(
+        &self,
+        key: Key,
+        side: ListSide,
+        value: &str,
+    ) -> Result<(Metadata, usize, UpsertOutcome), StorageError> {
+        loop {
+            let current = match self.get(key.clone()) {
+                Ok(current) => Some(current),
+                Err(StorageError::KeyNotFound(_)) => None,
+                Err(storage_error) => return Err(storage_error),
+            };
+            let mut elements = match &current {
+                Some(current) => list_elements(&key, &current.value)?,
+                None => VecDeque::new(),
+            };
+            push_element(&mut elements, side, value);
+            let length = elements.len();
+            let (written, outcome) = match current {
+                Some(current) => (
+                    self.upsert_if_version(
+                        list_request(key.clone(), elements),
+                        &VersionPrecondition::OneOf(vec![current.metadata.version.to_string()]),
+                    ),
+                    UpsertOutcome::Updated,
+                ),
+                None => (
+                    self.insert(list_request(key.clone(), elements)),
+                    UpsertOutcome::Created,
+                ),
+            };
+            match written {
+                Ok(response) => return Ok((response.metadata, length, outcome)),
+                Err(StorageError::VersionMismatch { .. } | StorageError::KeyAlreadyExists(_)) => {
+                    continue;
+                }
+                Err(storage_error) => return Err(storage_error),
+            }
+        }
+    }
+
+    /// Pops one element off an end of the list and returns it with the
+    /// remaining length; popping the last element deletes the key.
+    fn list_pop(&self, key: Key, side: ListSide) -> Result<(String, usize), StorageError> {
+        loop {
+            let current = match self.get(key.clone()) {
+                Ok(current) => current,
+                Err(StorageError::KeyNotFound(_)) => return Err(StorageError::ListEmpty(key)),
+                Err(storage_error) => return Err(storage_error),
+            };
+            let mut elements = list_elements(&key, &current.value)?;
+            let Some(element) = pop_element(&mut elements, side) else {
+                return Err(StorageError::ListEmpty(key));
+            };
+            let length = elements.len();
+            let written = if elements.is_empty() {
+                let text = String::from_utf8_lossy(&current.value.as_bytes()).into_owned();
+                self.delete_if(key.clone(), &text).map(|_| ())
+            } else {
+                self.upsert_if_version(
+                    list_request(key.clone(), elements),
+                    &VersionPrecondition::OneOf(vec![current.metadata.version.to_string()]),
+                )
+                .map(|_| ())
+            };
+            match written {
+                Ok(()) => return Ok((element, length)),
+                Err(StorageError::VersionMismatch { .. } | StorageError::KeyNotFound(_)) => {
+                    continue;
+                }
+                Err(storage_error) => return Err(storage_error),
+            }
+        }
+    }
+
+    /// The elements `start..=end` of the list (see [`list_window`]) and its
+    /// length. A missing key reads as an empty list.
+    fn list_slice(
+        &self,
+        key: &Key,
+        start: i64,
+        end: i64,
+    ) -> Result<(Vec<String>, usize), StorageError> {
+        let elements = match self.get(key.clone()) {
+            Ok(current) => list_elements(key, &current.value)?,
+            Err(StorageError::KeyNotFound(_)) => return Ok((Vec::new(), 0)),
+            Err(storage_error) => return Err(storage_error),
+        };
+        let window = list_window(elements.len(), start, end);
+        Ok((elements.range(window).cloned().collect(), elements.len()))
+    }
+
+    /// Adds `member` to the set stored under `key`, creating the set if the
+    /// key is missing. Returns whether the member is new and the set's size;
+    /// adding an existing member writes nothing.
+    fn add_member(&self, key: Key, member: &str) -> Result<(Metadata, bool, usize), StorageError> {
+        loop {
+            let current = match self.get(key.clone()) {
+                Ok(current) => Some(current),
+                Err(StorageError::KeyNotFound(_)) => None,
+                Err(storage_error) => return Err(storage_error),
+            };
+            let mut members = match &current {
+                Some(current) => set_contents(&key, &current.value)?,
+                None => HashSet::new(),
+            };
+            if !members.insert(member.to_string()) {
+                let metadata = current
+                    .expect("only a stored set can already hold the member")
+                    .metadata;
+                return Ok((metadata, false, members.len()));
+            }
+            let count = members.len();
+            let written = match current {
+                Some(current) => self.upsert_if_version(
+                    set_request(key.clone(), members),
+                    &VersionPrecondition::OneOf(vec![current.metadata.version.to_string()]),
+                ),
+                None => self.insert(set_request(key.clone(), members)),
+            };
+            match written {
+                Ok(response) => return Ok((response.metadata, true, count)),
+                Err(StorageError::VersionMismatch { .. } | StorageError::KeyAlreadyExists(_)) => {
+                    continue;
+                }
+                Err(storage_error) => return Err(storage_error),
+            }
+        }
+    }
+
+    /// Removes `member` from the set and returns whether it was there and
+    /// the set's remaining size; removing the last member deletes the key.
+    fn remove_member(&self, key: Key, member: &str) -> Result<(bool, usize), StorageError> {
+        loop {
+            let current = match self.get(key.clone()) {
+                Ok(current) => current,
+                Err(StorageError::KeyNotFound(_)) => return Ok((false, 0)),
+                Err(storage_error) => return Err(storage_error),
+            };
+            let mut members = set_contents(&key, &current.value)?;
+            if !members.remove(member) {
+                return Ok((false, members.len()));
+            }
+            let count = members.len();
+            let written = if members.is_empty() {
+                let text = String::from_utf8_lossy(&current.value.as_bytes()).into_owned();
+                self.delete_if(key.clone(), &text).map(|_| ())
+            } else {
+                self.upsert_if_version(
+                    set_request(key.clone(), members),
+                    &VersionPrecondition::OneOf(vec![current.metadata.version.to_string()]),
+                )
+                .map(|_| ())
+            };
+            match written {
+                Ok(()) => return Ok((true, count)),
+                Err(StorageError::VersionMismatch { .. } | StorageError::KeyNotFound(_)) => {
+                    continue;
+                }
+                Err(storage_error) => return Err(storage_error),
+            }
+        }
+    }
+
+    /// The members of the set, sorted. A missing key reads as an empty set.
+    fn members(&self, key: &Key) -> Result<Vec<String>, StorageError> {
+        match self.get(key.clone()) {
+            Ok(current) => {
+                let mut members: Vec<String> =
+                    set_contents(key, &current.value)?.into_iter().collect();
+                members.sort_unstable();
+                Ok(members)
+            }
+            Err(StorageError::KeyNotFound(_)) => Ok(Vec::new()),
+            Err(storage_error) => Err(storage_error),
+        }
+    }
+
+    fn is_member(&self, key: &Key, member: &str) -> Result<bool, StorageError> {
+        match self.get(key.clone()) {
+            Ok(current) => Ok(set_contents(key, &current.value)?.contains(member)),
+            Err(StorageError::KeyNotFound(_)) => Ok(false),
+            Err(storage_error) => Err(storage_error),
+        }
+    }
+
+    /// Creates a counter holding `bounds.initial`, failing with
+    /// `KeyAlreadyExists` when the key is taken. Backends that cannot keep
+    /// the bounds in metadata only support unbounded counters.
+    fn create_counter(
+        &self,
+        key: Key,
+        bounds: CounterBounds,
+    ) -> Result<KeyValueResponse, StorageError> {
+        if bounds.min.is_some() || bounds.max.is_some() {
+            return Err(StorageError::Unavailable(
+                "This backend cannot store counter bounds".to_string(),
+            ));
+        }
+        self.insert(counter_request(key, bounds.initial))
+    }
+
+    /// Adds `delta` to the counter and returns the new metadata and value,
+    /// and whether `overflow` clamped it to a bound.
+    fn counter_add(
+        &self,
+        key: Key,
+        delta: i64,
+        overflow: CounterOverflow,
+    ) -> Result<(Metadata, i64, bool), StorageError> {
+        loop {
+            let current = self.get(key.clone())?;
+            let (value, bounds) = counter_state(&key, &current)?;
+            let (sum, clamped) = counter_sum(&key, &bounds, value, delta, overflow)?;
+            match self.upsert_if_version(
+                counter_request(key.clone(), sum),
+                &VersionPrecondition::OneOf(vec![current.metadata.version.to_string()]),
+            ) {
+                Ok(response) => return Ok((response.metadata, sum, clamped)),
+                Err(StorageError::VersionMismatch { .. }) => continue,
+                Err(storage_error) => return Err(storage_error),
+            }
+        }
+    }
+
+    /// Sets the counter back to the value it was created with.
+    fn counter_reset(&self, key: Key) -> Result<(Metadata, i64), StorageError> {
+        loop {
+            let current = self.get(key.clone())?;
+            let (_, bounds) = counter_state(&key, &current)?;
+            match self.upsert_if_version(
+                counter_request(key.clone(), bounds.initial),
+                &VersionPrecondition::OneOf(vec![current.metadata.version.to_string()]),
+            ) {
+                Ok(response) => return Ok((response.metadata, bounds.initial)),
+                Err(StorageError::VersionMismatch { .. }) => continue,
+                Err(storage_error) => return Err(storage_error),
+            }
+        }
+    }
+
+    fn restore(&self, key: Key, target: RestoreTarget) -> Result<KeyValueResponse, StorageError> {
+        let current = self.get(key.clone())?;
+        let past = self.history(key.clone(), usize::MAX)?;
+        let chosen = target
+            .select(std::iter::once(&current).chain(&past))
+            .ok_or_else(|| StorageError::VersionNotFound(key.clone()))?;
+        let request = CreateKVRequest {
+            key,
+            value: chosen.value.clone(),
+            value_type: chosen.metadata.value_type,
+            encoding: chosen.metadata.encoding,
+            ttl_seconds: None,
+        };
+        self.upsert(request).map(|(response, _)| response)
+    }
+
+    fn count_prefix(&self, prefix: &str) -> usize {
+        self.list_keys()
+            .iter()
+            .filter(|key| key.as_str().starts_with(prefix))
+            .count()
+    }
+
+    fn scan_keys(&self, filter: &KeyFilter) -> Vec<Key> {
+        self.list_keys()
+            .into_iter()
+            .filter(|key| {
+                filter.matches_key(key)
+                    && (!filter.needs_metadata()
+                        || self
+                            .get(key.clone())
+                            .is_ok_and(|value| filter.matches(key, &value.metadata)))
+            })
+            .collect()
+    }
+
+    fn list_range(&self, start: Bound<&str>, end: Bound<&str>, limit: usize) -> Vec<Key> {
+        let mut keys: Vec<Key> = self
+            .list_keys()
+            .into_iter()
+            .filter(|key| RangeBounds::<str>::contains(&(start, end), key.as_str()))
+            .collect();
+        keys.sort();
+        keys.truncate(limit);
+        keys
+    }
+
+    fn list_keys_after(&self, cursor: Option<&str>, limit: usize) -> Vec<Key> {
+        let start = cursor.map_or(Bound::Unbounded, Bound::Excluded);
+        self.list_range(start, Bound::Unbounded, limit)
+    }
+
+    fn search_values(&self, search: &ValueSearch, after: Option<&str>, limit: usize) -> SearchPage {
+        let mut keys = self.list_keys();
+        keys.retain(|key| after.is_none_or(|after| key.as_str() > after));
+        keys.sort();
+
+        let mut matches = Vec::new();
+        for key in keys {
+            if matches.len() > limit {
+                break;
+            }
+            if let Ok(value) = self.get(key.clone())
+                && let Some(json) = value.value.as_json()
+                && search.matches(&value_text(json))
+            {
+                matches.push((key, value.metadata));
+            }
+        }
+
+        SearchPage::from_sorted(matches, limit)
+    }
+
+    fn exists(&self, key: &Key) -> bool {
+        self.get(key.clone()).is_ok()
+    }
+
+    fn random_entry(&self, prefix: Option<&str>) -> Option<KeyValueResponse> {
+        use rand::seq::IteratorRandom;
+
+        let key = self
+            .list_keys()
+            .into_iter()
+            .filter(|key| prefix.is_none_or(|prefix| key.as_str().starts_with(prefix)))
+            .choose(&mut rand::thread_rng())?;
+
+        self.get(key.clone()).ok().map(|value| KeyValueResponse {
+            key,
+            value: value.value,
+            metadata: value.metadata,
+        })
+    }
+
+    fn largest_entries(&self, limit: usize) -> Vec<(Key, Metadata)> {
+        let mut largest = LargestEntries::new(limit);
+        for key in self.list_keys() {
+            if let Ok(metadata) = self.get_metadata(&key) {
+                largest.offer(&key, &metadata);
+            }
+        }
+        largest.into_sorted()
+    }
+
+    fn get_metadata(&self, key: &Key) -> Result<Metadata, StorageError> {
+        self.get(key.clone()).map(|value| value.metadata)
+    }
+
+    fn get_ttl(&self, key: &Key) -> Result<Option<Duration>, StorageError> {
+        let value = self.get(key.clone())?;
+        Ok(value
+            .metadata
+            .expires_at
+            .map(|expires_at| (expires_at - Utc::now()).to_std().unwrap_or_default()))
+    }
+
+    fn purge_expired(&self) -> usize {
+        0
+    }
+
+    fn clear(&self) -> usize {
+        self.list_keys()
+            .into_iter()
+            .filter(|key| self.delete_silent(key.clone()).is_ok())
+            .count()
+    }
+
+    fn memory_usage(&self) -> Option<MemoryUsage> {
+        None
+    }
+
+    fn negative_cache_hits(&self) -> Option<u64> {
+        None
+    }
+
+    fn ping(&self) -> Result<(), StorageError> {
+        Ok(())
+    }
+
+    fn get_many(&self, keys: &[Key]) -> Vec<Result<ValueResponse, StorageError>> {
+        keys.iter().map(|key| self.get(key.clone())).collect()
+    }
+
+    /// A read that may be served from a replica lagging behind the primary,
+    /// so it can miss recent writes (including returning a key that was just
+    /// deleted). It never returns a value that was not written at some point.
+    /// Backends without replicas answer it like `get`.
+    fn get_stale(&self, key: Key) -> Result<ValueResponse, StorageError> {
+        self.get(key)
+    }
+
+    /// Attaches a JSON Schema to a live key, after checking that the current
+    /// value satisfies it, or detaches it with `None`. While attached, every
+    /// write to the key must satisfy it too (`SchemaViolation` otherwise),
+    /// and deleting or expiring the key drops it. The schema is assumed to
+    /// compile; callers check that with [`json_schema::compile`] first.
+    ///
+    /// [`json_schema::compile`]: crate::service::json_schema::compile
+    fn set_schema(&self, _key: &Key, _schema: Option<Value>) -> Result<Metadata, StorageError> {
+        Err(StorageError::Unavailable(
+            "This backend does not support schemas".to_string(),
+        ))
+    }
+
+    /// Upserts every body as `upsert` would, in order, and returns one result
+    /// per body in the same order; a key given twice ends with the later
+    /// value. It is not atomic: a failing body is reported in its slot and
+    /// the others are still written. Batch endpoints, CSV imports and seed
+    /// files write through it, so backends that pay per write (a disk
+    /// backend's fsync) should apply the bodies in one go.
+    fn upsert_many(
+        &self,
+        bodies: Vec<CreateKVRequest>,
+    ) -> Vec<Result<(KeyValueResponse, UpsertOutcome), StorageError>> {
+        self.batch_apply(bodies.into_iter().map(WriteOp::Upsert).collect())
+            .into_iter()
+            .map(|result| result?.into_upserted())
+            .collect()
+    }
+
+    fn delete_many(&self, keys: Vec<Key>) -> Vec<Result<ValueResponse, StorageError>> {
+        self.batch_apply(keys.into_iter().map(WriteOp::Delete).collect())
+            .into_iter()
+            .map(|result| result?.into_deleted())
+            .collect()
+    }
+
+    fn delete_many_strict(
+        &self,
+        keys: Vec<Key>,
+    ) -> Result<Vec<Result<ValueResponse, StorageError>>, StorageError> {
+        let missing: Vec<Key> = keys
+            .iter()
+            .filter(|key| !self.exists(key))
+            .cloned()
+            .collect();
+        if !missing.is_empty() {
+            return Err(StorageError::KeysNotFound(missing));
+        }
+        Ok(self.delete_many(keys))
+    }
+
+    fn batch_apply(&self, ops: Vec<WriteOp>) -> Vec<Result<WriteOutcome, StorageError>> {
+        ops.into_iter()
+            .map(|op| match op {
+                WriteOp::Upsert(body) => self
+                    .upsert(body)
+                    .map(|(response, outcome)| WriteOutcome::Upserted(response, outcome)),
+                WriteOp::Insert(body) => self
+                    .insert(body)
+                    .map(|response| WriteOutcome::Upserted(response, UpsertOutcome::Created)),
+                WriteOp::Delete(key) => self.delete(key).map(WriteOutcome::Deleted),
+            })
+            .collect()
+    }
+
+    /// Reports what `batch_apply(ops)` would return right now, op by op,
+    /// without changing the store: the ops run through the same write paths
+    /// against a private copy of the keys they touch, so every check a real
+    /// run makes (conflicts, types, schemas, limits, the memory budget) is
+    /// made here too. Backends that cannot copy their state fail every op.
+    fn batch_plan(&self, ops: Vec<WriteOp>) -> Vec<Result<WriteOutcome, StorageError>> {
+        let unsupported =
+            StorageError::Unavailable("This backend does not support dry runs".to_string());
+        vec![Err(unsupported); ops.len()]
+    }
+
+    /// Applies `ops` in order as one unit: either every op takes effect or,
+    /// if any fails (including a `Cas` mismatch or a failed `Check`), none
+    /// does. Backends that cannot stage writes reject transactions outright.
+    fn transaction(&self, _ops: Vec<TxOp>) -> Result<Vec<OpResult>, TxError> {
+        Err(TxError::Storage(StorageError::Unavailable(
+            "This backend does not support transactions".to_string(),
+        )))
+    }
 }
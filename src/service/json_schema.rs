@@ -0,0 +1,81 @@
+use crate::app::models::SchemaViolation;
+use crate::service::StorageError;
+use crate::types::Key;
+use serde_json::Value;
+
+/// Most violations reported for one write, so a large document that is
+/// wrong everywhere does not produce an enormous error.
+pub const MAX_VIOLATIONS: usize = 20;
+
+/// Compiles a JSON Schema document (draft 4 to 2020-12, picked from its
+/// `$schema`, 2020-12 when absent). Remote `$ref`s are not fetched, so a
+/// schema that needs one fails to compile.
+pub fn compile(schema: &Value) -> Result<jsonschema::Validator, String> {
+    jsonschema::validator_for(schema).map_err(|err| err.to_string())
+}
+
+/// Checks `document` against the schema attached to `key`, reporting every
+/// violation (up to [`MAX_VIOLATIONS`]) rather than just the first.
+pub fn validate(key: &Key, schema: &Value, document: &Value) -> Result<(), StorageError> {
+    let violations: Vec<SchemaViolation> = match compile(schema) {
+        Ok(validator) => validator
+            .iter_errors(document)
+            .take(MAX_VIOLATIONS)
+            .map(|error| SchemaViolation {
+                path: error.instance_path.as_str().to_string(),
+                message: error.to_string(),
+            })
+            .collect(),
+        Err(message) => vec![SchemaViolation {
+            path: String::new(),
+            message,
+        }],
+    };
+    if violations.is_empty() {
+        Ok(())
+    } else {
+        Err(StorageError::SchemaViolation {
+            key: key.clone(),
+            violations,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn key() -> Key {
+        Key::new("config".to_string()).unwrap()
+    }
+
+    #[test]
+    fn test_validate_reports_every_violation_with_its_path() {
+        let schema = json!({
+            "type": "object",
+            "required": ["port", "host"],
+            "properties": {"port": {"type": "integer", "maximum": 65535}}
+        });
+        assert!(validate(&key(), &schema, &json!({"port": 80, "host": "a"})).is_ok());
+
+        let Err(StorageError::SchemaViolation { violations, .. }) =
+            validate(&key(), &schema, &json!({"port": "eighty"}))
+        else {
+            panic!("expected a schema violation");
+        };
+        let paths: Vec<&str> = violations.iter().map(|v| v.path.as_str()).collect();
+        assert_eq!(violations.len(), 2);
+        assert!(paths.contains(&"/port"));
+        assert!(paths.contains(&""));
+        assert!(violations.iter().any(|v| v.message.contains("\"host\"")));
+    }
+
+    #[test]
+    fn test_compile_rejects_invalid_schemas() {
+        assert!(compile(&json!({"type": "integer"})).is_ok());
+        assert!(compile(&json!(true)).is_ok());
+        assert!(compile(&json!({"type": "no-such-type"})).is_err());
+        assert!(compile(&json!({"$ref": "https://example.com/schema.json"})).is_err());
+    }
+}
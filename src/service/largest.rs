@@ -0,0 +1,81 @@
+use crate::app::models::Metadata;
+use crate::types::Key;
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+
+struct Ranked {
+    key: Key,
+    metadata: Metadata,
+}
+
+impl Ranked {
+    fn is_outranked_by(&self, key: &Key, metadata: &Metadata) -> bool {
+        metadata
+            .size_bytes
+            .cmp(&self.metadata.size_bytes)
+            .then_with(|| self.key.cmp(key))
+            == Ordering::Greater
+    }
+}
+
+impl Ord for Ranked {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.metadata
+            .size_bytes
+            .cmp(&other.metadata.size_bytes)
+            .then_with(|| other.key.cmp(&self.key))
+    }
+}
+
+impl PartialOrd for Ranked {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Ranked {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Ranked {}
+
+pub struct LargestEntries {
+    limit: usize,
+    heap: BinaryHeap<Reverse<Ranked>>,
+}
+
+impl LargestEntries {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            heap: BinaryHeap::with_capacity(limit.saturating_add(1).min(1024)),
+        }
+    }
+
+    pub fn offer(&mut self, key: &Key, metadata: &Metadata) {
+        if self.limit == 0 {
+            return;
+        }
+        if self.heap.len() == self.limit {
+            match self.heap.peek() {
+                Some(Reverse(smallest)) if smallest.is_outranked_by(key, metadata) => {}
+                _ => return,
+            }
+            self.heap.pop();
+        }
+        self.heap.push(Reverse(Ranked {
+            key: key.clone(),
+            metadata: metadata.clone(),
+        }));
+    }
+
+    pub fn into_sorted(self) -> Vec<(Key, Metadata)> {
+        self.heap
+            .into_sorted_vec()
+            .into_iter()
+            .map(|Reverse(ranked)| (ranked.key, ranked.metadata))
+            .collect()
+    }
+}
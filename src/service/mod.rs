@@ -1,10 +1,40 @@
+pub mod async_interface;
+pub mod blocking;
+pub mod compression;
 pub mod error;
+pub mod filter;
 pub mod in_memory;
 pub mod interface;
+pub mod json_schema;
+pub mod largest;
+pub mod merge_patch;
+pub mod negative_cache;
+pub mod search;
+pub mod sharded;
+#[cfg(feature = "otel")]
+pub mod traced;
+pub mod transform;
+pub mod validation;
 
 #[cfg(test)]
 mod tests;
 
+pub use async_interface::AsyncStorage;
+pub use blocking::BlockingStorage;
 pub use error::StorageError;
-pub use in_memory::InMemoryStorage;
-pub use interface::Storage;
+pub use filter::{KeyFilter, KeyPattern, MAX_PATTERN_LEN};
+pub use in_memory::{DEFAULT_MAX_VALUE_BYTES, EvictionPolicy, InMemoryConfig, InMemoryStorage};
+pub use interface::{
+    CasOutcome, MemoryUsage, OpResult, RestoreTarget, Storage, TxCheck, TxError, TxOp,
+    UpsertOutcome, VersionPrecondition, WriteOp, WriteOutcome, appended_value, counter_request,
+    counter_state, hash_fields, hash_request, json_document, list_elements, list_request,
+    list_window, patched_value, set_contents, set_request,
+};
+pub use largest::LargestEntries;
+pub use negative_cache::NegativeCache;
+pub use search::{SearchPage, ValueSearch};
+pub use sharded::ShardedStorage;
+#[cfg(feature = "otel")]
+pub use traced::Traced;
+pub use transform::{TransformChain, Transforming, ValueTransformer};
+pub use validation::ValidationError;
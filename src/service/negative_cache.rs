@@ -0,0 +1,473 @@
+use crate::app::models::{
+    CounterBounds, CounterOverflow, CreateKVRequest, KeyValueResponse, ListSide, Metadata,
+    ValueResponse,
+};
+use crate::service::{
+    CasOutcome, KeyFilter, MemoryUsage, OpResult, RestoreTarget, SearchPage, Storage, StorageError,
+    TxError, TxOp, UpsertOutcome, ValueSearch, VersionPrecondition, WriteOp, WriteOutcome,
+};
+use crate::types::Key;
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use dashmap::mapref::entry::Entry;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::ops::Bound;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// Upper bound on remembered misses, so a scan over random keys cannot grow
+/// the cache without limit between sweeps.
+pub const MAX_CACHED_MISSES: usize = 100_000;
+
+/// Remembers keys that recently missed in `get` and answers repeated lookups
+/// for them without touching the wrapped backend. Writes that can create a key
+/// forget it first, so a cached miss never hides a value written through this
+/// wrapper.
+pub struct NegativeCache<S> {
+    inner: S,
+    ttl: Duration,
+    misses: DashMap<Key, Instant>,
+    writes: AtomicU64,
+    hits: AtomicU64,
+}
+
+impl<S: Storage> NegativeCache<S> {
+    pub fn new(inner: S, ttl: Duration) -> Self {
+        Self {
+            inner,
+            ttl,
+            misses: DashMap::new(),
+            writes: AtomicU64::new(0),
+            hits: AtomicU64::new(0),
+        }
+    }
+
+    pub fn hits(&self) -> u64 {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    fn cached_miss(&self, key: &Key) -> bool {
+        let Some(expires_at) = self.misses.get(key).map(|entry| *entry) else {
+            return false;
+        };
+        if expires_at > Instant::now() {
+            return true;
+        }
+        self.misses
+            .remove_if(key, |_, current| *current == expires_at);
+        false
+    }
+
+    fn remember_miss(&self, key: Key, writes_before: u64) {
+        if self.misses.len() >= MAX_CACHED_MISSES {
+            return;
+        }
+        // The write counter is checked under the entry lock: a writer bumps it
+        // before taking the same lock to forget the key, so a miss observed
+        // before a concurrent create is either dropped here or removed there.
+        if let Entry::Vacant(entry) = self.misses.entry(key)
+            && self.writes.load(Ordering::SeqCst) == writes_before
+        {
+            entry.insert(Instant::now() + self.ttl);
+        }
+    }
+
+    fn forget<'a>(&self, keys: impl IntoIterator<Item = &'a Key>) {
+        self.writes.fetch_add(1, Ordering::SeqCst);
+        for key in keys {
+            self.misses.remove(key);
+        }
+    }
+}
+
+impl<S: Storage> Storage for NegativeCache<S> {
+    fn get(&self, key: Key) -> Result<ValueResponse, StorageError> {
+        if self.cached_miss(&key) {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return Err(StorageError::KeyNotFound(key));
+        }
+        let writes_before = self.writes.load(Ordering::SeqCst);
+        let result = self.inner.get(key.clone());
+        if let Err(StorageError::KeyNotFound(_)) = result {
+            self.remember_miss(key, writes_before);
+        }
+        result
+    }
+
+    fn insert(&self, body: CreateKVRequest) -> Result<KeyValueResponse, StorageError> {
+        self.forget([&body.key]);
+        self.inner.insert(body)
+    }
+
+    fn upsert(
+        &self,
+        body: CreateKVRequest,
+    ) -> Result<(KeyValueResponse, UpsertOutcome), StorageError> {
+        self.forget([&body.key]);
+        self.inner.upsert(body)
+    }
+
+    fn upsert_if_version(
+        &self,
+        body: CreateKVRequest,
+        expected: &VersionPrecondition,
+    ) -> Result<KeyValueResponse, StorageError> {
+        self.forget([&body.key]);
+        self.inner.upsert_if_version(body, expected)
+    }
+
+    fn delete(&self, key: Key) -> Result<ValueResponse, StorageError> {
+        self.inner.delete(key)
+    }
+
+    fn list_keys(&self) -> Vec<Key> {
+        self.inner.list_keys()
+    }
+
+    fn list_entries(&self, filter: &KeyFilter, limit: usize) -> Vec<KeyValueResponse> {
+        self.inner.list_entries(filter, limit)
+    }
+
+    fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    fn set_ttl(&self, key: &Key, ttl: Duration) -> Result<Metadata, StorageError> {
+        self.inner.set_ttl(key, ttl)
+    }
+
+    fn set_schema(&self, key: &Key, schema: Option<Value>) -> Result<Metadata, StorageError> {
+        self.inner.set_schema(key, schema)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    fn delete_silent(&self, key: Key) -> Result<(), StorageError> {
+        self.inner.delete_silent(key)
+    }
+
+    fn delete_if(&self, key: Key, expected: &str) -> Result<ValueResponse, StorageError> {
+        self.inner.delete_if(key, expected)
+    }
+
+    fn upsert_if_unmodified(
+        &self,
+        body: CreateKVRequest,
+        since: DateTime<Utc>,
+    ) -> Result<(KeyValueResponse, UpsertOutcome), StorageError> {
+        self.forget([&body.key]);
+        self.inner.upsert_if_unmodified(body, since)
+    }
+
+    fn delete_if_unmodified(
+        &self,
+        key: Key,
+        since: DateTime<Utc>,
+    ) -> Result<ValueResponse, StorageError> {
+        self.inner.delete_if_unmodified(key, since)
+    }
+
+    fn history(&self, key: Key, limit: usize) -> Result<Vec<ValueResponse>, StorageError> {
+        self.inner.history(key, limit)
+    }
+
+    fn append(&self, key: Key, suffix: &str) -> Result<(Metadata, UpsertOutcome), StorageError> {
+        self.forget([&key]);
+        self.inner.append(key, suffix)
+    }
+
+    fn compare_and_swap(
+        &self,
+        expected: Option<&Value>,
+        body: CreateKVRequest,
+    ) -> Result<CasOutcome, StorageError> {
+        self.forget([&body.key]);
+        self.inner.compare_and_swap(expected, body)
+    }
+
+    fn merge_patch(&self, key: Key, patch: &Value) -> Result<KeyValueResponse, StorageError> {
+        self.inner.merge_patch(key, patch)
+    }
+
+    fn get_fields(&self, key: &Key) -> Result<BTreeMap<String, String>, StorageError> {
+        self.inner.get_fields(key)
+    }
+
+    fn get_field(&self, key: &Key, field: &Key) -> Result<String, StorageError> {
+        self.inner.get_field(key, field)
+    }
+
+    fn set_field(
+        &self,
+        key: Key,
+        field: &Key,
+        value: &str,
+    ) -> Result<(Metadata, UpsertOutcome), StorageError> {
+        self.forget([&key]);
+        self.inner.set_field(key, field, value)
+    }
+
+    fn delete_field(&self, key: Key, field: &Key) -> Result<(), StorageError> {
+        self.inner.delete_field(key, field)
+    }
+
+    fn list_push(
+        &self,
+        key: Key,
+        side: ListSide,
+        value: &str,
+    ) -> Result<(Metadata, usize, UpsertOutcome), StorageError> {
+        self.forget([&key]);
+        self.inner.list_push(key, side, value)
+    }
+
+    fn list_pop(&self, key: Key, side: ListSide) -> Result<(String, usize), StorageError> {
+        self.inner.list_pop(key, side)
+    }
+
+    fn add_member(&self, key: Key, member: &str) -> Result<(Metadata, bool, usize), StorageError> {
+        self.forget([&key]);
+        self.inner.add_member(key, member)
+    }
+
+    fn remove_member(&self, key: Key, member: &str) -> Result<(bool, usize), StorageError> {
+        self.inner.remove_member(key, member)
+    }
+
+    fn members(&self, key: &Key) -> Result<Vec<String>, StorageError> {
+        self.inner.members(key)
+    }
+
+    fn is_member(&self, key: &Key, member: &str) -> Result<bool, StorageError> {
+        self.inner.is_member(key, member)
+    }
+
+    fn create_counter(
+        &self,
+        key: Key,
+        bounds: CounterBounds,
+    ) -> Result<KeyValueResponse, StorageError> {
+        self.forget([&key]);
+        self.inner.create_counter(key, bounds)
+    }
+
+    fn counter_add(
+        &self,
+        key: Key,
+        delta: i64,
+        overflow: CounterOverflow,
+    ) -> Result<(Metadata, i64, bool), StorageError> {
+        self.inner.counter_add(key, delta, overflow)
+    }
+
+    fn counter_reset(&self, key: Key) -> Result<(Metadata, i64), StorageError> {
+        self.inner.counter_reset(key)
+    }
+
+    fn list_slice(
+        &self,
+        key: &Key,
+        start: i64,
+        end: i64,
+    ) -> Result<(Vec<String>, usize), StorageError> {
+        self.inner.list_slice(key, start, end)
+    }
+
+    fn restore(&self, key: Key, target: RestoreTarget) -> Result<KeyValueResponse, StorageError> {
+        self.forget([&key]);
+        self.inner.restore(key, target)
+    }
+
+    fn count_prefix(&self, prefix: &str) -> usize {
+        self.inner.count_prefix(prefix)
+    }
+
+    fn scan_keys(&self, filter: &KeyFilter) -> Vec<Key> {
+        self.inner.scan_keys(filter)
+    }
+
+    fn list_range(&self, start: Bound<&str>, end: Bound<&str>, limit: usize) -> Vec<Key> {
+        self.inner.list_range(start, end, limit)
+    }
+
+    fn list_keys_after(&self, cursor: Option<&str>, limit: usize) -> Vec<Key> {
+        self.inner.list_keys_after(cursor, limit)
+    }
+
+    fn search_values(&self, search: &ValueSearch, after: Option<&str>, limit: usize) -> SearchPage {
+        self.inner.search_values(search, after, limit)
+    }
+
+    fn exists(&self, key: &Key) -> bool {
+        self.inner.exists(key)
+    }
+
+    fn random_entry(&self, prefix: Option<&str>) -> Option<KeyValueResponse> {
+        self.inner.random_entry(prefix)
+    }
+
+    fn largest_entries(&self, limit: usize) -> Vec<(Key, Metadata)> {
+        self.inner.largest_entries(limit)
+    }
+
+    fn get_metadata(&self, key: &Key) -> Result<Metadata, StorageError> {
+        self.inner.get_metadata(key)
+    }
+
+    fn get_ttl(&self, key: &Key) -> Result<Option<Duration>, StorageError> {
+        self.inner.get_ttl(key)
+    }
+
+    fn purge_expired(&self) -> usize {
+        let now = Instant::now();
+        self.misses.retain(|_, expires_at| *expires_at > now);
+        self.inner.purge_expired()
+    }
+
+    fn clear(&self) -> usize {
+        self.inner.clear()
+    }
+
+    fn memory_usage(&self) -> Option<MemoryUsage> {
+        self.inner.memory_usage()
+    }
+
+    fn negative_cache_hits(&self) -> Option<u64> {
+        Some(self.hits())
+    }
+
+    fn ping(&self) -> Result<(), StorageError> {
+        self.inner.ping()
+    }
+
+    fn get_many(&self, keys: &[Key]) -> Vec<Result<ValueResponse, StorageError>> {
+        self.inner.get_many(keys)
+    }
+
+    /// Answers from remembered misses like `get`, but a miss reported by a
+    /// possibly lagging replica is not remembered.
+    fn get_stale(&self, key: Key) -> Result<ValueResponse, StorageError> {
+        if self.cached_miss(&key) {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return Err(StorageError::KeyNotFound(key));
+        }
+        self.inner.get_stale(key)
+    }
+
+    fn upsert_many(
+        &self,
+        bodies: Vec<CreateKVRequest>,
+    ) -> Vec<Result<(KeyValueResponse, UpsertOutcome), StorageError>> {
+        self.forget(bodies.iter().map(|body| &body.key));
+        self.inner.upsert_many(bodies)
+    }
+
+    fn delete_many(&self, keys: Vec<Key>) -> Vec<Result<ValueResponse, StorageError>> {
+        self.inner.delete_many(keys)
+    }
+
+    fn delete_many_strict(
+        &self,
+        keys: Vec<Key>,
+    ) -> Result<Vec<Result<ValueResponse, StorageError>>, StorageError> {
+        self.inner.delete_many_strict(keys)
+    }
+
+    fn batch_apply(&self, ops: Vec<WriteOp>) -> Vec<Result<WriteOutcome, StorageError>> {
+        self.forget(ops.iter().filter_map(|op| match op {
+            WriteOp::Upsert(body) | WriteOp::Insert(body) => Some(&body.key),
+            WriteOp::Delete(_) => None,
+        }));
+        self.inner.batch_apply(ops)
+    }
+
+    fn batch_plan(&self, ops: Vec<WriteOp>) -> Vec<Result<WriteOutcome, StorageError>> {
+        self.inner.batch_plan(ops)
+    }
+
+    fn transaction(&self, ops: Vec<TxOp>) -> Result<Vec<OpResult>, TxError> {
+        self.forget(ops.iter().filter_map(|op| match op {
+            TxOp::Set(body) | TxOp::Cas { body, .. } => Some(&body.key),
+            TxOp::Delete(_) | TxOp::Check { .. } => None,
+        }));
+        self.inner.transaction(ops)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::models::{ValueEncoding, ValueType};
+    use crate::service::InMemoryStorage;
+    use serde_json::json;
+
+    fn key(name: &str) -> Key {
+        Key::new(name.to_string()).unwrap()
+    }
+
+    fn request(name: &str, value: &str) -> CreateKVRequest {
+        CreateKVRequest {
+            key: key(name),
+            value: value.into(),
+            value_type: ValueType::Text,
+            encoding: ValueEncoding::Utf8,
+            ttl_seconds: None,
+        }
+    }
+
+    fn cache(ttl: Duration) -> NegativeCache<InMemoryStorage> {
+        NegativeCache::new(InMemoryStorage::new(), ttl)
+    }
+
+    #[test]
+    fn test_repeated_misses_are_served_from_cache() {
+        let storage = cache(Duration::from_secs(60));
+
+        for _ in 0..3 {
+            assert_eq!(
+                storage.get(key("ghost")).unwrap_err(),
+                StorageError::KeyNotFound(key("ghost"))
+            );
+        }
+        assert_eq!(storage.hits(), 2);
+        assert_eq!(storage.negative_cache_hits(), Some(2));
+    }
+
+    #[test]
+    fn test_writes_forget_cached_misses() {
+        let storage = cache(Duration::from_secs(60));
+        let names = ["insert", "upsert", "cas", "batch", "many", "append"];
+        for name in names {
+            assert!(storage.get(key(name)).is_err());
+        }
+
+        storage.insert(request("insert", "a")).unwrap();
+        storage.upsert(request("upsert", "b")).unwrap();
+        storage.compare_and_swap(None, request("cas", "c")).unwrap();
+        storage.batch_apply(vec![WriteOp::Upsert(request("batch", "d"))]);
+        storage.upsert_many(vec![request("many", "e")]);
+        storage.append(key("append"), "f").unwrap();
+
+        for (name, value) in names.into_iter().zip(["a", "b", "c", "d", "e", "f"]) {
+            assert_eq!(storage.get(key(name)).unwrap().value, json!(value));
+        }
+        assert_eq!(storage.hits(), 0);
+    }
+
+    #[test]
+    fn test_cached_misses_expire() {
+        let storage = cache(Duration::from_millis(20));
+        assert!(storage.get(key("ghost")).is_err());
+        std::thread::sleep(Duration::from_millis(40));
+
+        assert!(storage.get(key("ghost")).is_err());
+        assert_eq!(storage.hits(), 0);
+
+        std::thread::sleep(Duration::from_millis(40));
+        storage.purge_expired();
+        assert!(storage.misses.is_empty());
+    }
+}
@@ -0,0 +1,100 @@
+use crate::app::models::Metadata;
+use crate::types::Key;
+
+#[derive(Debug, Clone)]
+pub struct ValueSearch {
+    needle: String,
+    case_sensitive: bool,
+    exact: bool,
+}
+
+impl ValueSearch {
+    pub fn new(needle: &str, case_sensitive: bool) -> Self {
+        let needle = if case_sensitive {
+            needle.to_string()
+        } else {
+            needle.to_lowercase()
+        };
+        Self {
+            needle,
+            case_sensitive,
+            exact: false,
+        }
+    }
+
+    pub fn exact(value: &str) -> Self {
+        Self {
+            needle: value.to_string(),
+            case_sensitive: true,
+            exact: true,
+        }
+    }
+
+    pub fn exact_value(&self) -> Option<&str> {
+        self.exact.then_some(self.needle.as_str())
+    }
+
+    pub fn matches(&self, value: &str) -> bool {
+        if self.exact {
+            value == self.needle
+        } else if self.case_sensitive {
+            value.contains(&self.needle)
+        } else {
+            value.to_lowercase().contains(&self.needle)
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct SearchPage {
+    pub matches: Vec<(Key, Metadata)>,
+    pub next_cursor: Option<Key>,
+}
+
+impl SearchPage {
+    pub fn from_sorted(mut matches: Vec<(Key, Metadata)>, limit: usize) -> Self {
+        if matches.len() <= limit {
+            return Self {
+                matches,
+                next_cursor: None,
+            };
+        }
+
+        matches.truncate(limit);
+        let next_cursor = matches.last().map(|(key, _)| key.clone());
+        Self {
+            matches,
+            next_cursor,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_case_sensitive_match() {
+        let search = ValueSearch::new("Host", true);
+        assert!(search.matches("db.Host.internal"));
+        assert!(!search.matches("db.host.internal"));
+    }
+
+    #[test]
+    fn test_exact_match() {
+        let search = ValueSearch::exact("blue");
+        assert_eq!(search.exact_value(), Some("blue"));
+        assert!(search.matches("blue"));
+        assert!(!search.matches("light blue"));
+        assert!(!search.matches("Blue"));
+        assert_eq!(ValueSearch::new("blue", true).exact_value(), None);
+    }
+
+    #[test]
+    fn test_case_insensitive_match() {
+        let search = ValueSearch::new("Host", false);
+        assert!(search.matches("db.HOST.internal"));
+        assert!(search.matches("db.host.internal"));
+        assert!(!search.matches("db.internal"));
+    }
+}
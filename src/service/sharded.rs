@@ -0,0 +1,1082 @@
+use crate::app::models::{
+    CounterBounds, CounterOverflow, CreateKVRequest, KeyValueResponse, ListSide, Metadata,
+    ValueResponse,
+};
+use crate::service::{
+    CasOutcome, InMemoryConfig, InMemoryStorage, KeyFilter, LargestEntries, MemoryUsage, OpResult,
+    RestoreTarget, SearchPage, Storage, StorageError, TxError, TxOp, UpsertOutcome, ValueSearch,
+    VersionPrecondition, WriteOp, WriteOutcome,
+};
+use crate::types::Key;
+use chrono::{DateTime, Utc};
+use rand::Rng;
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::ops::Bound;
+use std::sync::RwLockReadGuard;
+use std::time::Duration;
+
+/// Points each shard gets on the hash ring. More points spread keys more
+/// evenly; 64 keeps every shard within a few percent of its fair share.
+const VIRTUAL_NODES: usize = 64;
+
+/// Spreads keys over several independent [`InMemoryStorage`] instances, so
+/// writers to different keys rarely contend on the same map. Each key lives
+/// on exactly one shard, picked by consistent hashing.
+///
+/// Key-count and memory limits are divided evenly between the shards and
+/// enforced per shard, so one shard can fill up before the store as a whole
+/// does. Listings and counts visit every shard and merge the results.
+pub struct ShardedStorage {
+    shards: Vec<InMemoryStorage>,
+    ring: Vec<(u64, usize)>,
+}
+
+impl ShardedStorage {
+    pub fn new(shards: usize) -> Self {
+        Self::with_config(shards, InMemoryConfig::default())
+    }
+
+    pub fn with_config(shards: usize, config: InMemoryConfig) -> Self {
+        let count = shards.max(1);
+        let shard_config = InMemoryConfig {
+            max_keys: config.max_keys.map(|max_keys| max_keys.div_ceil(count)),
+            max_bytes: config
+                .max_bytes
+                .map(|max_bytes| max_bytes.div_ceil(count as u64)),
+            ..config
+        };
+        let mut ring: Vec<(u64, usize)> = (0..count)
+            .flat_map(|shard| {
+                (0..VIRTUAL_NODES).map(move |node| (ring_hash(&(shard, node)), shard))
+            })
+            .collect();
+        ring.sort_unstable();
+
+        Self {
+            shards: (0..count)
+                .map(|_| InMemoryStorage::with_config(shard_config.clone()))
+                .collect(),
+            ring,
+        }
+    }
+
+    pub fn shard_count(&self) -> usize {
+        self.shards.len()
+    }
+
+    fn shard_index(&self, key: &Key) -> usize {
+        let hash = ring_hash(key.as_str());
+        let point = self.ring.partition_point(|(node, _)| *node < hash);
+        self.ring[point % self.ring.len()].1
+    }
+
+    fn shard(&self, key: &Key) -> &InMemoryStorage {
+        &self.shards[self.shard_index(key)]
+    }
+
+    /// The read gate of every shard, taken in shard order.
+    fn read_gates(&self) -> Vec<RwLockReadGuard<'_, ()>> {
+        self.shards.iter().map(InMemoryStorage::read_gate).collect()
+    }
+}
+
+fn ring_hash(value: &(impl Hash + ?Sized)) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+fn sorted_keys(mut keys: Vec<Key>, limit: usize) -> Vec<Key> {
+    keys.sort();
+    keys.truncate(limit);
+    keys
+}
+
+impl Storage for ShardedStorage {
+    fn get(&self, key: Key) -> Result<ValueResponse, StorageError> {
+        self.shard(&key).get(key)
+    }
+
+    fn get_stale(&self, key: Key) -> Result<ValueResponse, StorageError> {
+        self.shard(&key).get_stale(key)
+    }
+
+    /// Holds the read gate of every shard involved, taken in shard order like
+    /// a transaction's locks, so the keys come from the same state.
+    /// Listings, searches and counts hold every shard's gate the same way.
+    fn get_many(&self, keys: &[Key]) -> Vec<Result<ValueResponse, StorageError>> {
+        let placed: Vec<usize> = keys.iter().map(|key| self.shard_index(key)).collect();
+        let involved: BTreeSet<usize> = placed.iter().copied().collect();
+        let _gates: Vec<_> = involved
+            .iter()
+            .map(|shard| self.shards[*shard].read_gate())
+            .collect();
+        keys.iter()
+            .zip(placed)
+            .map(|(key, shard)| self.shards[shard].get_gated(key))
+            .collect()
+    }
+
+    fn insert(&self, body: CreateKVRequest) -> Result<KeyValueResponse, StorageError> {
+        self.shard(&body.key).insert(body)
+    }
+
+    fn upsert(
+        &self,
+        body: CreateKVRequest,
+    ) -> Result<(KeyValueResponse, UpsertOutcome), StorageError> {
+        self.shard(&body.key).upsert(body)
+    }
+
+    fn upsert_if_version(
+        &self,
+        body: CreateKVRequest,
+        expected: &VersionPrecondition,
+    ) -> Result<KeyValueResponse, StorageError> {
+        self.shard(&body.key).upsert_if_version(body, expected)
+    }
+
+    fn delete(&self, key: Key) -> Result<ValueResponse, StorageError> {
+        self.shard(&key).delete(key)
+    }
+
+    fn list_keys(&self) -> Vec<Key> {
+        sorted_keys(
+            self.shards.iter().flat_map(Storage::list_keys).collect(),
+            usize::MAX,
+        )
+    }
+
+    fn list_entries(&self, filter: &KeyFilter, limit: usize) -> Vec<KeyValueResponse> {
+        let _gates = self.read_gates();
+        let mut entries: Vec<KeyValueResponse> = self
+            .shards
+            .iter()
+            .flat_map(|shard| shard.list_entries_gated(filter, limit))
+            .collect();
+        entries.sort_by(|a, b| a.key.cmp(&b.key));
+        entries.truncate(limit);
+        entries
+    }
+
+    fn len(&self) -> usize {
+        self.shards.iter().map(Storage::len).sum()
+    }
+
+    fn set_ttl(&self, key: &Key, ttl: Duration) -> Result<Metadata, StorageError> {
+        self.shard(key).set_ttl(key, ttl)
+    }
+
+    fn set_schema(&self, key: &Key, schema: Option<Value>) -> Result<Metadata, StorageError> {
+        self.shard(key).set_schema(key, schema)
+    }
+
+    fn delete_if(&self, key: Key, expected: &str) -> Result<ValueResponse, StorageError> {
+        self.shard(&key).delete_if(key, expected)
+    }
+
+    fn upsert_if_unmodified(
+        &self,
+        body: CreateKVRequest,
+        since: DateTime<Utc>,
+    ) -> Result<(KeyValueResponse, UpsertOutcome), StorageError> {
+        self.shard(&body.key).upsert_if_unmodified(body, since)
+    }
+
+    fn delete_if_unmodified(
+        &self,
+        key: Key,
+        since: DateTime<Utc>,
+    ) -> Result<ValueResponse, StorageError> {
+        self.shard(&key).delete_if_unmodified(key, since)
+    }
+
+    fn history(&self, key: Key, limit: usize) -> Result<Vec<ValueResponse>, StorageError> {
+        self.shard(&key).history(key, limit)
+    }
+
+    fn append(&self, key: Key, suffix: &str) -> Result<(Metadata, UpsertOutcome), StorageError> {
+        self.shard(&key).append(key, suffix)
+    }
+
+    fn compare_and_swap(
+        &self,
+        expected: Option<&Value>,
+        body: CreateKVRequest,
+    ) -> Result<CasOutcome, StorageError> {
+        self.shard(&body.key).compare_and_swap(expected, body)
+    }
+
+    fn merge_patch(&self, key: Key, patch: &Value) -> Result<KeyValueResponse, StorageError> {
+        self.shard(&key).merge_patch(key, patch)
+    }
+
+    fn get_fields(&self, key: &Key) -> Result<BTreeMap<String, String>, StorageError> {
+        self.shard(key).get_fields(key)
+    }
+
+    fn get_field(&self, key: &Key, field: &Key) -> Result<String, StorageError> {
+        self.shard(key).get_field(key, field)
+    }
+
+    fn set_field(
+        &self,
+        key: Key,
+        field: &Key,
+        value: &str,
+    ) -> Result<(Metadata, UpsertOutcome), StorageError> {
+        self.shard(&key).set_field(key, field, value)
+    }
+
+    fn delete_field(&self, key: Key, field: &Key) -> Result<(), StorageError> {
+        self.shard(&key).delete_field(key, field)
+    }
+
+    fn list_push(
+        &self,
+        key: Key,
+        side: ListSide,
+        value: &str,
+    ) -> Result<(Metadata, usize, UpsertOutcome), StorageError> {
+        self.shard(&key).list_push(key, side, value)
+    }
+
+    fn list_pop(&self, key: Key, side: ListSide) -> Result<(String, usize), StorageError> {
+        self.shard(&key).list_pop(key, side)
+    }
+
+    fn list_slice(
+        &self,
+        key: &Key,
+        start: i64,
+        end: i64,
+    ) -> Result<(Vec<String>, usize), StorageError> {
+        self.shard(key).list_slice(key, start, end)
+    }
+
+    fn add_member(&self, key: Key, member: &str) -> Result<(Metadata, bool, usize), StorageError> {
+        self.shard(&key).add_member(key, member)
+    }
+
+    fn remove_member(&self, key: Key, member: &str) -> Result<(bool, usize), StorageError> {
+        self.shard(&key).remove_member(key, member)
+    }
+
+    fn members(&self, key: &Key) -> Result<Vec<String>, StorageError> {
+        self.shard(key).members(key)
+    }
+
+    fn is_member(&self, key: &Key, member: &str) -> Result<bool, StorageError> {
+        self.shard(key).is_member(key, member)
+    }
+
+    fn create_counter(
+        &self,
+        key: Key,
+        bounds: CounterBounds,
+    ) -> Result<KeyValueResponse, StorageError> {
+        self.shard(&key).create_counter(key, bounds)
+    }
+
+    fn counter_add(
+        &self,
+        key: Key,
+        delta: i64,
+        overflow: CounterOverflow,
+    ) -> Result<(Metadata, i64, bool), StorageError> {
+        self.shard(&key).counter_add(key, delta, overflow)
+    }
+
+    fn counter_reset(&self, key: Key) -> Result<(Metadata, i64), StorageError> {
+        self.shard(&key).counter_reset(key)
+    }
+
+    fn restore(&self, key: Key, target: RestoreTarget) -> Result<KeyValueResponse, StorageError> {
+        self.shard(&key).restore(key, target)
+    }
+
+    fn count_prefix(&self, prefix: &str) -> usize {
+        let _gates = self.read_gates();
+        self.shards
+            .iter()
+            .map(|shard| shard.count_prefix_gated(prefix))
+            .sum()
+    }
+
+    fn scan_keys(&self, filter: &KeyFilter) -> Vec<Key> {
+        sorted_keys(
+            self.shards
+                .iter()
+                .flat_map(|shard| shard.scan_keys(filter))
+                .collect(),
+            usize::MAX,
+        )
+    }
+
+    fn list_range(&self, start: Bound<&str>, end: Bound<&str>, limit: usize) -> Vec<Key> {
+        sorted_keys(
+            self.shards
+                .iter()
+                .flat_map(|shard| shard.list_range(start, end, limit))
+                .collect(),
+            limit,
+        )
+    }
+
+    /// Asks every shard for one match more than the page holds, so the
+    /// merged page knows whether any shard has more to give.
+    fn search_values(&self, search: &ValueSearch, after: Option<&str>, limit: usize) -> SearchPage {
+        let _gates = self.read_gates();
+        let mut matches: Vec<(Key, Metadata)> = self
+            .shards
+            .iter()
+            .flat_map(|shard| {
+                shard
+                    .search_values_gated(search, after, limit.saturating_add(1))
+                    .matches
+            })
+            .collect();
+        matches.sort_by(|a, b| a.0.cmp(&b.0));
+        SearchPage::from_sorted(matches, limit)
+    }
+
+    fn exists(&self, key: &Key) -> bool {
+        self.shard(key).exists(key)
+    }
+
+    /// Picks a shard with probability proportional to its matching keys, so
+    /// every key is equally likely however unevenly the shards are filled.
+    fn random_entry(&self, prefix: Option<&str>) -> Option<KeyValueResponse> {
+        let _gates = self.read_gates();
+        let counts: Vec<usize> = self
+            .shards
+            .iter()
+            .map(|shard| shard.count_prefix_gated(prefix.unwrap_or_default()))
+            .collect();
+        let total: usize = counts.iter().sum();
+        if total == 0 {
+            return None;
+        }
+        let mut pick = rand::thread_rng().gen_range(0..total);
+        let (shard, _) =
+            self.shards
+                .iter()
+                .zip(&counts)
+                .find(|(_, count)| match pick.checked_sub(**count) {
+                    Some(rest) => {
+                        pick = rest;
+                        false
+                    }
+                    None => true,
+                })?;
+        shard.random_entry_gated(prefix)
+    }
+
+    fn largest_entries(&self, limit: usize) -> Vec<(Key, Metadata)> {
+        let _gates = self.read_gates();
+        let mut largest = LargestEntries::new(limit);
+        for shard in &self.shards {
+            for (key, metadata) in shard.largest_entries_gated(limit) {
+                largest.offer(&key, &metadata);
+            }
+        }
+        largest.into_sorted()
+    }
+
+    fn get_metadata(&self, key: &Key) -> Result<Metadata, StorageError> {
+        self.shard(key).get_metadata(key)
+    }
+
+    fn get_ttl(&self, key: &Key) -> Result<Option<Duration>, StorageError> {
+        self.shard(key).get_ttl(key)
+    }
+
+    fn purge_expired(&self) -> usize {
+        self.shards.iter().map(Storage::purge_expired).sum()
+    }
+
+    fn clear(&self) -> usize {
+        self.shards.iter().map(Storage::clear).sum()
+    }
+
+    fn memory_usage(&self) -> Option<MemoryUsage> {
+        let usages: Vec<MemoryUsage> = self
+            .shards
+            .iter()
+            .filter_map(Storage::memory_usage)
+            .collect();
+        Some(MemoryUsage {
+            used_bytes: usages.iter().map(|usage| usage.used_bytes).sum(),
+            limit_bytes: usages.iter().map(|usage| usage.limit_bytes).sum(),
+        })
+    }
+
+    /// Plans each shard's share of the ops on that shard, keeping their
+    /// order within it; ops on different keys cannot affect one another.
+    fn batch_plan(&self, ops: Vec<WriteOp>) -> Vec<Result<WriteOutcome, StorageError>> {
+        let mut by_shard: BTreeMap<usize, (Vec<usize>, Vec<WriteOp>)> = BTreeMap::new();
+        for (index, op) in ops.into_iter().enumerate() {
+            let (indices, ops) = by_shard.entry(self.shard_index(op.key())).or_default();
+            indices.push(index);
+            ops.push(op);
+        }
+        let mut results = Vec::new();
+        for (shard, (indices, ops)) in by_shard {
+            results.extend(indices.into_iter().zip(self.shards[shard].batch_plan(ops)));
+        }
+        results.sort_by_key(|(index, _)| *index);
+        results.into_iter().map(|(_, result)| result).collect()
+    }
+
+    /// Splits the ops by shard, keeping their order within each shard, and
+    /// stages every share before committing any. Transactions that share a
+    /// shard wait for each other's lock, but they cannot deadlock: the
+    /// shards involved are always locked in index order. Ops on different
+    /// keys cannot affect one another, so the op reported on failure is the
+    /// first one in request order that fails, exactly as with a single
+    /// store.
+    fn transaction(&self, ops: Vec<TxOp>) -> Result<Vec<OpResult>, TxError> {
+        let mut by_shard: BTreeMap<usize, Vec<(usize, TxOp)>> = BTreeMap::new();
+        for (index, op) in ops.into_iter().enumerate() {
+            by_shard
+                .entry(self.shard_index(op.key()))
+                .or_default()
+                .push((index, op));
+        }
+        let involved: BTreeSet<usize> = by_shard.keys().copied().collect();
+        let _locks: Vec<_> = involved
+            .iter()
+            .map(|shard| self.shards[*shard].lock_writes())
+            .collect();
+
+        let mut staged = Vec::with_capacity(by_shard.len());
+        let mut results = Vec::new();
+        let mut failure: Option<TxError> = None;
+        for (shard, ops) in by_shard {
+            let indices: Vec<usize> = ops.iter().map(|(index, _)| *index).collect();
+            match self.shards[shard].stage(ops) {
+                Ok((tx, shard_results)) => {
+                    staged.push((shard, tx));
+                    results.extend(indices.into_iter().zip(shard_results));
+                }
+                Err(tx_error) => {
+                    if failure
+                        .as_ref()
+                        .is_none_or(|failure| tx_error.index() < failure.index())
+                    {
+                        failure = Some(tx_error);
+                    }
+                }
+            }
+        }
+        if let Some(tx_error) = failure {
+            return Err(tx_error);
+        }
+
+        let _readers: Vec<_> = involved
+            .iter()
+            .map(|shard| self.shards[*shard].lock_reads())
+            .collect();
+        for (shard, tx) in staged {
+            self.shards[shard].commit(tx);
+        }
+        results.sort_by_key(|(index, _)| *index);
+        Ok(results.into_iter().map(|(_, result)| result).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::models::{ValueEncoding, ValueType};
+    use crate::service::tests::*;
+
+    const SHARDS: usize = 4;
+
+    fn create_storage() -> ShardedStorage {
+        ShardedStorage::new(SHARDS)
+    }
+
+    fn key(name: &str) -> Key {
+        Key::new(name.to_string()).unwrap()
+    }
+
+    fn text(name: &str, value: &str) -> CreateKVRequest {
+        CreateKVRequest {
+            key: key(name),
+            value: value.into(),
+            value_type: ValueType::Text,
+            encoding: ValueEncoding::Utf8,
+            ttl_seconds: None,
+        }
+    }
+
+    #[test]
+    fn test_sharded_get_nonexistent() {
+        let storage = create_storage();
+        test_get_nonexistent_key(&storage);
+    }
+
+    #[test]
+    fn test_sharded_upsert_new() {
+        let storage = create_storage();
+        test_upsert_new_key(&storage);
+    }
+
+    #[test]
+    fn test_sharded_upsert_preserves_created_at() {
+        let storage = create_storage();
+        test_upsert_existing_key_preserves_created_at(&storage);
+    }
+
+    #[test]
+    fn test_sharded_get_existing() {
+        let storage = create_storage();
+        test_get_existing_key(&storage);
+    }
+
+    #[test]
+    fn test_sharded_delete_existing() {
+        let storage = create_storage();
+        test_delete_existing_key(&storage);
+    }
+
+    #[test]
+    fn test_sharded_delete_nonexistent() {
+        let storage = create_storage();
+        test_delete_nonexistent_key(&storage);
+    }
+
+    #[test]
+    fn test_sharded_list_keys_empty() {
+        let storage = create_storage();
+        test_list_keys_empty(&storage);
+    }
+
+    #[test]
+    fn test_sharded_list_keys_multiple() {
+        let storage = create_storage();
+        test_list_keys_multiple(&storage);
+    }
+
+    #[test]
+    fn test_sharded_listing_is_sorted() {
+        let storage = create_storage();
+        test_listing_is_sorted(&storage);
+    }
+
+    #[test]
+    fn test_sharded_list_entries_respects_limit() {
+        let storage = create_storage();
+        test_list_entries_respects_limit(&storage);
+    }
+
+    #[test]
+    fn test_sharded_list_entries_spans_index_batches() {
+        let storage = create_storage();
+        test_list_entries_spans_index_batches(&storage);
+    }
+
+    #[test]
+    fn test_sharded_get_many_mixed() {
+        let storage = create_storage();
+        test_get_many_mixed(&storage);
+    }
+
+    #[test]
+    fn test_sharded_len_tracks_writes() {
+        let storage = create_storage();
+        test_len_tracks_writes(&storage);
+    }
+
+    #[test]
+    fn test_sharded_count_prefix() {
+        let storage = create_storage();
+        test_count_prefix(&storage);
+    }
+
+    #[test]
+    fn test_sharded_exists() {
+        let storage = create_storage();
+        test_exists(&storage);
+    }
+
+    #[test]
+    fn test_sharded_random_entry_empty() {
+        let storage = create_storage();
+        test_random_entry_empty(&storage);
+    }
+
+    #[test]
+    fn test_sharded_random_entry_distribution() {
+        let storage = create_storage();
+        test_random_entry_distribution(&storage);
+    }
+
+    #[test]
+    fn test_sharded_random_entry_prefix() {
+        let storage = create_storage();
+        test_random_entry_prefix(&storage);
+    }
+
+    #[test]
+    fn test_sharded_scan_keys_by_update_time() {
+        let storage = create_storage();
+        test_scan_keys_by_update_time(&storage);
+    }
+
+    #[test]
+    fn test_sharded_list_range_boundaries() {
+        let storage = create_storage();
+        test_list_range_boundaries(&storage);
+    }
+
+    #[test]
+    fn test_sharded_list_range_pages() {
+        let storage = create_storage();
+        test_list_range_pages(&storage);
+    }
+
+    #[test]
+    fn test_sharded_list_keys_after_cursor() {
+        let storage = create_storage();
+        test_list_keys_after_cursor(&storage);
+    }
+
+    #[test]
+    fn test_sharded_list_range_after_deletes() {
+        let storage = create_storage();
+        test_list_range_after_deletes(&storage);
+    }
+
+    #[test]
+    fn test_sharded_search_values_pages() {
+        let storage = create_storage();
+        test_search_values_pages(&storage);
+    }
+
+    #[test]
+    fn test_sharded_search_values_case_insensitive() {
+        let storage = create_storage();
+        test_search_values_case_insensitive(&storage);
+    }
+
+    #[test]
+    fn test_sharded_get_metadata() {
+        let storage = create_storage();
+        test_get_metadata(&storage);
+    }
+
+    #[test]
+    fn test_sharded_size_bytes_tracks_value() {
+        let storage = create_storage();
+        test_size_bytes_tracks_value(&storage);
+    }
+
+    #[test]
+    fn test_sharded_set_and_get_ttl() {
+        let storage = create_storage();
+        test_set_and_get_ttl(&storage);
+    }
+
+    #[test]
+    fn test_sharded_ttl_missing_key() {
+        let storage = create_storage();
+        test_ttl_missing_key(&storage);
+    }
+
+    #[test]
+    fn test_sharded_expired_key_is_absent() {
+        let storage = create_storage();
+        test_expired_key_is_absent(&storage);
+    }
+
+    #[test]
+    fn test_sharded_write_clears_ttl() {
+        let storage = create_storage();
+        test_write_clears_ttl(&storage);
+    }
+
+    #[test]
+    fn test_sharded_upsert_if_version() {
+        let storage = create_storage();
+        test_upsert_if_version(&storage);
+    }
+
+    #[test]
+    fn test_sharded_upsert_if_version_race() {
+        let storage = create_storage();
+        test_upsert_if_version_race(&storage);
+    }
+
+    #[test]
+    fn test_sharded_value_type_is_kept_across_writes() {
+        let storage = create_storage();
+        test_value_type_is_kept_across_writes(&storage);
+    }
+
+    #[test]
+    fn test_sharded_version_counter() {
+        let storage = create_storage();
+        test_version_counter(&storage);
+    }
+
+    #[test]
+    fn test_sharded_concurrent_upserts_produce_unique_versions() {
+        let storage = create_storage();
+        test_concurrent_upserts_produce_unique_versions(&storage);
+    }
+
+    #[test]
+    fn test_sharded_largest_entries() {
+        let storage = create_storage();
+        test_largest_entries(&storage);
+    }
+
+    #[test]
+    fn test_sharded_listing_sorted_after_shuffled_concurrent_inserts() {
+        let storage = create_storage();
+        test_listing_sorted_after_shuffled_concurrent_inserts(&storage);
+    }
+
+    #[test]
+    fn test_sharded_delete_silent() {
+        let storage = create_storage();
+        test_delete_silent(&storage);
+    }
+
+    #[test]
+    fn test_sharded_delete_if_value_matches() {
+        let storage = create_storage();
+        test_delete_if_value_matches(&storage);
+    }
+
+    #[test]
+    fn test_sharded_unmodified_since_guards_writes() {
+        let storage = create_storage();
+        test_unmodified_since_guards_writes(&storage);
+    }
+
+    #[test]
+    fn test_sharded_stale_reads_match_strong_reads() {
+        let storage = create_storage();
+        test_stale_reads_match_strong_reads(&storage);
+    }
+
+    #[test]
+    fn test_sharded_schema_guards_writes() {
+        let storage = create_storage();
+        test_schema_guards_writes(&storage);
+    }
+
+    #[test]
+    fn test_sharded_structured_values_round_trip() {
+        let storage = create_storage();
+        test_structured_values_round_trip(&storage);
+    }
+
+    #[test]
+    fn test_sharded_concurrent_appends_keep_every_fragment() {
+        let storage = create_storage();
+        test_concurrent_appends_keep_every_fragment(&storage);
+    }
+
+    #[test]
+    fn test_sharded_upsert_many() {
+        let storage = create_storage();
+        test_upsert_many(&storage);
+    }
+
+    #[test]
+    fn test_sharded_upsert_many_matches_single_upserts() {
+        test_upsert_many_matches_single_upserts(&create_storage(), &create_storage());
+    }
+
+    #[test]
+    fn test_sharded_batch_apply() {
+        let storage = create_storage();
+        test_batch_apply(&storage);
+    }
+
+    #[test]
+    fn test_sharded_batch_plan() {
+        let storage = create_storage();
+        test_batch_plan(&storage);
+    }
+
+    #[test]
+    fn test_sharded_delete_many_strict() {
+        let storage = create_storage();
+        test_delete_many_strict(&storage);
+    }
+
+    #[test]
+    fn test_sharded_concurrent_merge_patches_keep_every_field() {
+        let storage = create_storage();
+        test_concurrent_merge_patches_keep_every_field(&storage);
+    }
+
+    #[test]
+    fn test_sharded_binary_values_round_trip() {
+        let storage = create_storage();
+        test_binary_values_round_trip(&storage);
+    }
+
+    #[test]
+    fn test_sharded_clear_removes_every_key() {
+        let storage = ShardedStorage::with_config(
+            SHARDS,
+            InMemoryConfig {
+                index_values: true,
+                ..Default::default()
+            },
+        );
+        test_clear_removes_every_key(&storage);
+        assert_eq!(storage.memory_usage().unwrap().used_bytes, 0);
+    }
+
+    #[test]
+    fn test_sharded_hash_fields_and_type_conflicts() {
+        let storage = create_storage();
+        test_hash_fields_and_type_conflicts(&storage);
+    }
+
+    #[test]
+    fn test_sharded_concurrent_field_writes_keep_every_field() {
+        let storage = create_storage();
+        test_concurrent_field_writes_keep_every_field(&storage);
+    }
+
+    #[test]
+    fn test_sharded_list_push_pop_and_range() {
+        let storage = create_storage();
+        test_list_push_pop_and_range(&storage);
+    }
+
+    #[test]
+    fn test_sharded_list_type_conflicts() {
+        let storage = create_storage();
+        test_list_type_conflicts(&storage);
+    }
+
+    #[test]
+    fn test_sharded_concurrent_list_pops_consume_each_element_once() {
+        let storage = create_storage();
+        test_concurrent_list_pops_consume_each_element_once(&storage);
+    }
+
+    #[test]
+    fn test_sharded_set_membership_operations() {
+        let storage = create_storage();
+        test_set_membership_operations(&storage);
+    }
+
+    #[test]
+    fn test_sharded_counter_add_reset_and_bounds() {
+        let storage = create_storage();
+        test_counter_add_reset_and_bounds(&storage);
+    }
+
+    #[test]
+    fn test_sharded_counter_arithmetic_never_wraps() {
+        let storage = create_storage();
+        test_counter_arithmetic_never_wraps(&storage);
+    }
+
+    #[test]
+    fn test_sharded_counter_errors_on_other_values() {
+        let storage = create_storage();
+        test_counter_errors_on_other_values(&storage);
+    }
+
+    #[test]
+    fn test_sharded_concurrent_counter_adds_stop_at_bounds() {
+        let storage = create_storage();
+        test_concurrent_counter_adds_stop_at_bounds(&storage);
+    }
+
+    #[test]
+    fn test_sharded_counter_reset_racing_with_adds() {
+        let storage = create_storage();
+        test_counter_reset_racing_with_adds(&storage);
+    }
+
+    #[test]
+    fn test_sharded_transaction_applies_all_or_nothing() {
+        let storage = create_storage();
+        test_transaction_applies_all_or_nothing(&storage);
+    }
+
+    #[test]
+    fn test_sharded_concurrent_transfers_conserve_total() {
+        let storage = create_storage();
+        test_concurrent_transfers_conserve_total(&storage);
+    }
+
+    #[test]
+    fn test_sharded_transaction_checks_gate_the_writes() {
+        let storage = create_storage();
+        test_transaction_checks_gate_the_writes(&storage);
+    }
+
+    #[test]
+    fn test_sharded_overlapping_transactions_stay_consistent() {
+        let storage = create_storage();
+        test_overlapping_transactions_stay_consistent(&storage);
+    }
+
+    #[test]
+    fn test_sharded_overlapping_transactions_stay_consistent_in_listings() {
+        let storage = create_storage();
+        test_overlapping_transactions_stay_consistent_in_listings(&storage);
+    }
+
+    #[test]
+    fn test_sharded_compare_and_swap() {
+        let storage = create_storage();
+        test_compare_and_swap(&storage);
+    }
+
+    #[test]
+    fn test_sharded_concurrent_compare_and_swap_has_one_winner() {
+        let storage = create_storage();
+        test_concurrent_compare_and_swap_has_one_winner(&storage);
+    }
+
+    #[test]
+    fn test_sharded_shard_counts() {
+        for shards in [1, 2, 3, 16] {
+            let storage = ShardedStorage::new(shards);
+            test_list_keys_multiple(&storage);
+        }
+        test_concurrent_upserts(ShardedStorage::new(SHARDS));
+    }
+
+    #[test]
+    fn test_sharded_concurrent_access() {
+        let storage = create_storage();
+        test_concurrent_upserts(storage);
+    }
+
+    #[test]
+    fn test_sharded_find_by_value() {
+        let storage = create_storage();
+        test_find_by_value(&storage);
+    }
+
+    #[test]
+    fn test_sharded_find_by_value_indexed() {
+        let storage = ShardedStorage::with_config(
+            SHARDS,
+            InMemoryConfig {
+                index_values: true,
+                ..Default::default()
+            },
+        );
+        test_find_by_value(&storage);
+    }
+
+    #[test]
+    fn test_keys_spread_over_every_shard() {
+        let storage = create_storage();
+        for i in 0..400 {
+            storage.upsert(text(&format!("key-{i}"), "v")).unwrap();
+        }
+
+        for shard in &storage.shards {
+            assert!(
+                (50..=150).contains(&shard.len()),
+                "uneven shard: {}",
+                shard.len()
+            );
+        }
+        assert_eq!(storage.len(), 400);
+        let names: Vec<Key> = (0..400).map(|i| key(&format!("key-{i}"))).collect();
+        for name in &names {
+            assert_eq!(storage.shard(name).get(name.clone()).unwrap().value, "v");
+        }
+    }
+
+    #[test]
+    fn test_adding_a_shard_moves_few_keys() {
+        let (before, after) = (ShardedStorage::new(4), ShardedStorage::new(5));
+        let names: Vec<Key> = (0..1000).map(|i| key(&format!("user-{i}"))).collect();
+        let moved = names
+            .iter()
+            .filter(|name| {
+                let (old, new) = (before.shard_index(name), after.shard_index(name));
+                assert!(new == old || new == 4, "a key moved between old shards");
+                new != old
+            })
+            .count();
+
+        assert!((100..=350).contains(&moved), "{moved} of 1000 keys moved");
+    }
+
+    #[test]
+    fn test_limits_are_split_between_shards() {
+        let storage = ShardedStorage::with_config(
+            SHARDS,
+            InMemoryConfig {
+                max_keys: Some(10),
+                max_bytes: Some(1000),
+                ..Default::default()
+            },
+        );
+        assert_eq!(storage.memory_usage().unwrap().limit_bytes, Some(1000));
+        for i in 0..100 {
+            storage.upsert(text(&format!("key-{i}"), "v")).unwrap();
+        }
+
+        for shard in &storage.shards {
+            assert!(shard.len() <= 3);
+        }
+        assert!(storage.len() <= 12);
+    }
+
+    #[test]
+    fn test_transaction_across_shards_rolls_back_every_shard() {
+        let storage = create_storage();
+        let names: Vec<String> = (0..8).map(|i| format!("account-{i}")).collect();
+        let shards: BTreeSet<usize> = names
+            .iter()
+            .map(|name| storage.shard_index(&key(name)))
+            .collect();
+        assert!(shards.len() > 1);
+        for name in &names {
+            storage.upsert(text(name, "old")).unwrap();
+        }
+
+        let mut ops: Vec<TxOp> = names
+            .iter()
+            .map(|name| TxOp::Set(text(name, "new")))
+            .collect();
+        ops.push(TxOp::Delete(key("missing")));
+        ops.push(TxOp::Cas {
+            expected: Some("stale".into()),
+            body: text(&names[0], "newer"),
+        });
+        let error = storage.transaction(ops).unwrap_err();
+        assert!(matches!(
+            error,
+            TxError::Failed {
+                index: 8,
+                error: StorageError::KeyNotFound(_)
+            }
+        ));
+        for name in &names {
+            assert_eq!(storage.get(key(name)).unwrap().value, "old");
+        }
+
+        let ops = names
+            .iter()
+            .map(|name| TxOp::Set(text(name, "new")))
+            .collect();
+        assert_eq!(storage.transaction(ops).unwrap().len(), names.len());
+        for name in &names {
+            assert_eq!(storage.get(key(name)).unwrap().value, "new");
+        }
+    }
+}
@@ -1,5 +1,12 @@
 use super::interface::Storage;
-use crate::app::models::CreateKVRequest;
+use crate::app::models::{
+    CounterBounds, CounterOverflow, CreateKVRequest, KeyValueResponse, ListSide, StoredValue,
+    ValueEncoding, ValueType, content_etag, content_etag_bytes, value_text,
+};
+use crate::service::{
+    CasOutcome, KeyFilter, OpResult, StorageError, TxCheck, TxError, TxOp, UpsertOutcome,
+    ValueSearch, VersionPrecondition, WriteOp, WriteOutcome,
+};
 use crate::types::Key;
 
 pub fn test_get_nonexistent_key<S: Storage>(storage: &S) {
@@ -11,11 +18,15 @@ pub fn test_get_nonexistent_key<S: Storage>(storage: &S) {
 pub fn test_upsert_new_key<S: Storage>(storage: &S) {
     let request = CreateKVRequest {
         key: Key::new("test-key".to_string()).unwrap(),
-        value: "test-value".to_string(),
+        value: "test-value".into(),
+        value_type: ValueType::Text,
+        encoding: ValueEncoding::Utf8,
+        ttl_seconds: None,
     };
 
-    let response = storage.upsert(request);
+    let (response, outcome) = storage.upsert(request).unwrap();
 
+    assert_eq!(outcome, UpsertOutcome::Created);
     assert_eq!(response.key.as_str(), "test-key");
     assert_eq!(response.value, "test-value");
     assert_eq!(response.metadata.created_at, response.metadata.updated_at);
@@ -26,19 +37,27 @@ pub fn test_upsert_existing_key_preserves_created_at<S: Storage>(storage: &S) {
 
     let first_request = CreateKVRequest {
         key: key.clone(),
-        value: "first-value".to_string(),
+        value: "first-value".into(),
+        value_type: ValueType::Text,
+        encoding: ValueEncoding::Utf8,
+        ttl_seconds: None,
     };
-    let first_response = storage.upsert(first_request);
+    let (first_response, first_outcome) = storage.upsert(first_request).unwrap();
+    assert_eq!(first_outcome, UpsertOutcome::Created);
     let original_created_at = first_response.metadata.created_at;
 
     std::thread::sleep(std::time::Duration::from_millis(10));
 
     let second_request = CreateKVRequest {
         key: key.clone(),
-        value: "second-value".to_string(),
+        value: "second-value".into(),
+        value_type: ValueType::Text,
+        encoding: ValueEncoding::Utf8,
+        ttl_seconds: None,
     };
-    let second_response = storage.upsert(second_request);
+    let (second_response, second_outcome) = storage.upsert(second_request).unwrap();
 
+    assert_eq!(second_outcome, UpsertOutcome::Updated);
     assert_eq!(second_response.value, "second-value");
     assert_eq!(
         second_response.metadata.created_at, original_created_at,
@@ -53,9 +72,12 @@ pub fn test_upsert_existing_key_preserves_created_at<S: Storage>(storage: &S) {
 pub fn test_get_existing_key<S: Storage>(storage: &S) {
     let request = CreateKVRequest {
         key: Key::new("test-key".to_string()).unwrap(),
-        value: "test-value".to_string(),
+        value: "test-value".into(),
+        value_type: ValueType::Text,
+        encoding: ValueEncoding::Utf8,
+        ttl_seconds: None,
     };
-    storage.upsert(request);
+    storage.upsert(request).unwrap();
 
     let key = Key::new("test-key".to_string()).unwrap();
     let result = storage.get(key);
@@ -68,9 +90,12 @@ pub fn test_get_existing_key<S: Storage>(storage: &S) {
 pub fn test_delete_existing_key<S: Storage>(storage: &S) {
     let request = CreateKVRequest {
         key: Key::new("test-key".to_string()).unwrap(),
-        value: "test-value".to_string(),
+        value: "test-value".into(),
+        value_type: ValueType::Text,
+        encoding: ValueEncoding::Utf8,
+        ttl_seconds: None,
     };
-    storage.upsert(request);
+    storage.upsert(request).unwrap();
 
     let key = Key::new("test-key".to_string()).unwrap();
     let deleted = storage.delete(key.clone());
@@ -92,32 +117,839 @@ pub fn test_delete_nonexistent_key<S: Storage>(storage: &S) {
     );
 }
 
+pub fn test_delete_silent<S: Storage>(storage: &S) {
+    let key = Key::new("test-key".to_string()).unwrap();
+    storage
+        .upsert(CreateKVRequest {
+            key: key.clone(),
+            value: "test-value".into(),
+            value_type: ValueType::Text,
+            encoding: ValueEncoding::Utf8,
+            ttl_seconds: None,
+        })
+        .unwrap();
+
+    assert_eq!(storage.delete_silent(key.clone()), Ok(()));
+    assert!(!storage.exists(&key));
+    assert_eq!(
+        storage.delete_silent(key.clone()),
+        Err(StorageError::KeyNotFound(key))
+    );
+}
+
+pub fn test_delete_if_value_matches<S: Storage>(storage: &S) {
+    let key = Key::new("test-key".to_string()).unwrap();
+    storage
+        .upsert(CreateKVRequest {
+            key: key.clone(),
+            value: "v1".into(),
+            value_type: ValueType::Text,
+            encoding: ValueEncoding::Utf8,
+            ttl_seconds: None,
+        })
+        .unwrap();
+
+    assert_eq!(
+        storage.delete_if(key.clone(), "v0").unwrap_err(),
+        StorageError::VersionMismatch {
+            key: key.clone(),
+            current: Some(content_etag("v1")),
+        }
+    );
+    assert!(storage.exists(&key));
+
+    let deleted = storage.delete_if(key.clone(), "v1").unwrap();
+    assert_eq!(deleted.value, "v1");
+    assert!(!storage.exists(&key));
+    assert_eq!(
+        storage.delete_if(key.clone(), "v1").unwrap_err(),
+        StorageError::KeyNotFound(key)
+    );
+}
+
+pub fn test_unmodified_since_guards_writes<S: Storage>(storage: &S) {
+    let key = Key::new("test-key".to_string()).unwrap();
+    let request = |value: &str| CreateKVRequest {
+        key: key.clone(),
+        value: value.into(),
+        value_type: ValueType::Text,
+        encoding: ValueEncoding::Utf8,
+        ttl_seconds: None,
+    };
+    let (written, outcome) = storage
+        .upsert_if_unmodified(request("v1"), chrono::Utc::now())
+        .unwrap();
+    assert_eq!(outcome, UpsertOutcome::Created);
+    let updated_at = written.metadata.updated_at;
+    let stale = updated_at - chrono::TimeDelta::seconds(1);
+
+    let modified = StorageError::ModifiedSince {
+        key: key.clone(),
+        updated_at,
+    };
+    assert_eq!(
+        storage
+            .upsert_if_unmodified(request("v2"), stale)
+            .unwrap_err(),
+        modified
+    );
+    assert_eq!(
+        storage
+            .delete_if_unmodified(key.clone(), stale)
+            .unwrap_err(),
+        modified
+    );
+    assert_eq!(storage.get(key.clone()).unwrap().value, "v1");
+
+    // A date in the same second as the write counts as unmodified.
+    let same_second = chrono::DateTime::from_timestamp(updated_at.timestamp(), 0).unwrap();
+    let (written, outcome) = storage
+        .upsert_if_unmodified(request("v2"), same_second)
+        .unwrap();
+    assert_eq!(outcome, UpsertOutcome::Updated);
+    assert_eq!(written.metadata.version, 2);
+
+    let deleted = storage
+        .delete_if_unmodified(key.clone(), written.metadata.updated_at)
+        .unwrap();
+    assert_eq!(deleted.value, "v2");
+    assert_eq!(
+        storage
+            .delete_if_unmodified(key.clone(), chrono::Utc::now())
+            .unwrap_err(),
+        StorageError::KeyNotFound(key)
+    );
+}
+
+pub fn test_structured_values_round_trip<S: Storage>(storage: &S) {
+    let values = [
+        serde_json::json!({"theme": "dark", "limits": {"retries": 3}}),
+        serde_json::json!([1, "two", [3.5]]),
+        serde_json::json!(42),
+        serde_json::json!(true),
+        serde_json::json!("plain"),
+    ];
+    for (i, value) in values.iter().enumerate() {
+        let key = Key::new(format!("cfg-{}", i)).unwrap();
+        storage
+            .upsert(CreateKVRequest {
+                key: key.clone(),
+                value: value.clone().into(),
+                value_type: ValueType::Text,
+                encoding: ValueEncoding::Utf8,
+                ttl_seconds: None,
+            })
+            .unwrap();
+
+        let stored = storage.get(key).unwrap();
+        assert_eq!(&stored.value, value);
+        assert_eq!(stored.metadata.size_bytes, value_text(value).len());
+    }
+}
+
 pub fn test_list_keys_empty<S: Storage>(storage: &S) {
     let keys = storage.list_keys();
     assert_eq!(keys.len(), 0, "Empty storage should have 0 keys");
 }
 
 pub fn test_list_keys_multiple<S: Storage>(storage: &S) {
-    storage.upsert(CreateKVRequest {
-        key: Key::new("key1".to_string()).unwrap(),
-        value: "value1".to_string(),
-    });
-    storage.upsert(CreateKVRequest {
-        key: Key::new("key2".to_string()).unwrap(),
-        value: "value2".to_string(),
-    });
-    storage.upsert(CreateKVRequest {
-        key: Key::new("key3".to_string()).unwrap(),
-        value: "value3".to_string(),
-    });
+    storage
+        .upsert(CreateKVRequest {
+            key: Key::new("key2".to_string()).unwrap(),
+            value: "value2".into(),
+            value_type: ValueType::Text,
+            encoding: ValueEncoding::Utf8,
+            ttl_seconds: None,
+        })
+        .unwrap();
+    storage
+        .upsert(CreateKVRequest {
+            key: Key::new("key3".to_string()).unwrap(),
+            value: "value3".into(),
+            value_type: ValueType::Text,
+            encoding: ValueEncoding::Utf8,
+            ttl_seconds: None,
+        })
+        .unwrap();
+    storage
+        .upsert(CreateKVRequest {
+            key: Key::new("key1".to_string()).unwrap(),
+            value: "value1".into(),
+            value_type: ValueType::Text,
+            encoding: ValueEncoding::Utf8,
+            ttl_seconds: None,
+        })
+        .unwrap();
 
     let keys = storage.list_keys();
     assert_eq!(keys.len(), 3, "Should have 3 keys");
 
     let key_strs: Vec<String> = keys.iter().map(|k| k.as_str().to_string()).collect();
-    assert!(key_strs.contains(&"key1".to_string()));
-    assert!(key_strs.contains(&"key2".to_string()));
-    assert!(key_strs.contains(&"key3".to_string()));
+    assert_eq!(
+        key_strs,
+        vec!["key1", "key2", "key3"],
+        "Keys should be sorted"
+    );
+}
+
+pub fn test_listing_is_sorted<S: Storage>(storage: &S) {
+    put_keys(storage, &["b-2", "a", "b-10", "B", "a-1", "_x", "0"]);
+    let expected = vec!["0", "B", "_x", "a", "a-1", "b-10", "b-2"];
+
+    assert_eq!(key_names(storage.list_keys()), expected);
+    assert_eq!(
+        key_names(storage.scan_keys(&KeyFilter {
+            prefix: Some("b-".to_string()),
+            ..Default::default()
+        })),
+        vec!["b-10", "b-2"]
+    );
+
+    let entries: Vec<String> = storage
+        .list_entries(&KeyFilter::default(), 3)
+        .into_iter()
+        .map(|entry| entry.key.into_string())
+        .collect();
+    assert_eq!(entries, vec!["0", "B", "_x"]);
+}
+
+pub fn test_list_entries_respects_limit<S: Storage>(storage: &S) {
+    for i in 0..5 {
+        storage
+            .upsert(CreateKVRequest {
+                key: Key::new(format!("key{}", i)).unwrap(),
+                value: format!("value{}", i).into(),
+                value_type: ValueType::Text,
+                encoding: ValueEncoding::Utf8,
+                ttl_seconds: None,
+            })
+            .unwrap();
+    }
+
+    let entries = storage.list_entries(&KeyFilter::default(), 3);
+    assert_eq!(entries.len(), 3, "Should stop at the requested limit");
+
+    for entry in entries {
+        let stored = storage.get(entry.key.clone()).unwrap();
+        assert_eq!(entry.value, stored.value);
+        assert_eq!(entry.metadata.created_at, stored.metadata.created_at);
+    }
+}
+
+pub fn test_list_entries_spans_index_batches<S: Storage>(storage: &S) {
+    let names: Vec<String> = (0..2500).map(|i| format!("item-{:04}", i)).collect();
+    let names: Vec<&str> = names.iter().map(String::as_str).collect();
+    put_keys(storage, &names);
+    put_keys(storage, &["a-first", "z-last"]);
+    let filter = KeyFilter {
+        prefix: Some("item-".to_string()),
+        ..Default::default()
+    };
+
+    let entries = storage.list_entries(&filter, 2100);
+    assert_eq!(entries.len(), 2100);
+    assert_eq!(entries[0].key.as_str(), "item-0000");
+    assert_eq!(entries[2099].key.as_str(), "item-2099");
+
+    let entries = storage.list_entries(&filter, 5000);
+    assert_eq!(entries.len(), 2500);
+    assert_eq!(entries[2499].key.as_str(), "item-2499");
+}
+
+pub fn test_get_many_mixed<S: Storage>(storage: &S) {
+    storage
+        .upsert(CreateKVRequest {
+            key: Key::new("present".to_string()).unwrap(),
+            value: "here".into(),
+            value_type: ValueType::Text,
+            encoding: ValueEncoding::Utf8,
+            ttl_seconds: None,
+        })
+        .unwrap();
+
+    let keys = vec![
+        Key::new("present".to_string()).unwrap(),
+        Key::new("absent".to_string()).unwrap(),
+    ];
+    let results = storage.get_many(&keys);
+
+    assert_eq!(results.len(), 2);
+    assert_eq!(results[0].as_ref().unwrap().value, "here");
+    assert!(matches!(results[1], Err(StorageError::KeyNotFound(_))));
+}
+
+pub fn test_len_tracks_writes<S: Storage>(storage: &S) {
+    assert_eq!(storage.len(), 0);
+    assert!(storage.is_empty());
+
+    for name in ["one", "two"] {
+        storage
+            .upsert(CreateKVRequest {
+                key: Key::new(name.to_string()).unwrap(),
+                value: "v".into(),
+                value_type: ValueType::Text,
+                encoding: ValueEncoding::Utf8,
+                ttl_seconds: None,
+            })
+            .unwrap();
+    }
+    assert_eq!(storage.len(), 2);
+
+    storage
+        .delete(Key::new("one".to_string()).unwrap())
+        .unwrap();
+    assert_eq!(storage.len(), 1);
+    assert!(!storage.is_empty());
+}
+
+pub fn test_count_prefix<S: Storage>(storage: &S) {
+    assert_eq!(storage.count_prefix(""), 0);
+    assert_eq!(storage.count_prefix("tenant-"), 0);
+
+    for name in ["tenant-1-a", "tenant-1-b", "tenant-2-a", "tenant-10-a"] {
+        storage
+            .upsert(CreateKVRequest {
+                key: Key::new(name.to_string()).unwrap(),
+                value: "v".into(),
+                value_type: ValueType::Text,
+                encoding: ValueEncoding::Utf8,
+                ttl_seconds: None,
+            })
+            .unwrap();
+    }
+
+    assert_eq!(storage.count_prefix("tenant-1-"), 2);
+    assert_eq!(storage.count_prefix("tenant-1"), 3);
+    assert_eq!(storage.count_prefix("tenant-"), 4);
+    assert_eq!(storage.count_prefix(""), 4);
+    assert_eq!(storage.count_prefix("other"), 0);
+    assert_eq!(storage.count_prefix("zzz"), 0);
+    assert_eq!(storage.count_prefix("tenant-1-a-suffix"), 0);
+}
+
+pub fn test_exists<S: Storage>(storage: &S) {
+    let key = Key::new("maybe".to_string()).unwrap();
+    assert!(!storage.exists(&key));
+
+    storage
+        .upsert(CreateKVRequest {
+            key: key.clone(),
+            value: "v".into(),
+            value_type: ValueType::Text,
+            encoding: ValueEncoding::Utf8,
+            ttl_seconds: None,
+        })
+        .unwrap();
+    assert!(storage.exists(&key));
+
+    storage.delete(key.clone()).unwrap();
+    assert!(!storage.exists(&key));
+}
+
+pub fn test_random_entry_empty<S: Storage>(storage: &S) {
+    assert!(storage.random_entry(None).is_none());
+}
+
+pub fn test_random_entry_distribution<S: Storage>(storage: &S) {
+    let names = ["r0", "r1", "r2", "r3"];
+    for name in names {
+        storage
+            .upsert(CreateKVRequest {
+                key: Key::new(name.to_string()).unwrap(),
+                value: name.into(),
+                value_type: ValueType::Text,
+                encoding: ValueEncoding::Utf8,
+                ttl_seconds: None,
+            })
+            .unwrap();
+    }
+
+    let samples = 4000;
+    let mut counts = std::collections::HashMap::new();
+    for _ in 0..samples {
+        let entry = storage.random_entry(None).unwrap();
+        assert_eq!(entry.value, entry.key.as_str());
+        *counts.entry(entry.key).or_insert(0) += 1;
+    }
+
+    let expected = samples / names.len();
+    for name in names {
+        let count = counts
+            .get(&Key::new(name.to_string()).unwrap())
+            .copied()
+            .unwrap_or(0);
+        assert!(
+            count > expected * 7 / 10 && count < expected * 13 / 10,
+            "key {} sampled {} times, expected about {}",
+            name,
+            count,
+            expected
+        );
+    }
+}
+
+pub fn test_random_entry_prefix<S: Storage>(storage: &S) {
+    for name in ["user-1", "user-2", "order-1"] {
+        storage
+            .upsert(CreateKVRequest {
+                key: Key::new(name.to_string()).unwrap(),
+                value: "v".into(),
+                value_type: ValueType::Text,
+                encoding: ValueEncoding::Utf8,
+                ttl_seconds: None,
+            })
+            .unwrap();
+    }
+
+    for _ in 0..50 {
+        let entry = storage.random_entry(Some("user-")).unwrap();
+        assert!(entry.key.as_str().starts_with("user-"));
+    }
+    assert!(storage.random_entry(Some("missing-")).is_none());
+}
+
+pub fn test_scan_keys_by_update_time<S: Storage>(storage: &S) {
+    let put = |name: &str| {
+        storage
+            .upsert(CreateKVRequest {
+                key: Key::new(name.to_string()).unwrap(),
+                value: "v".into(),
+                value_type: ValueType::Text,
+                encoding: ValueEncoding::Utf8,
+                ttl_seconds: None,
+            })
+            .unwrap()
+    };
+
+    put("old-a");
+    put("old-b");
+    std::thread::sleep(std::time::Duration::from_millis(10));
+    let checkpoint = chrono::Utc::now();
+    std::thread::sleep(std::time::Duration::from_millis(10));
+    put("new-a");
+    put("old-a");
+
+    let mut changed: Vec<String> = storage
+        .scan_keys(&KeyFilter {
+            updated_since: Some(checkpoint),
+            ..Default::default()
+        })
+        .into_iter()
+        .map(|key| key.into_string())
+        .collect();
+    changed.sort();
+    assert_eq!(changed, vec!["new-a", "old-a"]);
+
+    let created: Vec<String> = storage
+        .scan_keys(&KeyFilter {
+            created_since: Some(checkpoint),
+            ..Default::default()
+        })
+        .into_iter()
+        .map(|key| key.into_string())
+        .collect();
+    assert_eq!(created, vec!["new-a"]);
+
+    let prefixed = storage.scan_keys(&KeyFilter {
+        prefix: Some("old-".to_string()),
+        updated_since: Some(checkpoint),
+        ..Default::default()
+    });
+    assert_eq!(prefixed, vec![Key::new("old-a".to_string()).unwrap()]);
+}
+
+fn put_keys<S: Storage>(storage: &S, names: &[&str]) {
+    for name in names {
+        storage
+            .upsert(CreateKVRequest {
+                key: Key::new(name.to_string()).unwrap(),
+                value: "v".into(),
+                value_type: ValueType::Text,
+                encoding: ValueEncoding::Utf8,
+                ttl_seconds: None,
+            })
+            .unwrap();
+    }
+}
+
+fn key_names(keys: Vec<Key>) -> Vec<String> {
+    keys.into_iter().map(|key| key.into_string()).collect()
+}
+
+pub fn test_list_range_boundaries<S: Storage>(storage: &S) {
+    use std::ops::Bound::{Excluded, Included, Unbounded};
+
+    put_keys(
+        storage,
+        &[
+            "order-000099",
+            "order-000150",
+            "order-000100",
+            "order-000200",
+            "order-000101",
+            "user-1",
+        ],
+    );
+
+    assert_eq!(
+        key_names(storage.list_range(Included("order-000100"), Excluded("order-000200"), 100)),
+        vec!["order-000100", "order-000101", "order-000150"]
+    );
+    assert_eq!(
+        key_names(storage.list_range(Excluded("order-000100"), Included("order-000200"), 100)),
+        vec!["order-000101", "order-000150", "order-000200"]
+    );
+    assert_eq!(
+        key_names(storage.list_range(Included("order-000200"), Unbounded, 100)),
+        vec!["order-000200", "user-1"]
+    );
+    assert!(
+        storage
+            .list_range(Included("order-000150"), Excluded("order-000150"), 100)
+            .is_empty()
+    );
+    assert!(
+        storage
+            .list_range(Included("z"), Excluded("a"), 100)
+            .is_empty()
+    );
+}
+
+pub fn test_list_range_pages<S: Storage>(storage: &S) {
+    use std::ops::Bound::{Excluded, Included};
+
+    put_keys(storage, &["k-1", "k-2", "k-3", "k-4", "k-5", "other"]);
+
+    let mut pages = Vec::new();
+    let mut lower = Included("k-".to_string());
+    loop {
+        let page = storage.list_range(lower.as_ref().map(String::as_str), Excluded("k-~"), 2);
+        let Some(last) = page.last() else {
+            break;
+        };
+        lower = Excluded(last.as_str().to_string());
+        pages.push(key_names(page));
+    }
+
+    assert_eq!(
+        pages,
+        vec![vec!["k-1", "k-2"], vec!["k-3", "k-4"], vec!["k-5"]]
+    );
+}
+
+pub fn test_list_keys_after_cursor<S: Storage>(storage: &S) {
+    put_keys(storage, &["c", "a", "e", "b", "d"]);
+
+    assert_eq!(key_names(storage.list_keys_after(None, 2)), vec!["a", "b"]);
+    assert_eq!(
+        key_names(storage.list_keys_after(Some("b"), 2)),
+        vec!["c", "d"]
+    );
+    assert_eq!(
+        key_names(storage.list_keys_after(Some("bb"), 10)),
+        vec!["c", "d", "e"]
+    );
+    assert!(storage.list_keys_after(Some("e"), 10).is_empty());
+}
+
+pub fn test_list_range_after_deletes<S: Storage>(storage: &S) {
+    use std::ops::Bound::Unbounded;
+
+    put_keys(storage, &["a", "b", "c", "d"]);
+    storage.delete(Key::new("b".to_string()).unwrap()).unwrap();
+    storage.delete(Key::new("d".to_string()).unwrap()).unwrap();
+    put_keys(storage, &["b2"]);
+
+    assert_eq!(
+        key_names(storage.list_range(Unbounded, Unbounded, 100)),
+        vec!["a", "b2", "c"]
+    );
+    assert_eq!(
+        key_names(storage.list_range(Unbounded, Unbounded, 2)),
+        vec!["a", "b2"]
+    );
+}
+
+pub fn test_search_values_pages<S: Storage>(storage: &S) {
+    for (name, value) in [
+        ("svc-a", "db.leaked.example"),
+        ("svc-b", "clean"),
+        ("svc-c", "leaked again"),
+        ("svc-d", "also leaked"),
+    ] {
+        storage
+            .upsert(CreateKVRequest {
+                key: Key::new(name.to_string()).unwrap(),
+                value: value.into(),
+                value_type: ValueType::Text,
+                encoding: ValueEncoding::Utf8,
+                ttl_seconds: None,
+            })
+            .unwrap();
+    }
+    let search = ValueSearch::new("leaked", true);
+    let names = |page: &crate::service::SearchPage| -> Vec<String> {
+        page.matches
+            .iter()
+            .map(|(key, _)| key.as_str().to_string())
+            .collect()
+    };
+
+    let first = storage.search_values(&search, None, 2);
+    assert_eq!(names(&first), vec!["svc-a", "svc-c"]);
+    let cursor = first.next_cursor.expect("first page should be truncated");
+
+    let second = storage.search_values(&search, Some(cursor.as_str()), 2);
+    assert_eq!(names(&second), vec!["svc-d"]);
+    assert!(second.next_cursor.is_none());
+
+    let exact = storage.search_values(&search, None, 3);
+    assert_eq!(exact.matches.len(), 3);
+    assert!(exact.next_cursor.is_none());
+}
+
+pub fn test_search_values_case_insensitive<S: Storage>(storage: &S) {
+    storage
+        .upsert(CreateKVRequest {
+            key: Key::new("host".to_string()).unwrap(),
+            value: "Prod-DB.internal".into(),
+            value_type: ValueType::Text,
+            encoding: ValueEncoding::Utf8,
+            ttl_seconds: None,
+        })
+        .unwrap();
+
+    assert!(
+        storage
+            .search_values(&ValueSearch::new("prod-db", true), None, 10)
+            .matches
+            .is_empty()
+    );
+    assert_eq!(
+        storage
+            .search_values(&ValueSearch::new("prod-db", false), None, 10)
+            .matches
+            .len(),
+        1
+    );
+}
+
+pub fn test_find_by_value<S: Storage>(storage: &S) {
+    for (name, value) in [
+        ("dup-3", "shared"),
+        ("dup-1", "shared"),
+        ("solo", "unique"),
+        ("dup-2", "shared"),
+        ("near", "shared "),
+    ] {
+        storage
+            .upsert(CreateKVRequest {
+                key: Key::new(name.to_string()).unwrap(),
+                value: value.into(),
+                value_type: ValueType::Text,
+                encoding: ValueEncoding::Utf8,
+                ttl_seconds: None,
+            })
+            .unwrap();
+    }
+    let search = ValueSearch::exact("shared");
+    let names = |page: crate::service::SearchPage| -> Vec<String> {
+        page.matches
+            .into_iter()
+            .map(|(key, _)| key.into_string())
+            .collect()
+    };
+
+    assert_eq!(
+        names(storage.search_values(&search, None, 10)),
+        vec!["dup-1", "dup-2", "dup-3"]
+    );
+
+    let first = storage.search_values(&search, None, 2);
+    assert_eq!(first.next_cursor.as_ref().unwrap().as_str(), "dup-2");
+    assert_eq!(
+        names(storage.search_values(&search, Some("dup-2"), 2)),
+        vec!["dup-3"]
+    );
+
+    storage
+        .upsert(CreateKVRequest {
+            key: Key::new("dup-1".to_string()).unwrap(),
+            value: "moved".into(),
+            value_type: ValueType::Text,
+            encoding: ValueEncoding::Utf8,
+            ttl_seconds: None,
+        })
+        .unwrap();
+    storage
+        .delete(Key::new("dup-3".to_string()).unwrap())
+        .unwrap();
+    assert_eq!(
+        names(storage.search_values(&search, None, 10)),
+        vec!["dup-2"]
+    );
+    assert!(
+        storage
+            .search_values(&ValueSearch::exact("missing"), None, 10)
+            .matches
+            .is_empty()
+    );
+}
+
+pub fn test_get_metadata<S: Storage>(storage: &S) {
+    let key = Key::new("config".to_string()).unwrap();
+    assert!(storage.get_metadata(&key).is_err());
+
+    let (created, _) = storage
+        .upsert(CreateKVRequest {
+            key: key.clone(),
+            value: "v".into(),
+            value_type: ValueType::Text,
+            encoding: ValueEncoding::Utf8,
+            ttl_seconds: None,
+        })
+        .unwrap();
+    let metadata = storage.get_metadata(&key).unwrap();
+    assert_eq!(metadata.created_at, created.metadata.created_at);
+    assert_eq!(metadata.updated_at, created.metadata.updated_at);
+    assert_eq!(metadata.expires_at, None);
+
+    storage
+        .set_ttl(&key, std::time::Duration::from_secs(60))
+        .unwrap();
+    assert!(storage.get_metadata(&key).unwrap().expires_at.is_some());
+}
+
+pub fn test_size_bytes_tracks_value<S: Storage>(storage: &S) {
+    let key = Key::new("sized".to_string()).unwrap();
+    let (created, _) = storage
+        .upsert(CreateKVRequest {
+            key: key.clone(),
+            value: "abc".into(),
+            value_type: ValueType::Text,
+            encoding: ValueEncoding::Utf8,
+            ttl_seconds: None,
+        })
+        .unwrap();
+    assert_eq!(created.metadata.size_bytes, 3);
+
+    let (updated, _) = storage
+        .upsert(CreateKVRequest {
+            key: key.clone(),
+            value: "héllo wörld".into(),
+            value_type: ValueType::Text,
+            encoding: ValueEncoding::Utf8,
+            ttl_seconds: None,
+        })
+        .unwrap();
+    assert_eq!(updated.metadata.size_bytes, 13);
+    assert_eq!(storage.get_metadata(&key).unwrap().size_bytes, 13);
+    assert_eq!(storage.get(key).unwrap().metadata.size_bytes, 13);
+}
+
+pub fn test_set_and_get_ttl<S: Storage>(storage: &S) {
+    let key = Key::new("session".to_string()).unwrap();
+    storage
+        .upsert(CreateKVRequest {
+            key: key.clone(),
+            value: "v".into(),
+            value_type: ValueType::Text,
+            encoding: ValueEncoding::Utf8,
+            ttl_seconds: None,
+        })
+        .unwrap();
+    assert_eq!(storage.get_ttl(&key).unwrap(), None);
+
+    let metadata = storage
+        .set_ttl(&key, std::time::Duration::from_secs(60))
+        .unwrap();
+    assert!(metadata.expires_at.is_some());
+
+    let remaining = storage.get_ttl(&key).unwrap().unwrap();
+    assert!(remaining <= std::time::Duration::from_secs(60));
+    assert!(remaining > std::time::Duration::from_secs(55));
+    assert_eq!(storage.get(key).unwrap().value, "v");
+}
+
+pub fn test_ttl_missing_key<S: Storage>(storage: &S) {
+    let key = Key::new("ghost".to_string()).unwrap();
+    assert!(storage.get_ttl(&key).is_err());
+    assert!(
+        storage
+            .set_ttl(&key, std::time::Duration::from_secs(60))
+            .is_err()
+    );
+}
+
+pub fn test_expired_key_is_absent<S: Storage>(storage: &S) {
+    let key = Key::new("short-lived".to_string()).unwrap();
+    storage
+        .upsert(CreateKVRequest {
+            key: key.clone(),
+            value: "v".into(),
+            value_type: ValueType::Text,
+            encoding: ValueEncoding::Utf8,
+            ttl_seconds: None,
+        })
+        .unwrap();
+    storage.set_ttl(&key, std::time::Duration::ZERO).unwrap();
+
+    assert!(storage.get(key.clone()).is_err());
+    assert!(!storage.exists(&key));
+    assert!(storage.list_keys().is_empty());
+    assert!(storage.get_ttl(&key).is_err());
+    assert!(storage.delete(key.clone()).is_err());
+
+    let recreated = storage
+        .insert(CreateKVRequest {
+            key: key.clone(),
+            value: "again".into(),
+            value_type: ValueType::Text,
+            encoding: ValueEncoding::Utf8,
+            ttl_seconds: None,
+        })
+        .unwrap();
+    assert_eq!(recreated.metadata.expires_at, None);
+
+    storage.set_ttl(&key, std::time::Duration::ZERO).unwrap();
+    let (_, outcome) = storage
+        .upsert(CreateKVRequest {
+            key: key.clone(),
+            value: "revived".into(),
+            value_type: ValueType::Text,
+            encoding: ValueEncoding::Utf8,
+            ttl_seconds: None,
+        })
+        .unwrap();
+    assert_eq!(outcome, UpsertOutcome::Created);
+}
+
+pub fn test_write_clears_ttl<S: Storage>(storage: &S) {
+    let key = Key::new("persisted".to_string()).unwrap();
+    storage
+        .upsert(CreateKVRequest {
+            key: key.clone(),
+            value: "v1".into(),
+            value_type: ValueType::Text,
+            encoding: ValueEncoding::Utf8,
+            ttl_seconds: None,
+        })
+        .unwrap();
+    storage
+        .set_ttl(&key, std::time::Duration::from_secs(60))
+        .unwrap();
+
+    let (updated, _) = storage
+        .upsert(CreateKVRequest {
+            key: key.clone(),
+            value: "v2".into(),
+            value_type: ValueType::Text,
+            encoding: ValueEncoding::Utf8,
+            ttl_seconds: None,
+        })
+        .unwrap();
+    assert_eq!(updated.metadata.expires_at, None);
+    assert_eq!(storage.get_ttl(&key).unwrap(), None);
 }
 
 pub fn test_concurrent_upserts<S: Storage + Sync + Send + 'static>(storage: S) {
@@ -132,9 +964,12 @@ pub fn test_concurrent_upserts<S: Storage + Sync + Send + 'static>(storage: S) {
         let handle = thread::spawn(move || {
             let request = CreateKVRequest {
                 key: Key::new(format!("key-{}", i)).unwrap(),
-                value: format!("value-{}", i),
+                value: format!("value-{}", i).into(),
+                value_type: ValueType::Text,
+                encoding: ValueEncoding::Utf8,
+                ttl_seconds: None,
             };
-            storage_clone.upsert(request);
+            storage_clone.upsert(request).unwrap();
         });
         handles.push(handle);
     }
@@ -146,3 +981,1934 @@ pub fn test_concurrent_upserts<S: Storage + Sync + Send + 'static>(storage: S) {
     let keys = storage_arc.list_keys();
     assert_eq!(keys.len(), 10, "All concurrent upserts should succeed");
 }
+
+pub fn test_upsert_if_version<S: Storage>(storage: &S) {
+    let key = Key::new("cas".to_string()).unwrap();
+    let write = |value: &str, expected: &VersionPrecondition| {
+        storage.upsert_if_version(
+            CreateKVRequest {
+                key: key.clone(),
+                value: value.into(),
+                value_type: ValueType::Text,
+                encoding: ValueEncoding::Utf8,
+                ttl_seconds: None,
+            },
+            expected,
+        )
+    };
+
+    assert_eq!(
+        write("v1", &VersionPrecondition::Exists).unwrap_err(),
+        StorageError::VersionMismatch {
+            key: key.clone(),
+            current: None
+        }
+    );
+    assert!(!storage.exists(&key));
+
+    storage
+        .upsert(CreateKVRequest {
+            key: key.clone(),
+            value: "v1".into(),
+            value_type: ValueType::Text,
+            encoding: ValueEncoding::Utf8,
+            ttl_seconds: None,
+        })
+        .unwrap();
+
+    let stale = VersionPrecondition::OneOf(vec![content_etag("v0")]);
+    assert_eq!(
+        write("v2", &stale).unwrap_err(),
+        StorageError::VersionMismatch {
+            key: key.clone(),
+            current: Some(content_etag("v1"))
+        }
+    );
+    assert_eq!(storage.get(key.clone()).unwrap().value, "v1");
+
+    let current = VersionPrecondition::OneOf(vec![content_etag("v0"), content_etag("v1")]);
+    let written = write("v2", &current).unwrap();
+    assert_eq!(written.value, "v2");
+    assert_eq!(storage.get(key.clone()).unwrap().value, "v2");
+
+    assert!(write("v3", &VersionPrecondition::Exists).is_ok());
+    assert_eq!(storage.get(key).unwrap().value, "v3");
+}
+
+pub fn test_upsert_if_version_race<S: Storage + Sync>(storage: &S) {
+    use std::sync::Barrier;
+    use std::thread;
+
+    let key = Key::new("race".to_string()).unwrap();
+    for round in 0..50 {
+        let base = format!("base-{}", round);
+        storage
+            .upsert(CreateKVRequest {
+                key: key.clone(),
+                value: base.clone().into(),
+                value_type: ValueType::Text,
+                encoding: ValueEncoding::Utf8,
+                ttl_seconds: None,
+            })
+            .unwrap();
+        let expected = VersionPrecondition::OneOf(vec![content_etag(&base)]);
+        let barrier = Barrier::new(2);
+
+        let results: Vec<bool> = thread::scope(|scope| {
+            let handles: Vec<_> = ["left", "right"]
+                .into_iter()
+                .map(|writer| {
+                    let (key, expected, barrier) = (&key, &expected, &barrier);
+                    scope.spawn(move || {
+                        barrier.wait();
+                        storage
+                            .upsert_if_version(
+                                CreateKVRequest {
+                                    key: key.clone(),
+                                    value: format!("{}-{}", writer, round).into(),
+                                    value_type: ValueType::Text,
+                                    encoding: ValueEncoding::Utf8,
+                                    ttl_seconds: None,
+                                },
+                                expected,
+                            )
+                            .is_ok()
+                    })
+                })
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+
+        assert_eq!(
+            results.iter().filter(|won| **won).count(),
+            1,
+            "exactly one conditional write must win round {}",
+            round
+        );
+        let winner = if results[0] { "left" } else { "right" };
+        assert_eq!(
+            storage.get(key.clone()).unwrap().value,
+            format!("{}-{}", winner, round)
+        );
+    }
+}
+
+pub fn test_value_type_is_kept_across_writes<S: Storage>(storage: &S) {
+    let key = Key::new("typed".to_string()).unwrap();
+    let write = |value: serde_json::Value, value_type: ValueType| {
+        storage.upsert(CreateKVRequest {
+            key: key.clone(),
+            value: StoredValue::Json(value),
+            value_type,
+            encoding: ValueEncoding::Utf8,
+            ttl_seconds: None,
+        })
+    };
+
+    let (created, _) = storage
+        .upsert(CreateKVRequest {
+            key: key.clone(),
+            value: "{\"theme\":\"dark\"}".into(),
+            value_type: ValueType::Json,
+            encoding: ValueEncoding::Utf8,
+            ttl_seconds: None,
+        })
+        .unwrap();
+    assert_eq!(created.metadata.value_type, ValueType::Json);
+    assert_eq!(
+        storage.get_metadata(&key).unwrap().value_type,
+        ValueType::Json
+    );
+
+    let json_mismatch = StorageError::TypeMismatch {
+        key: key.clone(),
+        expected: ValueType::Json,
+    };
+    assert_eq!(
+        write("plain".into(), ValueType::Text).unwrap_err(),
+        json_mismatch
+    );
+    assert_eq!(
+        write("7".into(), ValueType::Number).unwrap_err(),
+        json_mismatch
+    );
+    let (updated, _) = write("{\"theme\":\"light\"}".into(), ValueType::Text).unwrap();
+    assert_eq!(updated.metadata.value_type, ValueType::Json);
+
+    storage.delete(key.clone()).unwrap();
+    let (created, _) = write(serde_json::json!(3), ValueType::Number).unwrap();
+    assert_eq!(created.metadata.value_type, ValueType::Number);
+    let (updated, _) = write("4.5".into(), ValueType::Text).unwrap();
+    assert_eq!(updated.metadata.value_type, ValueType::Number);
+    assert_eq!(
+        write("abc".into(), ValueType::Text).unwrap_err(),
+        StorageError::TypeMismatch {
+            key: key.clone(),
+            expected: ValueType::Number,
+        }
+    );
+    assert_eq!(storage.get(key).unwrap().value, "4.5");
+}
+
+pub fn test_version_counter<S: Storage>(storage: &S) {
+    let key = Key::new("versioned".to_string()).unwrap();
+    let write = |value: &str| {
+        storage
+            .upsert(CreateKVRequest {
+                key: key.clone(),
+                value: value.into(),
+                value_type: ValueType::Text,
+                encoding: ValueEncoding::Utf8,
+                ttl_seconds: None,
+            })
+            .unwrap()
+            .0
+            .metadata
+            .version
+    };
+
+    assert_eq!(write("a"), 1);
+    assert_eq!(write("b"), 2);
+    assert_eq!(write("b"), 3);
+    assert_eq!(storage.get(key.clone()).unwrap().metadata.version, 3);
+
+    storage.delete(key.clone()).unwrap();
+    let recreated = storage
+        .insert(CreateKVRequest {
+            key: key.clone(),
+            value: "c".into(),
+            value_type: ValueType::Text,
+            encoding: ValueEncoding::Utf8,
+            ttl_seconds: None,
+        })
+        .unwrap();
+    assert_eq!(recreated.metadata.version, 1);
+    assert_eq!(write("d"), 2);
+
+    storage
+        .set_ttl(&key, std::time::Duration::from_millis(5))
+        .unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(10));
+    assert_eq!(write("e"), 1);
+}
+
+pub fn test_concurrent_upserts_produce_unique_versions<S: Storage + Sync>(storage: &S) {
+    use std::collections::BTreeSet;
+    use std::thread;
+
+    const THREADS: u64 = 8;
+    const WRITES_PER_THREAD: u64 = 50;
+    let key = Key::new("hot-key".to_string()).unwrap();
+
+    let versions: Vec<u64> = thread::scope(|scope| {
+        let handles: Vec<_> = (0..THREADS)
+            .map(|thread_id| {
+                let key = &key;
+                scope.spawn(move || {
+                    (0..WRITES_PER_THREAD)
+                        .map(|i| {
+                            storage
+                                .upsert(CreateKVRequest {
+                                    key: key.clone(),
+                                    value: format!("{}-{}", thread_id, i).into(),
+                                    value_type: ValueType::Text,
+                                    encoding: ValueEncoding::Utf8,
+                                    ttl_seconds: None,
+                                })
+                                .unwrap()
+                                .0
+                                .metadata
+                                .version
+                        })
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap())
+            .collect()
+    });
+
+    let total = THREADS * WRITES_PER_THREAD;
+    let unique: BTreeSet<u64> = versions.iter().copied().collect();
+    assert_eq!(unique.len() as u64, total, "versions must never repeat");
+    assert_eq!(unique, (1..=total).collect());
+    assert_eq!(storage.get(key).unwrap().metadata.version, total);
+}
+
+pub fn test_largest_entries<S: Storage>(storage: &S) {
+    assert!(storage.largest_entries(10).is_empty());
+
+    for (name, size) in [
+        ("tiny", 1),
+        ("huge", 500),
+        ("medium", 40),
+        ("large", 120),
+        ("also-medium", 40),
+    ] {
+        storage
+            .upsert(CreateKVRequest {
+                key: Key::new(name.to_string()).unwrap(),
+                value: "x".repeat(size).into(),
+                value_type: ValueType::Text,
+                encoding: ValueEncoding::Utf8,
+                ttl_seconds: None,
+            })
+            .unwrap();
+    }
+
+    let ranked = |limit: usize| -> Vec<(String, usize)> {
+        storage
+            .largest_entries(limit)
+            .into_iter()
+            .map(|(key, metadata)| (key.as_str().to_string(), metadata.size_bytes))
+            .collect()
+    };
+
+    assert_eq!(
+        ranked(3),
+        vec![
+            ("huge".to_string(), 500),
+            ("large".to_string(), 120),
+            ("also-medium".to_string(), 40),
+        ]
+    );
+    assert_eq!(ranked(10).len(), 5);
+    assert_eq!(ranked(10)[4], ("tiny".to_string(), 1));
+    assert!(ranked(0).is_empty());
+
+    storage
+        .delete(Key::new("huge".to_string()).unwrap())
+        .unwrap();
+    assert_eq!(ranked(1), vec![("large".to_string(), 120)]);
+}
+
+pub fn test_listing_sorted_after_shuffled_concurrent_inserts<S: Storage + Sync>(storage: &S) {
+    use rand::seq::SliceRandom;
+    use std::thread;
+
+    const THREADS: usize = 8;
+    let mut names: Vec<String> = (0..400)
+        .map(|i| format!("{}-{}", ["user", "order", "Session", "_tmp"][i % 4], i))
+        .collect();
+    names.shuffle(&mut rand::thread_rng());
+
+    thread::scope(|scope| {
+        for chunk in names.chunks(names.len() / THREADS) {
+            scope.spawn(move || {
+                for name in chunk {
+                    storage
+                        .insert(CreateKVRequest {
+                            key: Key::new(name.clone()).unwrap(),
+                            value: "v".into(),
+                            value_type: ValueType::Text,
+                            encoding: ValueEncoding::Utf8,
+                            ttl_seconds: None,
+                        })
+                        .unwrap();
+                }
+            });
+        }
+    });
+
+    let mut expected = names.clone();
+    expected.sort();
+    assert_eq!(key_names(storage.list_keys()), expected);
+
+    let mut paged = Vec::new();
+    let mut cursor: Option<Key> = None;
+    loop {
+        let page = storage.list_keys_after(cursor.as_ref().map(Key::as_str), 37);
+        let Some(last) = page.last().cloned() else {
+            break;
+        };
+        paged.extend(key_names(page));
+        cursor = Some(last);
+    }
+    assert_eq!(paged, expected);
+}
+
+pub fn test_concurrent_appends_keep_every_fragment<S: Storage + Sync>(storage: &S) {
+    use std::thread;
+
+    const THREADS: usize = 8;
+    const APPENDS: usize = 50;
+    let key = Key::new("log".to_string()).unwrap();
+
+    thread::scope(|scope| {
+        for thread_id in 0..THREADS {
+            let key = key.clone();
+            scope.spawn(move || {
+                for i in 0..APPENDS {
+                    storage
+                        .append(key.clone(), &format!("[{}-{}]", thread_id, i))
+                        .unwrap();
+                }
+            });
+        }
+    });
+
+    let stored = storage.get(key.clone()).unwrap();
+    let text = stored.value.as_json().unwrap().as_str().unwrap();
+    for thread_id in 0..THREADS {
+        for i in 0..APPENDS {
+            assert_eq!(text.matches(&format!("[{}-{}]", thread_id, i)).count(), 1);
+        }
+    }
+    assert_eq!(stored.metadata.size_bytes, text.len());
+    assert_eq!(stored.metadata.version, (THREADS * APPENDS) as u64);
+
+    storage
+        .upsert(CreateKVRequest {
+            key: key.clone(),
+            value: serde_json::json!({"lines": []}).into(),
+            value_type: ValueType::Text,
+            encoding: ValueEncoding::Utf8,
+            ttl_seconds: None,
+        })
+        .unwrap();
+    assert_eq!(
+        storage.append(key.clone(), "more").unwrap_err(),
+        StorageError::NotAppendable(key)
+    );
+}
+
+pub fn test_compare_and_swap<S: Storage>(storage: &S) {
+    let key = Key::new("lock".to_string()).unwrap();
+    let request = |value: &str| CreateKVRequest {
+        key: key.clone(),
+        value: value.into(),
+        value_type: ValueType::Text,
+        encoding: ValueEncoding::Utf8,
+        ttl_seconds: None,
+    };
+
+    let free = serde_json::json!("free");
+    assert!(matches!(
+        storage.compare_and_swap(Some(&free), request("a")).unwrap(),
+        CasOutcome::Mismatch(None)
+    ));
+    assert!(!storage.exists(&key));
+
+    match storage.compare_and_swap(None, request("a")).unwrap() {
+        CasOutcome::Swapped(response) => assert_eq!(response.value, "a"),
+        other => panic!("expected swap, got {:?}", other),
+    }
+    match storage.compare_and_swap(None, request("b")).unwrap() {
+        CasOutcome::Mismatch(Some(current)) => assert_eq!(current.version, 1),
+        other => panic!("expected mismatch, got {:?}", other),
+    }
+
+    let held = serde_json::json!("a");
+    assert!(matches!(
+        storage.compare_and_swap(Some(&free), request("b")).unwrap(),
+        CasOutcome::Mismatch(Some(_))
+    ));
+    match storage.compare_and_swap(Some(&held), request("b")).unwrap() {
+        CasOutcome::Swapped(response) => assert_eq!(response.metadata.version, 2),
+        other => panic!("expected swap, got {:?}", other),
+    }
+    assert_eq!(storage.get(key).unwrap().value, "b");
+}
+
+pub fn test_concurrent_compare_and_swap_has_one_winner<S: Storage + Sync>(storage: &S) {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+
+    const THREADS: usize = 16;
+    let key = Key::new("lock".to_string()).unwrap();
+
+    for expected in [None, Some(serde_json::json!("round-0"))] {
+        let winners = AtomicUsize::new(0);
+        thread::scope(|scope| {
+            for thread_id in 0..THREADS {
+                let key = key.clone();
+                let expected = expected.clone();
+                let winners = &winners;
+                scope.spawn(move || {
+                    let request = CreateKVRequest {
+                        key,
+                        value: format!("worker-{}", thread_id).into(),
+                        value_type: ValueType::Text,
+                        encoding: ValueEncoding::Utf8,
+                        ttl_seconds: None,
+                    };
+                    if let CasOutcome::Swapped(_) = storage
+                        .compare_and_swap(expected.as_ref(), request)
+                        .unwrap()
+                    {
+                        winners.fetch_add(1, Ordering::SeqCst);
+                    }
+                });
+            }
+        });
+        assert_eq!(winners.load(Ordering::SeqCst), 1);
+
+        let stored = storage.get(key.clone()).unwrap();
+        assert!(
+            stored
+                .value
+                .as_json()
+                .unwrap()
+                .as_str()
+                .unwrap()
+                .starts_with("worker-")
+        );
+        storage
+            .upsert(CreateKVRequest {
+                key: key.clone(),
+                value: "round-0".into(),
+                value_type: ValueType::Text,
+                encoding: ValueEncoding::Utf8,
+                ttl_seconds: None,
+            })
+            .unwrap();
+    }
+}
+
+pub fn test_upsert_many<S: Storage>(storage: &S) {
+    let request = |name: &str, value: &str| CreateKVRequest {
+        key: Key::new(name.to_string()).unwrap(),
+        value: value.into(),
+        value_type: ValueType::Text,
+        encoding: ValueEncoding::Utf8,
+        ttl_seconds: None,
+    };
+    storage.upsert(request("a", "old")).unwrap();
+
+    let results = storage.upsert_many(vec![
+        request("a", "new"),
+        request("b", "1"),
+        request("b", "2"),
+    ]);
+    let outcomes: Vec<UpsertOutcome> = results
+        .into_iter()
+        .map(|result| result.unwrap().1)
+        .collect();
+    assert_eq!(
+        outcomes,
+        vec![
+            UpsertOutcome::Updated,
+            UpsertOutcome::Created,
+            UpsertOutcome::Updated
+        ]
+    );
+    assert_eq!(
+        storage
+            .get(Key::new("a".to_string()).unwrap())
+            .unwrap()
+            .value,
+        "new"
+    );
+    assert_eq!(
+        storage
+            .get(Key::new("b".to_string()).unwrap())
+            .unwrap()
+            .value,
+        "2"
+    );
+    assert!(storage.upsert_many(Vec::new()).is_empty());
+}
+
+/// `upsert_many` on one store and the same bodies upserted one by one on
+/// another end the same way, failures included.
+pub fn test_upsert_many_matches_single_upserts<S: Storage>(batched: &S, single: &S) {
+    let request = |name: &str, value: serde_json::Value, value_type: ValueType| CreateKVRequest {
+        key: Key::new(name.to_string()).unwrap(),
+        value: value.into(),
+        value_type,
+        encoding: ValueEncoding::Utf8,
+        ttl_seconds: None,
+    };
+    let bodies = || {
+        vec![
+            request("count", 1.into(), ValueType::Number),
+            request("name", "ada".into(), ValueType::Text),
+            request("count", "many".into(), ValueType::Text),
+            request("name", "grace".into(), ValueType::Text),
+            request("count", 2.into(), ValueType::Number),
+        ]
+    };
+    let summary = |result: Result<(KeyValueResponse, UpsertOutcome), StorageError>| {
+        result.map(|(response, outcome)| {
+            (
+                response.key,
+                response.value,
+                response.metadata.version,
+                outcome,
+            )
+        })
+    };
+
+    let from_batch: Vec<_> = batched
+        .upsert_many(bodies())
+        .into_iter()
+        .map(summary)
+        .collect();
+    let from_singles: Vec<_> = bodies()
+        .into_iter()
+        .map(|body| summary(single.upsert(body)))
+        .collect();
+    assert_eq!(from_batch, from_singles);
+    assert!(matches!(
+        from_batch[2],
+        Err(StorageError::TypeMismatch { .. })
+    ));
+    assert_eq!(from_batch.iter().filter(|result| result.is_ok()).count(), 4);
+
+    for name in ["count", "name"] {
+        let key = Key::new(name.to_string()).unwrap();
+        let (left, right) = (batched.get(key.clone()).unwrap(), single.get(key).unwrap());
+        assert_eq!(left.value, right.value);
+        assert_eq!(left.metadata.version, right.metadata.version);
+    }
+}
+
+pub fn test_batch_apply<S: Storage>(storage: &S) {
+    let key = |name: &str| Key::new(name.to_string()).unwrap();
+    let upsert = |name: &str, value: &str| {
+        WriteOp::Upsert(CreateKVRequest {
+            key: key(name),
+            value: value.into(),
+            value_type: ValueType::Text,
+            encoding: ValueEncoding::Utf8,
+            ttl_seconds: None,
+        })
+    };
+
+    let results = storage.batch_apply(vec![
+        upsert("a", "1"),
+        upsert("b", "2"),
+        WriteOp::Delete(key("a")),
+        WriteOp::Delete(key("missing")),
+        upsert("b", "3"),
+    ]);
+    assert_eq!(results.len(), 5);
+    assert!(matches!(
+        results[0],
+        Ok(WriteOutcome::Upserted(_, UpsertOutcome::Created))
+    ));
+    match &results[2] {
+        Ok(WriteOutcome::Deleted(deleted)) => assert_eq!(deleted.value, "1"),
+        other => panic!("expected delete, got {:?}", other),
+    }
+    assert_eq!(
+        results[3].as_ref().unwrap_err(),
+        &StorageError::KeyNotFound(key("missing"))
+    );
+    assert!(matches!(
+        results[4],
+        Ok(WriteOutcome::Upserted(_, UpsertOutcome::Updated))
+    ));
+
+    assert!(!storage.exists(&key("a")));
+    assert_eq!(storage.get(key("b")).unwrap().value, "3");
+}
+
+pub fn test_batch_plan<S: Storage>(storage: &S) {
+    let key = |name: &str| Key::new(name.to_string()).unwrap();
+    let body = |name: &str, value: &str| CreateKVRequest {
+        key: key(name),
+        value: value.into(),
+        value_type: ValueType::Text,
+        encoding: ValueEncoding::Utf8,
+        ttl_seconds: None,
+    };
+    storage.upsert(body("a", "1")).unwrap();
+    storage.upsert(body("b", "2")).unwrap();
+
+    let ops = vec![
+        WriteOp::Upsert(body("a", "10")),
+        WriteOp::Upsert(body("new", "x")),
+        WriteOp::Insert(body("b", "20")),
+        WriteOp::Delete(key("b")),
+        WriteOp::Insert(body("b", "21")),
+        WriteOp::Delete(key("missing")),
+        WriteOp::Upsert(body("new", "y")),
+    ];
+    let outcome = |result: &Result<WriteOutcome, StorageError>| match result {
+        Ok(WriteOutcome::Upserted(_, UpsertOutcome::Created)) => "created",
+        Ok(WriteOutcome::Upserted(_, UpsertOutcome::Updated)) => "updated",
+        Ok(WriteOutcome::Deleted(_)) => "deleted",
+        Err(StorageError::KeyAlreadyExists(_)) => "exists",
+        Err(StorageError::KeyNotFound(_)) => "not_found",
+        Err(other) => panic!("unexpected error {:?}", other),
+    };
+    let planned: Vec<&str> = storage
+        .batch_plan(ops.clone())
+        .iter()
+        .map(outcome)
+        .collect();
+    assert_eq!(
+        planned,
+        [
+            "updated",
+            "created",
+            "exists",
+            "deleted",
+            "created",
+            "not_found",
+            "updated"
+        ]
+    );
+    match &storage.batch_plan(vec![WriteOp::Upsert(body("a", "11"))])[0] {
+        Ok(WriteOutcome::Upserted(response, _)) => assert_eq!(response.metadata.version, 2),
+        other => panic!("expected upsert, got {:?}", other),
+    }
+
+    assert_eq!(storage.len(), 2);
+    assert!(!storage.exists(&key("new")));
+    let a = storage.get(key("a")).unwrap();
+    assert_eq!(a.value, "1");
+    assert_eq!(a.metadata.version, 1);
+    assert_eq!(storage.get(key("b")).unwrap().value, "2");
+
+    let applied: Vec<&str> = storage.batch_apply(ops).iter().map(outcome).collect();
+    assert_eq!(applied, planned);
+}
+
+pub fn test_delete_many_strict<S: Storage>(storage: &S) {
+    let key = |name: &str| Key::new(name.to_string()).unwrap();
+    for name in ["a", "b"] {
+        storage
+            .upsert(CreateKVRequest {
+                key: key(name),
+                value: name.into(),
+                value_type: ValueType::Text,
+                encoding: ValueEncoding::Utf8,
+                ttl_seconds: None,
+            })
+            .unwrap();
+    }
+
+    assert_eq!(
+        storage
+            .delete_many_strict(vec![key("a"), key("x"), key("b"), key("y")])
+            .unwrap_err(),
+        StorageError::KeysNotFound(vec![key("x"), key("y")])
+    );
+    assert!(storage.exists(&key("a")));
+    assert!(storage.exists(&key("b")));
+
+    let deleted = storage
+        .delete_many_strict(vec![key("b"), key("a")])
+        .unwrap();
+    assert_eq!(deleted[0].as_ref().unwrap().value, "b");
+    assert_eq!(deleted[1].as_ref().unwrap().value, "a");
+    assert!(storage.is_empty());
+
+    let results = storage.delete_many(vec![key("a")]);
+    assert_eq!(
+        results[0].as_ref().unwrap_err(),
+        &StorageError::KeyNotFound(key("a"))
+    );
+}
+
+pub fn test_concurrent_merge_patches_keep_every_field<S: Storage + Sync>(storage: &S) {
+    use std::thread;
+
+    const THREADS: usize = 8;
+    const PATCHES: usize = 25;
+    let key = Key::new("doc".to_string()).unwrap();
+    storage
+        .upsert(CreateKVRequest {
+            key: key.clone(),
+            value: serde_json::json!({"owner": "ops", "stale": true}).into(),
+            value_type: ValueType::Text,
+            encoding: ValueEncoding::Utf8,
+            ttl_seconds: None,
+        })
+        .unwrap();
+
+    thread::scope(|scope| {
+        for thread_id in 0..THREADS {
+            let key = key.clone();
+            scope.spawn(move || {
+                for i in 0..PATCHES {
+                    let patch = serde_json::json!({
+                        "counters": { format!("t{}", thread_id): i },
+                        "stale": null,
+                    });
+                    storage.merge_patch(key.clone(), &patch).unwrap();
+                }
+            });
+        }
+    });
+
+    let stored = storage.get(key.clone()).unwrap();
+    let document = stored.value.as_json().unwrap();
+    assert_eq!(document["owner"], "ops");
+    assert!(document.get("stale").is_none());
+    for thread_id in 0..THREADS {
+        assert_eq!(document["counters"][format!("t{}", thread_id)], PATCHES - 1);
+    }
+    assert_eq!(stored.metadata.version, (1 + THREADS * PATCHES) as u64);
+
+    let missing = Key::new("missing".to_string()).unwrap();
+    assert_eq!(
+        storage
+            .merge_patch(missing.clone(), &serde_json::json!({"a": 1}))
+            .unwrap_err(),
+        StorageError::KeyNotFound(missing)
+    );
+    storage
+        .upsert(CreateKVRequest {
+            key: key.clone(),
+            value: "plain".into(),
+            value_type: ValueType::Text,
+            encoding: ValueEncoding::Utf8,
+            ttl_seconds: None,
+        })
+        .unwrap();
+    assert_eq!(
+        storage
+            .merge_patch(key.clone(), &serde_json::json!({"a": 1}))
+            .unwrap_err(),
+        StorageError::NotJson(key)
+    );
+}
+
+pub fn test_binary_values_round_trip<S: Storage>(storage: &S) {
+    let key = Key::new("avatar".to_string()).unwrap();
+    let bytes = vec![0x89, b'P', b'N', b'G', 0x00, 0xff, 0xfe, 0x80];
+    let blob = StoredValue::Binary {
+        bytes: bytes.clone(),
+        content_type: "image/png".to_string(),
+    };
+    let (response, outcome) = storage
+        .upsert(CreateKVRequest {
+            key: key.clone(),
+            value: blob.clone(),
+            value_type: ValueType::Text,
+            encoding: ValueEncoding::Base64,
+            ttl_seconds: None,
+        })
+        .unwrap();
+    assert_eq!(outcome, UpsertOutcome::Created);
+    assert_eq!(response.metadata.size_bytes, bytes.len());
+    assert_eq!(response.metadata.content_type.as_deref(), Some("image/png"));
+
+    let stored = storage.get(key.clone()).unwrap();
+    assert_eq!(stored.value, blob);
+    assert_eq!(stored.etag(), content_etag_bytes(&bytes));
+    assert_eq!(
+        storage.append(key.clone(), "tail").unwrap_err(),
+        StorageError::NotAppendable(key.clone())
+    );
+    assert_eq!(
+        storage
+            .merge_patch(key.clone(), &serde_json::json!({"a": 1}))
+            .unwrap_err(),
+        StorageError::BinaryValue(key.clone())
+    );
+
+    let (response, _) = storage
+        .upsert(CreateKVRequest {
+            key: key.clone(),
+            value: "text again".into(),
+            value_type: ValueType::Text,
+            encoding: ValueEncoding::Utf8,
+            ttl_seconds: None,
+        })
+        .unwrap();
+    assert_eq!(response.metadata.content_type, None);
+    assert_eq!(response.metadata.size_bytes, "text again".len());
+}
+
+pub fn test_clear_removes_every_key<S: Storage>(storage: &S) {
+    for name in ["a", "b", "c"] {
+        storage
+            .upsert(CreateKVRequest {
+                key: Key::new(name.to_string()).unwrap(),
+                value: name.into(),
+                value_type: ValueType::Text,
+                encoding: ValueEncoding::Utf8,
+                ttl_seconds: None,
+            })
+            .unwrap();
+    }
+
+    assert_eq!(storage.clear(), 3);
+    assert!(storage.list_keys().is_empty());
+    assert!(storage.get(Key::new("a".to_string()).unwrap()).is_err());
+    assert_eq!(storage.clear(), 0);
+}
+
+pub fn test_hash_fields_and_type_conflicts<S: Storage>(storage: &S) {
+    let key = Key::new("user-1".to_string()).unwrap();
+    let name = Key::new("name".to_string()).unwrap();
+    let email = Key::new("email".to_string()).unwrap();
+
+    let (metadata, outcome) = storage.set_field(key.clone(), &name, "Ada").unwrap();
+    assert_eq!(outcome, UpsertOutcome::Created);
+    assert_eq!(metadata.version, 1);
+    let (_, outcome) = storage
+        .set_field(key.clone(), &email, "ada@example.com")
+        .unwrap();
+    assert_eq!(outcome, UpsertOutcome::Created);
+    let (metadata, outcome) = storage.set_field(key.clone(), &name, "Ada L.").unwrap();
+    assert_eq!(outcome, UpsertOutcome::Updated);
+    assert_eq!(metadata.version, 3);
+
+    assert_eq!(storage.get_field(&key, &name).unwrap(), "Ada L.");
+    let fields = storage.get_fields(&key).unwrap();
+    assert_eq!(fields.len(), 2);
+    assert_eq!(fields["email"], "ada@example.com");
+
+    let phone = Key::new("phone".to_string()).unwrap();
+    assert_eq!(
+        storage.get_field(&key, &phone).unwrap_err(),
+        StorageError::FieldNotFound {
+            key: key.clone(),
+            field: phone.clone()
+        }
+    );
+    assert_eq!(
+        storage.delete_field(key.clone(), &phone).unwrap_err(),
+        StorageError::FieldNotFound {
+            key: key.clone(),
+            field: phone
+        }
+    );
+    let missing = Key::new("missing".to_string()).unwrap();
+    assert_eq!(
+        storage.get_fields(&missing).unwrap_err(),
+        StorageError::KeyNotFound(missing.clone())
+    );
+    assert_eq!(
+        storage.delete_field(missing.clone(), &name).unwrap_err(),
+        StorageError::KeyNotFound(missing)
+    );
+
+    let plain = CreateKVRequest {
+        key: key.clone(),
+        value: "plain".into(),
+        value_type: ValueType::Text,
+        encoding: ValueEncoding::Utf8,
+        ttl_seconds: None,
+    };
+    assert_eq!(
+        storage.upsert(plain.clone()).unwrap_err(),
+        StorageError::WrongType(key.clone())
+    );
+    assert_eq!(
+        storage.append(key.clone(), "more").unwrap_err(),
+        StorageError::WrongType(key.clone())
+    );
+    assert_eq!(
+        storage
+            .merge_patch(key.clone(), &serde_json::json!({"a": 1}))
+            .unwrap_err(),
+        StorageError::WrongType(key.clone())
+    );
+    assert_eq!(storage.get_fields(&key).unwrap().len(), 2);
+
+    storage.delete_field(key.clone(), &name).unwrap();
+    assert_eq!(storage.get_fields(&key).unwrap().len(), 1);
+    storage.delete_field(key.clone(), &email).unwrap();
+    assert_eq!(
+        storage.get(key.clone()).unwrap_err(),
+        StorageError::KeyNotFound(key.clone())
+    );
+
+    storage.upsert(plain).unwrap();
+    assert_eq!(
+        storage.set_field(key.clone(), &name, "Ada").unwrap_err(),
+        StorageError::WrongType(key.clone())
+    );
+    assert_eq!(
+        storage.get_field(&key, &name).unwrap_err(),
+        StorageError::WrongType(key.clone())
+    );
+    assert_eq!(
+        storage.delete_field(key.clone(), &name).unwrap_err(),
+        StorageError::WrongType(key.clone())
+    );
+    assert_eq!(*storage.get(key).unwrap().value.as_bytes(), *b"plain");
+}
+
+pub fn test_concurrent_field_writes_keep_every_field<S: Storage + Sync>(storage: &S) {
+    use std::thread;
+
+    const THREADS: usize = 8;
+    const WRITES: usize = 25;
+    let key = Key::new("profile".to_string()).unwrap();
+
+    thread::scope(|scope| {
+        for thread_id in 0..THREADS {
+            let key = key.clone();
+            scope.spawn(move || {
+                for i in 0..WRITES {
+                    let field = Key::new(format!("t{}-{}", thread_id, i)).unwrap();
+                    storage
+                        .set_field(key.clone(), &field, &i.to_string())
+                        .unwrap();
+                }
+            });
+        }
+    });
+
+    let fields = storage.get_fields(&key).unwrap();
+    assert_eq!(fields.len(), THREADS * WRITES);
+    for thread_id in 0..THREADS {
+        assert_eq!(fields[&format!("t{}-{}", thread_id, WRITES - 1)], "24");
+    }
+    let metadata = storage.get(key).unwrap().metadata;
+    assert_eq!(metadata.version, (THREADS * WRITES) as u64);
+}
+
+pub fn test_list_push_pop_and_range<S: Storage>(storage: &S) {
+    let key = Key::new("jobs".to_string()).unwrap();
+
+    let (metadata, length, outcome) = storage
+        .list_push(key.clone(), ListSide::Right, "a")
+        .unwrap();
+    assert_eq!((length, outcome), (1, UpsertOutcome::Created));
+    assert_eq!(metadata.version, 1);
+    for value in ["b", "c"] {
+        storage
+            .list_push(key.clone(), ListSide::Right, value)
+            .unwrap();
+    }
+    let (metadata, length, outcome) = storage.list_push(key.clone(), ListSide::Left, "z").unwrap();
+    assert_eq!((length, outcome), (4, UpsertOutcome::Updated));
+    assert_eq!(metadata.version, 4);
+
+    let slice = |start, end| storage.list_slice(&key, start, end).unwrap();
+    assert_eq!(
+        slice(0, -1),
+        (vec!["z".into(), "a".into(), "b".into(), "c".into()], 4)
+    );
+    assert_eq!(slice(1, 2).0, ["a", "b"]);
+    assert_eq!(slice(-2, -1).0, ["b", "c"]);
+    assert_eq!(slice(-100, 0).0, ["z"]);
+    assert_eq!(slice(2, 100).0, ["b", "c"]);
+    assert!(slice(3, 1).0.is_empty());
+    assert!(slice(5, 10).0.is_empty());
+    let missing = Key::new("missing".to_string()).unwrap();
+    assert_eq!(storage.list_slice(&missing, 0, -1).unwrap(), (vec![], 0));
+
+    assert_eq!(
+        storage.list_pop(key.clone(), ListSide::Left).unwrap(),
+        ("z".to_string(), 3)
+    );
+    assert_eq!(
+        storage.list_pop(key.clone(), ListSide::Right).unwrap(),
+        ("c".to_string(), 2)
+    );
+    storage.list_pop(key.clone(), ListSide::Left).unwrap();
+    assert_eq!(
+        storage.list_pop(key.clone(), ListSide::Left).unwrap(),
+        ("b".to_string(), 0)
+    );
+    assert_eq!(
+        storage.get(key.clone()).unwrap_err(),
+        StorageError::KeyNotFound(key.clone())
+    );
+    assert_eq!(
+        storage.list_pop(key.clone(), ListSide::Left).unwrap_err(),
+        StorageError::ListEmpty(key.clone())
+    );
+}
+
+pub fn test_list_type_conflicts<S: Storage>(storage: &S) {
+    let list = Key::new("queue".to_string()).unwrap();
+    let hash = Key::new("profile".to_string()).unwrap();
+    let plain = Key::new("name".to_string()).unwrap();
+    let field = Key::new("field".to_string()).unwrap();
+    storage
+        .list_push(list.clone(), ListSide::Right, "a")
+        .unwrap();
+    storage.set_field(hash.clone(), &field, "value").unwrap();
+    storage
+        .upsert(CreateKVRequest {
+            key: plain.clone(),
+            value: "Ada".into(),
+            value_type: ValueType::Text,
+            encoding: ValueEncoding::Utf8,
+            ttl_seconds: None,
+        })
+        .unwrap();
+
+    for key in [&hash, &plain] {
+        let wrong_type = StorageError::WrongType(key.clone());
+        assert_eq!(
+            storage
+                .list_push(key.clone(), ListSide::Left, "x")
+                .unwrap_err(),
+            wrong_type
+        );
+        assert_eq!(
+            storage.list_pop(key.clone(), ListSide::Left).unwrap_err(),
+            wrong_type
+        );
+        assert_eq!(storage.list_slice(key, 0, -1).unwrap_err(), wrong_type);
+    }
+
+    let wrong_type = StorageError::WrongType(list.clone());
+    assert_eq!(
+        storage.set_field(list.clone(), &field, "x").unwrap_err(),
+        wrong_type
+    );
+    assert_eq!(storage.append(list.clone(), "x").unwrap_err(), wrong_type);
+    assert_eq!(
+        storage
+            .upsert(CreateKVRequest {
+                key: list.clone(),
+                value: "plain".into(),
+                value_type: ValueType::Text,
+                encoding: ValueEncoding::Utf8,
+                ttl_seconds: None,
+            })
+            .unwrap_err(),
+        wrong_type
+    );
+    assert_eq!(storage.list_slice(&list, 0, -1).unwrap().1, 1);
+}
+
+pub fn test_set_membership_operations<S: Storage>(storage: &S) {
+    let tags = Key::new("tags".to_string()).unwrap();
+    let plain = Key::new("name".to_string()).unwrap();
+
+    let (metadata, added, count) = storage.add_member(tags.clone(), "rust").unwrap();
+    assert!(added);
+    assert_eq!(count, 1);
+    assert_eq!(metadata.member_count, Some(1));
+    let (metadata, added, count) = storage.add_member(tags.clone(), "db").unwrap();
+    assert!(added);
+    assert_eq!(count, 2);
+    assert_eq!(metadata.version, 2);
+
+    let (metadata, added, count) = storage.add_member(tags.clone(), "rust").unwrap();
+    assert!(!added, "Adding an existing member should be a no-op");
+    assert_eq!(count, 2);
+    assert_eq!(metadata.version, 2);
+    assert_eq!(metadata.member_count, Some(2));
+
+    assert_eq!(storage.members(&tags).unwrap(), vec!["db", "rust"]);
+    assert!(storage.is_member(&tags, "rust").unwrap());
+    assert!(!storage.is_member(&tags, "go").unwrap());
+    assert_eq!(
+        storage.get(tags.clone()).unwrap().metadata.member_count,
+        Some(2)
+    );
+
+    assert_eq!(
+        storage.remove_member(tags.clone(), "go").unwrap(),
+        (false, 2)
+    );
+    assert_eq!(
+        storage.remove_member(tags.clone(), "rust").unwrap(),
+        (true, 1)
+    );
+    assert_eq!(
+        storage.remove_member(tags.clone(), "db").unwrap(),
+        (true, 0)
+    );
+    assert!(
+        !storage.exists(&tags),
+        "Removing the last member deletes the key"
+    );
+    assert_eq!(
+        storage.remove_member(tags.clone(), "db").unwrap(),
+        (false, 0)
+    );
+    assert!(storage.members(&tags).unwrap().is_empty());
+    assert!(!storage.is_member(&tags, "db").unwrap());
+
+    storage.add_member(tags.clone(), "rust").unwrap();
+    storage
+        .upsert(CreateKVRequest {
+            key: plain.clone(),
+            value: "Ada".into(),
+            value_type: ValueType::Text,
+            encoding: ValueEncoding::Utf8,
+            ttl_seconds: None,
+        })
+        .unwrap();
+    let wrong_type = StorageError::WrongType(plain.clone());
+    assert_eq!(
+        storage.add_member(plain.clone(), "x").unwrap_err(),
+        wrong_type
+    );
+    assert_eq!(
+        storage.remove_member(plain.clone(), "x").unwrap_err(),
+        wrong_type
+    );
+    assert_eq!(storage.members(&plain).unwrap_err(), wrong_type);
+    assert_eq!(storage.is_member(&plain, "x").unwrap_err(), wrong_type);
+
+    let wrong_type = StorageError::WrongType(tags.clone());
+    assert_eq!(storage.append(tags.clone(), "x").unwrap_err(), wrong_type);
+    assert_eq!(
+        storage
+            .list_push(tags.clone(), ListSide::Right, "x")
+            .unwrap_err(),
+        wrong_type
+    );
+    assert_eq!(storage.members(&tags).unwrap(), vec!["rust"]);
+}
+
+pub fn test_concurrent_list_pops_consume_each_element_once<S: Storage + Sync>(storage: &S) {
+    use std::collections::HashSet;
+    use std::sync::Mutex;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+
+    const PRODUCERS: usize = 4;
+    const CONSUMERS: usize = 4;
+    const PUSHES: usize = 100;
+    let key = Key::new("queue".to_string()).unwrap();
+    let consumed = AtomicUsize::new(0);
+    let popped = Mutex::new(Vec::new());
+
+    thread::scope(|scope| {
+        for producer in 0..PRODUCERS {
+            let key = key.clone();
+            scope.spawn(move || {
+                for i in 0..PUSHES {
+                    storage
+                        .list_push(
+                            key.clone(),
+                            ListSide::Right,
+                            &format!("p{}-{}", producer, i),
+                        )
+                        .unwrap();
+                }
+            });
+        }
+        for _ in 0..CONSUMERS {
+            let key = key.clone();
+            let (consumed, popped) = (&consumed, &popped);
+            scope.spawn(move || {
+                let mut mine = Vec::new();
+                while consumed.load(Ordering::SeqCst) < PRODUCERS * PUSHES {
+                    match storage.list_pop(key.clone(), ListSide::Left) {
+                        Ok((element, _)) => {
+                            consumed.fetch_add(1, Ordering::SeqCst);
+                            mine.push(element);
+                        }
+                        Err(StorageError::ListEmpty(_)) => thread::yield_now(),
+                        Err(storage_error) => panic!("unexpected error: {storage_error}"),
+                    }
+                }
+                popped.lock().unwrap().extend(mine);
+            });
+        }
+    });
+
+    let popped = popped.into_inner().unwrap();
+    assert_eq!(popped.len(), PRODUCERS * PUSHES);
+    let unique: HashSet<_> = popped.iter().collect();
+    assert_eq!(unique.len(), PRODUCERS * PUSHES);
+    assert_eq!(
+        storage.list_pop(key.clone(), ListSide::Left).unwrap_err(),
+        StorageError::ListEmpty(key)
+    );
+}
+
+pub fn test_counter_add_reset_and_bounds<S: Storage>(storage: &S) {
+    let key = Key::new("seats".to_string()).unwrap();
+    let bounds = CounterBounds {
+        initial: 5,
+        min: Some(0),
+        max: Some(10),
+    };
+    let created = storage.create_counter(key.clone(), bounds).unwrap();
+    assert_eq!(created.value, StoredValue::Json(5.into()));
+    assert_eq!(created.metadata.value_type, ValueType::Counter);
+    assert_eq!(created.metadata.counter, Some(bounds));
+    assert_eq!(
+        storage.create_counter(key.clone(), bounds).unwrap_err(),
+        StorageError::KeyAlreadyExists(key.clone())
+    );
+
+    let add = |delta: i64, overflow: CounterOverflow| {
+        storage
+            .counter_add(key.clone(), delta, overflow)
+            .map(|(_, value, clamped)| (value, clamped))
+    };
+    let out_of_range = StorageError::CounterOutOfRange {
+        key: key.clone(),
+        min: 0,
+        max: 10,
+    };
+    assert_eq!(add(3, CounterOverflow::Error).unwrap(), (8, false));
+    assert_eq!(add(5, CounterOverflow::Error).unwrap_err(), out_of_range);
+    assert_eq!(
+        storage.get(key.clone()).unwrap().value,
+        StoredValue::Json(8.into())
+    );
+    assert_eq!(add(5, CounterOverflow::Clamp).unwrap(), (10, true));
+    assert_eq!(add(-100, CounterOverflow::Clamp).unwrap(), (0, true));
+    assert_eq!(
+        add(i64::MIN, CounterOverflow::Error).unwrap_err(),
+        out_of_range
+    );
+
+    let (metadata, value) = storage.counter_reset(key.clone()).unwrap();
+    assert_eq!(value, 5);
+    assert_eq!(metadata.counter, Some(bounds));
+    assert_eq!(
+        storage.get(key.clone()).unwrap().value,
+        StoredValue::Json(5.into())
+    );
+
+    // Plain writes are held to the counter's type and bounds.
+    let write = |value: serde_json::Value| {
+        storage.upsert(CreateKVRequest {
+            key: key.clone(),
+            value: StoredValue::Json(value),
+            value_type: ValueType::Text,
+            encoding: ValueEncoding::Utf8,
+            ttl_seconds: None,
+        })
+    };
+    assert_eq!(write(11.into()).unwrap_err(), out_of_range);
+    assert!(matches!(
+        write(7.5.into()).unwrap_err(),
+        StorageError::TypeMismatch { .. }
+    ));
+    let (written, _) = write(7.into()).unwrap();
+    assert_eq!(written.metadata.counter, Some(bounds));
+    assert_eq!(add(1, CounterOverflow::Error).unwrap(), (8, false));
+}
+
+pub fn test_counter_arithmetic_never_wraps<S: Storage>(storage: &S) {
+    let key = Key::new("big".to_string()).unwrap();
+    storage
+        .create_counter(
+            key.clone(),
+            CounterBounds {
+                initial: i64::MAX - 1,
+                ..CounterBounds::default()
+            },
+        )
+        .unwrap();
+
+    assert_eq!(
+        storage
+            .counter_add(key.clone(), 5, CounterOverflow::Error)
+            .unwrap_err(),
+        StorageError::CounterOutOfRange {
+            key: key.clone(),
+            min: i64::MIN,
+            max: i64::MAX,
+        }
+    );
+    let (_, value, clamped) = storage
+        .counter_add(key.clone(), i64::MAX, CounterOverflow::Clamp)
+        .unwrap();
+    assert_eq!((value, clamped), (i64::MAX, true));
+    let (_, value, _) = storage
+        .counter_add(key.clone(), i64::MIN, CounterOverflow::Error)
+        .unwrap();
+    assert_eq!(value, -1);
+    let (_, value, clamped) = storage
+        .counter_add(key.clone(), i64::MIN, CounterOverflow::Clamp)
+        .unwrap();
+    assert_eq!((value, clamped), (i64::MIN, true));
+}
+
+pub fn test_counter_errors_on_other_values<S: Storage>(storage: &S) {
+    let missing = Key::new("missing".to_string()).unwrap();
+    let plain = Key::new("name".to_string()).unwrap();
+    let tags = Key::new("tags".to_string()).unwrap();
+    storage
+        .upsert(CreateKVRequest {
+            key: plain.clone(),
+            value: "41".into(),
+            value_type: ValueType::Text,
+            encoding: ValueEncoding::Utf8,
+            ttl_seconds: None,
+        })
+        .unwrap();
+    storage.add_member(tags.clone(), "rust").unwrap();
+
+    assert_eq!(
+        storage
+            .counter_add(missing.clone(), 1, CounterOverflow::Error)
+            .unwrap_err(),
+        StorageError::KeyNotFound(missing.clone())
+    );
+    assert_eq!(
+        storage.counter_reset(missing.clone()).unwrap_err(),
+        StorageError::KeyNotFound(missing)
+    );
+    for key in [plain, tags] {
+        assert_eq!(
+            storage
+                .counter_add(key.clone(), 1, CounterOverflow::Error)
+                .unwrap_err(),
+            StorageError::NotCounter(key.clone())
+        );
+        assert_eq!(
+            storage.counter_reset(key.clone()).unwrap_err(),
+            StorageError::NotCounter(key)
+        );
+    }
+}
+
+pub fn test_concurrent_counter_adds_stop_at_bounds<S: Storage + Sync>(storage: &S) {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+
+    const THREADS: usize = 8;
+    const ADDS: usize = 50;
+    const MAX: i64 = 150;
+    let key = Key::new("tickets".to_string()).unwrap();
+    storage
+        .create_counter(
+            key.clone(),
+            CounterBounds {
+                initial: 0,
+                min: Some(0),
+                max: Some(MAX),
+            },
+        )
+        .unwrap();
+    let granted = AtomicUsize::new(0);
+
+    thread::scope(|scope| {
+        for _ in 0..THREADS {
+            let (key, granted) = (key.clone(), &granted);
+            scope.spawn(move || {
+                for _ in 0..ADDS {
+                    match storage.counter_add(key.clone(), 1, CounterOverflow::Error) {
+                        Ok(_) => {
+                            granted.fetch_add(1, Ordering::SeqCst);
+                        }
+                        Err(StorageError::CounterOutOfRange { .. }) => {}
+                        Err(storage_error) => panic!("unexpected error: {storage_error}"),
+                    }
+                }
+            });
+        }
+    });
+
+    assert_eq!(granted.into_inner(), MAX as usize);
+    assert_eq!(
+        storage.get(key).unwrap().value,
+        StoredValue::Json(MAX.into())
+    );
+}
+
+pub fn test_counter_reset_racing_with_adds<S: Storage + Sync>(storage: &S) {
+    use std::sync::Mutex;
+    use std::thread;
+
+    const THREADS: usize = 4;
+    const ADDS: usize = 200;
+    let key = Key::new("hits".to_string()).unwrap();
+    storage
+        .create_counter(
+            key.clone(),
+            CounterBounds {
+                initial: 1000,
+                ..CounterBounds::default()
+            },
+        )
+        .unwrap();
+    let versions = Mutex::new(Vec::new());
+
+    let reset_version = thread::scope(|scope| {
+        for _ in 0..THREADS {
+            let (key, versions) = (key.clone(), &versions);
+            scope.spawn(move || {
+                let mut mine = Vec::new();
+                for _ in 0..ADDS {
+                    let (metadata, _, _) = storage
+                        .counter_add(key.clone(), 1, CounterOverflow::Error)
+                        .unwrap();
+                    mine.push(metadata.version);
+                }
+                versions.lock().unwrap().extend(mine);
+            });
+        }
+        let reset = scope.spawn(|| {
+            thread::yield_now();
+            storage.counter_reset(key.clone()).unwrap()
+        });
+        let (metadata, value) = reset.join().unwrap();
+        assert_eq!(value, 1000);
+        metadata.version
+    });
+
+    // Every add is serialized with the reset: those ordered after it count
+    // from the initial value, those before it are wiped out.
+    let after_reset = versions
+        .into_inner()
+        .unwrap()
+        .into_iter()
+        .filter(|version| *version > reset_version)
+        .count() as i64;
+    assert_eq!(
+        storage.get(key).unwrap().value,
+        StoredValue::Json((1000 + after_reset).into())
+    );
+}
+
+fn balance(name: &str, amount: i64) -> CreateKVRequest {
+    CreateKVRequest {
+        key: Key::new(name.to_string()).unwrap(),
+        value: StoredValue::Json(amount.into()),
+        value_type: ValueType::Text,
+        encoding: ValueEncoding::Utf8,
+        ttl_seconds: None,
+    }
+}
+
+pub fn test_transaction_applies_all_or_nothing<S: Storage>(storage: &S) {
+    let key = |name: &str| Key::new(name.to_string()).unwrap();
+    let amount = |name: &str| storage.get(key(name)).unwrap().value;
+    storage.upsert(balance("alice", 100)).unwrap();
+    storage.upsert(balance("bob", 5)).unwrap();
+    storage.upsert(balance("stale", 1)).unwrap();
+
+    let results = storage
+        .transaction(vec![
+            TxOp::Cas {
+                expected: Some(100.into()),
+                body: balance("alice", 70),
+            },
+            TxOp::Cas {
+                expected: Some(5.into()),
+                body: balance("bob", 35),
+            },
+            TxOp::Set(balance("audit", 1)),
+            TxOp::Delete(key("stale")),
+        ])
+        .unwrap();
+    assert_eq!(results.len(), 4);
+    assert!(matches!(&results[0], OpResult::Swapped(response) if response.metadata.version == 2));
+    assert!(matches!(
+        results[2],
+        OpResult::Set(_, UpsertOutcome::Created)
+    ));
+    assert!(
+        matches!(&results[3], OpResult::Deleted(response) if response.value == StoredValue::Json(1.into()))
+    );
+    assert_eq!(amount("alice"), StoredValue::Json(70.into()));
+    assert_eq!(amount("bob"), StoredValue::Json(35.into()));
+    assert_eq!(amount("audit"), StoredValue::Json(1.into()));
+    assert!(!storage.exists(&key("stale")));
+
+    let results = storage
+        .transaction(vec![
+            TxOp::Set(balance("scratch", 1)),
+            TxOp::Cas {
+                expected: Some(1.into()),
+                body: balance("scratch", 2),
+            },
+            TxOp::Delete(key("scratch")),
+        ])
+        .unwrap();
+    assert_eq!(results.len(), 3);
+    assert!(!storage.exists(&key("scratch")));
+    assert!(storage.transaction(Vec::new()).unwrap().is_empty());
+
+    let error = storage
+        .transaction(vec![
+            TxOp::Set(balance("audit", 2)),
+            TxOp::Delete(key("bob")),
+            TxOp::Cas {
+                expected: Some(100.into()),
+                body: balance("alice", 0),
+            },
+        ])
+        .unwrap_err();
+    assert!(matches!(
+        error,
+        TxError::Mismatch { index: 2, current: Some(ref metadata) } if metadata.version == 2
+    ));
+    assert_eq!(amount("audit"), StoredValue::Json(1.into()));
+    assert_eq!(amount("bob"), StoredValue::Json(35.into()));
+    assert_eq!(amount("alice"), StoredValue::Json(70.into()));
+
+    let error = storage
+        .transaction(vec![
+            TxOp::Set(balance("fresh", 1)),
+            TxOp::Cas {
+                expected: None,
+                body: balance("bob", 0),
+            },
+        ])
+        .unwrap_err();
+    assert!(matches!(
+        error,
+        TxError::Mismatch {
+            index: 1,
+            current: Some(_)
+        }
+    ));
+    assert!(!storage.exists(&key("fresh")));
+
+    let error = storage
+        .transaction(vec![
+            TxOp::Set(balance("fresh", 1)),
+            TxOp::Delete(key("missing")),
+        ])
+        .unwrap_err();
+    assert!(matches!(
+        error,
+        TxError::Failed {
+            index: 1,
+            error: StorageError::KeyNotFound(_)
+        }
+    ));
+    assert!(!storage.exists(&key("fresh")));
+
+    let (response, _) = storage.upsert(balance("alice", 71)).unwrap();
+    assert_eq!(response.metadata.version, 3);
+}
+
+/// Transfers between counters with `cas` on both balances, racing plain
+/// `counter_add` deposits. A commit must neither act on balances that moved
+/// nor overwrite a deposit that landed while it ran, so the total comes out
+/// exact under any interleaving.
+pub fn test_concurrent_transfers_conserve_total<S: Storage + Sync>(storage: &S) {
+    use std::thread;
+
+    const ACCOUNTS: usize = 4;
+    const THREADS: usize = 8;
+    const TRANSFERS: usize = 25;
+    const DEPOSITS: usize = 2000;
+    let name = |account: usize| format!("account-{account}");
+    for account in 0..ACCOUNTS {
+        let bounds = CounterBounds {
+            initial: 100,
+            min: None,
+            max: None,
+        };
+        storage
+            .create_counter(Key::new(name(account)).unwrap(), bounds)
+            .unwrap();
+    }
+
+    thread::scope(|scope| {
+        scope.spawn(move || {
+            for deposit in 0..DEPOSITS {
+                let key = Key::new(name(deposit % ACCOUNTS)).unwrap();
+                storage.counter_add(key, 1, CounterOverflow::Error).unwrap();
+            }
+        });
+        for thread in 0..THREADS {
+            scope.spawn(move || {
+                for transfer in 0..TRANSFERS {
+                    let from = name((thread + transfer) % ACCOUNTS);
+                    let to = name((thread + transfer + 1) % ACCOUNTS);
+                    loop {
+                        let read = |name: &str| {
+                            let key = Key::new(name.to_string()).unwrap();
+                            match storage.get(key).unwrap().value {
+                                StoredValue::Json(value) => value.as_i64().unwrap(),
+                                other => panic!("unexpected value {other:?}"),
+                            }
+                        };
+                        let (source, target) = (read(&from), read(&to));
+                        let outcome = storage.transaction(vec![
+                            TxOp::Cas {
+                                expected: Some(source.into()),
+                                body: balance(&from, source - 1),
+                            },
+                            TxOp::Cas {
+                                expected: Some(target.into()),
+                                body: balance(&to, target + 1),
+                            },
+                        ]);
+                        match outcome {
+                            Ok(_) => break,
+                            Err(TxError::Mismatch { .. }) => continue,
+                            Err(tx_error) => panic!("unexpected error: {tx_error:?}"),
+                        }
+                    }
+                }
+            });
+        }
+    });
+
+    let total: i64 = (0..ACCOUNTS)
+        .map(|account| {
+            let key = Key::new(name(account)).unwrap();
+            match storage.get(key).unwrap().value {
+                StoredValue::Json(value) => value.as_i64().unwrap(),
+                other => panic!("unexpected value {other:?}"),
+            }
+        })
+        .sum();
+    assert_eq!(total, (100 * ACCOUNTS + DEPOSITS) as i64);
+}
+
+pub fn test_transaction_checks_gate_the_writes<S: Storage>(storage: &S) {
+    let key = |name: &str| Key::new(name.to_string()).unwrap();
+    let amount = |name: &str| storage.get(key(name)).unwrap().value;
+    let check = |name: &str, expected: TxCheck| TxOp::Check {
+        key: key(name),
+        expected,
+    };
+    storage.upsert(balance("alice", 1)).unwrap();
+    storage.upsert(balance("bob", 2)).unwrap();
+
+    let results = storage
+        .transaction(vec![
+            check("alice", TxCheck::Value(Some(1.into()))),
+            check("bob", TxCheck::Version(1)),
+            check("carol", TxCheck::Value(None)),
+            TxOp::Set(balance("alice", 10)),
+            TxOp::Set(balance("bob", 20)),
+            check("alice", TxCheck::Value(Some(10.into()))),
+        ])
+        .unwrap();
+    assert!(matches!(&results[0], OpResult::Checked(Some(metadata)) if metadata.version == 1));
+    assert!(matches!(results[2], OpResult::Checked(None)));
+    assert!(matches!(&results[5], OpResult::Checked(Some(metadata)) if metadata.version == 2));
+    assert_eq!(amount("alice"), StoredValue::Json(10.into()));
+    assert_eq!(storage.get_metadata(&key("alice")).unwrap().version, 2);
+
+    let error = storage
+        .transaction(vec![
+            TxOp::Set(balance("alice", 11)),
+            check("bob", TxCheck::Version(1)),
+            TxOp::Set(balance("bob", 21)),
+        ])
+        .unwrap_err();
+    assert!(matches!(
+        error,
+        TxError::Mismatch { index: 1, current: Some(ref metadata) } if metadata.version == 2
+    ));
+    assert_eq!(amount("alice"), StoredValue::Json(10.into()));
+    assert_eq!(amount("bob"), StoredValue::Json(20.into()));
+
+    let error = storage
+        .transaction(vec![
+            TxOp::Delete(key("bob")),
+            check("carol", TxCheck::Version(1)),
+        ])
+        .unwrap_err();
+    assert!(matches!(
+        error,
+        TxError::Mismatch {
+            index: 1,
+            current: None
+        }
+    ));
+    let error = storage
+        .transaction(vec![check("bob", TxCheck::Value(None))])
+        .unwrap_err();
+    assert!(matches!(error, TxError::Mismatch { index: 0, .. }));
+    assert!(storage.exists(&key("bob")));
+    assert!(!storage.exists(&key("carol")));
+}
+
+/// Two writers keep `left` and `right` equal through transactions that
+/// check both versions, while a reader asserts it never sees them differ.
+pub fn test_overlapping_transactions_stay_consistent<S: Storage + Sync>(storage: &S) {
+    let pair = [
+        Key::new("left".to_string()).unwrap(),
+        Key::new("right".to_string()).unwrap(),
+    ];
+    overlapping_transactions(storage, |storage| {
+        storage
+            .get_many(&pair)
+            .into_iter()
+            .map(|result| result.unwrap().value)
+            .collect()
+    });
+}
+
+/// [`test_overlapping_transactions_stay_consistent`] with the reader
+/// listing the entries instead of fetching the keys.
+pub fn test_overlapping_transactions_stay_consistent_in_listings<S: Storage + Sync>(storage: &S) {
+    overlapping_transactions(storage, |storage| {
+        storage
+            .list_entries(&KeyFilter::default(), 10)
+            .into_iter()
+            .map(|entry| entry.value)
+            .collect()
+    });
+}
+
+fn overlapping_transactions<S: Storage + Sync>(
+    storage: &S,
+    watch: impl Fn(&S) -> Vec<StoredValue> + Sync,
+) {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+
+    const WRITERS: usize = 2;
+    const ROUNDS: i64 = 500;
+    let pair = [
+        Key::new("left".to_string()).unwrap(),
+        Key::new("right".to_string()).unwrap(),
+    ];
+    storage.upsert(balance("left", 0)).unwrap();
+    storage.upsert(balance("right", 0)).unwrap();
+    let read_pair = || -> Vec<(i64, u64)> {
+        storage
+            .get_many(&pair)
+            .into_iter()
+            .map(|result| {
+                let current = result.unwrap();
+                match current.value {
+                    StoredValue::Json(value) => (value.as_i64().unwrap(), current.metadata.version),
+                    other => panic!("unexpected value {other:?}"),
+                }
+            })
+            .collect()
+    };
+
+    let running = AtomicUsize::new(WRITERS);
+    thread::scope(|scope| {
+        scope.spawn(|| {
+            while running.load(Ordering::Relaxed) > 0 {
+                let seen = watch(storage);
+                assert_eq!(seen.len(), 2);
+                assert_eq!(seen[0], seen[1], "saw half a transaction");
+            }
+        });
+        for _ in 0..WRITERS {
+            scope.spawn(|| {
+                for _ in 0..ROUNDS {
+                    loop {
+                        let read = read_pair();
+                        let next = read[0].0 + 1;
+                        let outcome = storage.transaction(vec![
+                            TxOp::Check {
+                                key: pair[0].clone(),
+                                expected: TxCheck::Version(read[0].1),
+                            },
+                            TxOp::Check {
+                                key: pair[1].clone(),
+                                expected: TxCheck::Version(read[1].1),
+                            },
+                            TxOp::Set(balance("left", next)),
+                            TxOp::Set(balance("right", next)),
+                        ]);
+                        match outcome {
+                            Ok(_) => break,
+                            Err(TxError::Mismatch { .. }) => continue,
+                            Err(tx_error) => panic!("unexpected error: {tx_error:?}"),
+                        }
+                    }
+                }
+                running.fetch_sub(1, Ordering::Relaxed);
+            });
+        }
+    });
+
+    let read = read_pair();
+    assert_eq!(read[0].0, WRITERS as i64 * ROUNDS);
+    assert_eq!(read[1].0, WRITERS as i64 * ROUNDS);
+}
+
+pub fn test_stale_reads_match_strong_reads<S: Storage>(storage: &S) {
+    let key = Key::new("test-key".to_string()).unwrap();
+    assert_eq!(
+        storage.get_stale(key.clone()).unwrap_err(),
+        StorageError::KeyNotFound(key.clone())
+    );
+
+    storage
+        .upsert(CreateKVRequest {
+            key: key.clone(),
+            value: "v1".into(),
+            value_type: ValueType::Text,
+            encoding: ValueEncoding::Utf8,
+            ttl_seconds: None,
+        })
+        .unwrap();
+    let stale = storage.get_stale(key.clone()).unwrap();
+    let strong = storage.get(key.clone()).unwrap();
+    assert_eq!(stale.value, strong.value);
+    assert_eq!(stale.metadata.version, strong.metadata.version);
+    assert_eq!(stale.metadata.updated_at, strong.metadata.updated_at);
+
+    storage.delete(key.clone()).unwrap();
+    assert_eq!(
+        storage.get_stale(key.clone()).unwrap_err(),
+        StorageError::KeyNotFound(key)
+    );
+}
+
+pub fn test_schema_guards_writes<S: Storage>(storage: &S) {
+    let key = Key::new("config".to_string()).unwrap();
+    let write = |value: serde_json::Value| {
+        storage.upsert(CreateKVRequest {
+            key: key.clone(),
+            value: value.into(),
+            value_type: ValueType::Text,
+            encoding: ValueEncoding::Utf8,
+            ttl_seconds: None,
+        })
+    };
+    let schema = serde_json::json!({
+        "type": "object",
+        "required": ["port"],
+        "properties": {"port": {"type": "integer"}}
+    });
+    assert_eq!(
+        storage.set_schema(&key, Some(schema.clone())).unwrap_err(),
+        StorageError::KeyNotFound(key.clone())
+    );
+
+    write(serde_json::json!({"port": 80})).unwrap();
+    let metadata = storage.set_schema(&key, Some(schema.clone())).unwrap();
+    assert_eq!(metadata.schema.as_ref(), Some(&schema));
+    assert_eq!(metadata.version, 1, "attaching a schema is not a write");
+
+    let Err(StorageError::SchemaViolation { violations, .. }) =
+        write(serde_json::json!({"port": "eighty"}))
+    else {
+        panic!("a value breaking the schema must be rejected");
+    };
+    assert_eq!(violations.len(), 1);
+    assert_eq!(violations[0].path, "/port");
+    assert!(matches!(
+        storage.merge_patch(key.clone(), &serde_json::json!({"port": null})),
+        Err(StorageError::SchemaViolation { .. })
+    ));
+    assert_eq!(storage.get(key.clone()).unwrap().metadata.version, 1);
+
+    let (response, _) = write(serde_json::json!({"port": 8080})).unwrap();
+    assert_eq!(response.metadata.schema.as_ref(), Some(&schema));
+
+    let strict = serde_json::json!({"properties": {"port": {"maximum": 1024}}});
+    assert!(matches!(
+        storage.set_schema(&key, Some(strict)),
+        Err(StorageError::SchemaViolation { .. })
+    ));
+    assert_eq!(
+        storage.get_metadata(&key).unwrap().schema,
+        Some(schema.clone())
+    );
+
+    assert_eq!(storage.set_schema(&key, None).unwrap().schema, None);
+    write(serde_json::json!({"port": "eighty"})).unwrap();
+
+    write(serde_json::json!({"port": 80})).unwrap();
+    storage.set_schema(&key, Some(schema)).unwrap();
+    storage.delete(key.clone()).unwrap();
+    let (response, _) = write(serde_json::json!({"port": "eighty"})).unwrap();
+    assert_eq!(
+        response.metadata.schema, None,
+        "a recreated key has no schema"
+    );
+
+    let encoded = Key::new("encoded".to_string()).unwrap();
+    storage
+        .upsert(CreateKVRequest {
+            key: encoded.clone(),
+            value: "aGVsbG8=".into(),
+            value_type: ValueType::Text,
+            encoding: ValueEncoding::Base64,
+            ttl_seconds: None,
+        })
+        .unwrap();
+    assert_eq!(
+        storage
+            .set_schema(&encoded, Some(serde_json::json!({"type": "string"})))
+            .unwrap_err(),
+        StorageError::NotJson(encoded)
+    );
+}
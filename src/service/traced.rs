@@ -0,0 +1,468 @@
+use crate::app::models::{
+    CounterBounds, CounterOverflow, CreateKVRequest, KeyValueResponse, ListSide, Metadata,
+    ValueResponse,
+};
+use crate::service::{
+    CasOutcome, KeyFilter, MemoryUsage, OpResult, RestoreTarget, SearchPage, Storage, StorageError,
+    TxError, TxOp, UpsertOutcome, ValueSearch, VersionPrecondition, WriteOp, WriteOutcome,
+};
+use crate::types::Key;
+use chrono::{DateTime, Utc};
+use opentelemetry::global::{self, BoxedTracer};
+use opentelemetry::trace::{TraceContextExt, Tracer};
+use opentelemetry::{Array, KeyValue, StringValue, Value as AttributeValue};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::ops::Bound;
+use std::time::Duration;
+
+/// Instrumentation scope of every span the server creates.
+pub const TRACER_NAME: &str = "kv-rust";
+/// Span attribute holding the key an operation works on. Values are never
+/// recorded.
+pub const KEY_ATTRIBUTE: &str = "kv.key";
+/// Span attribute holding the keys of a multi-key operation.
+pub const KEYS_ATTRIBUTE: &str = "kv.keys";
+
+/// Most keys listed in `kv.keys`, so a 1000-entry batch does not produce a
+/// span larger than the request.
+const MAX_RECORDED_KEYS: usize = 32;
+
+/// Wraps every call into the backend in a `storage.<method>` span, a child of
+/// whatever span is current (the request span when called from a handler).
+/// Only keys are recorded, never values. The background expiry sweep is not
+/// traced, as it would add a root span every second.
+pub struct Traced<S> {
+    inner: S,
+    tracer: BoxedTracer,
+}
+
+impl<S: Storage> Traced<S> {
+    /// Uses the global tracer provider, so install it first.
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            tracer: global::tracer(TRACER_NAME),
+        }
+    }
+
+    fn span<T>(
+        &self,
+        name: &'static str,
+        attribute: Option<KeyValue>,
+        op: impl FnOnce() -> T,
+    ) -> T {
+        self.tracer.in_span(name, |cx| {
+            if let Some(attribute) = attribute {
+                cx.span().set_attribute(attribute);
+            }
+            op()
+        })
+    }
+}
+
+fn key_attribute(key: &Key) -> Option<KeyValue> {
+    Some(KeyValue::new(KEY_ATTRIBUTE, key.as_str().to_string()))
+}
+
+fn keys_attribute<'a>(keys: impl IntoIterator<Item = &'a Key>) -> Option<KeyValue> {
+    let keys: Vec<StringValue> = keys
+        .into_iter()
+        .take(MAX_RECORDED_KEYS)
+        .map(|key| key.as_str().to_string().into())
+        .collect();
+    Some(KeyValue::new(
+        KEYS_ATTRIBUTE,
+        AttributeValue::Array(Array::String(keys)),
+    ))
+}
+
+fn op_key(op: &WriteOp) -> &Key {
+    match op {
+        WriteOp::Upsert(body) | WriteOp::Insert(body) => &body.key,
+        WriteOp::Delete(key) => key,
+    }
+}
+
+fn tx_key(op: &TxOp) -> &Key {
+    match op {
+        TxOp::Set(body) | TxOp::Cas { body, .. } => &body.key,
+        TxOp::Delete(key) => key,
+        TxOp::Check { key, .. } => key,
+    }
+}
+
+impl<S: Storage> Storage for Traced<S> {
+    fn get(&self, key: Key) -> Result<ValueResponse, StorageError> {
+        self.span("storage.get", key_attribute(&key), || self.inner.get(key))
+    }
+
+    fn insert(&self, body: CreateKVRequest) -> Result<KeyValueResponse, StorageError> {
+        self.span("storage.insert", key_attribute(&body.key), || {
+            self.inner.insert(body)
+        })
+    }
+
+    fn upsert(
+        &self,
+        body: CreateKVRequest,
+    ) -> Result<(KeyValueResponse, UpsertOutcome), StorageError> {
+        self.span("storage.upsert", key_attribute(&body.key), || {
+            self.inner.upsert(body)
+        })
+    }
+
+    fn upsert_if_version(
+        &self,
+        body: CreateKVRequest,
+        expected: &VersionPrecondition,
+    ) -> Result<KeyValueResponse, StorageError> {
+        self.span(
+            "storage.upsert_if_version",
+            key_attribute(&body.key),
+            || self.inner.upsert_if_version(body, expected),
+        )
+    }
+
+    fn delete(&self, key: Key) -> Result<ValueResponse, StorageError> {
+        self.span("storage.delete", key_attribute(&key), || {
+            self.inner.delete(key)
+        })
+    }
+
+    fn list_keys(&self) -> Vec<Key> {
+        self.span("storage.list_keys", None, || self.inner.list_keys())
+    }
+
+    fn list_entries(&self, filter: &KeyFilter, limit: usize) -> Vec<KeyValueResponse> {
+        self.span("storage.list_entries", None, || {
+            self.inner.list_entries(filter, limit)
+        })
+    }
+
+    fn len(&self) -> usize {
+        self.span("storage.len", None, || self.inner.len())
+    }
+
+    fn set_ttl(&self, key: &Key, ttl: Duration) -> Result<Metadata, StorageError> {
+        self.span("storage.set_ttl", key_attribute(key), || {
+            self.inner.set_ttl(key, ttl)
+        })
+    }
+
+    fn set_schema(&self, key: &Key, schema: Option<Value>) -> Result<Metadata, StorageError> {
+        self.span("storage.set_schema", key_attribute(key), || {
+            self.inner.set_schema(key, schema)
+        })
+    }
+
+    fn is_empty(&self) -> bool {
+        self.span("storage.is_empty", None, || self.inner.is_empty())
+    }
+
+    fn delete_silent(&self, key: Key) -> Result<(), StorageError> {
+        self.span("storage.delete_silent", key_attribute(&key), || {
+            self.inner.delete_silent(key)
+        })
+    }
+
+    fn delete_if(&self, key: Key, expected: &str) -> Result<ValueResponse, StorageError> {
+        self.span("storage.delete_if", key_attribute(&key), || {
+            self.inner.delete_if(key, expected)
+        })
+    }
+
+    fn upsert_if_unmodified(
+        &self,
+        body: CreateKVRequest,
+        since: DateTime<Utc>,
+    ) -> Result<(KeyValueResponse, UpsertOutcome), StorageError> {
+        self.span(
+            "storage.upsert_if_unmodified",
+            key_attribute(&body.key),
+            || self.inner.upsert_if_unmodified(body, since),
+        )
+    }
+
+    fn delete_if_unmodified(
+        &self,
+        key: Key,
+        since: DateTime<Utc>,
+    ) -> Result<ValueResponse, StorageError> {
+        self.span("storage.delete_if_unmodified", key_attribute(&key), || {
+            self.inner.delete_if_unmodified(key, since)
+        })
+    }
+
+    fn history(&self, key: Key, limit: usize) -> Result<Vec<ValueResponse>, StorageError> {
+        self.span("storage.history", key_attribute(&key), || {
+            self.inner.history(key, limit)
+        })
+    }
+
+    fn append(&self, key: Key, suffix: &str) -> Result<(Metadata, UpsertOutcome), StorageError> {
+        self.span("storage.append", key_attribute(&key), || {
+            self.inner.append(key, suffix)
+        })
+    }
+
+    fn compare_and_swap(
+        &self,
+        expected: Option<&Value>,
+        body: CreateKVRequest,
+    ) -> Result<CasOutcome, StorageError> {
+        self.span("storage.compare_and_swap", key_attribute(&body.key), || {
+            self.inner.compare_and_swap(expected, body)
+        })
+    }
+
+    fn merge_patch(&self, key: Key, patch: &Value) -> Result<KeyValueResponse, StorageError> {
+        self.span("storage.merge_patch", key_attribute(&key), || {
+            self.inner.merge_patch(key, patch)
+        })
+    }
+
+    fn get_fields(&self, key: &Key) -> Result<BTreeMap<String, String>, StorageError> {
+        self.span("storage.get_fields", key_attribute(key), || {
+            self.inner.get_fields(key)
+        })
+    }
+
+    fn get_field(&self, key: &Key, field: &Key) -> Result<String, StorageError> {
+        self.span("storage.get_field", key_attribute(key), || {
+            self.inner.get_field(key, field)
+        })
+    }
+
+    fn set_field(
+        &self,
+        key: Key,
+        field: &Key,
+        value: &str,
+    ) -> Result<(Metadata, UpsertOutcome), StorageError> {
+        self.span("storage.set_field", key_attribute(&key), || {
+            self.inner.set_field(key, field, value)
+        })
+    }
+
+    fn delete_field(&self, key: Key, field: &Key) -> Result<(), StorageError> {
+        self.span("storage.delete_field", key_attribute(&key), || {
+            self.inner.delete_field(key, field)
+        })
+    }
+
+    fn list_push(
+        &self,
+        key: Key,
+        side: ListSide,
+        value: &str,
+    ) -> Result<(Metadata, usize, UpsertOutcome), StorageError> {
+        self.span("storage.list_push", key_attribute(&key), || {
+            self.inner.list_push(key, side, value)
+        })
+    }
+
+    fn list_pop(&self, key: Key, side: ListSide) -> Result<(String, usize), StorageError> {
+        self.span("storage.list_pop", key_attribute(&key), || {
+            self.inner.list_pop(key, side)
+        })
+    }
+
+    fn add_member(&self, key: Key, member: &str) -> Result<(Metadata, bool, usize), StorageError> {
+        self.span("storage.add_member", key_attribute(&key), || {
+            self.inner.add_member(key, member)
+        })
+    }
+
+    fn remove_member(&self, key: Key, member: &str) -> Result<(bool, usize), StorageError> {
+        self.span("storage.remove_member", key_attribute(&key), || {
+            self.inner.remove_member(key, member)
+        })
+    }
+
+    fn members(&self, key: &Key) -> Result<Vec<String>, StorageError> {
+        self.span("storage.members", key_attribute(key), || {
+            self.inner.members(key)
+        })
+    }
+
+    fn is_member(&self, key: &Key, member: &str) -> Result<bool, StorageError> {
+        self.span("storage.is_member", key_attribute(key), || {
+            self.inner.is_member(key, member)
+        })
+    }
+
+    fn create_counter(
+        &self,
+        key: Key,
+        bounds: CounterBounds,
+    ) -> Result<KeyValueResponse, StorageError> {
+        self.span("storage.create_counter", key_attribute(&key), || {
+            self.inner.create_counter(key, bounds)
+        })
+    }
+
+    fn counter_add(
+        &self,
+        key: Key,
+        delta: i64,
+        overflow: CounterOverflow,
+    ) -> Result<(Metadata, i64, bool), StorageError> {
+        self.span("storage.counter_add", key_attribute(&key), || {
+            self.inner.counter_add(key, delta, overflow)
+        })
+    }
+
+    fn counter_reset(&self, key: Key) -> Result<(Metadata, i64), StorageError> {
+        self.span("storage.counter_reset", key_attribute(&key), || {
+            self.inner.counter_reset(key)
+        })
+    }
+
+    fn list_slice(
+        &self,
+        key: &Key,
+        start: i64,
+        end: i64,
+    ) -> Result<(Vec<String>, usize), StorageError> {
+        self.span("storage.list_slice", key_attribute(key), || {
+            self.inner.list_slice(key, start, end)
+        })
+    }
+
+    fn restore(&self, key: Key, target: RestoreTarget) -> Result<KeyValueResponse, StorageError> {
+        self.span("storage.restore", key_attribute(&key), || {
+            self.inner.restore(key, target)
+        })
+    }
+
+    fn count_prefix(&self, prefix: &str) -> usize {
+        self.span("storage.count_prefix", None, || {
+            self.inner.count_prefix(prefix)
+        })
+    }
+
+    fn scan_keys(&self, filter: &KeyFilter) -> Vec<Key> {
+        self.span("storage.scan_keys", None, || self.inner.scan_keys(filter))
+    }
+
+    fn list_range(&self, start: Bound<&str>, end: Bound<&str>, limit: usize) -> Vec<Key> {
+        self.span("storage.list_range", None, || {
+            self.inner.list_range(start, end, limit)
+        })
+    }
+
+    fn list_keys_after(&self, cursor: Option<&str>, limit: usize) -> Vec<Key> {
+        self.span("storage.list_keys_after", None, || {
+            self.inner.list_keys_after(cursor, limit)
+        })
+    }
+
+    fn search_values(&self, search: &ValueSearch, after: Option<&str>, limit: usize) -> SearchPage {
+        self.span("storage.search_values", None, || {
+            self.inner.search_values(search, after, limit)
+        })
+    }
+
+    fn exists(&self, key: &Key) -> bool {
+        self.span("storage.exists", key_attribute(key), || {
+            self.inner.exists(key)
+        })
+    }
+
+    fn random_entry(&self, prefix: Option<&str>) -> Option<KeyValueResponse> {
+        self.span("storage.random_entry", None, || {
+            self.inner.random_entry(prefix)
+        })
+    }
+
+    fn largest_entries(&self, limit: usize) -> Vec<(Key, Metadata)> {
+        self.span("storage.largest_entries", None, || {
+            self.inner.largest_entries(limit)
+        })
+    }
+
+    fn get_metadata(&self, key: &Key) -> Result<Metadata, StorageError> {
+        self.span("storage.get_metadata", key_attribute(key), || {
+            self.inner.get_metadata(key)
+        })
+    }
+
+    fn get_ttl(&self, key: &Key) -> Result<Option<Duration>, StorageError> {
+        self.span("storage.get_ttl", key_attribute(key), || {
+            self.inner.get_ttl(key)
+        })
+    }
+
+    fn purge_expired(&self) -> usize {
+        self.inner.purge_expired()
+    }
+
+    fn clear(&self) -> usize {
+        self.span("storage.clear", None, || self.inner.clear())
+    }
+
+    fn memory_usage(&self) -> Option<MemoryUsage> {
+        self.inner.memory_usage()
+    }
+
+    fn negative_cache_hits(&self) -> Option<u64> {
+        self.inner.negative_cache_hits()
+    }
+
+    fn ping(&self) -> Result<(), StorageError> {
+        self.span("storage.ping", None, || self.inner.ping())
+    }
+
+    fn get_many(&self, keys: &[Key]) -> Vec<Result<ValueResponse, StorageError>> {
+        self.span("storage.get_many", keys_attribute(keys), || {
+            self.inner.get_many(keys)
+        })
+    }
+
+    fn get_stale(&self, key: Key) -> Result<ValueResponse, StorageError> {
+        self.span("storage.get_stale", key_attribute(&key), || {
+            self.inner.get_stale(key)
+        })
+    }
+
+    fn upsert_many(
+        &self,
+        bodies: Vec<CreateKVRequest>,
+    ) -> Vec<Result<(KeyValueResponse, UpsertOutcome), StorageError>> {
+        let keys = keys_attribute(bodies.iter().map(|body| &body.key));
+        self.span("storage.upsert_many", keys, || {
+            self.inner.upsert_many(bodies)
+        })
+    }
+
+    fn delete_many(&self, keys: Vec<Key>) -> Vec<Result<ValueResponse, StorageError>> {
+        self.span("storage.delete_many", keys_attribute(&keys), || {
+            self.inner.delete_many(keys)
+        })
+    }
+
+    fn delete_many_strict(
+        &self,
+        keys: Vec<Key>,
+    ) -> Result<Vec<Result<ValueResponse, StorageError>>, StorageError> {
+        self.span("storage.delete_many_strict", keys_attribute(&keys), || {
+            self.inner.delete_many_strict(keys)
+        })
+    }
+
+    fn batch_apply(&self, ops: Vec<WriteOp>) -> Vec<Result<WriteOutcome, StorageError>> {
+        let keys = keys_attribute(ops.iter().map(op_key));
+        self.span("storage.batch_apply", keys, || self.inner.batch_apply(ops))
+    }
+
+    fn batch_plan(&self, ops: Vec<WriteOp>) -> Vec<Result<WriteOutcome, StorageError>> {
+        let keys = keys_attribute(ops.iter().map(op_key));
+        self.span("storage.batch_plan", keys, || self.inner.batch_plan(ops))
+    }
+
+    fn transaction(&self, ops: Vec<TxOp>) -> Result<Vec<OpResult>, TxError> {
+        let keys = keys_attribute(ops.iter().map(tx_key));
+        self.span("storage.transaction", keys, || self.inner.transaction(ops))
+    }
+}
@@ -0,0 +1,646 @@
+use crate::app::models::{
+    CounterBounds, CounterOverflow, CreateKVRequest, KeyValueResponse, ListSide, Metadata,
+    StoredValue, ValueEncoding, ValueResponse,
+};
+use crate::service::{
+    CasOutcome, KeyFilter, MemoryUsage, OpResult, RestoreTarget, SearchPage, Storage, StorageError,
+    TxError, TxOp, UpsertOutcome, ValueSearch, VersionPrecondition, WriteOp, WriteOutcome,
+};
+use crate::types::Key;
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::ops::Bound;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Why a transformer refused a value. The message is returned to the client
+/// as is.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransformError(pub String);
+
+impl fmt::Display for TransformError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for TransformError {}
+
+/// Rewrites or rejects a text value before it is stored.
+pub trait ValueTransformer: Send + Sync {
+    fn transform(&self, key: &Key, value: String) -> Result<String, TransformError>;
+}
+
+/// Strips leading and trailing whitespace.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Trim;
+
+impl ValueTransformer for Trim {
+    fn transform(&self, _key: &Key, value: String) -> Result<String, TransformError> {
+        let trimmed = value.trim();
+        if trimmed.len() == value.len() {
+            Ok(value)
+        } else {
+            Ok(trimmed.to_string())
+        }
+    }
+}
+
+/// Rejects values holding control characters other than tab, line feed and
+/// carriage return.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RejectControlCharacters;
+
+impl ValueTransformer for RejectControlCharacters {
+    fn transform(&self, _key: &Key, value: String) -> Result<String, TransformError> {
+        match value
+            .char_indices()
+            .find(|(_, c)| c.is_control() && !matches!(c, '\t' | '\n' | '\r'))
+        {
+            Some((at, c)) => Err(TransformError(format!(
+                "The value contains the control character U+{:04X} at byte {}",
+                c as u32, at
+            ))),
+            None => Ok(value),
+        }
+    }
+}
+
+/// Transformers applied one after the other, each to the previous one's
+/// output. The first rejection stops the chain.
+#[derive(Clone, Default)]
+pub struct TransformChain(Vec<Arc<dyn ValueTransformer>>);
+
+impl TransformChain {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with(mut self, transformer: impl ValueTransformer + 'static) -> Self {
+        self.0.push(Arc::new(transformer));
+        self
+    }
+
+    /// Builds a chain of built-in transformers from their names, in order:
+    /// `trim` and `reject-control-characters`.
+    pub fn from_names<S: AsRef<str>>(names: &[S]) -> Result<Self, String> {
+        names
+            .iter()
+            .try_fold(Self::new(), |chain, name| match name.as_ref().trim() {
+                "trim" => Ok(chain.with(Trim)),
+                "reject-control-characters" => Ok(chain.with(RejectControlCharacters)),
+                other => Err(format!(
+                    "Unknown value transform '{}' (expected 'trim' or 'reject-control-characters')",
+                    other
+                )),
+            })
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl ValueTransformer for TransformChain {
+    fn transform(&self, key: &Key, value: String) -> Result<String, TransformError> {
+        self.0.iter().try_fold(value, |value, transformer| {
+            transformer.transform(key, value)
+        })
+    }
+}
+
+impl fmt::Debug for TransformChain {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "TransformChain({} transformers)", self.0.len())
+    }
+}
+
+/// Runs every value written through `insert`, the upserts, compare-and-swap,
+/// batches and transactions through a [`TransformChain`] before handing it
+/// to the wrapped backend, so the stored (and returned) value is the
+/// transformed one. Only text values are transformed: structured JSON,
+/// base64 text, blobs, and hash fields, list elements and set members pass
+/// through untouched. A rejected value fails with `TransformFailed`, and so
+/// does one the chain leaves empty.
+pub struct Transforming<S> {
+    inner: S,
+    chain: TransformChain,
+}
+
+impl<S: Storage> Transforming<S> {
+    pub fn new(inner: S, chain: TransformChain) -> Self {
+        Self { inner, chain }
+    }
+
+    fn transform(&self, mut body: CreateKVRequest) -> Result<CreateKVRequest, StorageError> {
+        if body.encoding != ValueEncoding::Utf8 {
+            return Ok(body);
+        }
+        if let StoredValue::Json(Value::String(text)) = &mut body.value {
+            let failed = |message: String| StorageError::TransformFailed {
+                key: body.key.clone(),
+                message,
+            };
+            let transformed = self
+                .chain
+                .transform(&body.key, std::mem::take(text))
+                .map_err(|err| failed(err.0))?;
+            if transformed.is_empty() {
+                return Err(failed("The transformed value is empty".to_string()));
+            }
+            *text = transformed;
+        }
+        Ok(body)
+    }
+
+    fn transform_op(&self, op: WriteOp) -> Result<WriteOp, StorageError> {
+        Ok(match op {
+            WriteOp::Upsert(body) => WriteOp::Upsert(self.transform(body)?),
+            WriteOp::Insert(body) => WriteOp::Insert(self.transform(body)?),
+            WriteOp::Delete(key) => WriteOp::Delete(key),
+        })
+    }
+
+    /// Transforms every item and runs the ones that passed through `run`,
+    /// slotting the rejections back in at their positions.
+    fn each<T, R>(
+        &self,
+        items: Vec<T>,
+        transform: impl Fn(T) -> Result<T, StorageError>,
+        run: impl FnOnce(Vec<T>) -> Vec<Result<R, StorageError>>,
+    ) -> Vec<Result<R, StorageError>> {
+        let total = items.len();
+        let mut passed = Vec::with_capacity(total);
+        let mut rejected = Vec::new();
+        for (index, item) in items.into_iter().enumerate() {
+            match transform(item) {
+                Ok(item) => passed.push(item),
+                Err(storage_error) => rejected.push((index, storage_error)),
+            }
+        }
+        if rejected.is_empty() {
+            return run(passed);
+        }
+        let mut results = run(passed).into_iter();
+        let mut rejected = rejected.into_iter().peekable();
+        (0..total)
+            .map(|index| match rejected.next_if(|(at, _)| *at == index) {
+                Some((_, storage_error)) => Err(storage_error),
+                None => results.next().unwrap_or_else(|| {
+                    Err(StorageError::Unavailable(
+                        "the backend returned fewer results than entries".to_string(),
+                    ))
+                }),
+            })
+            .collect()
+    }
+}
+
+impl<S: Storage> Storage for Transforming<S> {
+    fn get(&self, key: Key) -> Result<ValueResponse, StorageError> {
+        self.inner.get(key)
+    }
+
+    fn insert(&self, body: CreateKVRequest) -> Result<KeyValueResponse, StorageError> {
+        self.inner.insert(self.transform(body)?)
+    }
+
+    fn upsert(
+        &self,
+        body: CreateKVRequest,
+    ) -> Result<(KeyValueResponse, UpsertOutcome), StorageError> {
+        self.inner.upsert(self.transform(body)?)
+    }
+
+    fn upsert_if_version(
+        &self,
+        body: CreateKVRequest,
+        expected: &VersionPrecondition,
+    ) -> Result<KeyValueResponse, StorageError> {
+        self.inner
+            .upsert_if_version(self.transform(body)?, expected)
+    }
+
+    fn delete(&self, key: Key) -> Result<ValueResponse, StorageError> {
+        self.inner.delete(key)
+    }
+
+    fn list_keys(&self) -> Vec<Key> {
+        self.inner.list_keys()
+    }
+
+    fn list_entries(&self, filter: &KeyFilter, limit: usize) -> Vec<KeyValueResponse> {
+        self.inner.list_entries(filter, limit)
+    }
+
+    fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    fn set_ttl(&self, key: &Key, ttl: Duration) -> Result<Metadata, StorageError> {
+        self.inner.set_ttl(key, ttl)
+    }
+
+    fn set_schema(&self, key: &Key, schema: Option<Value>) -> Result<Metadata, StorageError> {
+        self.inner.set_schema(key, schema)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    fn delete_silent(&self, key: Key) -> Result<(), StorageError> {
+        self.inner.delete_silent(key)
+    }
+
+    fn delete_if(&self, key: Key, expected: &str) -> Result<ValueResponse, StorageError> {
+        self.inner.delete_if(key, expected)
+    }
+
+    fn upsert_if_unmodified(
+        &self,
+        body: CreateKVRequest,
+        since: DateTime<Utc>,
+    ) -> Result<(KeyValueResponse, UpsertOutcome), StorageError> {
+        self.inner
+            .upsert_if_unmodified(self.transform(body)?, since)
+    }
+
+    fn delete_if_unmodified(
+        &self,
+        key: Key,
+        since: DateTime<Utc>,
+    ) -> Result<ValueResponse, StorageError> {
+        self.inner.delete_if_unmodified(key, since)
+    }
+
+    fn history(&self, key: Key, limit: usize) -> Result<Vec<ValueResponse>, StorageError> {
+        self.inner.history(key, limit)
+    }
+
+    fn append(&self, key: Key, suffix: &str) -> Result<(Metadata, UpsertOutcome), StorageError> {
+        self.inner.append(key, suffix)
+    }
+
+    fn compare_and_swap(
+        &self,
+        expected: Option<&Value>,
+        body: CreateKVRequest,
+    ) -> Result<CasOutcome, StorageError> {
+        self.inner.compare_and_swap(expected, self.transform(body)?)
+    }
+
+    fn merge_patch(&self, key: Key, patch: &Value) -> Result<KeyValueResponse, StorageError> {
+        self.inner.merge_patch(key, patch)
+    }
+
+    fn get_fields(&self, key: &Key) -> Result<BTreeMap<String, String>, StorageError> {
+        self.inner.get_fields(key)
+    }
+
+    fn get_field(&self, key: &Key, field: &Key) -> Result<String, StorageError> {
+        self.inner.get_field(key, field)
+    }
+
+    fn set_field(
+        &self,
+        key: Key,
+        field: &Key,
+        value: &str,
+    ) -> Result<(Metadata, UpsertOutcome), StorageError> {
+        self.inner.set_field(key, field, value)
+    }
+
+    fn delete_field(&self, key: Key, field: &Key) -> Result<(), StorageError> {
+        self.inner.delete_field(key, field)
+    }
+
+    fn list_push(
+        &self,
+        key: Key,
+        side: ListSide,
+        value: &str,
+    ) -> Result<(Metadata, usize, UpsertOutcome), StorageError> {
+        self.inner.list_push(key, side, value)
+    }
+
+    fn list_pop(&self, key: Key, side: ListSide) -> Result<(String, usize), StorageError> {
+        self.inner.list_pop(key, side)
+    }
+
+    fn add_member(&self, key: Key, member: &str) -> Result<(Metadata, bool, usize), StorageError> {
+        self.inner.add_member(key, member)
+    }
+
+    fn remove_member(&self, key: Key, member: &str) -> Result<(bool, usize), StorageError> {
+        self.inner.remove_member(key, member)
+    }
+
+    fn members(&self, key: &Key) -> Result<Vec<String>, StorageError> {
+        self.inner.members(key)
+    }
+
+    fn is_member(&self, key: &Key, member: &str) -> Result<bool, StorageError> {
+        self.inner.is_member(key, member)
+    }
+
+    fn create_counter(
+        &self,
+        key: Key,
+        bounds: CounterBounds,
+    ) -> Result<KeyValueResponse, StorageError> {
+        self.inner.create_counter(key, bounds)
+    }
+
+    fn counter_add(
+        &self,
+        key: Key,
+        delta: i64,
+        overflow: CounterOverflow,
+    ) -> Result<(Metadata, i64, bool), StorageError> {
+        self.inner.counter_add(key, delta, overflow)
+    }
+
+    fn counter_reset(&self, key: Key) -> Result<(Metadata, i64), StorageError> {
+        self.inner.counter_reset(key)
+    }
+
+    fn list_slice(
+        &self,
+        key: &Key,
+        start: i64,
+        end: i64,
+    ) -> Result<(Vec<String>, usize), StorageError> {
+        self.inner.list_slice(key, start, end)
+    }
+
+    fn restore(&self, key: Key, target: RestoreTarget) -> Result<KeyValueResponse, StorageError> {
+        self.inner.restore(key, target)
+    }
+
+    fn count_prefix(&self, prefix: &str) -> usize {
+        self.inner.count_prefix(prefix)
+    }
+
+    fn scan_keys(&self, filter: &KeyFilter) -> Vec<Key> {
+        self.inner.scan_keys(filter)
+    }
+
+    fn list_range(&self, start: Bound<&str>, end: Bound<&str>, limit: usize) -> Vec<Key> {
+        self.inner.list_range(start, end, limit)
+    }
+
+    fn list_keys_after(&self, cursor: Option<&str>, limit: usize) -> Vec<Key> {
+        self.inner.list_keys_after(cursor, limit)
+    }
+
+    fn search_values(&self, search: &ValueSearch, after: Option<&str>, limit: usize) -> SearchPage {
+        self.inner.search_values(search, after, limit)
+    }
+
+    fn exists(&self, key: &Key) -> bool {
+        self.inner.exists(key)
+    }
+
+    fn random_entry(&self, prefix: Option<&str>) -> Option<KeyValueResponse> {
+        self.inner.random_entry(prefix)
+    }
+
+    fn largest_entries(&self, limit: usize) -> Vec<(Key, Metadata)> {
+        self.inner.largest_entries(limit)
+    }
+
+    fn get_metadata(&self, key: &Key) -> Result<Metadata, StorageError> {
+        self.inner.get_metadata(key)
+    }
+
+    fn get_ttl(&self, key: &Key) -> Result<Option<Duration>, StorageError> {
+        self.inner.get_ttl(key)
+    }
+
+    fn purge_expired(&self) -> usize {
+        self.inner.purge_expired()
+    }
+
+    fn clear(&self) -> usize {
+        self.inner.clear()
+    }
+
+    fn memory_usage(&self) -> Option<MemoryUsage> {
+        self.inner.memory_usage()
+    }
+
+    fn negative_cache_hits(&self) -> Option<u64> {
+        self.inner.negative_cache_hits()
+    }
+
+    fn ping(&self) -> Result<(), StorageError> {
+        self.inner.ping()
+    }
+
+    fn get_many(&self, keys: &[Key]) -> Vec<Result<ValueResponse, StorageError>> {
+        self.inner.get_many(keys)
+    }
+
+    fn get_stale(&self, key: Key) -> Result<ValueResponse, StorageError> {
+        self.inner.get_stale(key)
+    }
+
+    fn upsert_many(
+        &self,
+        bodies: Vec<CreateKVRequest>,
+    ) -> Vec<Result<(KeyValueResponse, UpsertOutcome), StorageError>> {
+        self.each(
+            bodies,
+            |body| self.transform(body),
+            |bodies| self.inner.upsert_many(bodies),
+        )
+    }
+
+    fn delete_many(&self, keys: Vec<Key>) -> Vec<Result<ValueResponse, StorageError>> {
+        self.inner.delete_many(keys)
+    }
+
+    fn delete_many_strict(
+        &self,
+        keys: Vec<Key>,
+    ) -> Result<Vec<Result<ValueResponse, StorageError>>, StorageError> {
+        self.inner.delete_many_strict(keys)
+    }
+
+    fn batch_apply(&self, ops: Vec<WriteOp>) -> Vec<Result<WriteOutcome, StorageError>> {
+        self.each(
+            ops,
+            |op| self.transform_op(op),
+            |ops| self.inner.batch_apply(ops),
+        )
+    }
+
+    fn batch_plan(&self, ops: Vec<WriteOp>) -> Vec<Result<WriteOutcome, StorageError>> {
+        self.each(
+            ops,
+            |op| self.transform_op(op),
+            |ops| self.inner.batch_plan(ops),
+        )
+    }
+
+    /// A rejected value fails the whole transaction, like any other failed
+    /// op.
+    fn transaction(&self, ops: Vec<TxOp>) -> Result<Vec<OpResult>, TxError> {
+        let ops = ops
+            .into_iter()
+            .enumerate()
+            .map(|(index, op)| {
+                let failed = |error| TxError::Failed { index, error };
+                Ok(match op {
+                    TxOp::Set(body) => TxOp::Set(self.transform(body).map_err(failed)?),
+                    TxOp::Cas { expected, body } => TxOp::Cas {
+                        expected,
+                        body: self.transform(body).map_err(failed)?,
+                    },
+                    op @ (TxOp::Delete(_) | TxOp::Check { .. }) => op,
+                })
+            })
+            .collect::<Result<Vec<_>, TxError>>()?;
+        self.inner.transaction(ops)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::models::ValueType;
+    use crate::service::InMemoryStorage;
+    use serde_json::json;
+
+    fn key(name: &str) -> Key {
+        Key::new(name.to_string()).unwrap()
+    }
+
+    fn request(name: &str, value: Value) -> CreateKVRequest {
+        CreateKVRequest {
+            key: key(name),
+            value: value.into(),
+            value_type: ValueType::Text,
+            encoding: ValueEncoding::Utf8,
+            ttl_seconds: None,
+        }
+    }
+
+    fn transforming() -> Transforming<InMemoryStorage> {
+        let chain = TransformChain::from_names(&["trim", "reject-control-characters"]).unwrap();
+        Transforming::new(InMemoryStorage::new(), chain)
+    }
+
+    #[test]
+    fn test_trim_strips_surrounding_whitespace() {
+        let k = key("k");
+        assert_eq!(Trim.transform(&k, "  a b \r\n".to_string()).unwrap(), "a b");
+        assert_eq!(Trim.transform(&k, "plain".to_string()).unwrap(), "plain");
+        assert_eq!(Trim.transform(&k, "\u{3000}x\t".to_string()).unwrap(), "x");
+    }
+
+    #[test]
+    fn test_reject_control_characters_allows_line_breaks_and_tabs() {
+        let k = key("k");
+        let text = "line one\r\n\tline two".to_string();
+        assert_eq!(
+            RejectControlCharacters.transform(&k, text.clone()).unwrap(),
+            text
+        );
+        let err = RejectControlCharacters
+            .transform(&k, "bell\u{7}".to_string())
+            .unwrap_err();
+        assert_eq!(
+            err.0,
+            "The value contains the control character U+0007 at byte 4"
+        );
+        assert!(
+            RejectControlCharacters
+                .transform(&k, "nul\0".to_string())
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_chain_runs_in_order_and_rejects_unknown_names() {
+        let k = key("k");
+        let chain = TransformChain::from_names(&["trim", "reject-control-characters"]).unwrap();
+        assert_eq!(chain.len(), 2);
+        assert_eq!(chain.transform(&k, " ok \n".to_string()).unwrap(), "ok");
+        assert!(chain.transform(&k, " \u{1b}[0m ".to_string()).is_err());
+        assert!(TransformChain::new().is_empty());
+
+        let err = TransformChain::from_names(&["trim", "lowercase"]).unwrap_err();
+        assert!(err.contains("'lowercase'"));
+    }
+
+    #[test]
+    fn test_writes_store_the_transformed_value() {
+        let storage = transforming();
+        let (response, _) = storage.upsert(request("padded", json!("  hi  "))).unwrap();
+        assert_eq!(response.value, json!("hi"));
+        assert_eq!(storage.get(key("padded")).unwrap().value, json!("hi"));
+
+        storage.insert(request("inserted", json!("x\n"))).unwrap();
+        assert_eq!(storage.get(key("inserted")).unwrap().value, json!("x"));
+
+        storage
+            .upsert(request("structured", json!({"name": "  kept  "})))
+            .unwrap();
+        assert_eq!(
+            storage.get(key("structured")).unwrap().value,
+            json!({"name": "  kept  "})
+        );
+    }
+
+    #[test]
+    fn test_rejections_fail_only_their_own_entry() {
+        let storage = transforming();
+        let err = storage.insert(request("bad", json!("a\u{7}"))).unwrap_err();
+        assert_eq!(err.error_code(), "TRANSFORM_FAILED");
+        assert!(!storage.exists(&key("bad")));
+        assert_eq!(
+            storage
+                .insert(request("blank", json!("   ")))
+                .unwrap_err()
+                .error_code(),
+            "TRANSFORM_FAILED"
+        );
+
+        let results = storage.batch_apply(vec![
+            WriteOp::Upsert(request("first", json!(" one "))),
+            WriteOp::Upsert(request("second", json!("\u{0}"))),
+            WriteOp::Delete(key("missing")),
+            WriteOp::Insert(request("third", json!("three "))),
+        ]);
+        assert_eq!(results.len(), 4);
+        assert!(results[0].is_ok());
+        assert_eq!(
+            results[1].as_ref().unwrap_err().error_code(),
+            "TRANSFORM_FAILED"
+        );
+        assert_eq!(
+            results[2].as_ref().unwrap_err().error_code(),
+            "KEY_NOT_FOUND"
+        );
+        assert!(results[3].is_ok());
+        assert_eq!(storage.get(key("first")).unwrap().value, json!("one"));
+        assert_eq!(storage.get(key("third")).unwrap().value, json!("three"));
+
+        let err = storage
+            .transaction(vec![
+                TxOp::Set(request("tx-a", json!("a"))),
+                TxOp::Set(request("tx-b", json!("\u{7}"))),
+            ])
+            .unwrap_err();
+        assert_eq!(err.index(), Some(1));
+        assert!(!storage.exists(&key("tx-a")));
+    }
+}
@@ -0,0 +1,387 @@
+//! The checks every write runs before it reaches a backend. `POST /keys`,
+//! `PUT /keys/{key}`, batches, transactions and imports all go through
+//! [`write`] or [`entry`], so the same bad input is reported the same way
+//! whichever route it came in on.
+use crate::app::models::{
+    BatchUpsertEntry, CreateKVRequest, NO_EXPIRY_TTL_SECONDS, StoredValue, ValueEncoding, ValueType,
+};
+use crate::app::write_ops::MAX_TTL_SECONDS;
+use crate::types::{Key, key};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::sync::RwLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static REJECT_BLANK_VALUES: AtomicBool = AtomicBool::new(false);
+static VALUE_LIMITS_BY_PREFIX: RwLock<BTreeMap<String, usize>> = RwLock::new(BTreeMap::new());
+
+/// Makes [`value`] also reject string values made only of whitespace
+/// (`KV_REJECT_BLANK_VALUES`). Empty strings are always rejected.
+pub fn set_reject_blank_values(reject: bool) {
+    REJECT_BLANK_VALUES.store(reject, Ordering::Relaxed);
+}
+
+pub fn reject_blank_values() -> bool {
+    REJECT_BLANK_VALUES.load(Ordering::Relaxed)
+}
+
+/// Sets the value size limits for keys under a prefix
+/// (`KV_MAX_VALUE_BYTES_BY_PREFIX`). The prefixes are case-folded like keys,
+/// so call this after [`key::set_case_insensitive`].
+pub fn set_value_limits_by_prefix(limits: &BTreeMap<String, usize>) {
+    let folded = limits
+        .iter()
+        .map(|(prefix, bytes)| (key::fold_case(prefix).into_owned(), *bytes))
+        .collect();
+    *VALUE_LIMITS_BY_PREFIX
+        .write()
+        .unwrap_or_else(|poisoned| poisoned.into_inner()) = folded;
+}
+
+/// One field of a request that failed validation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationError {
+    /// The request field at fault: `key`, `value`, `ttl_seconds`, or the
+    /// name of the path parameter it came from.
+    pub field: String,
+    pub code: &'static str,
+    pub reason: String,
+}
+
+impl ValidationError {
+    pub fn new(field: &str, code: &'static str, reason: impl Into<String>) -> Self {
+        ValidationError {
+            field: field.to_string(),
+            code,
+            reason: reason.into(),
+        }
+    }
+}
+
+impl std::fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Invalid value for field '{}': {}",
+            self.field, self.reason
+        )
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+pub fn key(field: &str, raw: String) -> Result<Key, ValidationError> {
+    Key::new(raw).map_err(|err| ValidationError::new(field, "INVALID_KEY", err.to_string()))
+}
+
+/// Checks a whole create or replace: the value against its type, encoding
+/// and size limit, then the TTL. The key was checked when the request was
+/// parsed.
+pub fn write(request: &CreateKVRequest) -> Result<(), ValidationError> {
+    value(&request.value, request.value_type, request.encoding)?;
+    value_size(&request.key, &request.value)?;
+    ttl(request.ttl_seconds)
+}
+
+/// Checks one entry of a batch, transaction or import, reporting every
+/// field that is wrong rather than only the first.
+pub fn entry(entry: BatchUpsertEntry) -> Result<CreateKVRequest, Vec<ValidationError>> {
+    let mut errors = Vec::new();
+    let key = key("key", entry.key).map_err(|err| errors.push(err)).ok();
+    let stored = StoredValue::from(entry.value);
+    if let Err(err) = value(&stored, entry.value_type, entry.encoding) {
+        errors.push(err);
+    } else if let Some(key) = &key
+        && let Err(err) = value_size(key, &stored)
+    {
+        errors.push(err);
+    }
+    if let Err(err) = ttl(entry.ttl_seconds) {
+        errors.push(err);
+    }
+
+    match key {
+        Some(key) if errors.is_empty() => Ok(CreateKVRequest {
+            key,
+            value: stored,
+            value_type: entry.value_type,
+            encoding: entry.encoding,
+            ttl_seconds: entry.ttl_seconds,
+        }),
+        _ => Err(errors),
+    }
+}
+
+/// Checks the value against the limit of the longest configured prefix the
+/// key starts with. These limits only narrow `KV_MAX_VALUE_BYTES`, which the
+/// backend still enforces for every key.
+pub fn value_size(key: &Key, value: &StoredValue) -> Result<(), ValidationError> {
+    let limits = VALUE_LIMITS_BY_PREFIX
+        .read()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    let Some((prefix, limit_bytes)) = limits
+        .iter()
+        .filter(|(prefix, _)| key.as_str().starts_with(prefix.as_str()))
+        .max_by_key(|(prefix, _)| prefix.len())
+    else {
+        return Ok(());
+    };
+    let size_bytes = value.size_bytes();
+    if size_bytes <= *limit_bytes {
+        return Ok(());
+    }
+    Err(ValidationError::new(
+        "value",
+        "VALUE_TOO_LARGE",
+        format!(
+            "Value is {} bytes, over the limit of {} bytes for keys starting with '{}'",
+            size_bytes, limit_bytes, prefix
+        ),
+    ))
+}
+
+pub fn ttl(ttl_seconds: Option<i64>) -> Result<(), ValidationError> {
+    match ttl_seconds {
+        None | Some(NO_EXPIRY_TTL_SECONDS) => Ok(()),
+        Some(seconds) if seconds > 0 && seconds as u64 <= MAX_TTL_SECONDS => Ok(()),
+        Some(seconds) => Err(ValidationError::new(
+            "ttl_seconds",
+            "VALIDATION_ERROR",
+            format!(
+                "ttl_seconds must be {} (no expiry) or between 1 and {}, got {}",
+                NO_EXPIRY_TTL_SECONDS, MAX_TTL_SECONDS, seconds
+            ),
+        )),
+    }
+}
+
+pub fn not_empty(field: &str, text: &str, reject_blank: bool) -> Result<(), ValidationError> {
+    let reason = if text.is_empty() {
+        "Value must not be empty; delete the key instead"
+    } else if reject_blank && text.trim().is_empty() {
+        "Value must not be blank (whitespace only)"
+    } else {
+        return Ok(());
+    };
+    Err(ValidationError::new(field, "EMPTY_VALUE", reason))
+}
+
+pub fn value(
+    value: &StoredValue,
+    value_type: ValueType,
+    encoding: ValueEncoding,
+) -> Result<(), ValidationError> {
+    let value = match value {
+        StoredValue::Json(value) => value,
+        StoredValue::Binary { .. }
+        | StoredValue::Hash(_)
+        | StoredValue::List(_)
+        | StoredValue::Set(_) => {
+            return Ok(());
+        }
+    };
+    let text = match value {
+        Value::String(text) => text,
+        Value::Null => {
+            return Err(ValidationError::new(
+                "value",
+                "NULL_VALUE",
+                "Value must not be null; delete the key instead",
+            ));
+        }
+        _ if encoding == ValueEncoding::Base64 => {
+            return Err(ValidationError::new(
+                "value",
+                "INVALID_BASE64",
+                "Base64 values must be strings",
+            ));
+        }
+        _ if value_type == ValueType::Number && !value.is_number() => {
+            return Err(invalid_number());
+        }
+        _ if value_type == ValueType::Counter && !value.is_i64() => {
+            return Err(invalid_counter());
+        }
+        _ => return Ok(()),
+    };
+    not_empty("value", text, reject_blank_values())?;
+
+    let bytes = encoding.decode(text).map_err(|err| {
+        ValidationError::new(
+            "value",
+            "INVALID_BASE64",
+            format!("Value is not valid base64: {}", err),
+        )
+    })?;
+    match value_type {
+        ValueType::Text => Ok(()),
+        ValueType::Json => serde_json::from_slice::<Value>(&bytes)
+            .map(|_| ())
+            .map_err(|err| {
+                ValidationError::new(
+                    "value",
+                    "INVALID_JSON_VALUE",
+                    format!("Value is not valid JSON: {}", err),
+                )
+            }),
+        ValueType::Number => match serde_json::from_slice::<Value>(&bytes) {
+            Ok(parsed) if parsed.is_number() => Ok(()),
+            _ => Err(invalid_number()),
+        },
+        ValueType::Counter => match serde_json::from_slice::<Value>(&bytes) {
+            Ok(parsed) if parsed.is_i64() => Ok(()),
+            _ => Err(invalid_counter()),
+        },
+    }
+}
+
+fn invalid_counter() -> ValidationError {
+    ValidationError::new(
+        "value",
+        "INVALID_COUNTER_VALUE",
+        "Counter values must be integers between -2^63 and 2^63-1",
+    )
+}
+
+fn invalid_number() -> ValidationError {
+    ValidationError::new("value", "INVALID_NUMBER_VALUE", "Value is not a number")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_entry_reports_every_field() {
+        let raw: BatchUpsertEntry =
+            serde_json::from_value(json!({ "key": "bad key", "ttl_seconds": 0 })).unwrap();
+        let errors = entry(raw).unwrap_err();
+        let fields: Vec<(&str, &str)> = errors
+            .iter()
+            .map(|failure| (failure.field.as_str(), failure.code))
+            .collect();
+        assert_eq!(
+            fields,
+            vec![
+                ("key", "INVALID_KEY"),
+                ("value", "NULL_VALUE"),
+                ("ttl_seconds", "VALIDATION_ERROR")
+            ]
+        );
+
+        let raw: BatchUpsertEntry =
+            serde_json::from_value(json!({ "key": "ok", "value": 1 })).unwrap();
+        assert_eq!(entry(raw).unwrap().key.as_str(), "ok");
+    }
+
+    #[test]
+    fn test_key_names_the_field() {
+        let err = key("field", "bad key".to_string()).unwrap_err();
+        assert_eq!(err.code, "INVALID_KEY");
+        assert_eq!(
+            err.to_string(),
+            format!("Invalid value for field 'field': {}", err.reason)
+        );
+    }
+
+    #[test]
+    fn test_longest_prefix_value_limit_wins() {
+        set_value_limits_by_prefix(&BTreeMap::from([
+            ("limit-test-".to_string(), 4),
+            ("limit-test-big-".to_string(), 8),
+        ]));
+        let sized = |raw: &str, size: usize| {
+            let key = Key::new(raw.to_string()).unwrap();
+            value_size(&key, &json!("x".repeat(size)).into())
+        };
+
+        assert!(sized("limit-test-1", 4).is_ok());
+        let err = sized("limit-test-1", 5).unwrap_err();
+        assert_eq!(err.code, "VALUE_TOO_LARGE");
+        assert_eq!(
+            err.reason,
+            "Value is 5 bytes, over the limit of 4 bytes for keys starting with 'limit-test-'"
+        );
+        assert!(sized("limit-test-big-1", 8).is_ok());
+        assert!(sized("limit-test-big-1", 9).is_err());
+        assert!(sized("limit-other", 1000).is_ok());
+
+        let raw: BatchUpsertEntry =
+            serde_json::from_value(json!({ "key": "limit-test-2", "value": "12345" })).unwrap();
+        assert_eq!(entry(raw).unwrap_err()[0].code, "VALUE_TOO_LARGE");
+    }
+
+    #[test]
+    fn test_value_json() {
+        let utf8 = ValueEncoding::Utf8;
+        assert!(value(&json!("{\"a\": [1, 2]}").into(), ValueType::Json, utf8).is_ok());
+        assert!(value(&json!("\"plain string\"").into(), ValueType::Json, utf8).is_ok());
+        let err = value(&json!("{\"a\": ").into(), ValueType::Json, utf8).unwrap_err();
+        assert_eq!(err.code, "INVALID_JSON_VALUE");
+        assert!(value(&json!("{\"a\": ").into(), ValueType::Text, utf8).is_ok());
+    }
+
+    #[test]
+    fn test_value_number() {
+        let utf8 = ValueEncoding::Utf8;
+        for raw in [json!(42), json!(-1.5), json!("7"), json!("2e3")] {
+            assert!(value(&raw.into(), ValueType::Number, utf8).is_ok());
+        }
+        for raw in [json!("abc"), json!("NaN"), json!(true), json!([1])] {
+            let err = value(&raw.into(), ValueType::Number, utf8).unwrap_err();
+            assert_eq!(err.code, "INVALID_NUMBER_VALUE");
+        }
+    }
+
+    #[test]
+    fn test_value_base64() {
+        let base64 = ValueEncoding::Base64;
+        assert!(value(&json!("AAH/").into(), ValueType::Text, base64).is_ok());
+        let err = value(&json!("").into(), ValueType::Text, base64).unwrap_err();
+        assert_eq!(err.code, "EMPTY_VALUE");
+        let err = value(&json!("not base64!").into(), ValueType::Text, base64).unwrap_err();
+        assert_eq!(err.code, "INVALID_BASE64");
+        assert!(value(&json!("eyJhIjogMX0=").into(), ValueType::Json, base64).is_ok());
+        let err = value(&json!("AAH/").into(), ValueType::Json, base64).unwrap_err();
+        assert_eq!(err.code, "INVALID_JSON_VALUE");
+    }
+
+    #[test]
+    fn test_not_empty() {
+        assert_eq!(
+            not_empty("value", "", false).unwrap_err().code,
+            "EMPTY_VALUE"
+        );
+        assert!(not_empty("value", " \t\n", false).is_ok());
+        assert_eq!(
+            not_empty("value", " \t\n", true).unwrap_err().code,
+            "EMPTY_VALUE"
+        );
+        assert!(not_empty("value", " x ", true).is_ok());
+        let err = value(&json!("").into(), ValueType::Json, ValueEncoding::Utf8);
+        assert_eq!(err.unwrap_err().code, "EMPTY_VALUE");
+    }
+
+    #[test]
+    fn test_structured_and_null_values() {
+        let utf8 = ValueEncoding::Utf8;
+        for raw in [
+            json!({"a": [1, 2]}),
+            json!([1, "two"]),
+            json!(3),
+            json!(false),
+        ] {
+            assert!(value(&raw.clone().into(), ValueType::Text, utf8).is_ok());
+            assert!(value(&raw.clone().into(), ValueType::Json, utf8).is_ok());
+        }
+        let err = value(&json!(null).into(), ValueType::Text, utf8).unwrap_err();
+        assert_eq!(err.code, "NULL_VALUE");
+        let err = value(
+            &json!({"a": 1}).into(),
+            ValueType::Text,
+            ValueEncoding::Base64,
+        );
+        assert_eq!(err.unwrap_err().code, "INVALID_BASE64");
+    }
+}
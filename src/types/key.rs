@@ -1,8 +1,10 @@
 use serde::{Deserialize, Serialize};
+use std::borrow::{Borrow, Cow};
 use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
 use utoipa::ToSchema;
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize, ToSchema)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize, ToSchema)]
 #[serde(try_from = "String", into = "String")]
 #[schema(example = "user-123", pattern = "^[a-zA-Z0-9_-]{1,255}$")]
 pub struct Key(String);
@@ -17,8 +19,42 @@ pub enum KeyError {
 
 const MAX_LENGTH: usize = 255;
 
+static CASE_INSENSITIVE: AtomicBool = AtomicBool::new(false);
+
+/// Makes `Key::new` lowercase every key before validating it
+/// (`KV_CASE_INSENSITIVE`), so `MyKey` and `mykey` name the same entry.
+/// Only the lowercased form is stored; the original casing is lost.
+pub fn set_case_insensitive(enabled: bool) {
+    CASE_INSENSITIVE.store(enabled, Ordering::Relaxed);
+}
+
+pub fn case_insensitive() -> bool {
+    CASE_INSENSITIVE.load(Ordering::Relaxed)
+}
+
+/// Folds text compared against keys (prefixes, patterns, range bounds) the
+/// way `Key::new` folds keys, so it still matches in case-insensitive mode.
+pub fn fold_case(text: &str) -> Cow<'_, str> {
+    if case_insensitive() && text.chars().any(char::is_uppercase) {
+        Cow::Owned(text.to_lowercase())
+    } else {
+        Cow::Borrowed(text)
+    }
+}
+
 impl Key {
     pub fn new(s: String) -> Result<Self, KeyError> {
+        Self::parse(s, case_insensitive())
+    }
+
+    /// The length limit applies to the lowercased form, which can be longer
+    /// than the original for a few non-ASCII letters.
+    fn parse(s: String, fold_case: bool) -> Result<Self, KeyError> {
+        let s = if fold_case && s.chars().any(char::is_uppercase) {
+            s.to_lowercase()
+        } else {
+            s
+        };
         if s.is_empty() {
             return Err(KeyError::Empty);
         }
@@ -41,6 +77,12 @@ impl Key {
     }
 }
 
+impl Borrow<str> for Key {
+    fn borrow(&self) -> &str {
+        &self.0
+    }
+}
+
 impl TryFrom<String> for Key {
     type Error = KeyError;
 
@@ -375,6 +417,25 @@ mod tests {
         assert!(Key::new("---".to_string()).is_ok());
     }
 
+    #[test]
+    fn test_case_folding() {
+        let key = Key::parse("MyKey-ÄB_1".to_string(), true).unwrap();
+        assert_eq!(key.as_str(), "mykey-äb_1");
+        assert_eq!(key, Key::parse("mykey-äb_1".to_string(), true).unwrap());
+        assert_eq!(
+            Key::parse("MyKey".to_string(), false).unwrap().as_str(),
+            "MyKey"
+        );
+        assert!(matches!(
+            Key::parse("My Key".to_string(), true),
+            Err(KeyError::InvalidCharacters)
+        ));
+        assert!(matches!(
+            Key::parse("A".repeat(MAX_LENGTH + 1), true),
+            Err(KeyError::TooLong)
+        ));
+    }
+
     #[test]
     fn test_mixed_case() {
         assert!(Key::new("MyKeyName".to_string()).is_ok());
@@ -1,2 +1,4 @@
 pub mod key;
+pub mod timestamp;
 pub use key::Key;
+pub use timestamp::TimestampFormat;
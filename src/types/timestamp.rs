@@ -0,0 +1,216 @@
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de::Error as _};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TimestampFormat {
+    #[default]
+    Rfc3339,
+    EpochMillis,
+    EpochSeconds,
+}
+
+static FORMAT: AtomicU8 = AtomicU8::new(TimestampFormat::Rfc3339 as u8);
+
+pub fn set_timestamp_format(format: TimestampFormat) {
+    FORMAT.store(format as u8, Ordering::Relaxed);
+}
+
+pub fn timestamp_format() -> TimestampFormat {
+    match FORMAT.load(Ordering::Relaxed) {
+        1 => TimestampFormat::EpochMillis,
+        2 => TimestampFormat::EpochSeconds,
+        _ => TimestampFormat::Rfc3339,
+    }
+}
+
+impl FromStr for TimestampFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "rfc3339" => Ok(TimestampFormat::Rfc3339),
+            "epoch_millis" => Ok(TimestampFormat::EpochMillis),
+            "epoch_seconds" => Ok(TimestampFormat::EpochSeconds),
+            other => Err(format!(
+                "Unknown timestamp format '{}' (expected 'rfc3339', 'epoch_millis' or 'epoch_seconds')",
+                other
+            )),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawTimestamp {
+    Number(i64),
+    Text(String),
+}
+
+struct TimestampError(String);
+
+impl fmt::Display for TimestampError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl TimestampFormat {
+    pub fn serialize<S: Serializer>(
+        self,
+        timestamp: &DateTime<Utc>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match self {
+            TimestampFormat::Rfc3339 => {
+                serializer.serialize_str(&timestamp.to_rfc3339_opts(SecondsFormat::AutoSi, true))
+            }
+            TimestampFormat::EpochMillis => serializer.serialize_i64(timestamp.timestamp_millis()),
+            TimestampFormat::EpochSeconds => serializer.serialize_i64(timestamp.timestamp()),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> Result<DateTime<Utc>, D::Error> {
+        let parsed = match RawTimestamp::deserialize(deserializer)? {
+            RawTimestamp::Text(text) => DateTime::parse_from_rfc3339(&text)
+                .map(|timestamp| timestamp.with_timezone(&Utc))
+                .map_err(|err| TimestampError(err.to_string())),
+            RawTimestamp::Number(number) => match self {
+                TimestampFormat::EpochMillis => DateTime::from_timestamp_millis(number)
+                    .ok_or_else(|| TimestampError(format!("{} is out of range", number))),
+                TimestampFormat::EpochSeconds => DateTime::from_timestamp(number, 0)
+                    .ok_or_else(|| TimestampError(format!("{} is out of range", number))),
+                TimestampFormat::Rfc3339 => Err(TimestampError(
+                    "expected an RFC 3339 timestamp string".to_string(),
+                )),
+            },
+        };
+
+        parsed.map_err(D::Error::custom)
+    }
+}
+
+pub mod configured {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(
+        timestamp: &DateTime<Utc>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        timestamp_format().serialize(timestamp, serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<DateTime<Utc>, D::Error> {
+        timestamp_format().deserialize(deserializer)
+    }
+}
+
+pub mod configured_option {
+    use super::*;
+
+    struct Wrapped<'a>(&'a DateTime<Utc>);
+
+    impl Serialize for Wrapped<'_> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            configured::serialize(self.0, serializer)
+        }
+    }
+
+    #[derive(Deserialize)]
+    struct Unwrapped(#[serde(with = "configured")] DateTime<Utc>);
+
+    pub fn serialize<S: Serializer>(
+        timestamp: &Option<DateTime<Utc>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        timestamp.as_ref().map(Wrapped).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<DateTime<Utc>>, D::Error> {
+        Ok(Option::<Unwrapped>::deserialize(deserializer)?.map(|Unwrapped(timestamp)| timestamp))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn sample() -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339("2026-01-22T10:30:00.123Z")
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    #[test]
+    fn test_rfc3339_is_default() {
+        assert_eq!(TimestampFormat::default(), TimestampFormat::Rfc3339);
+        let value = TimestampFormat::Rfc3339
+            .serialize(&sample(), serde_json::value::Serializer)
+            .unwrap();
+        assert_eq!(value, json!("2026-01-22T10:30:00.123Z"));
+    }
+
+    #[test]
+    fn test_epoch_millis_round_trip() {
+        let format = TimestampFormat::EpochMillis;
+        let value = format
+            .serialize(&sample(), serde_json::value::Serializer)
+            .unwrap();
+        assert_eq!(value, json!(1769077800123i64));
+        assert_eq!(format.deserialize(value).unwrap(), sample());
+    }
+
+    #[test]
+    fn test_epoch_seconds_round_trip() {
+        let format = TimestampFormat::EpochSeconds;
+        let value = format
+            .serialize(&sample(), serde_json::value::Serializer)
+            .unwrap();
+        assert_eq!(value, json!(1769077800i64));
+        assert_eq!(
+            format.deserialize(value).unwrap(),
+            DateTime::from_timestamp(1769077800, 0).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_epoch_formats_accept_rfc3339_strings() {
+        let value = json!("2026-01-22T10:30:00.123Z");
+        assert_eq!(
+            TimestampFormat::EpochMillis.deserialize(value).unwrap(),
+            sample()
+        );
+    }
+
+    #[test]
+    fn test_rfc3339_rejects_numbers() {
+        assert!(
+            TimestampFormat::Rfc3339
+                .deserialize(json!(1769077800))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_parse_format_names() {
+        assert_eq!(
+            "epoch_millis".parse::<TimestampFormat>(),
+            Ok(TimestampFormat::EpochMillis)
+        );
+        assert_eq!(
+            "EPOCH_SECONDS".parse::<TimestampFormat>(),
+            Ok(TimestampFormat::EpochSeconds)
+        );
+        assert!("iso".parse::<TimestampFormat>().is_err());
+    }
+}
@@ -0,0 +1,171 @@
+//! Runs with `KV_CASE_INSENSITIVE` switched on. The setting is process-wide,
+//! so these tests live in their own binary rather than next to the
+//! case-sensitive ones in `api_integration_test.rs`.
+
+use actix_web::middleware::from_fn;
+use actix_web::{App, test, web};
+use kv_rust::app::idempotency::IdempotencyCache;
+use kv_rust::app::snapshot::SnapshotCache;
+use kv_rust::app::{self, read_ops, write_ops};
+use kv_rust::service::{AsyncStorage, InMemoryStorage, validation};
+use kv_rust::types::key;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+fn create_test_app() -> App<
+    impl actix_web::dev::ServiceFactory<
+        actix_web::dev::ServiceRequest,
+        Config = (),
+        Response = actix_web::dev::ServiceResponse,
+        Error = actix_web::Error,
+        InitError = (),
+    >,
+> {
+    key::set_case_insensitive(true);
+    let storage: Arc<dyn AsyncStorage> = Arc::new(InMemoryStorage::new());
+
+    App::new()
+        .app_data(web::Data::new(storage))
+        .app_data(web::Data::new(IdempotencyCache::default()))
+        .app_data(web::Data::new(SnapshotCache::default()))
+        .app_data(app::json_config(app::DEFAULT_MAX_BODY_BYTES))
+        .app_data(app::payload_config(app::DEFAULT_MAX_BODY_BYTES))
+        .app_data(app::path_config())
+        .app_data(app::query_config())
+        .wrap(from_fn(app::msgpack::negotiate))
+        .configure(read_ops::configure)
+        .configure(write_ops::configure)
+}
+
+#[actix_web::test]
+async fn test_keys_differing_in_case_are_one_entry() {
+    let app = test::init_service(create_test_app()).await;
+
+    let req = test::TestRequest::post()
+        .uri("/keys")
+        .set_json(serde_json::json!({"key": "MyKey", "value": "first"}))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 201);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["key"], "mykey");
+
+    // A create under another casing collides with the existing entry.
+    let req = test::TestRequest::post()
+        .uri("/keys")
+        .set_json(serde_json::json!({"key": "MYKEY", "value": "second"}))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 409);
+
+    let req = test::TestRequest::put()
+        .uri("/keys/mYkEy")
+        .set_json(serde_json::json!({"value": "updated"}))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+
+    for uri in ["/keys/mykey", "/keys/MyKey", "/keys/MYKEY"] {
+        let req = test::TestRequest::get().uri(uri).to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["key"], "mykey", "{}", uri);
+        assert_eq!(body["value"], "updated", "{}", uri);
+        assert_eq!(body["metadata"]["version"], 2, "{}", uri);
+    }
+
+    let req = test::TestRequest::get().uri("/keys").to_request();
+    let keys: Vec<String> = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(keys, ["mykey"]);
+
+    let req = test::TestRequest::delete().uri("/keys/MYKEY").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+
+    let req = test::TestRequest::get().uri("/keys/mykey").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 404);
+}
+
+#[actix_web::test]
+async fn test_batches_and_listings_use_the_lowercased_form() {
+    let app = test::init_service(create_test_app()).await;
+
+    let req = test::TestRequest::post()
+        .uri("/keys/batch")
+        .set_json(serde_json::json!({"entries": [
+            {"key": "User-1", "value": "a"},
+            {"key": "user-1", "value": "b"},
+            {"key": "USER-2", "value": "c"},
+            {"key": "Team-1", "value": "d"}
+        ]}))
+        .to_request();
+    let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    let statuses: Vec<&str> = body["results"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|result| result["status"].as_str().unwrap())
+        .collect();
+    assert_eq!(statuses, ["created", "updated", "created", "created"]);
+
+    // The two spellings of user-1 landed on the same entry; the later wins.
+    let req = test::TestRequest::get().uri("/keys/USER-1").to_request();
+    let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["value"], "b");
+
+    let req = test::TestRequest::get().uri("/keys").to_request();
+    let keys: Vec<String> = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(keys, ["team-1", "user-1", "user-2"]);
+
+    for uri in [
+        "/keys?prefix=USER-",
+        "/keys?pattern=User-%3F",
+        "/keys?start=User&end=UZ",
+    ] {
+        let req = test::TestRequest::get().uri(uri).to_request();
+        let keys: Vec<String> = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(keys, ["user-1", "user-2"], "{}", uri);
+    }
+
+    let req = test::TestRequest::get()
+        .uri("/keys/count?prefix=User")
+        .to_request();
+    let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["count"], 2);
+
+    let req = test::TestRequest::put()
+        .uri("/keys/Profile/fields/Name")
+        .set_json(serde_json::json!({"value": "Ada"}))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+
+    let req = test::TestRequest::get()
+        .uri("/keys/PROFILE/fields/NAME")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+}
+
+#[actix_web::test]
+async fn test_value_limit_prefixes_are_folded_like_keys() {
+    let app = test::init_service(create_test_app()).await;
+    validation::set_value_limits_by_prefix(&BTreeMap::from([("Session-".to_string(), 4)]));
+
+    let req = test::TestRequest::put()
+        .uri("/keys/SESSION-1")
+        .set_json(serde_json::json!({"value": "12345"}))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 400);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["error"]["code"], "VALUE_TOO_LARGE");
+    assert!(
+        body["reason"]
+            .as_str()
+            .unwrap()
+            .ends_with("for keys starting with 'session-'"),
+        "{}",
+        body
+    );
+}
@@ -0,0 +1,193 @@
+//! Spans exported with the `otel` feature, captured by an in-memory exporter.
+//! The tracer provider is process-wide, so these tests live in their own
+//! binary. Run them with `cargo test --features otel`.
+#![cfg(feature = "otel")]
+
+use actix_web::middleware::from_fn;
+use actix_web::{App, test, web};
+use kv_rust::app::idempotency::IdempotencyCache;
+use kv_rust::app::snapshot::SnapshotCache;
+use kv_rust::app::telemetry;
+use kv_rust::app::{self, read_ops, write_ops};
+use kv_rust::service::traced::{KEY_ATTRIBUTE, KEYS_ATTRIBUTE};
+use kv_rust::service::{AsyncStorage, InMemoryStorage, Traced};
+use opentelemetry::trace::{SpanId, SpanKind, TraceId};
+use opentelemetry::{Array, Value};
+use opentelemetry_sdk::trace::{InMemorySpanExporter, SdkTracerProvider, SpanData};
+use std::sync::{Arc, OnceLock};
+
+/// The exporter every test reads from. Tests run in parallel, so each sends
+/// its requests under its own `traceparent` and only looks at that trace.
+fn exporter() -> &'static InMemorySpanExporter {
+    static EXPORTER: OnceLock<InMemorySpanExporter> = OnceLock::new();
+    EXPORTER.get_or_init(|| {
+        let exporter = InMemorySpanExporter::default();
+        telemetry::install(
+            SdkTracerProvider::builder()
+                .with_simple_exporter(exporter.clone())
+                .build(),
+        );
+        exporter
+    })
+}
+
+const CALLER_SPAN: &str = "00f067aa0ba902b7";
+
+fn traceparent(trace_id: &str) -> (&'static str, String) {
+    ("traceparent", format!("00-{}-{}-01", trace_id, CALLER_SPAN))
+}
+
+fn spans_of(trace_id: &str) -> Vec<SpanData> {
+    let trace_id = TraceId::from_hex(trace_id).unwrap();
+    exporter()
+        .get_finished_spans()
+        .unwrap()
+        .into_iter()
+        .filter(|span| span.span_context.trace_id() == trace_id)
+        .collect()
+}
+
+fn create_test_app() -> App<
+    impl actix_web::dev::ServiceFactory<
+        actix_web::dev::ServiceRequest,
+        Config = (),
+        Response = actix_web::dev::ServiceResponse,
+        Error = actix_web::Error,
+        InitError = (),
+    >,
+> {
+    let storage: Arc<dyn AsyncStorage> = Arc::new(Traced::new(InMemoryStorage::new()));
+
+    App::new()
+        .app_data(web::Data::new(storage))
+        .app_data(web::Data::new(IdempotencyCache::default()))
+        .app_data(web::Data::new(SnapshotCache::default()))
+        .app_data(app::json_config(app::DEFAULT_MAX_BODY_BYTES))
+        .app_data(app::payload_config(app::DEFAULT_MAX_BODY_BYTES))
+        .app_data(app::path_config())
+        .app_data(app::query_config())
+        .wrap(from_fn(app::msgpack::negotiate))
+        .wrap(from_fn(telemetry::trace_requests))
+        .configure(read_ops::configure)
+        .configure(write_ops::configure)
+}
+
+fn attribute<'a>(span: &'a SpanData, name: &str) -> Option<&'a Value> {
+    span.attributes
+        .iter()
+        .find(|attribute| attribute.key.as_str() == name)
+        .map(|attribute| &attribute.value)
+}
+
+fn children<'a>(spans: &'a [SpanData], parent: &SpanData) -> Vec<&'a SpanData> {
+    spans
+        .iter()
+        .filter(|span| span.parent_span_id == parent.span_context.span_id())
+        .collect()
+}
+
+#[actix_web::test]
+async fn test_requests_and_storage_calls_create_spans() {
+    exporter();
+    let app = test::init_service(create_test_app()).await;
+    let trace_id = "0af7651916cd43dd8448eb211c80319c";
+
+    let req = test::TestRequest::put()
+        .uri("/keys/user-1")
+        .insert_header(traceparent(trace_id))
+        .set_json(serde_json::json!({"value": "top secret"}))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 201);
+    let req = test::TestRequest::get()
+        .uri("/keys/user-1")
+        .insert_header(traceparent(trace_id))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 200);
+
+    let spans = spans_of(trace_id);
+    let servers: Vec<&SpanData> = spans
+        .iter()
+        .filter(|span| span.span_kind == SpanKind::Server)
+        .collect();
+    let names: Vec<&str> = servers.iter().map(|span| span.name.as_ref()).collect();
+    assert_eq!(names, ["PUT /keys/{key}", "GET /keys/{key}"]);
+    let statuses: Vec<Option<&Value>> = servers
+        .iter()
+        .map(|span| attribute(span, "http.response.status_code"))
+        .collect();
+    assert_eq!(statuses, [Some(&Value::I64(201)), Some(&Value::I64(200))]);
+
+    for server in servers {
+        assert_eq!(
+            server.parent_span_id,
+            SpanId::from_hex(CALLER_SPAN).unwrap()
+        );
+        assert_eq!(
+            attribute(server, KEY_ATTRIBUTE),
+            Some(&Value::from("user-1"))
+        );
+        assert_eq!(
+            attribute(server, "http.route"),
+            Some(&Value::from("/keys/{key}"))
+        );
+        let storage_spans = children(&spans, server);
+        assert!(!storage_spans.is_empty(), "{} has no children", server.name);
+        for child in storage_spans {
+            assert!(child.name.starts_with("storage."), "{}", child.name);
+            assert_eq!(
+                attribute(child, KEY_ATTRIBUTE),
+                Some(&Value::from("user-1"))
+            );
+        }
+    }
+
+    // Keys are recorded, values never are.
+    for span in &spans {
+        for attribute in &span.attributes {
+            assert!(
+                !attribute.value.as_str().contains("top secret"),
+                "{} leaks the value in {}",
+                span.name,
+                attribute.key
+            );
+        }
+    }
+}
+
+#[actix_web::test]
+async fn test_batch_spans_record_their_keys() {
+    exporter();
+    let app = test::init_service(create_test_app()).await;
+
+    let trace_id = "4bf92f3577b34da6a3ce929d0e0e4736";
+    let req = test::TestRequest::post()
+        .uri("/keys/batch")
+        .insert_header(traceparent(trace_id))
+        .set_json(serde_json::json!({"entries": [
+            {"key": "a", "value": "1"},
+            {"key": "b", "value": "2"}
+        ]}))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 200);
+
+    let spans = spans_of(trace_id);
+    let server = spans
+        .iter()
+        .find(|span| span.span_kind == SpanKind::Server)
+        .unwrap();
+    assert_eq!(server.name, "POST /keys/batch");
+    assert_eq!(
+        server.parent_span_id,
+        SpanId::from_hex(CALLER_SPAN).unwrap()
+    );
+    assert_eq!(attribute(server, KEY_ATTRIBUTE), None);
+
+    let upsert_many = children(&spans, server)
+        .into_iter()
+        .find(|span| span.name == "storage.upsert_many")
+        .unwrap();
+    assert_eq!(
+        attribute(upsert_many, KEYS_ATTRIBUTE),
+        Some(&Value::Array(Array::String(vec!["a".into(), "b".into()])))
+    );
+}
@@ -0,0 +1,109 @@
+//! Runs with `KV_MAX_VALUE_BYTES_BY_PREFIX` set. The limits are process-wide,
+//! so these tests live in their own binary rather than next to the ones in
+//! `api_integration_test.rs`.
+
+use actix_web::middleware::from_fn;
+use actix_web::{App, test, web};
+use kv_rust::app::idempotency::IdempotencyCache;
+use kv_rust::app::snapshot::SnapshotCache;
+use kv_rust::app::{self, read_ops, write_ops};
+use kv_rust::service::{AsyncStorage, InMemoryConfig, InMemoryStorage, validation};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+fn create_test_app() -> App<
+    impl actix_web::dev::ServiceFactory<
+        actix_web::dev::ServiceRequest,
+        Config = (),
+        Response = actix_web::dev::ServiceResponse,
+        Error = actix_web::Error,
+        InitError = (),
+    >,
+> {
+    validation::set_value_limits_by_prefix(&BTreeMap::from([
+        ("session-".to_string(), 4),
+        ("session-admin-".to_string(), 8),
+        ("cache-".to_string(), 12),
+    ]));
+    let storage: Arc<dyn AsyncStorage> = Arc::new(InMemoryStorage::with_config(InMemoryConfig {
+        max_value_bytes: 16,
+        ..Default::default()
+    }));
+
+    App::new()
+        .app_data(web::Data::new(storage))
+        .app_data(web::Data::new(IdempotencyCache::default()))
+        .app_data(web::Data::new(SnapshotCache::default()))
+        .app_data(app::json_config(app::DEFAULT_MAX_BODY_BYTES))
+        .app_data(app::payload_config(app::DEFAULT_MAX_BODY_BYTES))
+        .app_data(app::path_config())
+        .app_data(app::query_config())
+        .wrap(from_fn(app::msgpack::negotiate))
+        .configure(read_ops::configure)
+        .configure(write_ops::configure)
+}
+
+#[actix_web::test]
+async fn test_value_size_limits_by_overlapping_prefixes() {
+    let app = test::init_service(create_test_app()).await;
+
+    for (key, size, status) in [
+        ("session-1", 4, 201),
+        ("session-2", 5, 400),
+        ("session-admin-1", 8, 201),
+        ("session-admin-2", 9, 400),
+        ("cache-page", 12, 201),
+        ("cache-page-2", 13, 400),
+        ("other", 16, 201),
+        ("other-2", 17, 413),
+    ] {
+        let req = test::TestRequest::put()
+            .uri(&format!("/keys/{}", key))
+            .set_json(serde_json::json!({"value": "x".repeat(size)}))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(
+            resp.status().as_u16(),
+            status,
+            "{} with {} bytes",
+            key,
+            size
+        );
+    }
+
+    let req = test::TestRequest::post()
+        .uri("/keys")
+        .set_json(serde_json::json!({"key": "session-admin-3", "value": "x".repeat(9)}))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status().as_u16(), 400);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["error"]["code"], "VALUE_TOO_LARGE");
+    assert_eq!(body["field"], "value");
+    assert_eq!(
+        body["reason"],
+        "Value is 9 bytes, over the limit of 8 bytes for keys starting with 'session-admin-'"
+    );
+}
+
+#[actix_web::test]
+async fn test_batches_report_values_over_their_prefix_limit() {
+    let app = test::init_service(create_test_app()).await;
+
+    let req = test::TestRequest::post()
+        .uri("/keys/batch")
+        .set_json(serde_json::json!({"entries": [
+            {"key": "session-a", "value": "1234"},
+            {"key": "session-b", "value": "12345"},
+        ]}))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status().as_u16(), 200);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["results"][0]["status"], "created");
+    assert_eq!(body["results"][1]["status"], "error");
+    assert_eq!(body["results"][1]["error"]["code"], "VALUE_TOO_LARGE");
+
+    let req = test::TestRequest::get().uri("/keys/session-b").to_request();
+    assert_eq!(test::call_service(&app, req).await.status().as_u16(), 404);
+}